@@ -0,0 +1,132 @@
+//! Benchmarks for the graph lookups used by the plugin chain.
+//!
+//! The `plugin_chain` group runs `edge-add-remove` and `channel-filter` on a graph
+//! of the size of the production graphs. To compare against an older revision, run
+//! `cargo bench --bench plugin_chain -- --save-baseline before` on that revision
+//! and `cargo bench --bench plugin_chain -- --baseline before` on this one.
+//!
+//! The `lookups` group compares the indexed lookups with full scans of the graph,
+//! which is how they were implemented before the indexes were added.
+
+use cincinnati::plugins::prelude::*;
+use cincinnati::plugins::prelude_plugin_impl::*;
+use cincinnati::{ConcreteRelease, Graph, MapImpl, Release};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use std::collections::HashMap;
+
+const RELEASES_COUNT: usize = 10_000;
+const KEY_PREFIX: &str = "io.openshift.upgrades.graph";
+
+/// Builds a graph in which every release declares its predecessor via `previous.add`
+/// and every tenth release removes it again via `previous.remove`.
+fn generate_graph(releases_count: usize) -> Graph {
+    let mut graph = Graph::default();
+
+    for i in 0..releases_count {
+        let mut metadata = MapImpl::new();
+        metadata.insert(
+            format!("{}.release.channels", KEY_PREFIX),
+            if i % 2 == 0 { "stable" } else { "fast" }.to_string(),
+        );
+        if i > 0 {
+            let key = if i % 10 == 0 {
+                "previous.remove"
+            } else {
+                "previous.add"
+            };
+            metadata.insert(format!("{}.{}", KEY_PREFIX, key), format!("{}.0.0", i - 1));
+        }
+
+        graph
+            .add_release(Release::Concrete(ConcreteRelease {
+                version: format!("{}.0.0", i),
+                payload: format!("image/{}", i),
+                metadata,
+            }))
+            .expect("could not add release");
+    }
+
+    graph
+}
+
+fn plugin_chain(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("could not create runtime");
+    let graph = generate_graph(RELEASES_COUNT);
+    let parameters: HashMap<String, String> = [("channel", "stable")]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    let edge_add_remove = EdgeAddRemovePlugin::default();
+    let channel_filter = ChannelFilterPlugin::default();
+
+    c.bench_function("plugin_chain/edge-add-remove+channel-filter", |b| {
+        b.iter_batched(
+            || InternalIO {
+                graph: graph.clone().into(),
+                parameters: parameters.clone(),
+            },
+            |io| {
+                runtime
+                    .block_on(async {
+                        let io = edge_add_remove.run_internal(io).await?;
+                        channel_filter.run_internal(io).await
+                    })
+                    .expect("plugin chain failed")
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn lookups(c: &mut Criterion) {
+    let mut graph = generate_graph(RELEASES_COUNT);
+    let versions: Vec<String> = (0..RELEASES_COUNT)
+        .step_by(100)
+        .map(|i| format!("{}.0.0", i))
+        .collect();
+    let keys: Vec<String> = ["previous.add", "previous.remove", "next.add", "next.remove"]
+        .iter()
+        .map(|suffix| format!("{}.{}", KEY_PREFIX, suffix))
+        .collect();
+
+    let mut group = c.benchmark_group("lookups");
+
+    group.bench_function("find_by_version/scan", |b| {
+        b.iter(|| {
+            for version in &versions {
+                black_box(graph.find_by_fn(|release| release.version() == version));
+            }
+        })
+    });
+    group.bench_function("find_by_version/indexed", |b| {
+        b.iter(|| {
+            for version in &versions {
+                black_box(graph.find_by_version(version));
+            }
+        })
+    });
+
+    group.bench_function("find_by_metadata_key/scan", |b| {
+        b.iter(|| {
+            for key in &keys {
+                black_box(graph.find_by_metadata_key(key));
+            }
+        })
+    });
+    for key in &keys {
+        graph.index_metadata_key(key);
+    }
+    group.bench_function("find_by_metadata_key/indexed", |b| {
+        b.iter(|| {
+            for key in &keys {
+                black_box(graph.find_by_metadata_key(key));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, plugin_chain, lookups);
+criterion_main!(benches);
@@ -0,0 +1,496 @@
+//! Rendering of graphs for visualization.
+//!
+//! Releases are grouped into clusters by their minor version. Conditional edges
+//! are drawn differently from unconditional ones and are labeled with the names
+//! of their risks. Edges which are both unconditional and conditional are drawn
+//! as unconditional edges, as that is how they are served.
+
+use crate::{EdgeWeight, Graph, Release};
+use daggy::petgraph::visit::IntoNodeReferences;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// Media type of Graphviz DOT.
+pub const DOT_CONTENT_TYPE: &str = "text/vnd.graphviz";
+
+/// Media type of Mermaid flowcharts.
+pub const MERMAID_CONTENT_TYPE: &str = "text/vnd.mermaid";
+
+/// Media type of GraphML.
+pub const GRAPHML_CONTENT_TYPE: &str = "application/graphml+xml";
+
+/// Metadata key holding the URL of a release, which is used as a link target.
+static URL_METADATA_KEY: &str = "url";
+
+/// Formats a graph can be exported to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Dot,
+    Mermaid,
+    GraphMl,
+}
+
+impl ExportFormat {
+    /// All export formats.
+    pub const ALL: [ExportFormat; 3] = [
+        ExportFormat::Dot,
+        ExportFormat::Mermaid,
+        ExportFormat::GraphMl,
+    ];
+
+    /// Returns the media type of this format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::Dot => DOT_CONTENT_TYPE,
+            ExportFormat::Mermaid => MERMAID_CONTENT_TYPE,
+            ExportFormat::GraphMl => GRAPHML_CONTENT_TYPE,
+        }
+    }
+
+    /// Returns the format with the given media type, if any.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| format.content_type() == content_type)
+    }
+
+    /// Renders the graph in this format.
+    pub fn render(self, graph: &Graph) -> String {
+        match self {
+            ExportFormat::Dot => to_dot(graph),
+            ExportFormat::Mermaid => to_mermaid(graph),
+            ExportFormat::GraphMl => to_graphml(graph),
+        }
+    }
+}
+
+/// Releases and edges of a graph, with stable node identifiers.
+struct Layout<'a> {
+    /// Releases by their minor version; releases without a SemVer version have none.
+    clusters: BTreeMap<Option<(u64, u64)>, Vec<(usize, &'a Release)>>,
+    /// Edges as (from, to) node identifiers.
+    edges: Vec<(usize, usize, &'a EdgeWeight)>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(graph: &'a Graph) -> Self {
+        let mut releases: Vec<(Option<semver::Version>, &Release)> = graph
+            .dag
+            .node_references()
+            .map(|(_, release)| (semver::Version::parse(release.version()).ok(), release))
+            .collect();
+        releases.sort_by(|(a_semver, a), (b_semver, b)| {
+            a_semver
+                .cmp(b_semver)
+                .then_with(|| a.version().cmp(b.version()))
+        });
+
+        let mut ids = HashMap::with_capacity(releases.len());
+        let mut clusters: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (id, (semver, release)) in releases.into_iter().enumerate() {
+            ids.insert(release.version(), id);
+            clusters
+                .entry(semver.map(|version| (version.major, version.minor)))
+                .or_default()
+                .push((id, release));
+        }
+
+        let mut edges: Vec<_> = graph
+            .edges()
+            .map(|(from, to, weight)| (ids[from.version()], ids[to.version()], weight))
+            .collect();
+        edges.sort_by_key(|(from, to, _)| (*from, *to));
+
+        Layout { clusters, edges }
+    }
+}
+
+fn cluster_name(minor: (u64, u64)) -> String {
+    format!("{}.{}", minor.0, minor.1)
+}
+
+fn release_url(release: &Release) -> Option<&str> {
+    match release {
+        Release::Concrete(release) => release.metadata.get(URL_METADATA_KEY).map(String::as_str),
+        Release::Abstract(_) => None,
+    }
+}
+
+fn risk_names(weight: &EdgeWeight) -> String {
+    weight
+        .risks
+        .iter()
+        .map(|risk| risk.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Renders the graph as a Graphviz digraph.
+pub fn to_dot(graph: &Graph) -> String {
+    let layout = Layout::new(graph);
+    let mut dot = String::new();
+
+    let node = |dot: &mut String, indent: &str, id: usize, release: &Release| {
+        let mut attributes = format!("label=\"{}\"", escape_dot(release.version()));
+        if let Some(url) = release_url(release) {
+            let _ = write!(attributes, " href=\"{}\"", escape_dot(url));
+        }
+        if let Release::Abstract(_) = release {
+            attributes.push_str(" style=dashed");
+        }
+        let _ = writeln!(dot, "{}n{} [ {} ];", indent, id, attributes);
+    };
+
+    dot.push_str("digraph Upgrades {\n  labelloc=t;\n  rankdir=BT;\n");
+    for (minor, releases) in &layout.clusters {
+        match minor {
+            Some(minor) => {
+                let name = cluster_name(*minor);
+                let _ = writeln!(dot, "  subgraph \"cluster_{}\" {{", name);
+                let _ = writeln!(dot, "    label=\"{}\";", name);
+                for (id, release) in releases {
+                    node(&mut dot, "    ", *id, release);
+                }
+                dot.push_str("  }\n");
+            }
+            None => {
+                for (id, release) in releases {
+                    node(&mut dot, "  ", *id, release);
+                }
+            }
+        }
+    }
+    for (from, to, weight) in &layout.edges {
+        if weight.unconditional {
+            let _ = writeln!(dot, "  n{}->n{};", from, to);
+        } else {
+            let tooltip = weight
+                .risks
+                .iter()
+                .map(|risk| format!("{}: {}", risk.name, risk.message))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let _ = writeln!(
+                dot,
+                "  n{}->n{} [ style=dashed color=orange fontcolor=orange label=\"{}\" tooltip=\"{}\" ];",
+                from,
+                to,
+                escape_dot(&risk_names(weight)),
+                escape_dot(&tooltip)
+            );
+        }
+    }
+    dot.push_str("}\n");
+
+    dot
+}
+
+fn escape_mermaid(value: &str) -> String {
+    value.replace('"', "#quot;")
+}
+
+/// Renders the graph as a Mermaid flowchart.
+pub fn to_mermaid(graph: &Graph) -> String {
+    let layout = Layout::new(graph);
+    let mut mermaid = String::from("flowchart BT\n");
+
+    let node = |mermaid: &mut String, indent: &str, id: usize, release: &Release| {
+        let _ = match release {
+            Release::Concrete(_) => writeln!(
+                mermaid,
+                "{}n{}[\"{}\"]",
+                indent,
+                id,
+                escape_mermaid(release.version())
+            ),
+            Release::Abstract(_) => writeln!(
+                mermaid,
+                "{}n{}([\"{}\"])",
+                indent,
+                id,
+                escape_mermaid(release.version())
+            ),
+        };
+        if let Some(url) = release_url(release) {
+            let _ = writeln!(
+                mermaid,
+                "{}click n{} \"{}\"",
+                indent,
+                id,
+                escape_mermaid(url)
+            );
+        }
+    };
+
+    for (minor, releases) in &layout.clusters {
+        match minor {
+            Some(minor) => {
+                let name = cluster_name(*minor);
+                let _ = writeln!(
+                    mermaid,
+                    "  subgraph cluster_{}_{} [\"{}\"]",
+                    minor.0, minor.1, name
+                );
+                for (id, release) in releases {
+                    node(&mut mermaid, "    ", *id, release);
+                }
+                mermaid.push_str("  end\n");
+            }
+            None => {
+                for (id, release) in releases {
+                    node(&mut mermaid, "  ", *id, release);
+                }
+            }
+        }
+    }
+
+    let mut conditional_links = vec![];
+    for (link, (from, to, weight)) in layout.edges.iter().enumerate() {
+        if weight.unconditional {
+            let _ = writeln!(mermaid, "  n{} --> n{}", from, to);
+        } else {
+            let _ = writeln!(
+                mermaid,
+                "  n{} -.->|\"{}\"| n{}",
+                from,
+                escape_mermaid(&risk_names(weight)),
+                to
+            );
+            conditional_links.push(link.to_string());
+        }
+    }
+    if !conditional_links.is_empty() {
+        let _ = writeln!(
+            mermaid,
+            "  linkStyle {} stroke:orange,color:orange",
+            conditional_links.join(",")
+        );
+    }
+
+    mermaid
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Renders the graph as GraphML.
+///
+/// Clusters are represented as nested graphs. Edges carry whether they are
+/// conditional and the names of their risks as data.
+pub fn to_graphml(graph: &Graph) -> String {
+    let layout = Layout::new(graph);
+    let mut graphml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n",
+        "  <key id=\"version\" for=\"node\" attr.name=\"version\" attr.type=\"string\"/>\n",
+        "  <key id=\"payload\" for=\"node\" attr.name=\"payload\" attr.type=\"string\"/>\n",
+        "  <key id=\"url\" for=\"node\" attr.name=\"url\" attr.type=\"string\"/>\n",
+        "  <key id=\"conditional\" for=\"edge\" attr.name=\"conditional\" attr.type=\"boolean\">\n",
+        "    <default>false</default>\n",
+        "  </key>\n",
+        "  <key id=\"risks\" for=\"edge\" attr.name=\"risks\" attr.type=\"string\"/>\n",
+        "  <graph id=\"Upgrades\" edgedefault=\"directed\">\n",
+    ));
+
+    let node = |graphml: &mut String, indent: &str, id: usize, release: &Release| {
+        let _ = writeln!(graphml, "{}<node id=\"n{}\">", indent, id);
+        let _ = writeln!(
+            graphml,
+            "{}  <data key=\"version\">{}</data>",
+            indent,
+            escape_xml(release.version())
+        );
+        if let Release::Concrete(concrete) = release {
+            let _ = writeln!(
+                graphml,
+                "{}  <data key=\"payload\">{}</data>",
+                indent,
+                escape_xml(&concrete.payload)
+            );
+        }
+        if let Some(url) = release_url(release) {
+            let _ = writeln!(
+                graphml,
+                "{}  <data key=\"url\">{}</data>",
+                indent,
+                escape_xml(url)
+            );
+        }
+        let _ = writeln!(graphml, "{}</node>", indent);
+    };
+
+    for (minor, releases) in &layout.clusters {
+        match minor {
+            Some(minor) => {
+                let name = escape_xml(&cluster_name(*minor));
+                let _ = writeln!(graphml, "    <node id=\"cluster_{}\">", name);
+                let _ = writeln!(
+                    graphml,
+                    "      <graph id=\"cluster_{}:\" edgedefault=\"directed\">",
+                    name
+                );
+                for (id, release) in releases {
+                    node(&mut graphml, "        ", *id, release);
+                }
+                graphml.push_str("      </graph>\n    </node>\n");
+            }
+            None => {
+                for (id, release) in releases {
+                    node(&mut graphml, "    ", *id, release);
+                }
+            }
+        }
+    }
+
+    for (from, to, weight) in &layout.edges {
+        if weight.unconditional {
+            let _ = writeln!(
+                graphml,
+                "    <edge source=\"n{}\" target=\"n{}\"/>",
+                from, to
+            );
+        } else {
+            let _ = writeln!(
+                graphml,
+                "    <edge source=\"n{}\" target=\"n{}\">",
+                from, to
+            );
+            graphml.push_str("      <data key=\"conditional\">true</data>\n");
+            let _ = writeln!(
+                graphml,
+                "      <data key=\"risks\">{}</data>",
+                escape_xml(&risk_names(weight))
+            );
+            graphml.push_str("    </edge>\n");
+        }
+    }
+    graphml.push_str("  </graph>\n</graphml>\n");
+
+    graphml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{generate_custom_graph, generate_graph};
+    use crate::MapImpl;
+
+    #[test]
+    fn dot_export() {
+        let graph = generate_graph(true, false);
+
+        let expected = r#"digraph Upgrades {
+  labelloc=t;
+  rankdir=BT;
+  subgraph "cluster_1.0" {
+    label="1.0";
+    n0 [ label="1.0.0" ];
+  }
+  subgraph "cluster_2.0" {
+    label="2.0";
+    n1 [ label="2.0.0" ];
+  }
+  subgraph "cluster_3.0" {
+    label="3.0";
+    n2 [ label="3.0.0" ];
+  }
+  n0->n1;
+  n0->n2;
+  n1->n2;
+}
+"#;
+        assert_eq!(to_dot(&graph), expected);
+    }
+
+    #[test]
+    fn exports_style_conditional_edges() {
+        let mut graph = generate_graph(true, false);
+        let (from, to) = (
+            graph.find_by_version("1.0.0").unwrap(),
+            graph.find_by_version("2.0.0").unwrap(),
+        );
+        graph.remove_edge(&from, &to).unwrap();
+
+        let dot = to_dot(&graph);
+        assert!(
+            dot.contains("n0->n1 [ style=dashed color=orange fontcolor=orange label=\"BrokenUpdates\" tooltip=\"BrokenUpdates: Updates are broken for this provider\" ];"),
+            "{}",
+            dot
+        );
+
+        let mermaid = to_mermaid(&graph);
+        assert!(
+            mermaid.contains("  n0 -.->|\"BrokenUpdates\"| n1\n"),
+            "{}",
+            mermaid
+        );
+        assert!(
+            mermaid.ends_with("  linkStyle 0 stroke:orange,color:orange\n"),
+            "{}",
+            mermaid
+        );
+
+        let graphml = to_graphml(&graph);
+        assert!(
+            graphml.contains(concat!(
+                "    <edge source=\"n0\" target=\"n1\">\n",
+                "      <data key=\"conditional\">true</data>\n",
+                "      <data key=\"risks\">BrokenUpdates</data>\n",
+                "    </edge>\n",
+            )),
+            "{}",
+            graphml
+        );
+    }
+
+    #[test]
+    fn exports_group_by_minor_version() {
+        let graph = crate::testing::TestGraphBuilder::new()
+            .with_metadata((0..4).map(|i| (i, MapImpl::new())).collect())
+            .with_version_template("4.{{i}}.0")
+            .build();
+        let mut graph_with_patches = generate_custom_graph(
+            "image",
+            vec![(0, MapImpl::new()), (1, MapImpl::new())],
+            None,
+        );
+        graph_with_patches.merge(graph, Default::default()).unwrap();
+
+        let mermaid = to_mermaid(&graph_with_patches);
+        for minor in &["0.0", "1.0", "4.0", "4.1", "4.2", "4.3"] {
+            assert!(
+                mermaid.contains(&format!("[\"{}\"]\n", minor)),
+                "missing cluster {}: {}",
+                minor,
+                mermaid
+            );
+        }
+
+        let graphml = to_graphml(&graph_with_patches);
+        assert_eq!(graphml.matches("<graph id=\"cluster_").count(), 6);
+        assert_eq!(graphml.matches("<node id=\"n").count(), 6);
+        assert_eq!(graphml.matches("<edge ").count(), 4);
+    }
+
+    #[test]
+    fn export_format_content_types() {
+        for format in &ExportFormat::ALL {
+            assert_eq!(
+                ExportFormat::from_content_type(format.content_type()),
+                Some(*format)
+            );
+        }
+        assert_eq!(ExportFormat::from_content_type("application/json"), None);
+    }
+}
@@ -0,0 +1,350 @@
+//! Differences between two versions of a `Graph`.
+//!
+//! Releases and edges are identified by version, so that deltas stay
+//! meaningful across graphs which have been built independently.
+
+use crate::{ConditionalUpdateRisk, Graph, MapImpl, Release};
+use daggy::petgraph::visit::IntoNodeReferences;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+/// Changes to the metadata of a release which is present in both graphs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct MetadataDelta {
+    /// Version of the release.
+    pub version: String,
+    /// Metadata keys which only exist in the newer graph.
+    pub added_keys: Vec<String>,
+    /// Metadata keys which only exist in the older graph.
+    pub removed_keys: Vec<String>,
+    /// Metadata keys which exist in both graphs with different values.
+    pub changed_keys: Vec<String>,
+}
+
+/// Changes between two graphs, as returned by `Graph::diff`.
+///
+/// All lists are sorted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct GraphDelta {
+    /// Versions of releases which only exist in the newer graph.
+    pub added_releases: Vec<String>,
+    /// Versions of releases which only exist in the older graph.
+    pub removed_releases: Vec<String>,
+    /// Edges, as (from, to) versions, which only exist in the newer graph.
+    pub added_edges: Vec<(String, String)>,
+    /// Edges, as (from, to) versions, which only exist in the older graph.
+    pub removed_edges: Vec<(String, String)>,
+    /// Releases whose metadata changed.
+    pub changed_metadata: Vec<MetadataDelta>,
+    /// Conditional edges, as (from, to) versions, which only exist in the newer graph.
+    pub added_conditional_edges: Vec<(String, String)>,
+    /// Conditional edges, as (from, to) versions, which only exist in the older graph.
+    pub removed_conditional_edges: Vec<(String, String)>,
+    /// Conditional edges, as (from, to) versions, which exist in both graphs with different risks.
+    pub changed_conditional_edges: Vec<(String, String)>,
+    /// Names of risks which only exist in the newer graph.
+    pub added_risks: Vec<String>,
+    /// Names of risks which only exist in the older graph.
+    pub removed_risks: Vec<String>,
+}
+
+impl GraphDelta {
+    /// Returns true if both graphs were equivalent.
+    pub fn is_empty(&self) -> bool {
+        self.added_releases.is_empty()
+            && self.removed_releases.is_empty()
+            && self.added_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.changed_metadata.is_empty()
+            && self.added_conditional_edges.is_empty()
+            && self.removed_conditional_edges.is_empty()
+            && self.changed_conditional_edges.is_empty()
+            && self.added_risks.is_empty()
+            && self.removed_risks.is_empty()
+    }
+}
+
+impl fmt::Display for GraphDelta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "releases +{}/-{}, edges +{}/-{}, metadata changed on {} releases, conditional edges +{}/-{}/~{}, risks +{}/-{}",
+            self.added_releases.len(),
+            self.removed_releases.len(),
+            self.added_edges.len(),
+            self.removed_edges.len(),
+            self.changed_metadata.len(),
+            self.added_conditional_edges.len(),
+            self.removed_conditional_edges.len(),
+            self.changed_conditional_edges.len(),
+            self.added_risks.len(),
+            self.removed_risks.len(),
+        )
+    }
+}
+
+/// Returns the elements only in `new` and the elements only in `old`.
+fn set_difference<T: Ord + Clone>(old: &BTreeSet<T>, new: &BTreeSet<T>) -> (Vec<T>, Vec<T>) {
+    (
+        new.difference(old).cloned().collect(),
+        old.difference(new).cloned().collect(),
+    )
+}
+
+impl Graph {
+    /// Computes the changes required to get from this graph to `newer`.
+    pub fn diff(&self, newer: &Graph) -> GraphDelta {
+        let old_releases = self.releases_by_version();
+        let new_releases = newer.releases_by_version();
+
+        let (added_releases, removed_releases) = set_difference(
+            &old_releases.keys().cloned().collect(),
+            &new_releases.keys().cloned().collect(),
+        );
+        let (added_edges, removed_edges) =
+            set_difference(&self.edges_by_version(), &newer.edges_by_version());
+        let old_conditional_edges = self.conditional_edges_by_version();
+        let new_conditional_edges = newer.conditional_edges_by_version();
+        let (added_conditional_edges, removed_conditional_edges) = set_difference(
+            &old_conditional_edges.keys().cloned().collect(),
+            &new_conditional_edges.keys().cloned().collect(),
+        );
+        let changed_conditional_edges = old_conditional_edges
+            .iter()
+            .filter(|(edge, old_risks)| {
+                new_conditional_edges
+                    .get(*edge)
+                    .map_or(false, |new_risks| new_risks != *old_risks)
+            })
+            .map(|(edge, _)| edge.clone())
+            .collect();
+        let (added_risks, removed_risks) = set_difference(&self.risk_names(), &newer.risk_names());
+
+        let changed_metadata = old_releases
+            .iter()
+            .filter_map(|(version, old)| {
+                new_releases
+                    .get(version)
+                    .and_then(|new| metadata_delta(version, old, new))
+            })
+            .collect();
+
+        GraphDelta {
+            added_releases: added_releases.into_iter().map(String::from).collect(),
+            removed_releases: removed_releases.into_iter().map(String::from).collect(),
+            added_edges,
+            removed_edges,
+            changed_metadata,
+            added_conditional_edges,
+            removed_conditional_edges,
+            changed_conditional_edges,
+            added_risks,
+            removed_risks,
+        }
+    }
+
+    fn releases_by_version(&self) -> BTreeMap<&str, &Release> {
+        self.dag
+            .node_references()
+            .map(|(_, release)| (release.version(), release))
+            .collect()
+    }
+
+    fn edges_by_version(&self) -> BTreeSet<(String, String)> {
+        self.edges()
+            .filter(|(_, _, weight)| weight.unconditional)
+            .map(|(from, to, _)| (from.version().to_string(), to.version().to_string()))
+            .collect()
+    }
+
+    /// Returns the risks of the conditional edges, regardless of their order.
+    fn conditional_edges_by_version(
+        &self,
+    ) -> BTreeMap<(String, String), HashSet<&ConditionalUpdateRisk>> {
+        self.edges()
+            .filter(|(_, _, weight)| weight.is_conditional())
+            .map(|(from, to, weight)| {
+                (
+                    (from.version().to_string(), to.version().to_string()),
+                    weight.risks.iter().collect(),
+                )
+            })
+            .collect()
+    }
+
+    fn risk_names(&self) -> BTreeSet<String> {
+        self.edges()
+            .flat_map(|(_, _, weight)| weight.risks.iter())
+            .map(|risk| risk.name.clone())
+            .collect()
+    }
+}
+
+fn release_metadata(release: &Release) -> Option<&MapImpl<String, String>> {
+    match release {
+        Release::Concrete(release) => Some(&release.metadata),
+        Release::Abstract(_) => None,
+    }
+}
+
+/// Compares the metadata of two releases with the same version.
+fn metadata_delta(version: &str, old: &Release, new: &Release) -> Option<MetadataDelta> {
+    let empty = MapImpl::new();
+    let (old, new) = (
+        release_metadata(old).unwrap_or(&empty),
+        release_metadata(new).unwrap_or(&empty),
+    );
+
+    let old_keys: BTreeSet<&String> = old.keys().collect();
+    let new_keys: BTreeSet<&String> = new.keys().collect();
+    let (added_keys, removed_keys) = set_difference(&old_keys, &new_keys);
+    let changed_keys: Vec<String> = old_keys
+        .intersection(&new_keys)
+        .filter(|key| old.get(**key) != new.get(**key))
+        .map(|key| key.to_string())
+        .collect();
+
+    if added_keys.is_empty() && removed_keys.is_empty() && changed_keys.is_empty() {
+        return None;
+    }
+
+    Some(MetadataDelta {
+        version: version.to_string(),
+        added_keys: added_keys.into_iter().cloned().collect(),
+        removed_keys: removed_keys.into_iter().cloned().collect(),
+        changed_keys,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{generate_custom_graph, generate_graph};
+    use commons::prelude_errors::*;
+
+    #[test]
+    fn diff_identical_graphs() {
+        let graph = generate_graph(true, false);
+
+        let delta = graph.diff(&graph.clone());
+        assert!(delta.is_empty(), "unexpected delta: {:?}", delta);
+    }
+
+    #[test]
+    fn diff_releases_and_edges() {
+        let old = generate_custom_graph(
+            "image",
+            (0..3).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![(0, 1), (1, 2)]),
+        );
+        let new = generate_custom_graph(
+            "image",
+            (0..4).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![(0, 1), (0, 2), (2, 3)]),
+        );
+
+        let delta = old.diff(&new);
+        assert_eq!(
+            delta,
+            GraphDelta {
+                added_releases: vec!["3.0.0".to_string()],
+                added_edges: vec![
+                    ("0.0.0".to_string(), "2.0.0".to_string()),
+                    ("2.0.0".to_string(), "3.0.0".to_string()),
+                ],
+                removed_edges: vec![("1.0.0".to_string(), "2.0.0".to_string())],
+                ..Default::default()
+            }
+        );
+
+        let reverse = new.diff(&old);
+        assert_eq!(reverse.removed_releases, delta.added_releases);
+        assert_eq!(reverse.added_edges, delta.removed_edges);
+    }
+
+    #[test]
+    fn diff_metadata() {
+        let metadata = |pairs: &[(&str, &str)]| -> MapImpl<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let old = generate_custom_graph(
+            "image",
+            vec![(
+                0,
+                metadata(&[("kept", "a"), ("changed", "a"), ("removed", "a")]),
+            )],
+            None,
+        );
+        let new = generate_custom_graph(
+            "image",
+            vec![(
+                0,
+                metadata(&[("kept", "a"), ("changed", "b"), ("added", "a")]),
+            )],
+            None,
+        );
+
+        assert_eq!(
+            old.diff(&new).changed_metadata,
+            vec![MetadataDelta {
+                version: "0.0.0".to_string(),
+                added_keys: vec!["added".to_string()],
+                removed_keys: vec!["removed".to_string()],
+                changed_keys: vec!["changed".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn diff_conditional_edges() {
+        let old = generate_graph(false, false);
+        let new = generate_graph(true, false);
+
+        let delta = old.diff(&new);
+        assert_eq!(
+            delta.added_conditional_edges,
+            vec![("1.0.0".to_string(), "2.0.0".to_string())]
+        );
+        assert_eq!(delta.added_risks, vec!["BrokenUpdates".to_string()]);
+
+        let delta = new.diff(&generate_graph(true, true));
+        assert!(delta.added_conditional_edges.is_empty());
+        assert_eq!(delta.added_risks, vec!["AllBrokenUpdates".to_string()]);
+        assert_eq!(delta.removed_risks, vec!["BrokenUpdates".to_string()]);
+        assert_eq!(
+            delta.changed_conditional_edges,
+            vec![("1.0.0".to_string(), "2.0.0".to_string())]
+        );
+    }
+
+    #[test]
+    fn diff_conditional_edge_risks() -> Fallible<()> {
+        let old = generate_graph(true, false);
+        let mut new = old.clone();
+        let (from, to) = (
+            new.find_by_version("1.0.0").expect("missing release"),
+            new.find_by_version("2.0.0").expect("missing release"),
+        );
+        new.add_conditional_edge(
+            &from,
+            &to,
+            &[ConditionalUpdateRisk {
+                name: "OtherRisk".to_string(),
+                ..Default::default()
+            }],
+        )?;
+
+        let delta = old.diff(&new);
+        assert_eq!(
+            delta.changed_conditional_edges,
+            vec![("1.0.0".to_string(), "2.0.0".to_string())]
+        );
+        assert!(delta.added_conditional_edges.is_empty());
+        assert!(!delta.is_empty());
+
+        Ok(())
+    }
+}
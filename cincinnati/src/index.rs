@@ -0,0 +1,138 @@
+//! Lookup indexes for the releases of a `Graph`.
+//!
+//! The version index is always maintained. Metadata indexes are opt-in per key
+//! and only record which releases carry the key; lookups still read the values
+//! from the releases. Releases whose metadata may have been changed through a
+//! mutable reference are tracked as dirty and always considered by lookups.
+
+use crate::{EdgeWeight, Release};
+use daggy::petgraph::visit::IntoNodeReferences;
+use daggy::{Dag, NodeIndex};
+use std::collections::{BTreeSet, HashMap};
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct GraphIndex {
+    /// Node of each version.
+    versions: HashMap<String, NodeIndex>,
+    /// Nodes carrying each indexed metadata key.
+    metadata: HashMap<String, BTreeSet<NodeIndex>>,
+    /// Nodes whose metadata may have changed since they were indexed.
+    dirty: BTreeSet<NodeIndex>,
+}
+
+impl GraphIndex {
+    /// Returns the node of the given version.
+    pub(crate) fn find_version(&self, version: &str) -> Option<NodeIndex> {
+        self.versions.get(version).copied()
+    }
+
+    /// Returns true if the given metadata key is indexed.
+    pub(crate) fn is_metadata_indexed(&self, key: &str) -> bool {
+        self.metadata.contains_key(key)
+    }
+
+    /// Returns the nodes which may carry the given indexed metadata key, in ascending order.
+    ///
+    /// Returns `None` if the key isn't indexed.
+    pub(crate) fn metadata_candidates(&self, key: &str) -> Option<Vec<NodeIndex>> {
+        let indexed = self.metadata.get(key)?;
+        Some(indexed.union(&self.dirty).copied().collect())
+    }
+
+    /// Starts indexing the given metadata key.
+    pub(crate) fn index_metadata_key(&mut self, key: &str, dag: &Dag<Release, EdgeWeight>) {
+        if self.is_metadata_indexed(key) {
+            return;
+        }
+
+        let nodes = dag
+            .node_references()
+            .filter(|(_, release)| has_metadata_key(release, key))
+            .map(|(index, _)| index)
+            .collect();
+        self.metadata.insert(key.to_string(), nodes);
+    }
+
+    /// Records a node which was added to the graph.
+    pub(crate) fn insert(&mut self, index: NodeIndex, release: &Release) {
+        self.versions
+            .entry(release.version().to_string())
+            .or_insert(index);
+        for (key, nodes) in self.metadata.iter_mut() {
+            if has_metadata_key(release, key) {
+                nodes.insert(index);
+            }
+        }
+    }
+
+    /// Records a node whose release was replaced in place.
+    pub(crate) fn replace(&mut self, index: NodeIndex, old: &Release, new: &Release) {
+        self.forget(index, old);
+        self.insert(index, new);
+    }
+
+    /// Marks a node whose metadata may be changed.
+    pub(crate) fn mark_dirty(&mut self, index: NodeIndex) {
+        self.dirty.insert(index);
+    }
+
+    /// Records the removal of the `removed` node, after which the node at
+    /// `moved_from` has been moved to the index of the removed node.
+    pub(crate) fn remove(
+        &mut self,
+        removed: NodeIndex,
+        removed_release: &Release,
+        moved_from: NodeIndex,
+        moved_release: Option<&Release>,
+    ) {
+        self.forget(removed, removed_release);
+
+        let moved_release = match moved_release {
+            Some(release) if moved_from != removed => release,
+            _ => return,
+        };
+        if let Some(node) = self.versions.get_mut(moved_release.version()) {
+            if *node == moved_from {
+                *node = removed;
+            }
+        }
+        for nodes in self.metadata.values_mut().chain(Some(&mut self.dirty)) {
+            if nodes.remove(&moved_from) {
+                nodes.insert(removed);
+            }
+        }
+    }
+
+    /// Rebuilds all indexes from scratch, keeping the set of indexed metadata keys.
+    pub(crate) fn rebuild(&mut self, dag: &Dag<Release, EdgeWeight>) {
+        let keys: Vec<String> = self.metadata.keys().cloned().collect();
+        *self = GraphIndex::default();
+
+        for (index, release) in dag.node_references() {
+            self.versions
+                .entry(release.version().to_string())
+                .or_insert(index);
+        }
+        for key in keys {
+            self.index_metadata_key(&key, dag);
+        }
+    }
+
+    /// Removes all references to the given node.
+    fn forget(&mut self, index: NodeIndex, release: &Release) {
+        if self.versions.get(release.version()) == Some(&index) {
+            self.versions.remove(release.version());
+        }
+        for nodes in self.metadata.values_mut() {
+            nodes.remove(&index);
+        }
+        self.dirty.remove(&index);
+    }
+}
+
+fn has_metadata_key(release: &Release, key: &str) -> bool {
+    match release {
+        Release::Concrete(release) => release.metadata.contains_key(key),
+        Release::Abstract(_) => false,
+    }
+}
@@ -18,19 +18,36 @@ extern crate serde_derive;
 #[macro_use]
 pub mod plugins;
 mod conditional_edges;
-
-use crate::conditional_edges::*;
+pub mod export;
+mod graph_delta;
+mod index;
+pub mod lint;
+mod merge;
+pub mod release_metadata;
+mod upgrade_paths;
+pub mod v2;
+
+use crate::index::GraphIndex;
 use commons::prelude_errors::*;
 use daggy::petgraph::visit::{IntoNodeReferences, NodeRef};
 use daggy::{Dag, EdgeIndex, Walker};
 use serde::de::{self, Deserialize, Deserializer, MapAccess, Visitor};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::convert::TryFrom;
 use std::{collections, fmt};
 
+pub use crate::conditional_edges::*;
+pub use crate::graph_delta::{GraphDelta, MetadataDelta};
+pub use crate::merge::MergeStrategy;
+pub use crate::release_metadata::{MetadataKeys, ReleaseMetadata};
+pub use crate::upgrade_paths::{ConditionalEdgesFilter, UpgradePath};
 pub use daggy::{self, WouldCycle};
 
 pub const CONTENT_TYPE: &str = "application/json";
+/// Content type of graphs encoded as the `Graph` message of the plugin interface.
+pub const PROTOBUF_CONTENT_TYPE: &str = "application/x-protobuf";
 const EXPECT_NODE_WEIGHT: &str = "all exisitng nodes to have a weight (release)";
+const EXPECT_EDGE_WEIGHT: &str = "all existing edges to have a weight";
 
 #[cfg(not(any(test, feature = "test")))]
 pub use std::collections::HashMap as MapImpl;
@@ -44,11 +61,23 @@ pub use std::collections::BTreeMap as MapImpl;
 #[cfg(any(test, feature = "test"))]
 pub use std::collections::BTreeSet as SetImpl;
 
-/// Graph type which stores `Release` as node-weights and `Empty` as edge-weights.
+/// Graph type which stores `Release` as node-weights and `EdgeWeight` as edge-weights.
+///
+/// Conditional edges are stored as edges of the graph, so removing releases
+/// also removes the conditional edges from and to them.
 #[derive(Debug, Clone)]
 pub struct Graph {
-    dag: Dag<Release, Empty>,
-    conditional_edges: Option<Vec<ConditionalEdge>>,
+    dag: Dag<Release, EdgeWeight>,
+    /// Conditional edges declared by version regexes which still have to be
+    /// resolved against the releases in the graph.
+    conditional_edge_declarations: Vec<ConditionalEdge>,
+    /// Conditional edges which were deserialized along with the graph but
+    /// reference releases missing from it. These are not serialized.
+    dangling_conditional_edges: Vec<ConditionalEdge>,
+    /// Whether the conditional edges are part of the serialized graph.
+    serialize_conditional_edges: bool,
+    /// Lookup indexes for the releases in `dag`.
+    index: GraphIndex,
 }
 
 /// Wrapper enum for the concrete and abstract release types.
@@ -68,6 +97,14 @@ impl Release {
         }
     }
 
+    /// Returns a typed view over the release metadata, unless the release is abstract.
+    pub fn release_metadata<'a>(&'a self, keys: &'a MetadataKeys) -> Option<ReleaseMetadata<'a>> {
+        match self {
+            Release::Abstract(_) => None,
+            Release::Concrete(release) => Some(ReleaseMetadata::new(&release.metadata, keys)),
+        }
+    }
+
     /// Get a mutable borrow of the release metadata if any
     pub fn get_metadata_mut(&mut self) -> Option<&mut MapImpl<String, String>> {
         match self {
@@ -129,23 +166,25 @@ pub struct ReleaseId(daggy::NodeIndex);
 ///
 /// See the `next_releases` method for more information.
 pub struct NextReleases<'a> {
-    children: daggy::Children<Release, Empty, daggy::petgraph::graph::DefaultIx>,
-    dag: &'a Dag<Release, Empty>,
+    children: daggy::Children<Release, EdgeWeight, daggy::petgraph::graph::DefaultIx>,
+    dag: &'a Dag<Release, EdgeWeight>,
 }
 
 impl<'a> Iterator for NextReleases<'a> {
     type Item = (daggy::EdgeIndex, daggy::NodeIndex, &'a Release);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.children
-            .walk_next(self.dag)
-            .map(|(edge_index, node_index)| {
-                (
+        while let Some((edge_index, node_index)) = self.children.walk_next(self.dag) {
+            // Conditional edges are not followed unless they are unconditional as well.
+            if self.dag[edge_index].unconditional {
+                return Some((
                     edge_index,
                     node_index,
                     self.dag.node_weight(node_index).expect(EXPECT_NODE_WEIGHT),
-                )
-            })
+                ));
+            }
+        }
+        None
     }
 }
 
@@ -153,29 +192,66 @@ impl<'a> Iterator for NextReleases<'a> {
 ///
 /// See the `previous_releases` method for more information.
 pub struct PreviousReleases<'a> {
-    parents: daggy::Parents<Release, Empty, daggy::petgraph::graph::DefaultIx>,
-    dag: &'a Dag<Release, Empty>,
+    parents: daggy::Parents<Release, EdgeWeight, daggy::petgraph::graph::DefaultIx>,
+    dag: &'a Dag<Release, EdgeWeight>,
 }
 
 impl<'a> Iterator for PreviousReleases<'a> {
     type Item = (daggy::EdgeIndex, daggy::NodeIndex, &'a Release);
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.parents
-            .walk_next(self.dag)
-            .map(|(edge_index, node_index)| {
-                (
+        while let Some((edge_index, node_index)) = self.parents.walk_next(self.dag) {
+            // Conditional edges are not followed unless they are unconditional as well.
+            if self.dag[edge_index].unconditional {
+                return Some((
                     edge_index,
                     node_index,
                     self.dag.node_weight(node_index).expect(EXPECT_NODE_WEIGHT),
-                )
-            })
+                ));
+            }
+        }
+        None
     }
 }
 
-/// Dummy type used as edge-weights inside `Graph`.
-#[derive(Debug, Clone)]
-pub struct Empty;
+/// Attributes of an edge inside `Graph`.
+///
+/// An edge is unconditional, conditional or both. A conditional edge carries
+/// the risks which clients need to evaluate before following it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EdgeWeight {
+    /// Whether clients may follow this edge without evaluating any risks.
+    pub unconditional: bool,
+    /// Risks attached to this edge; non-empty for conditional edges.
+    pub risks: Vec<ConditionalUpdateRisk>,
+    /// Free-form annotations. These are not serialized.
+    pub annotations: MapImpl<String, String>,
+}
+
+impl EdgeWeight {
+    /// Creates the weight of an unconditional edge.
+    pub fn unconditional() -> Self {
+        EdgeWeight {
+            unconditional: true,
+            risks: vec![],
+            annotations: MapImpl::new(),
+        }
+    }
+
+    /// Creates the weight of a conditional edge with the given risks.
+    pub fn conditional(risks: Vec<ConditionalUpdateRisk>) -> Self {
+        EdgeWeight {
+            unconditional: false,
+            risks,
+            annotations: MapImpl::new(),
+        }
+    }
+
+    /// Returns true if risks are attached to this edge.
+    pub fn is_conditional(&self) -> bool {
+        !self.risks.is_empty()
+    }
+}
 
 /// Errors that can be returned by the methods in this library
 pub mod errors {
@@ -201,13 +277,26 @@ pub mod errors {
     #[derive(Debug, Fail, Eq, PartialEq)]
     #[error("NodeWeight with index {} is missing", 0)]
     pub struct NodeWeightMissing(pub(crate) usize);
+
+    /// Release doesn't exist
+    #[derive(Debug, Fail, Eq, PartialEq)]
+    #[error("release with version {0:?} doesn't exist")]
+    pub struct ReleaseDoesntExist(pub(crate) String);
+
+    /// Release exists with different content in both merged graphs
+    #[derive(Debug, Fail, Eq, PartialEq)]
+    #[error("release with version {0:?} exists with different content in both graphs")]
+    pub struct ReleaseConflict(pub(crate) String);
 }
 
 impl Default for Graph {
     fn default() -> Self {
         Graph {
             dag: Default::default(),
-            conditional_edges: Some(vec![]),
+            conditional_edge_declarations: vec![],
+            dangling_conditional_edges: vec![],
+            serialize_conditional_edges: true,
+            index: Default::default(),
         }
     }
 }
@@ -244,31 +333,223 @@ impl Graph {
                         }
                     }
                 }
-                *node = release;
+                self.replace_node(id.0, release);
                 Ok(id)
             }
-            None => Ok(ReleaseId(self.dag.add_node(release))),
+            None => Ok(ReleaseId(self.add_node(release))),
         }
     }
 
+    /// Add a node to the graph and index it.
+    fn add_node(&mut self, release: Release) -> daggy::NodeIndex {
+        let index = self.dag.add_node(release);
+        self.index.insert(
+            index,
+            self.dag.node_weight(index).expect(EXPECT_NODE_WEIGHT),
+        );
+        index
+    }
+
+    /// Replace the release of a node and update the indexes.
+    ///
+    /// Returns the previous release.
+    fn replace_node(&mut self, index: daggy::NodeIndex, release: Release) -> Release {
+        let node = self.dag.node_weight_mut(index).expect(EXPECT_NODE_WEIGHT);
+        let old = std::mem::replace(node, release);
+        self.index.replace(
+            index,
+            &old,
+            self.dag.node_weight(index).expect(EXPECT_NODE_WEIGHT),
+        );
+        old
+    }
+
+    /// Remove a node from the graph and update the indexes.
+    ///
+    /// The last node takes the index of the removed one.
+    fn remove_node(&mut self, index: daggy::NodeIndex) -> Option<Release> {
+        let release = self.dag.remove_node(index)?;
+        let moved_from = daggy::NodeIndex::new(self.dag.node_count());
+        self.index
+            .remove(index, &release, moved_from, self.dag.node_weight(index));
+        Some(release)
+    }
+
+    /// Index the releases by the given metadata key.
+    ///
+    /// This speeds up `find_by_metadata_key` and `find_by_metadata_pair` for this key.
+    pub fn index_metadata_key(&mut self, key: &str) {
+        self.index.index_metadata_key(key, &self.dag);
+    }
+
     /// Add a transition (edge) from `source` to `target`.
     ///
+    /// If a conditional edge already connects both releases, it is made unconditional as well.
     /// Fails with the `WouldCycle` error if the new edge would lead to a cycle.
     pub fn add_edge(&mut self, from: &ReleaseId, to: &ReleaseId) -> Result<EdgeIndex, Error> {
         let from_release: String = self.find_by_releaseid(from)?.version().to_string();
         let to_release: String = self.find_by_releaseid(to)?.version().to_string();
-        if self.dag.find_edge(from.0, to.0).is_some() {
-            return Err(Error::from(errors::EdgeAlreadyExists {
-                from: from_release,
-                to: to_release,
-            }));
+        if let Some(edge) = self.dag.find_edge(from.0, to.0) {
+            let weight = self.dag.edge_weight_mut(edge).expect(EXPECT_EDGE_WEIGHT);
+            if weight.unconditional {
+                return Err(Error::from(errors::EdgeAlreadyExists {
+                    from: from_release,
+                    to: to_release,
+                }));
+            }
+            weight.unconditional = true;
+            return Ok(edge);
         }
 
         self.dag
-            .add_edge(from.0, to.0, Empty {})
+            .add_edge(from.0, to.0, EdgeWeight::unconditional())
             .map_err(Into::into)
     }
 
+    /// Add a conditional edge from `source` to `target` with the given risks.
+    ///
+    /// If an edge already connects both releases, the risks are attached to it.
+    /// Fails with the `WouldCycle` error if the new edge would lead to a cycle.
+    pub fn add_conditional_edge(
+        &mut self,
+        from: &ReleaseId,
+        to: &ReleaseId,
+        risks: &[ConditionalUpdateRisk],
+    ) -> Result<EdgeIndex, Error> {
+        ensure!(
+            !risks.is_empty(),
+            "conditional edges require at least one risk"
+        );
+        self.find_by_releaseid(from)?;
+        self.find_by_releaseid(to)?;
+
+        self.attach_risks(from.0, to.0, risks).map_err(Into::into)
+    }
+
+    /// Attach risks to the edge between the given nodes, adding the edge if needed.
+    fn attach_risks(
+        &mut self,
+        from: daggy::NodeIndex,
+        to: daggy::NodeIndex,
+        risks: &[ConditionalUpdateRisk],
+    ) -> Result<EdgeIndex, WouldCycle<EdgeWeight>> {
+        let edge = match self.dag.find_edge(from, to) {
+            Some(edge) => edge,
+            None => self
+                .dag
+                .add_edge(from, to, EdgeWeight::conditional(vec![]))?,
+        };
+
+        let weight = self.dag.edge_weight_mut(edge).expect(EXPECT_EDGE_WEIGHT);
+        for risk in risks {
+            if !weight.risks.contains(risk) {
+                weight.risks.push(risk.clone());
+            }
+        }
+
+        Ok(edge)
+    }
+
+    /// Removes the conditional edge between the given releases.
+    ///
+    /// An unconditional edge between the releases is kept, without any risks attached.
+    pub fn remove_conditional_edge(
+        &mut self,
+        from: &ReleaseId,
+        to: &ReleaseId,
+    ) -> Result<(), Error> {
+        match self.dag.find_edge(from.0, to.0) {
+            Some(edge) if self.dag[edge].is_conditional() => {
+                if self.dag[edge].unconditional {
+                    self.dag[edge].risks.clear();
+                } else {
+                    self.dag.remove_edge(edge);
+                }
+                Ok(())
+            }
+            _ => Err(Error::from(errors::EdgeDoesntExist {
+                from: self.find_by_releaseid(from)?.version().to_string(),
+                to: self.find_by_releaseid(to)?.version().to_string(),
+            })),
+        }
+    }
+
+    /// Returns the weight of the edge between the given releases, if any.
+    pub fn edge_weight(&self, from: &ReleaseId, to: &ReleaseId) -> Option<&EdgeWeight> {
+        self.dag
+            .find_edge(from.0, to.0)
+            .and_then(|edge| self.dag.edge_weight(edge))
+    }
+
+    /// Returns a mutable reference to the weight of the edge between the given releases, if any.
+    pub fn edge_weight_mut(&mut self, from: &ReleaseId, to: &ReleaseId) -> Option<&mut EdgeWeight> {
+        let edge = self.dag.find_edge(from.0, to.0)?;
+        self.dag.edge_weight_mut(edge)
+    }
+
+    /// Iterates over all releases.
+    pub fn releases(&self) -> impl Iterator<Item = &Release> {
+        self.dag.raw_nodes().iter().map(|node| &node.weight)
+    }
+
+    /// Iterates over all edges, conditional and unconditional, together with the releases they connect.
+    pub fn edges(&self) -> impl Iterator<Item = (&Release, &Release, &EdgeWeight)> {
+        self.dag.raw_edges().iter().map(move |edge| {
+            (
+                self.dag
+                    .node_weight(edge.source())
+                    .expect(EXPECT_NODE_WEIGHT),
+                self.dag
+                    .node_weight(edge.target())
+                    .expect(EXPECT_NODE_WEIGHT),
+                &edge.weight,
+            )
+        })
+    }
+
+    /// Returns the conditional edges, grouped by their risks.
+    pub fn conditional_edges(&self) -> Vec<ConditionalEdge> {
+        let mut conditional_edges: Vec<ConditionalEdge> = vec![];
+
+        for (from, to, weight) in self
+            .edges()
+            .filter(|(_, _, weight)| weight.is_conditional())
+        {
+            let edge = ConditionalUpdateEdge {
+                from: from.version().to_string(),
+                to: to.version().to_string(),
+            };
+            match conditional_edges
+                .iter_mut()
+                .find(|ce| ce.risks == weight.risks)
+            {
+                Some(ce) => ce.edges.push(edge),
+                None => conditional_edges.push(ConditionalEdge {
+                    edge_regex: Default::default(),
+                    edges: vec![edge],
+                    risks: weight.risks.clone(),
+                }),
+            }
+        }
+
+        conditional_edges
+    }
+
+    /// Queues a conditional edge declared by version regexes, to be resolved later on.
+    pub fn add_conditional_edge_declaration(&mut self, declaration: ConditionalEdge) {
+        self.conditional_edge_declarations.push(declaration);
+    }
+
+    /// Removes and returns the conditional edge declarations which haven't been resolved yet.
+    pub fn take_conditional_edge_declarations(&mut self) -> Vec<ConditionalEdge> {
+        std::mem::take(&mut self.conditional_edge_declarations)
+    }
+
+    /// Returns the deserialized conditional edges which reference releases missing from the graph.
+    pub fn dangling_conditional_edges(&self) -> &[ConditionalEdge] {
+        &self.dangling_conditional_edges
+    }
+
     /// Add edges for all given key/value pairs of releases.
     pub fn add_edges(&mut self, indices: MapImpl<ReleaseId, ReleaseId>) -> Result<(), Error> {
         indices
@@ -278,10 +559,7 @@ impl Graph {
 
     /// Returns a Some(ReleaseId) if the version exists in the graph, None otherwise.
     pub fn find_by_version(&self, version: &str) -> Option<ReleaseId> {
-        self.dag
-            .node_references()
-            .find(|nr| nr.weight().version() == version)
-            .map(|nr| ReleaseId(nr.id()))
+        self.index.find_version(version).map(ReleaseId)
     }
 
     /// Returns tuples of ReleaseId and its version String for releases for which
@@ -310,17 +588,15 @@ impl Graph {
     }
 
     /// Removes the directed edge between the given releases.
+    ///
+    /// A conditional edge between the releases is kept.
     pub fn remove_edge(&mut self, from: &ReleaseId, to: &ReleaseId) -> Result<(), Error> {
-        if let Some(edge) = self.dag.find_edge(from.0, to.0) {
-            self.dag
-                .remove_edge(edge)
-                .map(|_| ())
-                .ok_or_else(|| format_err!("could not remove edge '{:?}'", edge))
-        } else {
-            Err(Error::from(errors::EdgeDoesntExist {
+        match self.dag.find_edge(from.0, to.0) {
+            Some(edge) if self.dag[edge].unconditional => self.remove_edge_by_index(edge),
+            _ => Err(Error::from(errors::EdgeDoesntExist {
                 from: self.find_by_releaseid(from)?.version().to_string(),
                 to: self.find_by_releaseid(to)?.version().to_string(),
-            }))
+            })),
         }
     }
 
@@ -333,11 +609,19 @@ impl Graph {
 
     /// Remove the edge with the given index.
     ///
+    /// If the edge is conditional as well, only its unconditional part is removed.
     /// Fails if the edge wasn't found and thus couldn't be removed.
     pub fn remove_edge_by_index(&mut self, index: daggy::EdgeIndex) -> Result<(), Error> {
-        match self.dag.remove_edge(index) {
-            Some(_) => Ok(()),
-            None => bail!("could not remove edge with index {:?}", index),
+        match self.dag.edge_weight_mut(index) {
+            Some(weight) if weight.unconditional && weight.is_conditional() => {
+                weight.unconditional = false;
+                Ok(())
+            }
+            Some(weight) if weight.unconditional => {
+                self.dag.remove_edge(index);
+                Ok(())
+            }
+            _ => bail!("could not remove edge with index {:?}", index),
         }
     }
 
@@ -355,15 +639,30 @@ impl Graph {
             .try_for_each(|ei| self.remove_edge_by_index(*ei))
     }
 
+    /// Returns tuples of ReleaseId and its version String for releases for which
+    /// filter_fn returns true.
+    pub fn find_by_fn<F>(&self, mut filter_fn: F) -> Vec<(ReleaseId, String)>
+    where
+        F: FnMut(&Release) -> bool,
+    {
+        self.dag
+            .node_references()
+            .filter(|(_, release)| filter_fn(release))
+            .map(|(index, release)| (ReleaseId(index), release.version().to_string()))
+            .collect()
+    }
+
     /// Returns tuples of ReleaseId and its version String for releases for which
     /// filter_fn returns true.
     ///
     /// filter_fn is able to mutate the release as it receives a mutable borrow.
+    /// Prefer `find_by_fn` if no mutation is needed, as the indexes are rebuilt afterwards.
     pub fn find_by_fn_mut<F>(&mut self, mut filter_fn: F) -> Vec<(ReleaseId, String)>
     where
         F: FnMut(&mut Release) -> bool,
     {
-        self.dag
+        let found = self
+            .dag
             .node_weights_mut()
             .enumerate()
             .filter_map(|(i, nw)| {
@@ -376,44 +675,47 @@ impl Graph {
                     None
                 }
             })
-            .collect()
+            .collect();
+
+        // The releases may have been changed arbitrarily.
+        self.index.rebuild(&self.dag);
+
+        found
     }
 
     /// Returns tuples of ReleaseId and its version String for releases which
     /// match the given metadata key/value pair.
     pub fn find_by_metadata_pair(&self, key: &str, value: &str) -> Vec<(ReleaseId, String)> {
-        self.dag
-            .node_references()
-            .filter(|nr| {
-                if let Release::Concrete(release) = nr.weight() {
-                    if let Some(found_value) = release.metadata.get(key) {
-                        return found_value == value;
-                    }
-                }
-                false
-            })
-            .map(|nr| (ReleaseId(nr.id()), nr.1.version().to_owned()))
+        self.find_by_metadata_key(key)
+            .into_iter()
+            .filter(|(_, _, found_value)| found_value == value)
+            .map(|(id, version, _)| (id, version))
             .collect()
     }
 
     /// Returns tuples of ReleaseId, its version String, and the value for the given key for releases
     /// which match the given metadata key.
     pub fn find_by_metadata_key(&self, key: &str) -> Vec<(ReleaseId, String, String)> {
-        self.dag
-            .node_references()
-            .filter_map(|nr| {
-                if let Release::Concrete(release) = nr.weight() {
-                    if let Some(value) = release.metadata.get(key) {
-                        return Some((
-                            ReleaseId(nr.id()),
-                            release.version.to_owned(),
-                            value.to_owned(),
-                        ));
-                    }
+        let lookup = |(index, release): (daggy::NodeIndex, &Release)| {
+            if let Release::Concrete(release) = release {
+                if let Some(value) = release.metadata.get(key) {
+                    return Some((
+                        ReleaseId(index),
+                        release.version.to_owned(),
+                        value.to_owned(),
+                    ));
                 }
-                None
-            })
-            .collect()
+            }
+            None
+        };
+
+        match self.index.metadata_candidates(key) {
+            Some(candidates) => candidates
+                .into_iter()
+                .filter_map(|index| lookup((index, self.dag.node_weight(index)?)))
+                .collect(),
+            None => self.dag.node_references().filter_map(lookup).collect(),
+        }
     }
 
     /// Returns a mutable reference to the metadata for the given release.
@@ -421,6 +723,7 @@ impl Graph {
         &mut self,
         release_id: &ReleaseId,
     ) -> Result<&mut MapImpl<String, String>, Error> {
+        self.index.mark_dirty(release_id.0);
         match self.dag.node_weight_mut(release_id.0) {
             Some(Release::Concrete(release)) => Ok(&mut release.metadata),
             _ => bail!("could not get metadata reference"),
@@ -452,6 +755,11 @@ impl Graph {
         self.dag.node_count() as u64
     }
 
+    /// Returns the number of edges, including conditional ones.
+    pub fn edges_count(&self) -> u64 {
+        self.dag.edge_count() as u64
+    }
+
     /// Removes the nodes with the given ReleaseIds and returns the number of
     /// removed releases.
     ///
@@ -470,7 +778,7 @@ impl Graph {
         to_remove
             .into_iter()
             .rev()
-            .filter(|ni| self.dag.remove_node(*ni).is_some())
+            .filter(|ni| self.remove_node(*ni).is_some())
             .count()
     }
 
@@ -490,10 +798,7 @@ impl Graph {
             })
             .collect();
 
-        to_remove
-            .iter()
-            .filter(|ni| self.dag.remove_node(**ni).is_some())
-            .count()
+        self.remove_nodes(to_remove)
     }
 
     /// Iterates over all releases mutably
@@ -503,7 +808,12 @@ impl Graph {
     where
         F: FnMut(&mut Release) -> Result<(), Error>,
     {
-        self.dag.node_weights_mut().try_for_each(f)
+        let result = self.dag.node_weights_mut().try_for_each(f);
+
+        // The releases may have been changed arbitrarily.
+        self.index.rebuild(&self.dag);
+
+        result
     }
 
     /// Get the edges expressed as version -> versions; optionally include edges from/to `Release::Abstract`.
@@ -517,6 +827,7 @@ impl Graph {
         self.dag
             .raw_edges()
             .iter()
+            .filter(|edge| edge.weight.unconditional)
             .try_for_each(|edge| -> Result<(), Error> {
                 match (
                     self.dag.node_weight(edge.source()),
@@ -609,9 +920,9 @@ impl<'a> Deserialize<'a> for Graph {
                 let conditional_edges: Vec<ConditionalEdge> = conditional_edges.unwrap_or_default();
                 let mut graph = Graph {
                     dag: Dag::with_capacity(nodes.len(), edges.len()),
-                    conditional_edges: Some(Vec::with_capacity(conditional_edges.len())),
+                    ..Default::default()
                 };
-                let mut versions = collections::HashSet::with_capacity(nodes.len());
+                let mut versions = collections::HashMap::with_capacity(nodes.len());
                 for node in nodes {
                     // Validate version string is non-empty.
                     if node.version().is_empty() {
@@ -621,26 +932,64 @@ impl<'a> Deserialize<'a> for Graph {
                         ));
                     }
                     // Validate version string is unique in "nodes" set.
-                    if !versions.insert(node.version().to_string()) {
+                    if versions.contains_key(node.version()) {
                         return Err(de::Error::invalid_value(
                             de::Unexpected::Str(node.version()),
                             &"a unique string version",
                         ));
                     }
-                    graph.dag.add_node(node);
+                    let version = node.version().to_string();
+                    versions.insert(version, graph.add_node(node));
                 }
                 graph
                     .dag
-                    .add_edges(edges.into_iter().map(|(s, t)| (s, t, Empty {})))
+                    .add_edges(
+                        edges
+                            .into_iter()
+                            .map(|(s, t)| (s, t, EdgeWeight::unconditional())),
+                    )
                     .map_err(|_| {
                         de::Error::invalid_value(serde::de::Unexpected::StructVariant, &self)
                     })?;
 
-                graph
-                    .conditional_edges
-                    .as_mut()
-                    .unwrap()
-                    .extend(conditional_edges);
+                // Conditional edges referencing unknown releases are kept aside,
+                // so that they can be reported.
+                for ce in conditional_edges {
+                    let mut dangling = vec![];
+                    for edge in ce.edges {
+                        match (versions.get(&edge.from), versions.get(&edge.to)) {
+                            (Some(from), Some(to)) => {
+                                graph.attach_risks(*from, *to, &ce.risks).map_err(|_| {
+                                    de::Error::custom(format!(
+                                        "conditional edge from {} to {} would cause a cycle",
+                                        edge.from, edge.to
+                                    ))
+                                })?;
+                            }
+                            _ => dangling.push(edge),
+                        }
+                    }
+                    if !dangling.is_empty() {
+                        graph.dangling_conditional_edges.push(ConditionalEdge {
+                            edge_regex: ce.edge_regex,
+                            edges: dangling,
+                            risks: ce.risks,
+                        });
+                    }
+                }
+
+                let dangling_count: usize = graph
+                    .dangling_conditional_edges
+                    .iter()
+                    .map(|ce| ce.edges.len())
+                    .sum();
+                if dangling_count > 0 {
+                    log::warn!(
+                        "{} conditional edges reference releases missing from the graph",
+                        dangling_count
+                    );
+                }
+
                 Ok(graph)
             }
         }
@@ -658,7 +1007,7 @@ impl Serialize for Graph {
     where
         S: Serializer,
     {
-        struct Edges<'a>(&'a [daggy::petgraph::graph::Edge<Empty>]);
+        struct Edges<'a>(&'a [daggy::petgraph::graph::Edge<EdgeWeight>]);
         struct Nodes<'a>(&'a [daggy::petgraph::graph::Node<Release>]);
 
         impl<'a> Serialize for Edges<'a> {
@@ -666,7 +1015,12 @@ impl Serialize for Graph {
             where
                 S: Serializer,
             {
-                serializer.collect_seq(self.0.iter().map(|edge| (edge.source(), edge.target())))
+                serializer.collect_seq(
+                    self.0
+                        .iter()
+                        .filter(|edge| edge.weight.unconditional)
+                        .map(|edge| (edge.source(), edge.target())),
+                )
             }
         }
 
@@ -682,8 +1036,8 @@ impl Serialize for Graph {
         let mut state = serializer.serialize_struct("Graph", 2)?;
         state.serialize_field("nodes", &Nodes(self.dag.raw_nodes()))?;
         state.serialize_field("edges", &Edges(self.dag.raw_edges()))?;
-        if self.conditional_edges.is_some() {
-            state.serialize_field("conditionalEdges", &self.conditional_edges)?;
+        if self.serialize_conditional_edges {
+            state.serialize_field("conditionalEdges", &self.conditional_edges())?;
         }
         state.end()
     }
@@ -733,47 +1087,92 @@ impl PartialEq for Graph {
 #[cfg(any(test, feature = "test"))]
 impl Eq for Graph {}
 
-impl From<plugins::interface::Graph> for Graph {
-    fn from(mut graph: plugins::interface::Graph) -> Self {
+/// Converts a graph received through the plugin interface.
+///
+/// This can fail because the edges reference nodes by index, which may be out
+/// of range or cause a cycle.
+impl TryFrom<plugins::interface::Graph> for Graph {
+    type Error = Error;
+
+    fn try_from(mut graph: plugins::interface::Graph) -> Fallible<Self> {
         let mut graph_converted = Graph::default();
 
         // Convert nodes
         for node in graph.take_nodes().into_iter() {
-            graph_converted
-                .dag
-                .add_node(Release::Concrete(ConcreteRelease {
-                    version: node.version,
-                    payload: node.payload,
-                    metadata: node.metadata.into_iter().collect(),
-                }));
+            graph_converted.add_node(Release::Concrete(ConcreteRelease {
+                version: node.version,
+                payload: node.payload,
+                metadata: node.metadata.into_iter().collect(),
+            }));
         }
 
+        let node_count = graph_converted.dag.node_count();
+        let node_index = |index: u64| -> Fallible<daggy::NodeIndex> {
+            ensure!(
+                index < node_count as u64,
+                "edge references node {} of a graph with {} nodes",
+                index,
+                node_count
+            );
+            Ok(daggy::NodeIndex::new(index as usize))
+        };
+
         // Convert edges
         for edge in graph.take_edges().into_iter() {
             graph_converted
                 .dag
                 .add_edge(
-                    daggy::NodeIndex::from(edge.from as u32),
-                    daggy::NodeIndex::from(edge.to as u32),
-                    Empty {},
+                    node_index(edge.from)?,
+                    node_index(edge.to)?,
+                    EdgeWeight::unconditional(),
                 )
-                .expect("add_edge");
+                .map_err(|_| {
+                    format_err!("edge from {} to {} would cause a cycle", edge.from, edge.to)
+                })?;
         }
 
-        graph_converted
+        // Convert conditional edges
+        for conditional_edge in graph.take_conditional_edges().into_iter() {
+            let risks: Vec<ConditionalUpdateRisk> = conditional_edge
+                .risks
+                .into_iter()
+                .map(ConditionalUpdateRisk::from)
+                .collect();
+            for edge in conditional_edge.edges.iter() {
+                graph_converted
+                    .attach_risks(node_index(edge.from)?, node_index(edge.to)?, &risks)
+                    .map_err(|_| {
+                        format_err!(
+                            "conditional edge from {} to {} would cause a cycle",
+                            edge.from,
+                            edge.to
+                        )
+                    })?;
+            }
+        }
+
+        Ok(graph_converted)
     }
 }
 
 impl From<Graph> for plugins::interface::Graph {
     fn from(graph: Graph) -> Self {
+        Self::from(&graph)
+    }
+}
+
+impl From<&Graph> for plugins::interface::Graph {
+    fn from(graph: &Graph) -> Self {
         use crate::Release::{Abstract, Concrete};
-        use daggy::petgraph::visit::IntoNeighborsDirected;
+        use daggy::petgraph::visit::EdgeRef;
         use daggy::petgraph::Direction;
 
         let mut nodes_converted: Vec<plugins::interface::Graph_Node> =
             std::vec::Vec::with_capacity(graph.dag.node_count());
         let mut edges_converted: Vec<plugins::interface::Graph_Edge> =
             std::vec::Vec::with_capacity(graph.dag.edge_count());
+        let mut conditional_edges_converted: Vec<plugins::interface::Graph_ConditionalEdge> =
+            vec![];
 
         for node_reference in graph.dag.node_references() {
             let node_index = node_reference.0;
@@ -793,10 +1192,13 @@ impl From<Graph> for plugins::interface::Graph {
             }
             nodes_converted.push(node_converted);
 
-            // find neighbors and push edges
+            // find unconditional neighbors and push edges
             for neighbor in graph
                 .dag
-                .neighbors_directed(node_index, Direction::Outgoing)
+                .graph()
+                .edges_directed(node_index, Direction::Outgoing)
+                .filter(|edge| edge.weight().unconditional)
+                .map(|edge| edge.target())
             {
                 let mut edge_converted = plugins::interface::Graph_Edge::new();
                 edge_converted.set_from(node_index.index() as u64);
@@ -805,33 +1207,142 @@ impl From<Graph> for plugins::interface::Graph {
             }
         }
 
+        // group conditional edges by their risks
+        let mut risks_converted: Vec<&[ConditionalUpdateRisk]> = vec![];
+        for edge in graph
+            .dag
+            .graph()
+            .edge_references()
+            .filter(|edge| edge.weight().is_conditional())
+        {
+            let risks = edge.weight().risks.as_slice();
+            let position = match risks_converted.iter().position(|r| *r == risks) {
+                Some(position) => position,
+                None => {
+                    let mut conditional_edge_converted =
+                        plugins::interface::Graph_ConditionalEdge::new();
+                    conditional_edge_converted.set_risks(
+                        risks
+                            .iter()
+                            .cloned()
+                            .map(plugins::interface::Graph_Risk::from)
+                            .collect(),
+                    );
+                    conditional_edges_converted.push(conditional_edge_converted);
+                    risks_converted.push(risks);
+                    risks_converted.len() - 1
+                }
+            };
+
+            let mut edge_converted = plugins::interface::Graph_Edge::new();
+            edge_converted.set_from(edge.source().index() as u64);
+            edge_converted.set_to(edge.target().index() as u64);
+            conditional_edges_converted[position]
+                .mut_edges()
+                .push(edge_converted);
+        }
+
         let mut graph_converted = plugins::interface::Graph::new();
         graph_converted.set_nodes(nodes_converted.into());
         graph_converted.set_edges(edges_converted.into());
+        graph_converted.set_conditional_edges(conditional_edges_converted.into());
 
         graph_converted
     }
 }
 
+impl From<plugins::interface::Graph_Risk> for ConditionalUpdateRisk {
+    fn from(risk: plugins::interface::Graph_Risk) -> Self {
+        ConditionalUpdateRisk {
+            url: risk.url,
+            name: risk.name,
+            message: risk.message,
+            matching_rules: risk
+                .matching_rules
+                .into_iter()
+                .map(|rule| ClusterCondition {
+                    condition_type: rule.field_type,
+                    promql: PromQLClusterCondition {
+                        promql: rule.promql,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<ConditionalUpdateRisk> for plugins::interface::Graph_Risk {
+    fn from(risk: ConditionalUpdateRisk) -> Self {
+        let mut risk_converted = plugins::interface::Graph_Risk::new();
+        risk_converted.set_url(risk.url);
+        risk_converted.set_name(risk.name);
+        risk_converted.set_message(risk.message);
+        risk_converted.set_matching_rules(
+            risk.matching_rules
+                .into_iter()
+                .map(|rule| {
+                    let mut rule_converted = plugins::interface::Graph_ClusterCondition::new();
+                    rule_converted.set_field_type(rule.condition_type);
+                    rule_converted.set_promql(rule.promql.promql);
+                    rule_converted
+                })
+                .collect(),
+        );
+        risk_converted
+    }
+}
+
 #[cfg(any(test, feature = "test"))]
 pub mod testing {
     use super::*;
     use crate::plugins::internal::versioned_graph::VersionedGraph;
 
+    pub mod golden;
+
     pub fn generate_graph(include_conditional_edge: bool, include_always_condition: bool) -> Graph {
         let mut graph = Graph::default();
 
+        let v1 = graph.add_node(Release::Concrete(ConcreteRelease {
+            version: String::from("1.0.0"),
+            payload: String::from("image/1.0.0"),
+            metadata: MapImpl::new(),
+        }));
+        let v2 = graph.add_node(Release::Concrete(ConcreteRelease {
+            version: String::from("2.0.0"),
+            payload: String::from("image/2.0.0"),
+            metadata: MapImpl::new(),
+        }));
+        let v3 = graph.add_node(Release::Concrete(ConcreteRelease {
+            version: String::from("3.0.0"),
+            payload: String::from("image/3.0.0"),
+            metadata: MapImpl::new(),
+        }));
+        graph
+            .dag
+            .add_edge(v1, v2, EdgeWeight::unconditional())
+            .unwrap();
+        graph
+            .dag
+            .add_edge(v2, v3, EdgeWeight::unconditional())
+            .unwrap();
+        graph
+            .dag
+            .add_edge(v1, v3, EdgeWeight::unconditional())
+            .unwrap();
+
         if include_conditional_edge {
-            let mut ce = ConditionalEdge {
-                edge_regex: ConditionalUpdateEdge {
-                    from: "1[.]0[.].*".to_string(),
-                    to: "2.0.0".to_string(),
-                },
-                edges: vec![ConditionalUpdateEdge {
-                    from: "1.0.0".to_string(),
-                    to: "2.0.0".to_string(),
-                }],
-                risks: vec![ConditionalUpdateRisk {
+            let risk = if include_always_condition {
+                ConditionalUpdateRisk {
+                    url: "https://bug.example.com/show_bug.cgi?id=example".to_string(),
+                    name: "AllBrokenUpdates".to_string(),
+                    message: "All Updates are broken".to_string(),
+                    matching_rules: vec![ClusterCondition {
+                        condition_type: "Always".to_string(),
+                        promql: Default::default(),
+                    }],
+                }
+            } else {
+                ConditionalUpdateRisk {
                     url: "https://bug.example.com/show_bug.cgi?id=example".to_string(),
                     name: "BrokenUpdates".to_string(),
                     message: "Updates are broken for this provider".to_string(),
@@ -842,44 +1353,15 @@ pub mod testing {
                                 .to_string(),
                         },
                     }],
-                }],
+                }
             };
-            if include_always_condition {
-                ce.risks = vec![ConditionalUpdateRisk {
-                    url: "https://bug.example.com/show_bug.cgi?id=example".to_string(),
-                    name: "AllBrokenUpdates".to_string(),
-                    message: "All Updates are broken".to_string(),
-                    matching_rules: vec![ClusterCondition {
-                        condition_type: "Always".to_string(),
-                        promql: Default::default(),
-                    }],
-                }]
-            }
-            graph.conditional_edges = Some(vec![ce]);
-        }
-        if !include_conditional_edge {
-            graph.conditional_edges = None;
+            graph
+                .add_conditional_edge(&ReleaseId(v1), &ReleaseId(v2), &[risk])
+                .unwrap();
+        } else {
+            graph.serialize_conditional_edges = false;
         }
 
-        let v1 = graph.dag.add_node(Release::Concrete(ConcreteRelease {
-            version: String::from("1.0.0"),
-            payload: String::from("image/1.0.0"),
-            metadata: MapImpl::new(),
-        }));
-        let v2 = graph.dag.add_node(Release::Concrete(ConcreteRelease {
-            version: String::from("2.0.0"),
-            payload: String::from("image/2.0.0"),
-            metadata: MapImpl::new(),
-        }));
-        let v3 = graph.dag.add_node(Release::Concrete(ConcreteRelease {
-            version: String::from("3.0.0"),
-            payload: String::from("image/3.0.0"),
-            metadata: MapImpl::new(),
-        }));
-        graph.dag.add_edge(v1, v2, Empty {}).unwrap();
-        graph.dag.add_edge(v2, v3, Empty {}).unwrap();
-        graph.dag.add_edge(v1, v3, Empty {}).unwrap();
-
         graph
     }
 
@@ -964,7 +1446,7 @@ pub mod testing {
                         payload,
                         metadata,
                     });
-                    graph.dag.add_node(release)
+                    graph.add_node(release)
                 })
                 .collect();
 
@@ -974,14 +1456,20 @@ pub mod testing {
                 for (key, value) in &edges {
                     let one = nodes[*key];
                     let two = nodes[*value];
-                    graph.dag.add_edge(one, two, Empty {}).unwrap();
+                    graph
+                        .dag
+                        .add_edge(one, two, EdgeWeight::unconditional())
+                        .unwrap();
                 }
                 assert_eq!(edges.len(), graph.dag.edge_count());
             } else {
                 for i in 0..(nodes.len() - 1) {
                     let one = nodes[i];
                     let two = nodes[i + 1];
-                    graph.dag.add_edge(one, two, Empty {}).unwrap();
+                    graph
+                        .dag
+                        .add_edge(one, two, EdgeWeight::unconditional())
+                        .unwrap();
                 }
             };
 
@@ -1047,7 +1535,11 @@ pub mod testing {
                 versioned_right.version
             )
         }
-        compare_graphs_verbose(versioned_left.graph, versioned_right.graph, settings)
+        compare_graphs_verbose(
+            (*versioned_left.graph).clone(),
+            (*versioned_right.graph).clone(),
+            settings,
+        )
     }
 
     /// Compares two Graphs and gives a verbose error if not equal.
@@ -1192,6 +1684,7 @@ mod tests {
 
     use super::testing::*;
     use super::*;
+    use std::convert::TryInto;
 
     type TestResult<T> = Result<T, Box<dyn std::error::Error>>;
 
@@ -1237,33 +1730,39 @@ mod tests {
     fn test_graph_eq_false_for_unequal_graphs() {
         let graph1 = {
             let mut graph = Graph::default();
-            let v1 = graph.dag.add_node(Release::Concrete(ConcreteRelease {
+            let v1 = graph.add_node(Release::Concrete(ConcreteRelease {
                 version: String::from("1.0.0"),
                 payload: String::from("image/1.0.0"),
                 metadata: MapImpl::new(),
             }));
-            let v2 = graph.dag.add_node(Release::Concrete(ConcreteRelease {
+            let v2 = graph.add_node(Release::Concrete(ConcreteRelease {
                 version: String::from("2.0.0"),
                 payload: String::from("image/2.0.0"),
                 metadata: MapImpl::new(),
             }));
-            graph.dag.add_edge(v1, v2, Empty {}).unwrap();
+            graph
+                .dag
+                .add_edge(v1, v2, EdgeWeight::unconditional())
+                .unwrap();
 
             graph
         };
         let graph2 = {
             let mut graph = Graph::default();
-            let v3 = graph.dag.add_node(Release::Concrete(ConcreteRelease {
+            let v3 = graph.add_node(Release::Concrete(ConcreteRelease {
                 version: String::from("3.0.0"),
                 payload: String::from("image/3.0.0"),
                 metadata: MapImpl::new(),
             }));
-            let v2 = graph.dag.add_node(Release::Concrete(ConcreteRelease {
+            let v2 = graph.add_node(Release::Concrete(ConcreteRelease {
                 version: String::from("2.0.0"),
                 payload: String::from("image/2.0.0"),
                 metadata: MapImpl::new(),
             }));
-            graph.dag.add_edge(v2, v3, Empty {}).unwrap();
+            graph
+                .dag
+                .add_edge(v2, v3, EdgeWeight::unconditional())
+                .unwrap();
 
             graph
         };
@@ -1296,23 +1795,41 @@ mod tests {
 
         let graph1 = {
             let mut graph = Graph::default();
-            let v1 = graph.dag.add_node(r1.clone());
-            let v2 = graph.dag.add_node(r2.clone());
-            let v3 = graph.dag.add_node(r3.clone());
-            graph.dag.add_edge(v1, v2, Empty {}).unwrap();
-            graph.dag.add_edge(v1, v3, Empty {}).unwrap();
-            graph.dag.add_edge(v2, v3, Empty {}).unwrap();
+            let v1 = graph.add_node(r1.clone());
+            let v2 = graph.add_node(r2.clone());
+            let v3 = graph.add_node(r3.clone());
+            graph
+                .dag
+                .add_edge(v1, v2, EdgeWeight::unconditional())
+                .unwrap();
+            graph
+                .dag
+                .add_edge(v1, v3, EdgeWeight::unconditional())
+                .unwrap();
+            graph
+                .dag
+                .add_edge(v2, v3, EdgeWeight::unconditional())
+                .unwrap();
 
             graph
         };
         let graph2 = {
             let mut graph = Graph::default();
-            let v3 = graph.dag.add_node(r3);
-            let v2 = graph.dag.add_node(r2);
-            let v1 = graph.dag.add_node(r1);
-            graph.dag.add_edge(v2, v3, Empty {}).unwrap();
-            graph.dag.add_edge(v1, v2, Empty {}).unwrap();
-            graph.dag.add_edge(v1, v3, Empty {}).unwrap();
+            let v3 = graph.add_node(r3);
+            let v2 = graph.add_node(r2);
+            let v1 = graph.add_node(r1);
+            graph
+                .dag
+                .add_edge(v2, v3, EdgeWeight::unconditional())
+                .unwrap();
+            graph
+                .dag
+                .add_edge(v1, v2, EdgeWeight::unconditional())
+                .unwrap();
+            graph
+                .dag
+                .add_edge(v1, v3, EdgeWeight::unconditional())
+                .unwrap();
 
             graph
         };
@@ -1340,18 +1857,24 @@ mod tests {
 
         let graph1 = {
             let mut graph = Graph::default();
-            let v1 = graph.dag.add_node(r1.clone());
-            let v2 = graph.dag.add_node(r2.clone());
-            graph.dag.add_edge(v1, v2, Empty {}).unwrap();
+            let v1 = graph.add_node(r1.clone());
+            let v2 = graph.add_node(r2.clone());
+            graph
+                .dag
+                .add_edge(v1, v2, EdgeWeight::unconditional())
+                .unwrap();
 
             graph
         };
         let graph2 = {
             let mut graph = Graph::default();
-            let v1 = graph.dag.add_node(r1);
-            let v2 = graph.dag.add_node(r2);
-            let _ = graph.dag.add_node(r3);
-            graph.dag.add_edge(v1, v2, Empty {}).unwrap();
+            let v1 = graph.add_node(r1);
+            let v2 = graph.add_node(r2);
+            let _ = graph.add_node(r3);
+            graph
+                .dag
+                .add_edge(v1, v2, EdgeWeight::unconditional())
+                .unwrap();
 
             graph
         };
@@ -1361,11 +1884,122 @@ mod tests {
     #[test]
     fn roundtrip_conversion_from_graph_via_plugin_interface() {
         let graph_plugin_interface: plugins::interface::Graph = generate_graph(false, false).into();
-        let graph_native_converted: Graph = graph_plugin_interface.into();
+        let graph_native_converted: Graph = graph_plugin_interface.try_into().unwrap();
 
         assert_eq!(generate_graph(false, false), graph_native_converted);
     }
 
+    #[test]
+    fn conversion_from_invalid_plugin_interface_graph() {
+        let graph: plugins::interface::Graph = generate_graph(false, false).into();
+
+        let mut out_of_range = graph.clone();
+        out_of_range.mut_edges()[0].set_to(42);
+        let err = Graph::try_from(out_of_range).unwrap_err();
+        assert!(err.to_string().contains("node 42"), "{}", err);
+
+        let mut cycle = graph.clone();
+        let mut edge = plugins::interface::Graph_Edge::new();
+        edge.set_from(2);
+        edge.set_to(0);
+        cycle.mut_edges().push(edge);
+        assert!(Graph::try_from(cycle).is_err());
+
+        let mut conditional = graph;
+        let mut conditional_edge = plugins::interface::Graph_ConditionalEdge::new();
+        let mut edge = plugins::interface::Graph_Edge::new();
+        edge.set_from(0);
+        edge.set_to(42);
+        conditional_edge.mut_edges().push(edge);
+        conditional.mut_conditional_edges().push(conditional_edge);
+        assert!(Graph::try_from(conditional).is_err());
+    }
+
+    #[test]
+    fn roundtrip_conversion_from_graph_via_protobuf_bytes() -> TestResult<()> {
+        use protobuf::Message;
+
+        let graph = generate_graph(true, false);
+        let bytes = plugins::interface::Graph::from(graph.clone()).write_to_bytes()?;
+        let graph_native_converted: Graph =
+            plugins::interface::Graph::parse_from_bytes(&bytes)?.try_into()?;
+
+        assert_eq!(graph, graph_native_converted);
+        assert_eq!(
+            serde_json::to_value(&graph)?,
+            serde_json::to_value(&graph_native_converted)?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_graph_with_conditional_edges() -> TestResult<()> {
+        let json = r#"{"nodes":[{"version":"1.0.0","payload":"image/1.0.0","metadata":{}},{"version":"2.0.0","payload":"image/2.0.0","metadata":{}},{"version":"3.0.0","payload":"image/3.0.0","metadata":{}}],"edges":[[0,1]],"conditionalEdges":[{"edges":[{"from":"1.0.0","to":"3.0.0"},{"from":"2.0.0","to":"3.0.0"}],"risks":[{"url":"https://bug.example.com","name":"SomeRisk","message":"","matchingRules":[{"type":"Always"}]}]}]}"#;
+
+        let graph: Graph = serde_json::from_str(json)?;
+        let v1 = graph.find_by_version("1.0.0").unwrap();
+        let v2 = graph.find_by_version("2.0.0").unwrap();
+        let v3 = graph.find_by_version("3.0.0").unwrap();
+
+        // Conditional edges are not followed as unconditional edges.
+        assert_eq!(graph.next_releases(&v1).count(), 1);
+        assert_eq!(graph.previous_releases(&v3).count(), 0);
+        assert!(graph.edge_weight(&v2, &v3).unwrap().is_conditional());
+
+        assert_eq!(serde_json::to_string(&graph)?, json);
+        Ok(())
+    }
+
+    #[test]
+    fn removing_releases_removes_conditional_edges() -> TestResult<()> {
+        let mut graph = generate_graph(true, false);
+        let v2 = graph.find_by_version("2.0.0").unwrap();
+
+        assert_eq!(graph.conditional_edges().len(), 1);
+        graph.remove_releases(vec![v2]);
+        assert!(graph.conditional_edges().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn removing_unconditional_edge_keeps_conditional_edge() -> TestResult<()> {
+        let mut graph = generate_graph(true, false);
+        let v1 = graph.find_by_version("1.0.0").unwrap();
+        let v2 = graph.find_by_version("2.0.0").unwrap();
+
+        graph.remove_edge(&v1, &v2)?;
+        assert!(graph
+            .next_releases(&v1)
+            .all(|(_, _, r)| r.version() != "2.0.0"));
+        assert_eq!(graph.conditional_edges()[0].edges.len(), 1);
+        graph.remove_edge(&v1, &v2).unwrap_err();
+
+        graph.remove_conditional_edge(&v1, &v2)?;
+        assert!(graph.conditional_edges().is_empty());
+        assert!(graph.edge_weight(&v1, &v2).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn adding_edge_to_conditional_edge() -> TestResult<()> {
+        let mut graph = generate_graph(true, false);
+        let v1 = graph.find_by_version("1.0.0").unwrap();
+        let v2 = graph.find_by_version("2.0.0").unwrap();
+
+        graph.remove_edge(&v1, &v2)?;
+        graph.add_edge(&v1, &v2)?;
+        let weight = graph.edge_weight(&v1, &v2).unwrap();
+        assert!(weight.unconditional && weight.is_conditional());
+
+        graph.remove_conditional_edge(&v1, &v2)?;
+        assert_eq!(
+            graph.edge_weight(&v1, &v2),
+            Some(&EdgeWeight::unconditional())
+        );
+        Ok(())
+    }
+
     fn get_test_metadata_fn_mut(key_prefix: &str, key_suffix: &str) -> TestMetadata {
         vec![
             (
@@ -1546,4 +2180,147 @@ mod tests {
 
         Ok(())
     }
+
+    /// Asserts that every release is found by its version at its current index.
+    fn assert_versions_indexed(graph: &Graph) {
+        for (index, release) in graph.dag.node_references() {
+            assert_eq!(
+                graph.find_by_version(release.version()),
+                Some(ReleaseId(index)),
+                "version {} is not indexed",
+                release.version()
+            );
+        }
+    }
+
+    #[test]
+    fn index_follows_removed_releases() {
+        let mut graph = generate_custom_graph(
+            "image",
+            (0..6).map(|i| (i, Default::default())).collect(),
+            Some(vec![(0, 1), (1, 2), (2, 5)]),
+        );
+
+        let to_remove = vec![
+            graph.find_by_version("1.0.0").unwrap(),
+            graph.find_by_version("3.0.0").unwrap(),
+        ];
+        assert_eq!(graph.remove_releases(to_remove), 2);
+
+        assert_versions_indexed(&graph);
+        assert_eq!(graph.find_by_version("1.0.0"), None);
+        assert_eq!(graph.find_by_version("3.0.0"), None);
+    }
+
+    #[test]
+    fn index_follows_pruned_abstract_releases() {
+        let mut graph = generate_custom_graph(
+            "image",
+            (0..4).map(|i| (i, Default::default())).collect(),
+            Some(vec![(0, 1), (2, 3)]),
+        );
+        for version in &["10.0.0", "11.0.0"] {
+            graph
+                .add_release(Release::Abstract(AbstractRelease {
+                    version: version.to_string(),
+                }))
+                .unwrap();
+        }
+        let first = graph.find_by_version("0.0.0").unwrap();
+        let abstract_release = graph.find_by_version("10.0.0").unwrap();
+        graph.add_edge(&first, &abstract_release).unwrap();
+
+        assert_eq!(graph.prune_abstract(), 2);
+
+        assert_versions_indexed(&graph);
+        assert_eq!(graph.releases_count(), 4);
+        assert_eq!(graph.find_by_version("10.0.0"), None);
+        assert_eq!(graph.find_by_version("11.0.0"), None);
+    }
+
+    #[test]
+    fn index_follows_replaced_and_rewritten_releases() {
+        let mut graph = generate_custom_graph(
+            "image",
+            (0..3).map(|i| (i, Default::default())).collect(),
+            Some(vec![]),
+        );
+        let id = graph
+            .add_release(Release::Abstract(AbstractRelease {
+                version: "3.0.0".to_string(),
+            }))
+            .unwrap();
+        let replaced = graph
+            .add_release(Release::Concrete(ConcreteRelease {
+                version: "3.0.0".to_string(),
+                payload: "image/3.0.0".to_string(),
+                metadata: Default::default(),
+            }))
+            .unwrap();
+        assert_eq!(id, replaced);
+        assert_versions_indexed(&graph);
+
+        graph
+            .iter_releases_mut(|release| {
+                if let Release::Concrete(release) = release {
+                    release.version = format!("{}+amd64", release.version);
+                }
+                Ok(())
+            })
+            .unwrap();
+
+        assert_versions_indexed(&graph);
+        assert_eq!(graph.find_by_version("1.0.0"), None);
+        assert!(graph.find_by_version("1.0.0+amd64").is_some());
+    }
+
+    #[test]
+    fn metadata_index_follows_changes() {
+        let key = "prefix.suffix";
+        let metadata: TestMetadata = (0..4)
+            .map(|i| {
+                let metadata = if i < 2 {
+                    [(key.to_string(), "true".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect()
+                } else {
+                    Default::default()
+                };
+                (i, metadata)
+            })
+            .collect();
+        let mut unindexed = generate_custom_graph("image", metadata, Some(vec![]));
+        let mut indexed = unindexed.clone();
+        indexed.index_metadata_key(key);
+
+        let found = |graph: &Graph| {
+            let mut found: Vec<String> = graph
+                .find_by_metadata_pair(key, "true")
+                .into_iter()
+                .map(|(_, version)| version)
+                .collect();
+            found.sort();
+            found
+        };
+        assert_eq!(found(&indexed), vec!["0.0.0", "1.0.0"]);
+
+        for graph in &mut [&mut indexed, &mut unindexed] {
+            let with_key = graph.find_by_version("0.0.0").unwrap();
+            graph
+                .get_metadata_as_ref_mut(&with_key)
+                .unwrap()
+                .remove(key);
+            let without_key = graph.find_by_version("3.0.0").unwrap();
+            graph
+                .get_metadata_as_ref_mut(&without_key)
+                .unwrap()
+                .insert(key.to_string(), "true".to_string());
+            let removed = graph.find_by_version("1.0.0").unwrap();
+            graph.remove_releases(vec![removed]);
+        }
+
+        assert_eq!(found(&indexed), vec!["3.0.0"]);
+        assert_eq!(found(&indexed), found(&unindexed));
+    }
 }
@@ -0,0 +1,473 @@
+//! Semantic consistency checks for graphs.
+//!
+//! Each check implements `LintCheck` and reports its findings as
+//! human-readable messages. A `Linter` runs a set of checks against a graph.
+
+use crate::{Graph, MapImpl, MetadataKeys, Release};
+use daggy::petgraph::visit::IntoNodeReferences;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt;
+
+/// Default metadata key holding the comma-separated list of channels of a release.
+pub static DEFAULT_CHANNELS_KEY: &str = "io.openshift.upgrades.graph.release.channels";
+
+/// A single problem found by a check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LintFinding {
+    /// Name of the check which reported this finding.
+    pub check: &'static str,
+    /// Description of the problem.
+    pub message: String,
+}
+
+impl fmt::Display for LintFinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}", self.check, self.message)
+    }
+}
+
+/// A check which can be run against a graph.
+pub trait LintCheck: fmt::Debug + Send + Sync {
+    /// Name of the check, used in configuration and metrics.
+    fn name(&self) -> &'static str;
+
+    /// Returns a message for every problem found in the graph.
+    fn check(&self, graph: &Graph) -> Vec<String>;
+}
+
+/// Runs a set of checks against graphs.
+#[derive(Debug, Default)]
+pub struct Linter {
+    checks: Vec<Box<dyn LintCheck>>,
+}
+
+impl Linter {
+    /// Creates a linter running the given checks.
+    pub fn new(checks: Vec<Box<dyn LintCheck>>) -> Self {
+        Linter { checks }
+    }
+
+    /// Creates a linter running the default checks with the default metadata keys.
+    pub fn with_default_checks() -> Self {
+        Linter::new(default_checks(&MetadataKeys::default()))
+    }
+
+    /// Adds a check to this linter.
+    pub fn add_check(&mut self, check: Box<dyn LintCheck>) {
+        self.checks.push(check);
+    }
+
+    /// Returns the checks run by this linter.
+    pub fn checks(&self) -> impl Iterator<Item = &dyn LintCheck> {
+        self.checks.iter().map(AsRef::as_ref)
+    }
+
+    /// Runs all checks and returns their findings.
+    pub fn run(&self, graph: &Graph) -> Vec<LintFinding> {
+        self.checks
+            .iter()
+            .flat_map(|check| {
+                check
+                    .check(graph)
+                    .into_iter()
+                    .map(move |message| LintFinding {
+                        check: check.name(),
+                        message,
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Returns the checks which are run unless configured otherwise.
+///
+/// These hold for any graph served to clients, whereas the other built-in
+/// checks only apply to some graphs and need to be selected explicitly.
+pub fn default_checks(keys: &MetadataKeys) -> Vec<Box<dyn LintCheck>> {
+    vec![
+        Box::new(DowngradeEdges),
+        Box::new(DanglingConditionalEdges),
+        Box::new(ShadowedConditionalEdges),
+        Box::new(MissingChannels {
+            key: keys.channels.clone(),
+        }),
+    ]
+}
+
+/// Returns all built-in checks, reading the metadata at the given keys.
+pub fn all_checks(keys: &MetadataKeys) -> Vec<Box<dyn LintCheck>> {
+    let mut checks = default_checks(keys);
+    checks.push(Box::new(DuplicateVersionsAcrossArches {
+        key: keys.arch.clone(),
+    }));
+    checks
+}
+
+/// Returns the built-in check with the given name.
+pub fn check_by_name(name: &str, keys: &MetadataKeys) -> Option<Box<dyn LintCheck>> {
+    all_checks(keys)
+        .into_iter()
+        .find(|check| check.name() == name)
+}
+
+/// Reports edges whose target has a lower semantic version than their source.
+#[derive(Debug, Clone, Copy)]
+pub struct DowngradeEdges;
+
+impl LintCheck for DowngradeEdges {
+    fn name(&self) -> &'static str {
+        "downgrade-edges"
+    }
+
+    fn check(&self, graph: &Graph) -> Vec<String> {
+        graph
+            .dag
+            .raw_edges()
+            .iter()
+            .filter_map(|edge| {
+                let from = graph.dag.node_weight(edge.source())?.version();
+                let to = graph.dag.node_weight(edge.target())?.version();
+                let from_semver = semver::Version::parse(from).ok()?;
+                let to_semver = semver::Version::parse(to).ok()?;
+
+                if to_semver < from_semver {
+                    Some(format!("edge from {} to {} is a downgrade", from, to))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reports versions which exist for several architectures within the same graph.
+///
+/// Versions are compared without their build metadata, which may carry the architecture.
+/// This only applies to graphs filtered by architecture, as unfiltered graphs
+/// hold every version once per architecture.
+#[derive(Debug, Clone)]
+pub struct DuplicateVersionsAcrossArches {
+    /// Metadata key holding the architecture.
+    pub key: String,
+}
+
+impl LintCheck for DuplicateVersionsAcrossArches {
+    fn name(&self) -> &'static str {
+        "duplicate-versions-across-arches"
+    }
+
+    fn check(&self, graph: &Graph) -> Vec<String> {
+        let mut arches_by_version: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+
+        for (_, release) in graph.dag.node_references() {
+            let (version, build_arch) = match semver::Version::parse(release.version()) {
+                Ok(mut version) => {
+                    let build = version
+                        .build
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<_>>()
+                        .join(".");
+                    version.build.clear();
+                    (version.to_string(), build)
+                }
+                Err(_) => (release.version().to_string(), String::new()),
+            };
+
+            let arch = match release {
+                Release::Concrete(concrete) => concrete
+                    .metadata
+                    .get(&self.key)
+                    .cloned()
+                    .unwrap_or(build_arch),
+                Release::Abstract(_) => build_arch,
+            };
+
+            arches_by_version.entry(version).or_default().insert(arch);
+        }
+
+        arches_by_version
+            .into_iter()
+            .filter(|(_, arches)| arches.len() > 1)
+            .map(|(version, arches)| {
+                format!(
+                    "version {} exists for several architectures: {}",
+                    version,
+                    arches.into_iter().collect::<Vec<_>>().join(", ")
+                )
+            })
+            .collect()
+    }
+}
+
+/// Reports conditional edges which reference versions missing from the graph.
+///
+/// Such edges can't be part of the graph, so they are only known if the graph
+/// was deserialized along with them.
+#[derive(Debug, Clone, Copy)]
+pub struct DanglingConditionalEdges;
+
+impl LintCheck for DanglingConditionalEdges {
+    fn name(&self) -> &'static str {
+        "dangling-conditional-edges"
+    }
+
+    fn check(&self, graph: &Graph) -> Vec<String> {
+        let versions: HashSet<&str> = graph
+            .dag
+            .node_references()
+            .map(|(_, release)| release.version())
+            .collect();
+
+        graph
+            .dangling_conditional_edges()
+            .iter()
+            .flat_map(|ce| ce.edges.iter())
+            .filter_map(|edge| {
+                let missing: Vec<&str> = [edge.from.as_str(), edge.to.as_str()]
+                    .iter()
+                    .filter(|version| !versions.contains(*version))
+                    .cloned()
+                    .collect();
+
+                if missing.is_empty() {
+                    None
+                } else {
+                    Some(format!(
+                        "conditional edge from {} to {} references missing releases: {}",
+                        edge.from,
+                        edge.to,
+                        missing.join(", ")
+                    ))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Reports conditional edges which also exist as unconditional edges.
+///
+/// Clients follow the unconditional edge, so the risks of the conditional edge are never evaluated.
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowedConditionalEdges;
+
+impl LintCheck for ShadowedConditionalEdges {
+    fn name(&self) -> &'static str {
+        "shadowed-conditional-edges"
+    }
+
+    fn check(&self, graph: &Graph) -> Vec<String> {
+        graph
+            .edges()
+            .filter(|(_, _, weight)| weight.unconditional && weight.is_conditional())
+            .map(|(from, to, _)| {
+                format!(
+                    "conditional edge from {} to {} is shadowed by an unconditional edge",
+                    from.version(),
+                    to.version()
+                )
+            })
+            .collect()
+    }
+}
+
+/// Reports concrete releases which are not part of any channel.
+#[derive(Debug, Clone)]
+pub struct MissingChannels {
+    /// Metadata key holding the comma-separated list of channels.
+    pub key: String,
+}
+
+impl LintCheck for MissingChannels {
+    fn name(&self) -> &'static str {
+        "missing-channels"
+    }
+
+    fn check(&self, graph: &Graph) -> Vec<String> {
+        let has_channels = |metadata: &MapImpl<String, String>| {
+            metadata
+                .get(&self.key)
+                .map(|channels| channels.split(',').any(|c| !c.trim().is_empty()))
+                .unwrap_or(false)
+        };
+
+        graph
+            .dag
+            .node_references()
+            .filter_map(|(_, release)| match release {
+                Release::Concrete(concrete) if !has_channels(&concrete.metadata) => Some(format!(
+                    "release {} has no channels in metadata key {}",
+                    concrete.version, self.key
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{generate_custom_graph, generate_graph};
+
+    fn messages(check: &dyn LintCheck, graph: &Graph) -> Vec<String> {
+        check.check(graph)
+    }
+
+    #[test]
+    fn downgrade_edges() {
+        let graph = generate_custom_graph(
+            "image",
+            (0..3).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![(0, 1), (2, 1)]),
+        );
+
+        assert_eq!(
+            messages(&DowngradeEdges, &graph),
+            vec!["edge from 2.0.0 to 1.0.0 is a downgrade".to_string()]
+        );
+    }
+
+    #[test]
+    fn duplicate_versions_across_arches() {
+        let mut graph = Graph::default();
+        for version in &["1.0.0+amd64", "1.0.0+s390x", "2.0.0+amd64"] {
+            graph
+                .add_release(Release::Concrete(crate::ConcreteRelease {
+                    version: version.to_string(),
+                    payload: format!("image/{}", version),
+                    metadata: MapImpl::new(),
+                }))
+                .unwrap();
+        }
+        graph
+            .add_release(Release::Concrete(crate::ConcreteRelease {
+                version: "3.0.0".to_string(),
+                payload: "image/3.0.0".to_string(),
+                metadata: [("custom.arch".to_string(), "arm64".to_string())]
+                    .iter()
+                    .cloned()
+                    .collect(),
+            }))
+            .unwrap();
+        graph
+            .add_release(Release::Concrete(crate::ConcreteRelease {
+                version: "3.0.0+amd64".to_string(),
+                payload: "image/3.0.0+amd64".to_string(),
+                metadata: MapImpl::new(),
+            }))
+            .unwrap();
+
+        let check = DuplicateVersionsAcrossArches {
+            key: "custom.arch".to_string(),
+        };
+        assert_eq!(
+            messages(&check, &graph),
+            vec![
+                "version 1.0.0 exists for several architectures: amd64, s390x".to_string(),
+                "version 3.0.0 exists for several architectures: amd64, arm64".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowed_conditional_edges() {
+        let mut graph = generate_graph(true, false);
+        // 1.0.0 -> 2.0.0 exists both as an unconditional and a conditional edge.
+        assert_eq!(
+            messages(&ShadowedConditionalEdges, &graph),
+            vec![
+                "conditional edge from 1.0.0 to 2.0.0 is shadowed by an unconditional edge"
+                    .to_string()
+            ]
+        );
+
+        let v1 = graph.find_by_version("1.0.0").unwrap();
+        let v2 = graph.find_by_version("2.0.0").unwrap();
+        graph.remove_edge(&v1, &v2).unwrap();
+        assert!(messages(&ShadowedConditionalEdges, &graph).is_empty());
+    }
+
+    #[test]
+    fn dangling_conditional_edges() -> Result<(), serde_json::Error> {
+        let graph: Graph = serde_json::from_str(
+            r#"{
+                "nodes": [
+                    {"version": "1.0.0", "payload": "image/1.0.0", "metadata": {}},
+                    {"version": "2.0.0", "payload": "image/2.0.0", "metadata": {}}
+                ],
+                "edges": [],
+                "conditionalEdges": [{
+                    "edges": [
+                        {"from": "1.0.0", "to": "2.0.0"},
+                        {"from": "1.0.0", "to": "9.0.0"}
+                    ],
+                    "risks": [{"url": "https://example.com", "name": "SomeRisk", "message": "", "matchingRules": []}]
+                }]
+            }"#,
+        )?;
+
+        assert_eq!(graph.conditional_edges().len(), 1);
+        assert_eq!(
+            messages(&DanglingConditionalEdges, &graph),
+            vec![
+                "conditional edge from 1.0.0 to 9.0.0 references missing releases: 9.0.0"
+                    .to_string()
+            ]
+        );
+        assert!(messages(&DanglingConditionalEdges, &generate_graph(true, false)).is_empty());
+        assert!(Linter::with_default_checks()
+            .run(&graph)
+            .iter()
+            .any(|finding| finding.check == "dangling-conditional-edges"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_channels() {
+        let graph = generate_custom_graph(
+            "image",
+            vec![
+                (
+                    0,
+                    [(DEFAULT_CHANNELS_KEY.to_string(), "a,b".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                ),
+                (1, MapImpl::new()),
+            ],
+            None,
+        );
+
+        let check = MissingChannels {
+            key: DEFAULT_CHANNELS_KEY.to_string(),
+        };
+        assert_eq!(
+            messages(&check, &graph),
+            vec![format!(
+                "release 1.0.0 has no channels in metadata key {}",
+                DEFAULT_CHANNELS_KEY
+            )]
+        );
+    }
+
+    #[test]
+    fn linter_reports_check_names() {
+        let graph = generate_graph(true, false);
+        let linter = Linter::new(vec![Box::new(ShadowedConditionalEdges)]);
+
+        let findings = linter.run(&graph);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].check, "shadowed-conditional-edges");
+
+        let keys = MetadataKeys::default();
+        assert!(check_by_name("missing-channels", &keys).is_some());
+        assert!(check_by_name("duplicate-versions-across-arches", &keys).is_some());
+        assert!(check_by_name("unknown", &keys).is_none());
+        assert!(default_checks(&keys)
+            .iter()
+            .all(|check| check.name() != "duplicate-versions-across-arches"));
+    }
+}
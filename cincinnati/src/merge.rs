@@ -0,0 +1,285 @@
+//! Merging of graphs built from different sources.
+//!
+//! Releases are matched by version. Edges and conditional edges of both graphs
+//! are united.
+
+use crate::{errors, ConcreteRelease, Graph, Release, ReleaseId};
+use commons::prelude_errors::*;
+use daggy::petgraph::visit::EdgeRef;
+
+/// How `Graph::merge` resolves releases which exist in both graphs.
+///
+/// Abstract releases never conflict; they are replaced by the concrete release
+/// of the same version. Identical releases don't conflict either.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeStrategy {
+    /// Keep the release of the graph which is merged into.
+    PreferLeft,
+    /// Keep the release of the graph which is merged.
+    PreferRight,
+    /// Unite the metadata of both releases, preferring the values of the graph
+    /// which is merged. The payloads of both releases must be the same.
+    MergeMetadata,
+    /// Fail on conflicting releases.
+    Error,
+}
+
+impl Default for MergeStrategy {
+    fn default() -> Self {
+        MergeStrategy::Error
+    }
+}
+
+impl Graph {
+    /// Merges `other` into this graph.
+    ///
+    /// Releases of the same version are resolved according to `strategy`.
+    /// Edges, conditional edges and conditional edge declarations of both graphs
+    /// are united. Fails if the united edges contain a cycle, in which case this
+    /// graph may be left partially merged.
+    pub fn merge(&mut self, other: Graph, strategy: MergeStrategy) -> Fallible<()> {
+        let mut ids: Vec<ReleaseId> = Vec::with_capacity(other.dag.node_count());
+        for index in other.dag.graph().node_indices() {
+            let release = other.dag[index].clone();
+            let id = match self.find_by_version(release.version()) {
+                Some(id) => {
+                    self.merge_release(&id, release, strategy)?;
+                    id
+                }
+                None => ReleaseId(self.add_node(release)),
+            };
+            ids.push(id);
+        }
+
+        for edge in other.dag.graph().edge_references() {
+            let (from, to) = (&ids[edge.source().index()], &ids[edge.target().index()]);
+            let weight = edge.weight();
+
+            if weight.unconditional {
+                if let Err(e) = self.add_edge(from, to) {
+                    if e.downcast_ref::<errors::EdgeAlreadyExists>().is_none() {
+                        return Err(e);
+                    }
+                }
+            }
+            if weight.is_conditional() {
+                self.add_conditional_edge(from, to, &weight.risks)?;
+            }
+            if !weight.annotations.is_empty() {
+                let annotations = &mut self
+                    .edge_weight_mut(from, to)
+                    .ok_or_else(|| format_err!("merged edge is missing"))?
+                    .annotations;
+                for (key, value) in &weight.annotations {
+                    annotations
+                        .entry(key.clone())
+                        .or_insert_with(|| value.clone());
+                }
+            }
+        }
+
+        self.conditional_edge_declarations
+            .extend(other.conditional_edge_declarations);
+        self.dangling_conditional_edges
+            .extend(other.dangling_conditional_edges);
+
+        Ok(())
+    }
+
+    /// Resolves a release which exists in both graphs.
+    fn merge_release(
+        &mut self,
+        id: &ReleaseId,
+        release: Release,
+        strategy: MergeStrategy,
+    ) -> Fallible<()> {
+        let merged = match (self.find_by_releaseid(id)?, release) {
+            (_, Release::Abstract(_)) => return Ok(()),
+            (Release::Abstract(_), release) => release,
+            (Release::Concrete(left), Release::Concrete(right)) => {
+                if left == &right {
+                    return Ok(());
+                }
+                match strategy {
+                    MergeStrategy::PreferLeft => return Ok(()),
+                    MergeStrategy::PreferRight => Release::Concrete(right),
+                    MergeStrategy::MergeMetadata => Release::Concrete(merge_metadata(left, right)?),
+                    MergeStrategy::Error => {
+                        return Err(errors::ReleaseConflict(right.version).into())
+                    }
+                }
+            }
+        };
+
+        self.replace_node(id.0, merged);
+        Ok(())
+    }
+}
+
+/// Unites the metadata of two releases of the same payload.
+fn merge_metadata(left: &ConcreteRelease, right: ConcreteRelease) -> Fallible<ConcreteRelease> {
+    ensure!(
+        left.payload == right.payload,
+        "cannot merge the metadata of release {} with different payloads: {}, {}",
+        left.version,
+        left.payload,
+        right.payload
+    );
+
+    let mut merged = left.clone();
+    merged.metadata.extend(right.metadata);
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{generate_custom_graph, generate_graph};
+    use crate::{ConditionalUpdateRisk, MapImpl};
+
+    fn metadata(pairs: &[(&str, &str)]) -> MapImpl<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn release_metadata(graph: &Graph, version: &str) -> MapImpl<String, String> {
+        match graph
+            .find_by_releaseid(&graph.find_by_version(version).unwrap())
+            .unwrap()
+        {
+            Release::Concrete(release) => release.metadata.clone(),
+            Release::Abstract(_) => panic!("{} is abstract", version),
+        }
+    }
+
+    #[test]
+    fn merge_unites_releases_and_edges() {
+        let mut left = generate_custom_graph(
+            "image",
+            (0..3).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![(0, 1), (1, 2)]),
+        );
+        let right = generate_custom_graph(
+            "image",
+            (1..5).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![(0, 1), (1, 2), (0, 3)]),
+        );
+        let expected = generate_custom_graph(
+            "image",
+            (0..5).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![(0, 1), (1, 2), (2, 3), (1, 4)]),
+        );
+
+        left.merge(right, MergeStrategy::Error).unwrap();
+
+        assert_eq!(left, expected);
+    }
+
+    #[test]
+    fn merge_unites_conditional_edges() {
+        let mut left = generate_graph(true, false);
+        let mut right = generate_custom_graph(
+            "image",
+            (1..4).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![]),
+        );
+        let risk = ConditionalUpdateRisk {
+            name: "OtherRisk".to_string(),
+            ..Default::default()
+        };
+        let (from, to) = (
+            right.find_by_version("2.0.0").unwrap(),
+            right.find_by_version("3.0.0").unwrap(),
+        );
+        right
+            .add_conditional_edge(&from, &to, &[risk.clone()])
+            .unwrap();
+
+        left.merge(right, MergeStrategy::PreferLeft).unwrap();
+
+        let (from, to) = (
+            left.find_by_version("2.0.0").unwrap(),
+            left.find_by_version("3.0.0").unwrap(),
+        );
+        let weight = left.edge_weight(&from, &to).unwrap();
+        assert!(weight.unconditional);
+        assert_eq!(weight.risks, vec![risk]);
+        assert_eq!(left.conditional_edges().len(), 2);
+    }
+
+    #[test]
+    fn merge_strategies() {
+        let left = generate_custom_graph(
+            "image",
+            vec![(1, metadata(&[("a", "left"), ("b", "left")]))],
+            Some(vec![]),
+        );
+        let right = generate_custom_graph(
+            "image",
+            vec![(1, metadata(&[("b", "right"), ("c", "right")]))],
+            Some(vec![]),
+        );
+
+        let merged = |strategy| {
+            let mut merged = left.clone();
+            merged
+                .merge(right.clone(), strategy)
+                .map(|_| release_metadata(&merged, "1.0.0"))
+        };
+
+        assert_eq!(
+            merged(MergeStrategy::PreferLeft).unwrap(),
+            metadata(&[("a", "left"), ("b", "left")])
+        );
+        assert_eq!(
+            merged(MergeStrategy::PreferRight).unwrap(),
+            metadata(&[("b", "right"), ("c", "right")])
+        );
+        assert_eq!(
+            merged(MergeStrategy::MergeMetadata).unwrap(),
+            metadata(&[("a", "left"), ("b", "right"), ("c", "right")])
+        );
+        let error = merged(MergeStrategy::Error).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<errors::ReleaseConflict>(),
+            Some(&errors::ReleaseConflict("1.0.0".to_string()))
+        );
+
+        // identical releases never conflict
+        let mut identical = left.clone();
+        identical.merge(left.clone(), MergeStrategy::Error).unwrap();
+        assert_eq!(identical, left);
+    }
+
+    #[test]
+    fn merge_metadata_requires_same_payload() {
+        let mut left = generate_custom_graph("image", vec![(1, MapImpl::new())], Some(vec![]));
+        let right = generate_custom_graph("other", vec![(1, MapImpl::new())], Some(vec![]));
+
+        left.merge(right, MergeStrategy::MergeMetadata).unwrap_err();
+    }
+
+    #[test]
+    fn merge_fails_on_cycle() {
+        let mut left = generate_custom_graph(
+            "image",
+            (0..2).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![(0, 1)]),
+        );
+        let mut right = generate_custom_graph(
+            "image",
+            (0..2).map(|i| (i, MapImpl::new())).collect(),
+            Some(vec![]),
+        );
+        let (from, to) = (
+            right.find_by_version("1.0.0").unwrap(),
+            right.find_by_version("0.0.0").unwrap(),
+        );
+        right.add_edge(&from, &to).unwrap();
+
+        left.merge(right, MergeStrategy::Error).unwrap_err();
+    }
+}
@@ -5,8 +5,15 @@
 
 use crate as cincinnati;
 
+use self::cincinnati::plugins::condition::{ConditionalPluginSettings, PluginCondition};
+use self::cincinnati::plugins::failure_policy::{
+    FailurePolicy, FailurePolicyPluginSettings, OnError,
+};
 use self::cincinnati::plugins::BoxedPlugin;
 
+use super::external::subprocess::{SubprocessPlugin, SubprocessSettings};
+use super::external::wasm::{WasmPlugin, WasmPluginSettings};
+use super::external::web::{WebPluginClient, WebPluginSettings};
 use super::internal::arch_filter::ArchFilterPlugin;
 use super::internal::channel_filter::ChannelFilterPlugin;
 use super::internal::cincinnati_graph_fetch::CincinnatiGraphFetchPlugin;
@@ -17,6 +24,8 @@ use super::internal::edge_add_remove::EdgeAddRemovePlugin;
 use super::internal::github_openshift_secondary_metadata_scraper::{
     GithubOpenshiftSecondaryMetadataScraperPlugin, GithubOpenshiftSecondaryMetadataScraperSettings,
 };
+use super::internal::graph_lint::GraphLintPlugin;
+use super::internal::graph_merge::{GraphMergePlugin, GraphMergeSettings, ParallelPlugin};
 use super::internal::metadata_fetch_quay::QuayMetadataFetchPlugin;
 use super::internal::node_remove::NodeRemovePlugin;
 use super::internal::openshift_secondary_metadata_parser::{
@@ -25,20 +34,60 @@ use super::internal::openshift_secondary_metadata_parser::{
 use super::internal::release_scrape_dockerv2::{
     ReleaseScrapeDockerv2Plugin, ReleaseScrapeDockerv2Settings,
 };
+use super::internal::version_range_filter::VersionRangeFilterPlugin;
+use commons::de::in_toml_path;
 use commons::prelude_errors::*;
+use serde::Serialize;
 use std::fmt::Debug;
 
 /// Key used to look up plugin-type in a configuration entry.
 static CONFIG_PLUGIN_NAME_KEY: &str = "name";
 
+/// Key used to look up the run condition in a configuration entry.
+pub(crate) static CONFIG_PLUGIN_CONDITION_KEY: &str = "when";
+
+/// Key used to look up the failure policy in a configuration entry.
+pub(crate) static CONFIG_PLUGIN_ON_ERROR_KEY: &str = "on_error";
+
 /// Settings for a plugin.
 pub trait PluginSettings: Debug + Send {
     /// Build the corresponding plugin for this configuration.
     fn build_plugin(&self, registry: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin>;
+
+    /// Serialize the effective configuration, defaults included, as a configuration entry.
+    fn serialize_config(&self) -> Fallible<toml::Value>;
+}
+
+/// Serialize plugin settings as a configuration entry for the named plugin.
+pub fn serialize_plugin_config<T: Serialize>(name: &str, settings: &T) -> Fallible<toml::Value> {
+    let mut cfg = toml::Value::try_from(settings)
+        .context(format!("serializing the settings of plugin '{}'", name))?;
+    cfg.as_table_mut()
+        .ok_or_else(|| format_err!("settings of plugin '{}' are not a table", name))?
+        .insert(CONFIG_PLUGIN_NAME_KEY.to_string(), name.into());
+
+    Ok(cfg)
+}
+
+/// Add a key to a serialized configuration entry.
+pub(crate) fn insert_config_key(
+    cfg: &mut toml::Value,
+    key: &str,
+    value: toml::Value,
+) -> Fallible<()> {
+    cfg.as_table_mut()
+        .ok_or_else(|| format_err!("plugin configuration is not a table"))?
+        .insert(key.to_string(), value);
+
+    Ok(())
 }
 
 /// Validate configuration for a plugin and fill in defaults.
-pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+///
+/// A plugin with a `when` clause is wrapped so that it only runs if the clause holds,
+/// and a plugin with an `on_error` policy other than `fail` so that the policy
+/// applies to its failures.
+pub fn deserialize_config(mut cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
     let name = cfg
         .get(CONFIG_PLUGIN_NAME_KEY)
         .ok_or_else(|| format_err!("missing plugin name"))?
@@ -46,7 +95,46 @@ pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>>
         .ok_or_else(|| format_err!("invalid plugin name value"))?
         .to_string();
 
-    match name.as_str() {
+    let condition = match cfg
+        .as_table_mut()
+        .and_then(|table| table.remove(CONFIG_PLUGIN_CONDITION_KEY))
+    {
+        Some(when) => Some(
+            PluginCondition::deserialize_config(when)
+                .map_err(|e| in_toml_path(e, CONFIG_PLUGIN_CONDITION_KEY))?,
+        ),
+        None => None,
+    };
+
+    let on_error = match cfg
+        .as_table_mut()
+        .and_then(|table| table.remove(CONFIG_PLUGIN_ON_ERROR_KEY))
+    {
+        Some(on_error) => Some(
+            OnError::deserialize_config(on_error)
+                .map_err(|e| in_toml_path(e, CONFIG_PLUGIN_ON_ERROR_KEY))?,
+        ),
+        None => None,
+    };
+
+    let mut settings = deserialize_plugin_config(&name, cfg)?;
+
+    if let Some(on_error) = on_error.filter(|on_error| on_error.policy != FailurePolicy::Fail) {
+        settings = Box::new(FailurePolicyPluginSettings { on_error, settings });
+    }
+
+    match condition {
+        Some(condition) => Ok(Box::new(ConditionalPluginSettings {
+            condition,
+            settings,
+        })),
+        None => Ok(settings),
+    }
+}
+
+/// Validate the configuration of the named plugin.
+fn deserialize_plugin_config(name: &str, cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+    match name {
         ChannelFilterPlugin::PLUGIN_NAME => ChannelFilterPlugin::deserialize_config(cfg),
         EdgeAddRemovePlugin::PLUGIN_NAME => EdgeAddRemovePlugin::deserialize_config(cfg),
         NodeRemovePlugin::PLUGIN_NAME => NodeRemovePlugin::deserialize_config(cfg),
@@ -55,6 +143,10 @@ pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>>
             CincinnatiGraphFetchPlugin::deserialize_config(cfg)
         }
         ArchFilterPlugin::PLUGIN_NAME => ArchFilterPlugin::deserialize_config(cfg),
+        VersionRangeFilterPlugin::PLUGIN_NAME => VersionRangeFilterPlugin::deserialize_config(cfg),
+        GraphLintPlugin::PLUGIN_NAME => GraphLintPlugin::deserialize_config(cfg),
+        GraphMergePlugin::PLUGIN_NAME => GraphMergeSettings::deserialize_config(cfg),
+        ParallelPlugin::PLUGIN_NAME => GraphMergeSettings::deserialize_parallel_config(cfg),
         ReleaseScrapeDockerv2Plugin::PLUGIN_NAME => {
             ReleaseScrapeDockerv2Settings::deserialize_config(cfg)
         }
@@ -67,6 +159,9 @@ pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>>
         DkrV2OpenshiftSecondaryMetadataScraperPlugin::PLUGIN_NAME => {
             DkrV2OpenshiftSecondaryMetadataScraperSettings::deserialize_config(cfg)
         }
+        WebPluginClient::PLUGIN_NAME => WebPluginSettings::deserialize_config(cfg),
+        SubprocessPlugin::PLUGIN_NAME => SubprocessSettings::deserialize_config(cfg),
+        WasmPlugin::PLUGIN_NAME => WasmPluginSettings::deserialize_config(cfg),
         x => bail!("unknown plugin '{}'", x),
     }
 }
@@ -76,6 +171,10 @@ pub fn build_plugins(
     settings: &[Box<dyn PluginSettings>],
     registry: Option<&prometheus::Registry>,
 ) -> Fallible<Vec<BoxedPlugin>> {
+    if let Some(registry) = registry {
+        super::register_metrics(registry)?;
+    }
+
     let mut plugins = Vec::with_capacity(settings.len());
     for setting in settings {
         let plugin = setting.build_plugin(registry)?;
@@ -109,4 +208,60 @@ mod tests {
         let qm_settings = deserialize_config(quay_metadata_repo).unwrap();
         qm_settings.build_plugin(None).unwrap();
     }
+
+    #[test]
+    fn deserialize_when() {
+        let cfg = r#"
+            name = "node-remove"
+            when = { header = "X-Hotfix", equals = "true" }
+        "#;
+        let settings = deserialize_config(toml::from_str(cfg).unwrap()).unwrap();
+        let plugin = settings.build_plugin(None).unwrap();
+        assert_eq!(plugin.get_name(), "node-remove");
+        let condition = plugin.condition().expect("missing condition");
+        assert_eq!(condition.header(), Some("X-Hotfix"));
+        assert_eq!(condition.parameter(), "header.x-hotfix");
+
+        let cfg = r#"
+            name = "node-remove"
+            when = { parameter = "hotfix" }
+        "#;
+        deserialize_config(toml::from_str(cfg).unwrap()).unwrap_err();
+    }
+
+    #[test]
+    fn serialize_effective_config() -> Fallible<()> {
+        let cfg = r#"
+            name = "graph-merge"
+            strategy = "prefer-left"
+
+            [[chains]]
+            name = "hotfix"
+            plugins = [
+                { name = "node-remove", on_error = "skip", when = { header = "X-Hotfix", matches = "^1$" } },
+            ]
+        "#;
+        let serialized = deserialize_config(toml::from_str(cfg)?)?.serialize_config()?;
+
+        let expected: toml::Value = toml::from_str(
+            r#"
+            name = "graph-merge"
+            strategy = "prefer-left"
+            parameter_strategy = "prefer-right"
+
+            [[chains]]
+            name = "hotfix"
+            plugins = [
+                { name = "node-remove", key_prefix = "io.openshift.upgrades.graph", on_error = "skip", when = { header = "X-Hotfix", matches = "^1$" } },
+            ]
+            "#,
+        )?;
+        assert_eq!(serialized, expected);
+
+        // The serialized configuration is equivalent to the original one.
+        let reserialized = deserialize_config(serialized.clone())?.serialize_config()?;
+        assert_eq!(reserialized, serialized);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,315 @@
+//! Conditional plugin execution.
+//!
+//! A plugin entry in the configuration may carry a `when` clause, which makes
+//! the plugin run only for requests whose parameters satisfy it:
+//!
+//! ```toml
+//! [[plugin]]
+//! name = "node-remove"
+//! when = { parameter = "hotfix", equals = "true" }
+//! ```
+//!
+//! A condition tests a single parameter, or a request header via `header`,
+//! with exactly one of `equals`, `matches` (a regex) or `present`.
+
+use crate as cincinnati;
+
+use self::cincinnati::plugins::catalog::{self, PluginSettings};
+use self::cincinnati::plugins::{BoxedPlugin, InternalIO, Plugin, PluginIO};
+
+use async_trait::async_trait;
+use commons::prelude_errors::*;
+use opentelemetry::{trace::get_active_span, Key};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+
+/// Prefix of the parameters which carry request headers.
+pub static HEADER_PARAMETER_PREFIX: &str = "header.";
+
+/// Returns the name of the parameter carrying the given request header.
+pub fn header_parameter(header: &str) -> String {
+    format!("{}{}", HEADER_PARAMETER_PREFIX, header.to_ascii_lowercase())
+}
+
+/// Raw `when` clause, as found in the configuration.
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct ConditionConfig {
+    parameter: Option<String>,
+    header: Option<String>,
+    equals: Option<String>,
+    matches: Option<String>,
+    present: Option<bool>,
+}
+
+/// Test applied to the value of a parameter.
+#[derive(Debug, Clone)]
+pub enum Predicate {
+    /// The parameter is set to this value.
+    Equals(String),
+    /// The parameter is set to a value matching this regex.
+    Matches(regex::Regex),
+    /// The parameter is set (`true`) or unset (`false`).
+    Present(bool),
+}
+
+/// Condition on the request parameters for running a plugin.
+#[derive(Debug, Clone)]
+pub struct PluginCondition {
+    parameter: String,
+    header: Option<String>,
+    predicate: Predicate,
+}
+
+impl PluginCondition {
+    /// Validate a `when` clause.
+    pub fn deserialize_config(cfg: toml::Value) -> Fallible<Self> {
+        let config: ConditionConfig = commons::de::from_toml_value(cfg)?;
+
+        let (parameter, header) = match (config.parameter, config.header) {
+            (Some(parameter), None) => (parameter, None),
+            (None, Some(header)) => (header_parameter(&header), Some(header)),
+            _ => bail!("exactly one of 'parameter' or 'header' must be set"),
+        };
+        let predicate = match (config.equals, config.matches, config.present) {
+            (Some(value), None, None) => Predicate::Equals(value),
+            (None, Some(regex), None) => Predicate::Matches(
+                regex::Regex::new(&regex).context(format!("invalid regex '{}'", regex))?,
+            ),
+            (None, None, Some(present)) => Predicate::Present(present),
+            _ => bail!("exactly one of 'equals', 'matches' or 'present' must be set"),
+        };
+
+        Ok(Self {
+            parameter,
+            header,
+            predicate,
+        })
+    }
+
+    /// Serialize the condition as a `when` clause.
+    pub fn serialize_config(&self) -> Fallible<toml::Value> {
+        let mut config = match &self.header {
+            Some(header) => ConditionConfig {
+                header: Some(header.clone()),
+                ..Default::default()
+            },
+            None => ConditionConfig {
+                parameter: Some(self.parameter.clone()),
+                ..Default::default()
+            },
+        };
+        match &self.predicate {
+            Predicate::Equals(value) => config.equals = Some(value.clone()),
+            Predicate::Matches(regex) => config.matches = Some(regex.as_str().to_string()),
+            Predicate::Present(present) => config.present = Some(*present),
+        }
+
+        Ok(toml::Value::try_from(config)?)
+    }
+
+    /// Returns the name of the tested parameter.
+    pub fn parameter(&self) -> &str {
+        &self.parameter
+    }
+
+    /// Returns the tested request header, if the condition is on a header.
+    pub fn header(&self) -> Option<&str> {
+        self.header.as_deref()
+    }
+
+    /// Returns whether the given parameters satisfy the condition.
+    pub fn is_met(&self, parameters: &HashMap<String, String>) -> bool {
+        let value = parameters.get(&self.parameter);
+        match &self.predicate {
+            Predicate::Equals(expected) => value == Some(expected),
+            Predicate::Matches(regex) => value.map_or(false, |value| regex.is_match(value)),
+            Predicate::Present(present) => value.is_some() == *present,
+        }
+    }
+}
+
+impl fmt::Display for PluginCondition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.header {
+            Some(header) => write!(f, "header '{}'", header)?,
+            None => write!(f, "parameter '{}'", self.parameter)?,
+        }
+        match &self.predicate {
+            Predicate::Equals(value) => write!(f, " equals '{}'", value),
+            Predicate::Matches(regex) => write!(f, " matches '{}'", regex),
+            Predicate::Present(true) => write!(f, " is present"),
+            Predicate::Present(false) => write!(f, " is absent"),
+        }
+    }
+}
+
+/// Settings of a plugin which only runs if its condition is met.
+#[derive(Debug)]
+pub struct ConditionalPluginSettings {
+    pub condition: PluginCondition,
+    pub settings: Box<dyn PluginSettings>,
+}
+
+impl PluginSettings for ConditionalPluginSettings {
+    fn build_plugin(&self, registry: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
+        Ok(Box::new(ConditionalPlugin {
+            condition: self.condition.clone(),
+            plugin: self.settings.build_plugin(registry)?,
+        }))
+    }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        let mut cfg = self.settings.serialize_config()?;
+        catalog::insert_config_key(
+            &mut cfg,
+            catalog::CONFIG_PLUGIN_CONDITION_KEY,
+            self.condition.serialize_config()?,
+        )?;
+
+        Ok(cfg)
+    }
+}
+
+/// Plugin which runs the wrapped plugin only if its condition is met,
+/// and passes the input through otherwise.
+#[derive(Debug)]
+pub struct ConditionalPlugin {
+    condition: PluginCondition,
+    plugin: BoxedPlugin,
+}
+
+#[async_trait]
+impl Plugin<PluginIO> for ConditionalPlugin {
+    async fn run(&self, plugin_io: PluginIO) -> Fallible<PluginIO> {
+        let internal_io: InternalIO = plugin_io.try_into()?;
+        let met = self.condition.is_met(&internal_io.parameters);
+
+        get_active_span(|span| {
+            span.set_attribute(Key::new("when").string(self.condition.to_string()));
+            span.set_attribute(Key::new("when.skipped").bool(!met));
+        });
+
+        if !met {
+            log::debug!(
+                "skipping plugin '{}': {} does not hold",
+                self.get_name(),
+                self.condition
+            );
+            return Ok(internal_io.into());
+        }
+
+        self.plugin.run(internal_io.into()).await
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.plugin.get_name()
+    }
+
+    fn condition(&self) -> Option<&PluginCondition> {
+        Some(&self.condition)
+    }
+
+    fn parameters(&self) -> Option<Vec<String>> {
+        let mut parameters = self.plugin.parameters()?;
+        parameters.push(self.condition.parameter().to_string());
+        Some(parameters)
+    }
+
+    fn channels_key(&self) -> Option<String> {
+        self.plugin.channels_key()
+    }
+
+    fn nested_plugins(&self) -> Vec<&BoxedPlugin> {
+        self.plugin.nested_plugins()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::catalog::deserialize_config;
+    use std::sync::Arc;
+
+    fn condition(cfg: &str) -> Fallible<PluginCondition> {
+        PluginCondition::deserialize_config(toml::from_str(cfg)?)
+    }
+
+    fn parameters(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn evaluate_conditions() -> Fallible<()> {
+        let params = parameters(&[("channel", "fast-4.2"), ("header.x-hotfix", "1")]);
+
+        for (cfg, expected) in &[
+            ("parameter = 'channel'\nequals = 'fast-4.2'", true),
+            ("parameter = 'channel'\nequals = 'stable-4.2'", false),
+            ("parameter = 'channel'\nmatches = '^fast-'", true),
+            ("parameter = 'channel'\nmatches = '^stable-'", false),
+            ("parameter = 'arch'\nmatches = '.*'", false),
+            ("parameter = 'channel'\npresent = true", true),
+            ("parameter = 'arch'\npresent = false", true),
+            ("header = 'X-Hotfix'\npresent = true", true),
+        ] {
+            assert_eq!(condition(cfg)?.is_met(&params), *expected, "{}", cfg);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_conditions() {
+        for cfg in &[
+            "equals = 'x'",
+            "parameter = 'a'\nheader = 'b'\nequals = 'x'",
+            "parameter = 'a'",
+            "parameter = 'a'\nequals = 'x'\npresent = true",
+            "parameter = 'a'\nmatches = '('",
+            "parameter = 'a'\nequals = 'x'\nunknown = 1",
+        ] {
+            assert!(condition(cfg).is_err(), "{}", cfg);
+        }
+    }
+
+    #[test]
+    fn skip_plugin_unless_condition_is_met() -> Fallible<()> {
+        let runtime = commons::testing::init_runtime()?;
+
+        let cfg = r#"
+            name = "channel-filter"
+            when = { parameter = "filter", present = true }
+        "#;
+        let plugin = deserialize_config(toml::from_str(cfg)?)?.build_plugin(None)?;
+        assert_eq!(plugin.get_name(), "channel-filter");
+        assert!(plugin.condition().is_some());
+        assert_eq!(
+            plugin.parameters(),
+            Some(vec!["channel".to_string(), "filter".to_string()])
+        );
+
+        let graph = Arc::new(crate::Graph::default());
+
+        // The channel filter would fail on the missing channel parameter.
+        let io = runtime.block_on(plugin.run(PluginIO::InternalIO(InternalIO {
+            graph: graph.clone(),
+            parameters: Default::default(),
+        })))?;
+        let io: InternalIO = io.try_into()?;
+        assert!(Arc::ptr_eq(&io.graph, &graph));
+
+        let result = runtime.block_on(plugin.run(PluginIO::InternalIO(InternalIO {
+            graph,
+            parameters: parameters(&[("filter", "")]),
+        })));
+        assert!(result.is_err());
+
+        Ok(())
+    }
+}
@@ -1,3 +1,20 @@
 //! This module references the available external plugins
 
+pub mod subprocess;
+pub mod wasm;
 pub mod web;
+
+use crate::plugins::interface::{PluginError, PluginError_Kind};
+use commons::GraphError;
+
+/// Maps an error reported by an external plugin to the corresponding `GraphError`.
+pub(crate) fn plugin_error_to_graph_error(error: PluginError) -> GraphError {
+    let value = error.get_value().to_string();
+    match error.get_kind() {
+        PluginError_Kind::INVALID_PARAM => GraphError::InvalidParams(value),
+        PluginError_Kind::FAILED_DEPENDENCY => GraphError::FailedUpstreamFetch(value),
+        PluginError_Kind::GENERIC
+        | PluginError_Kind::INVALID_GRAPH
+        | PluginError_Kind::INTERNAL_FAILURE => GraphError::FailedPluginExecution(value),
+    }
+}
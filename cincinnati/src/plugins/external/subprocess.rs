@@ -0,0 +1,507 @@
+//! Plugin which runs an executable to process the graph.
+//!
+//! The executable receives the protobuf `PluginExchange` on stdin and answers
+//! on stdout. In `oneshot` mode, the default, a process is spawned per run. It
+//! reads stdin until EOF, then either writes a `PluginExchange` and exits
+//! successfully, or writes a `PluginError` and exits with a non-zero status.
+//!
+//! In `worker` mode a single long-lived process handles all runs, one at a
+//! time. Messages in both directions are framed as a one-byte tag, `0` for a
+//! `PluginExchange` and `1` for a `PluginError`, followed by the length of the
+//! message as big-endian `u32` and the message itself. A worker which fails or
+//! times out is killed, and a new one is spawned on the next run.
+
+use crate as cincinnati;
+
+use self::cincinnati::plugins::external::plugin_error_to_graph_error;
+use self::cincinnati::plugins::interface;
+use self::cincinnati::plugins::prelude_plugin_impl::*;
+use self::cincinnati::plugins::{ExternalIO, ExternalPlugin, ExternalPluginWrapper};
+
+use commons::GraphError;
+use protobuf::Message;
+use std::convert::TryFrom;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::Mutex;
+
+/// Default timeout of a plugin run, in seconds.
+pub static DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Default limit of the size of the output of a plugin run, in bytes.
+pub static DEFAULT_MAX_OUTPUT_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Frame tag of a `PluginExchange`.
+const TAG_EXCHANGE: u8 = 0;
+/// Frame tag of a `PluginError`.
+const TAG_ERROR: u8 = 1;
+
+/// How the executable is run.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SubprocessMode {
+    /// A process per run.
+    Oneshot,
+    /// A long-lived process for all runs.
+    Worker,
+}
+
+/// Plugin settings.
+#[derive(Clone, CustomDebug, Deserialize, Serialize, SmartDefault)]
+#[serde(default)]
+pub struct SubprocessSettings {
+    /// Path of the executable.
+    command: String,
+
+    /// Arguments passed to the executable.
+    args: Vec<String>,
+
+    #[default(SubprocessMode::Oneshot)]
+    mode: SubprocessMode,
+
+    /// Timeout of a plugin run, in seconds.
+    #[default(DEFAULT_TIMEOUT_SECS)]
+    timeout: u64,
+
+    /// Limit of the size of the output of a plugin run, in bytes.
+    #[default(DEFAULT_MAX_OUTPUT_BYTES)]
+    max_output_bytes: u64,
+
+    /// Limit of the address space of the process, in bytes.
+    max_memory_bytes: Option<u64>,
+}
+
+impl SubprocessSettings {
+    /// Validate plugin configuration and fill in defaults.
+    pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+        let settings: Self = commons::de::from_toml_value(cfg)?;
+
+        ensure!(!settings.command.is_empty(), "empty command");
+        ensure!(settings.timeout > 0, "timeout must be positive");
+        ensure!(
+            settings.max_output_bytes > 0,
+            "max_output_bytes must be positive"
+        );
+        ensure!(
+            cfg!(unix) || settings.max_memory_bytes.is_none(),
+            "max_memory_bytes is only supported on unix"
+        );
+
+        Ok(Box::new(settings))
+    }
+}
+
+impl PluginSettings for SubprocessSettings {
+    fn build_plugin(&self, _: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
+        let cfg = self.clone();
+        let plugin = SubprocessPlugin {
+            command: cfg.command,
+            args: cfg.args,
+            mode: cfg.mode,
+            timeout: Duration::from_secs(cfg.timeout),
+            max_output_bytes: cfg.max_output_bytes,
+            max_memory_bytes: cfg.max_memory_bytes,
+            worker: Default::default(),
+        };
+
+        Ok(new_plugin!(ExternalPluginWrapper(plugin)))
+    }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(SubprocessPlugin::PLUGIN_NAME, self)
+    }
+}
+
+/// Long-lived process handling the runs in `worker` mode.
+struct Worker {
+    /// Kept for the process to be killed when the worker is dropped.
+    _child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+/// Plugin which runs an executable, talking protobuf over stdin and stdout.
+#[derive(CustomDebug)]
+pub struct SubprocessPlugin {
+    pub command: String,
+    pub args: Vec<String>,
+    pub mode: SubprocessMode,
+    pub timeout: Duration,
+    pub max_output_bytes: u64,
+    pub max_memory_bytes: Option<u64>,
+
+    #[debug(skip)]
+    worker: Mutex<Option<Worker>>,
+}
+
+impl SubprocessPlugin {
+    /// Plugin name, for configuration.
+    pub const PLUGIN_NAME: &'static str = "subprocess";
+
+    /// Spawns the executable with piped stdin and stdout.
+    fn spawn(&self) -> Fallible<Child> {
+        let mut command = Command::new(&self.command);
+        command
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true);
+        if let Some(limit) = self.max_memory_bytes {
+            limit_memory(&mut command, limit);
+        }
+
+        Ok(command
+            .spawn()
+            .context(format!("spawning '{}'", self.command))?)
+    }
+
+    fn spawn_worker(&self) -> Fallible<Worker> {
+        let mut child = self.spawn()?;
+
+        Ok(Worker {
+            stdin: child
+                .stdin
+                .take()
+                .ok_or_else(|| format_err!("missing stdin"))?,
+            stdout: child
+                .stdout
+                .take()
+                .ok_or_else(|| format_err!("missing stdout"))?,
+            _child: child,
+        })
+    }
+
+    async fn run_oneshot(&self, input: &[u8]) -> Fallible<ExternalIO> {
+        let mut child = self.spawn()?;
+        let mut stdin = child
+            .stdin
+            .take()
+            .ok_or_else(|| format_err!("missing stdin"))?;
+        let stdout = child
+            .stdout
+            .take()
+            .ok_or_else(|| format_err!("missing stdout"))?;
+
+        let write = async move {
+            stdin.write_all(input).await
+            // stdin is dropped here, which closes it.
+        };
+        let (written, output) = tokio::join!(write, read_limited(stdout, self.max_output_bytes));
+        let output = output?;
+        let status = child.wait().await?;
+
+        if !status.success() {
+            return Err(self.plugin_error(&output, &format!("exited with {}", status)));
+        }
+        written.context("writing to the process")?;
+
+        Ok(ExternalIO { bytes: output })
+    }
+
+    async fn run_worker(&self, input: &[u8]) -> Fallible<ExternalIO> {
+        tokio::time::timeout(self.timeout, self.exchange_with_worker(input))
+            .await
+            .map_err(|_| self.timed_out())?
+    }
+
+    /// Sends `input` to the worker, spawning one if needed.
+    ///
+    /// The worker is taken out of its slot for the duration of the exchange
+    /// and only put back once a response was read, so a failed or cancelled
+    /// exchange drops (and thereby kills) it.
+    async fn exchange_with_worker(&self, input: &[u8]) -> Fallible<ExternalIO> {
+        let mut guard = self.worker.lock().await;
+        let mut worker = match guard.take() {
+            Some(worker) => worker,
+            None => self.spawn_worker()?,
+        };
+
+        match exchange_frames(&mut worker, input, self.max_output_bytes).await {
+            Ok((TAG_EXCHANGE, bytes)) => {
+                *guard = Some(worker);
+                Ok(ExternalIO { bytes })
+            }
+            Ok((_, bytes)) => {
+                *guard = Some(worker);
+                Err(self.plugin_error(&bytes, "returned an invalid error"))
+            }
+            Err(e) => {
+                warn!("killing worker of '{}': {}", self.command, e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Decodes the `PluginError` written by the process.
+    fn plugin_error(&self, bytes: &[u8], fallback: &str) -> Error {
+        match interface::PluginError::parse_from_bytes(bytes) {
+            Ok(error) if !bytes.is_empty() => plugin_error_to_graph_error(error).into(),
+            _ => GraphError::FailedPluginExecution(format!(
+                "subprocess plugin '{}' {}",
+                self.command, fallback
+            ))
+            .into(),
+        }
+    }
+
+    fn timed_out(&self) -> Error {
+        GraphError::FailedPluginExecution(format!(
+            "subprocess plugin '{}' timed out after {:?}",
+            self.command, self.timeout
+        ))
+        .into()
+    }
+}
+
+#[async_trait]
+impl ExternalPlugin for SubprocessPlugin {
+    const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+
+    async fn run_external(&self, io: ExternalIO) -> Fallible<ExternalIO> {
+        match self.mode {
+            SubprocessMode::Oneshot => {
+                tokio::time::timeout(self.timeout, self.run_oneshot(&io.bytes))
+                    .await
+                    .map_err(|_| self.timed_out())?
+            }
+            SubprocessMode::Worker => self.run_worker(&io.bytes).await,
+        }
+    }
+}
+
+/// Reads until EOF, failing if there are more than `limit` bytes.
+async fn read_limited<R: AsyncRead + Unpin>(reader: R, limit: u64) -> Fallible<Vec<u8>> {
+    let mut output = Vec::new();
+    reader
+        .take(limit.saturating_add(1))
+        .read_to_end(&mut output)
+        .await
+        .context("reading from the process")?;
+    ensure!(
+        output.len() as u64 <= limit,
+        "process output exceeds {} bytes",
+        limit
+    );
+
+    Ok(output)
+}
+
+/// Sends the input to the worker and returns the tag and message of its response.
+async fn exchange_frames(worker: &mut Worker, input: &[u8], limit: u64) -> Fallible<(u8, Vec<u8>)> {
+    let mut frame = Vec::with_capacity(5 + input.len());
+    frame.push(TAG_EXCHANGE);
+    frame.extend_from_slice(&u32::try_from(input.len())?.to_be_bytes());
+    frame.extend_from_slice(input);
+
+    let stdin = &mut worker.stdin;
+    let write = async move {
+        stdin.write_all(&frame).await?;
+        stdin.flush().await
+    };
+    let (written, response) = tokio::join!(write, read_frame(&mut worker.stdout, limit));
+    written.context("writing to the worker")?;
+
+    response
+}
+
+/// Reads a frame, failing if its message has more than `limit` bytes.
+async fn read_frame<R: AsyncRead + Unpin>(reader: &mut R, limit: u64) -> Fallible<(u8, Vec<u8>)> {
+    let tag = reader.read_u8().await.context("reading from the worker")?;
+    ensure!(
+        tag == TAG_EXCHANGE || tag == TAG_ERROR,
+        "invalid frame tag {}",
+        tag
+    );
+    let len = u64::from(reader.read_u32().await?);
+    ensure!(len <= limit, "worker output exceeds {} bytes", limit);
+
+    let mut message = vec![0; len as usize];
+    reader.read_exact(&mut message).await?;
+
+    Ok((tag, message))
+}
+
+/// Limits the address space of the process to `limit` bytes.
+#[cfg(unix)]
+fn limit_memory(command: &mut Command, limit: u64) {
+    let rlimit = libc::rlimit {
+        rlim_cur: limit as libc::rlim_t,
+        rlim_max: limit as libc::rlim_t,
+    };
+    // Safety: the closure only calls `setrlimit`, which is async-signal-safe.
+    unsafe {
+        command.pre_exec(move || {
+            if libc::setrlimit(libc::RLIMIT_AS, &rlimit) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(unix))]
+fn limit_memory(_: &mut Command, _: u64) {}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use cincinnati::plugins::{InternalIO, Plugin, PluginIO};
+    use cincinnati::testing::generate_graph;
+    use commons::testing::init_runtime;
+    use std::convert::TryInto;
+
+    fn subprocess_plugin(cfg: &str) -> Fallible<BoxedPlugin> {
+        let cfg = format!("name = 'subprocess'\n{}", cfg);
+        cincinnati::plugins::catalog::deserialize_config(toml::from_str(&cfg)?)?.build_plugin(None)
+    }
+
+    fn input() -> InternalIO {
+        InternalIO {
+            graph: generate_graph(true, false).into(),
+            parameters: [("channel".to_string(), "a".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn run(
+        runtime: &tokio::runtime::Runtime,
+        plugin: &BoxedPlugin,
+        input: InternalIO,
+    ) -> Fallible<InternalIO> {
+        runtime
+            .block_on(plugin.run(PluginIO::InternalIO(input)))?
+            .try_into()
+    }
+
+    fn failure_message(result: Fallible<InternalIO>) -> String {
+        match result.unwrap_err().downcast::<GraphError>() {
+            Ok(GraphError::FailedPluginExecution(msg)) => msg,
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn oneshot_passthrough() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let plugin = subprocess_plugin("command = 'cat'")?;
+
+        assert_eq!(plugin.get_name(), "subprocess");
+        assert_eq!(run(&runtime, &plugin, input())?, input());
+
+        Ok(())
+    }
+
+    #[test]
+    fn oneshot_plugin_error() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let tmp = tempfile::tempdir()?;
+        let error_path = tmp.path().join("error.pb");
+        let mut error = interface::PluginError::new();
+        error.set_kind(interface::PluginError_Kind::INVALID_PARAM);
+        error.set_value("missing arch".to_string());
+        std::fs::write(&error_path, error.write_to_bytes()?)?;
+
+        let plugin = subprocess_plugin(&format!(
+            "command = 'sh'\nargs = ['-c', 'cat > /dev/null; cat \"$0\"; exit 3', '{}']",
+            error_path.display()
+        ))?;
+
+        let err = run(&runtime, &plugin, input()).unwrap_err();
+        assert_eq!(
+            err.downcast::<GraphError>()?,
+            GraphError::InvalidParams("missing arch".to_string())
+        );
+
+        let plugin = subprocess_plugin("command = 'false'")?;
+        assert!(failure_message(run(&runtime, &plugin, input())).contains("exited with"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn oneshot_limits() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let plugin = subprocess_plugin("command = 'sleep'\nargs = ['10']\ntimeout = 1")?;
+        assert!(failure_message(run(&runtime, &plugin, input())).contains("timed out"));
+
+        let plugin = subprocess_plugin(
+            "command = 'sh'\nargs = ['-c', 'cat > /dev/null; head -c 2048 /dev/zero']\nmax_output_bytes = 1024",
+        )?;
+        let err = run(&runtime, &plugin, input()).unwrap_err();
+        assert!(err.to_string().contains("exceeds 1024 bytes"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn worker_passthrough() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        // `cat` echoes every request frame, which is a valid response frame.
+        let plugin = subprocess_plugin("command = 'cat'\nmode = 'worker'")?;
+
+        for _ in 0..3 {
+            assert_eq!(run(&runtime, &plugin, input())?, input());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn worker_respawned_after_failure() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let tmp = tempfile::tempdir()?;
+        let marker = tmp.path().join("started");
+
+        // Dies on the first start, echoes afterwards.
+        let plugin = subprocess_plugin(&format!(
+            "command = 'sh'\nargs = ['-c', 'if [ -e \"$0\" ]; then exec cat; fi; touch \"$0\"', '{}']\nmode = 'worker'",
+            marker.display()
+        ))?;
+
+        assert!(run(&runtime, &plugin, input()).is_err());
+        assert_eq!(run(&runtime, &plugin, input())?, input());
+
+        Ok(())
+    }
+
+    #[test]
+    fn worker_timeout_includes_waiting_for_the_worker() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let plugin =
+            subprocess_plugin("command = 'sleep'\nargs = ['10']\nmode = 'worker'\ntimeout = 1")?;
+
+        let started = std::time::Instant::now();
+        let (first, second) = runtime.block_on(async {
+            tokio::join!(
+                plugin.run(PluginIO::InternalIO(input())),
+                plugin.run(PluginIO::InternalIO(input())),
+            )
+        });
+
+        for result in vec![first, second] {
+            assert!(failure_message(result.and_then(|io| io.try_into())).contains("timed out"));
+        }
+        assert!(started.elapsed() < Duration::from_secs(2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_config() -> Fallible<()> {
+        for cfg in &[
+            "",
+            "command = 'cat'\nmode = 'daemon'",
+            "command = 'cat'\ntimeout = 0",
+        ] {
+            assert!(subprocess_plugin(cfg).is_err(), "{}", cfg);
+        }
+
+        Ok(())
+    }
+}
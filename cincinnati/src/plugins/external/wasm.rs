@@ -0,0 +1,450 @@
+//! Plugin which runs a WebAssembly module to process the graph.
+//!
+//! The module is compiled once, and instantiated afresh in a sandbox for every
+//! run, without any imports. It must export:
+//!
+//!  * `memory`, its linear memory;
+//!  * `alloc(len: i32) -> i32`, returning the address of `len` bytes in which
+//!    the host writes the protobuf `PluginExchange`;
+//!  * `run(ptr: i32, len: i32) -> i64`, processing the exchange at `ptr` and
+//!    returning the address of its result in the high 32 bits and the length
+//!    in the low 32 bits.
+//!
+//! The result is a tag byte, `0` for a `PluginExchange` and `1` for a
+//! `PluginError`, followed by the message. Runs are limited in fuel, which is
+//! roughly the number of executed instructions, and in memory.
+
+use crate as cincinnati;
+
+use self::cincinnati::plugins::external::plugin_error_to_graph_error;
+use self::cincinnati::plugins::interface;
+use self::cincinnati::plugins::prelude_plugin_impl::*;
+use self::cincinnati::plugins::{ExternalIO, ExternalPlugin, ExternalPluginWrapper};
+
+use commons::GraphError;
+use lazy_static::lazy_static;
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts};
+use protobuf::Message;
+use std::convert::TryFrom;
+use std::sync::Arc;
+use wasmtime::{Config, Engine, Linker, Module, Store, StoreLimitsBuilder, Trap};
+
+/// Default fuel of a plugin run.
+pub static DEFAULT_FUEL: u64 = 10_000_000_000;
+
+/// Default limit of the linear memory of a module instance, in bytes.
+pub static DEFAULT_MAX_MEMORY_BYTES: usize = 256 * 1024 * 1024;
+
+/// Result tag of a `PluginExchange`.
+const TAG_EXCHANGE: u8 = 0;
+/// Result tag of a `PluginError`.
+const TAG_ERROR: u8 = 1;
+
+lazy_static! {
+    static ref WASM_PLUGIN_RUNS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "wasm_plugin_runs_total",
+            "Total number of WebAssembly plugin runs, by module and result"
+        ),
+        &["module", "result"]
+    )
+    .unwrap();
+    static ref WASM_PLUGIN_FUEL: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "wasm_plugin_fuel_consumed",
+            "Fuel consumed by WebAssembly plugin runs"
+        )
+        .buckets(prometheus::exponential_buckets(1e4, 10.0, 8).unwrap()),
+        &["module"]
+    )
+    .unwrap();
+    static ref WASM_PLUGIN_MEMORY: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "wasm_plugin_memory_bytes",
+            "Linear memory size of WebAssembly plugin instances at the end of runs"
+        )
+        .buckets(prometheus::exponential_buckets(65536.0, 4.0, 8).unwrap()),
+        &["module"]
+    )
+    .unwrap();
+}
+
+/// Plugin settings.
+#[derive(Clone, CustomDebug, Deserialize, Serialize, SmartDefault)]
+#[serde(default)]
+pub struct WasmPluginSettings {
+    /// Path of the `.wasm` module, or of its text format.
+    module_path: PathBuf,
+
+    /// Name of the module in metrics, defaulting to the file stem of its path.
+    module_name: Option<String>,
+
+    /// Fuel of a plugin run.
+    #[default(DEFAULT_FUEL)]
+    fuel: u64,
+
+    /// Limit of the linear memory of the module, in bytes.
+    #[default(DEFAULT_MAX_MEMORY_BYTES)]
+    max_memory_bytes: usize,
+}
+
+impl WasmPluginSettings {
+    /// Validate plugin configuration and fill in defaults.
+    pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+        let settings: Self = commons::de::from_toml_value(cfg)?;
+
+        ensure!(
+            !settings.module_path.as_os_str().is_empty(),
+            "empty module_path"
+        );
+        ensure!(settings.fuel > 0, "fuel must be positive");
+
+        Ok(Box::new(settings))
+    }
+}
+
+impl PluginSettings for WasmPluginSettings {
+    fn build_plugin(&self, registry: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
+        if let Some(registry) = registry {
+            cincinnati::plugins::register_shared_collectors(
+                registry,
+                vec![
+                    Box::new(WASM_PLUGIN_RUNS.clone()),
+                    Box::new(WASM_PLUGIN_FUEL.clone()),
+                    Box::new(WASM_PLUGIN_MEMORY.clone()),
+                ],
+            )?;
+        }
+
+        let module_name = match &self.module_name {
+            Some(name) => name.clone(),
+            None => self
+                .module_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+        };
+        let plugin = WasmPlugin::try_new(
+            &self.module_path,
+            module_name,
+            self.fuel,
+            self.max_memory_bytes,
+        )?;
+
+        Ok(new_plugin!(ExternalPluginWrapper(plugin)))
+    }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(WasmPlugin::PLUGIN_NAME, self)
+    }
+}
+
+/// Plugin which runs a sandboxed WebAssembly module.
+#[derive(CustomDebug)]
+pub struct WasmPlugin {
+    pub module_name: String,
+    pub fuel: u64,
+    pub max_memory_bytes: usize,
+
+    #[debug(skip)]
+    module: Arc<Module>,
+}
+
+/// Result of a run of the module.
+struct RunOutput {
+    result: Fallible<Vec<u8>>,
+    fuel_consumed: u64,
+    memory_bytes: usize,
+}
+
+impl WasmPlugin {
+    /// Plugin name, for configuration.
+    pub const PLUGIN_NAME: &'static str = "wasm-plugin";
+
+    /// Compiles the module at the given path.
+    pub fn try_new(
+        module_path: &std::path::Path,
+        module_name: String,
+        fuel: u64,
+        max_memory_bytes: usize,
+    ) -> Fallible<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, module_path)
+            .context(format!("compiling WebAssembly module {:?}", module_path))?;
+
+        Ok(Self {
+            module_name,
+            fuel,
+            max_memory_bytes,
+            module: Arc::new(module),
+        })
+    }
+
+    /// Instantiates the module and runs it on the input, blocking until it returns.
+    fn run_module(module: &Module, fuel: u64, max_memory_bytes: usize, input: &[u8]) -> RunOutput {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(max_memory_bytes)
+            .instances(1)
+            .build();
+        let mut store = Store::new(module.engine(), limits);
+        store.limiter(|limits| limits);
+
+        let mut memory_bytes = 0;
+        let result = (|| -> Fallible<Vec<u8>> {
+            store.add_fuel(fuel)?;
+            let instance = Linker::new(module.engine()).instantiate(&mut store, module)?;
+            let memory = instance
+                .get_memory(&mut store, "memory")
+                .ok_or_else(|| format_err!("module does not export 'memory'"))?;
+            let alloc = instance.get_typed_func::<u32, u32>(&mut store, "alloc")?;
+            let run = instance.get_typed_func::<(u32, u32), u64>(&mut store, "run")?;
+
+            let len = u32::try_from(input.len())?;
+            let ptr = alloc.call(&mut store, len)?;
+            memory.write(&mut store, ptr as usize, input)?;
+            let packed = run.call(&mut store, (ptr, len));
+            memory_bytes = memory.data_size(&store);
+            let packed = packed?;
+
+            let start = (packed >> 32) as usize;
+            let end = start + (packed & 0xffff_ffff) as usize;
+            memory
+                .data(&store)
+                .get(start..end)
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| format_err!("result out of bounds of the module memory"))
+        })();
+
+        RunOutput {
+            result,
+            fuel_consumed: store.fuel_consumed().unwrap_or_default(),
+            memory_bytes,
+        }
+    }
+
+    /// Decodes the tagged result of a run, returning the label of its metric alongside.
+    fn decode_result(&self, result: Fallible<Vec<u8>>) -> (&'static str, Fallible<ExternalIO>) {
+        let mut bytes = match result {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                let label = match e.downcast_ref::<Trap>() {
+                    Some(Trap::OutOfFuel) => "out_of_fuel",
+                    Some(_) => "trap",
+                    None => "failure",
+                };
+                return (
+                    label,
+                    Err(GraphError::FailedPluginExecution(format!(
+                        "wasm plugin '{}' failed: {:#}",
+                        self.module_name, e
+                    ))
+                    .into()),
+                );
+            }
+        };
+
+        match bytes.first().copied() {
+            Some(TAG_EXCHANGE) => {
+                bytes.remove(0);
+                ("ok", Ok(ExternalIO { bytes }))
+            }
+            Some(TAG_ERROR) => match interface::PluginError::parse_from_bytes(&bytes[1..]) {
+                Ok(error) => (
+                    "plugin_error",
+                    Err(plugin_error_to_graph_error(error).into()),
+                ),
+                Err(e) => (
+                    "failure",
+                    Err(GraphError::FailedPluginExecution(format!(
+                        "wasm plugin '{}' returned an invalid error: {}",
+                        self.module_name, e
+                    ))
+                    .into()),
+                ),
+            },
+            tag => (
+                "failure",
+                Err(GraphError::FailedPluginExecution(format!(
+                    "wasm plugin '{}' returned an invalid result tag {:?}",
+                    self.module_name, tag
+                ))
+                .into()),
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl ExternalPlugin for WasmPlugin {
+    const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+
+    async fn run_external(&self, io: ExternalIO) -> Fallible<ExternalIO> {
+        let module = self.module.clone();
+        let (fuel, max_memory_bytes) = (self.fuel, self.max_memory_bytes);
+        let output = tokio::task::spawn_blocking(move || {
+            Self::run_module(&module, fuel, max_memory_bytes, &io.bytes)
+        })
+        .await?;
+
+        let (label, result) = self.decode_result(output.result);
+        WASM_PLUGIN_RUNS
+            .with_label_values(&[&self.module_name, label])
+            .inc();
+        WASM_PLUGIN_FUEL
+            .with_label_values(&[&self.module_name])
+            .observe(output.fuel_consumed as f64);
+        WASM_PLUGIN_MEMORY
+            .with_label_values(&[&self.module_name])
+            .observe(output.memory_bytes as f64);
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cincinnati::plugins::{InternalIO, Plugin, PluginIO};
+    use cincinnati::testing::generate_graph;
+    use commons::testing::init_runtime;
+    use std::convert::TryInto;
+
+    /// Returns its input, prefixed with the exchange tag.
+    static PASSTHROUGH_WAT: &str = r#"
+        (module
+          (memory (export "memory") 2)
+          (func (export "alloc") (param $len i32) (result i32)
+            (i32.const 1025))
+          (func (export "run") (param $ptr i32) (param $len i32) (result i64)
+            (i32.store8 (i32.sub (local.get $ptr) (i32.const 1)) (i32.const 0))
+            (i64.or
+              (i64.shl
+                (i64.extend_i32_u (i32.sub (local.get $ptr) (i32.const 1)))
+                (i64.const 32))
+              (i64.extend_i32_u (i32.add (local.get $len) (i32.const 1))))))
+    "#;
+
+    /// Returns an INVALID_PARAM error with the value "bad".
+    static ERROR_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (data (i32.const 0) "\01\08\02\12\03bad")
+          (func (export "alloc") (param $len i32) (result i32)
+            (i32.const 1024))
+          (func (export "run") (param $ptr i32) (param $len i32) (result i64)
+            (i64.const 8)))
+    "#;
+
+    /// Never returns.
+    static LOOP_WAT: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (func (export "alloc") (param $len i32) (result i32)
+            (i32.const 0))
+          (func (export "run") (param $ptr i32) (param $len i32) (result i64)
+            (loop $forever (br $forever))
+            (i64.const 0)))
+    "#;
+
+    /// Needs 4 MiB of memory.
+    static LARGE_MEMORY_WAT: &str = r#"
+        (module
+          (memory (export "memory") 64)
+          (func (export "alloc") (param $len i32) (result i32)
+            (i32.const 0))
+          (func (export "run") (param $ptr i32) (param $len i32) (result i64)
+            (i64.const 0)))
+    "#;
+
+    fn wasm_plugin(
+        dir: &tempfile::TempDir,
+        name: &str,
+        wat: &str,
+        extra_cfg: &str,
+    ) -> Fallible<BoxedPlugin> {
+        let path = dir.path().join(format!("{}.wat", name));
+        std::fs::write(&path, wat)?;
+        let cfg = format!(
+            "name = 'wasm-plugin'\nmodule_path = '{}'\n{}",
+            path.display(),
+            extra_cfg
+        );
+        cincinnati::plugins::catalog::deserialize_config(toml::from_str(&cfg)?)?.build_plugin(None)
+    }
+
+    fn input() -> InternalIO {
+        InternalIO {
+            graph: generate_graph(true, false).into(),
+            parameters: [("channel".to_string(), "a".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+        }
+    }
+
+    fn run(
+        runtime: &tokio::runtime::Runtime,
+        plugin: &BoxedPlugin,
+        input: InternalIO,
+    ) -> Fallible<InternalIO> {
+        runtime
+            .block_on(plugin.run(PluginIO::InternalIO(input)))?
+            .try_into()
+    }
+
+    fn runs(module: &str, result: &str) -> u64 {
+        WASM_PLUGIN_RUNS.with_label_values(&[module, result]).get()
+    }
+
+    #[test]
+    fn wasm_passthrough() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let tmp = tempfile::tempdir()?;
+        let plugin = wasm_plugin(&tmp, "passthrough", PASSTHROUGH_WAT, "")?;
+
+        assert_eq!(plugin.get_name(), "wasm-plugin");
+        assert_eq!(run(&runtime, &plugin, input())?, input());
+        assert_eq!(runs("passthrough", "ok"), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_plugin_error() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let tmp = tempfile::tempdir()?;
+        let plugin = wasm_plugin(&tmp, "error", ERROR_WAT, "")?;
+
+        let err = run(&runtime, &plugin, input()).unwrap_err();
+        assert_eq!(
+            err.downcast::<GraphError>()?,
+            GraphError::InvalidParams("bad".to_string())
+        );
+        assert_eq!(runs("error", "plugin_error"), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn wasm_limits() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let tmp = tempfile::tempdir()?;
+
+        let plugin = wasm_plugin(&tmp, "loop", LOOP_WAT, "fuel = 100000")?;
+        assert!(run(&runtime, &plugin, input()).is_err());
+        assert_eq!(runs("loop", "out_of_fuel"), 1);
+
+        let plugin = wasm_plugin(
+            &tmp,
+            "large",
+            LARGE_MEMORY_WAT,
+            "max_memory_bytes = 1048576\nmodule_name = 'large-memory'",
+        )?;
+        assert!(run(&runtime, &plugin, input()).is_err());
+        assert_eq!(runs("large-memory", "failure"), 1);
+
+        Ok(())
+    }
+}
@@ -1,16 +1,256 @@
 //! The web module can be used to talk to webservice which expose an endpoint
 //! according to the protobuf scheme
+//!
+//! The `web-plugin` POSTs the `PluginExchange` to the configured URL and
+//! expects a `PluginExchange` back. A service reports failures with a non-2xx
+//! status and a `PluginError` body, which is mapped to a `GraphError`.
+//! Unreachable services and other server errors are retried until the timeout.
 
-use std::path::PathBuf;
+use crate as cincinnati;
+
+use self::cincinnati::plugins::external::plugin_error_to_graph_error;
+use self::cincinnati::plugins::interface;
+use self::cincinnati::plugins::prelude_plugin_impl::*;
+use self::cincinnati::plugins::{ExternalIO, ExternalPlugin, ExternalPluginWrapper};
+use self::cincinnati::PROTOBUF_CONTENT_TYPE;
+
+use commons::tracing::{get_tracer, set_context};
+use commons::GraphError;
+use opentelemetry::{
+    trace::{get_active_span, mark_span_as_active, Tracer},
+    Context as ot_context, Key,
+};
+use protobuf::Message;
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_TYPE};
+use reqwest::StatusCode;
+use std::time::{Duration, Instant};
 use url::Url;
 
+/// Default timeout of a plugin run, including retries, in seconds.
+pub static DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+/// Delay before the first retry.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Maximum delay between retries.
+const MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Plugin settings.
+#[derive(Clone, CustomDebug, Deserialize, Serialize, SmartDefault)]
+#[serde(default)]
+pub struct WebPluginSettings {
+    /// URL of the plugin endpoint.
+    url: String,
+
+    /// Timeout of a plugin run, including retries, in seconds.
+    #[default(DEFAULT_TIMEOUT_SECS)]
+    timeout: u64,
+
+    /// PEM file with the CA certificates to trust in addition to the system ones.
+    ca_cert_path: Option<PathBuf>,
+
+    /// PEM file with the client certificate and private key, for mutual TLS.
+    client_cert_path: Option<PathBuf>,
+}
+
+impl WebPluginSettings {
+    /// Validate plugin configuration and fill in defaults.
+    pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+        let settings: Self = commons::de::from_toml_value(cfg)?;
+
+        ensure!(!settings.url.is_empty(), "empty url");
+        ensure!(settings.timeout > 0, "timeout must be positive");
+
+        Ok(Box::new(settings))
+    }
+}
+
+impl PluginSettings for WebPluginSettings {
+    fn build_plugin(&self, _: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
+        let plugin = WebPluginClient::try_new(
+            Url::parse(&self.url).context(format!("parsing url '{}'", self.url))?,
+            Duration::from_secs(self.timeout),
+            self.ca_cert_path.clone(),
+            self.client_cert_path.clone(),
+        )?;
+
+        Ok(new_plugin!(ExternalPluginWrapper(plugin)))
+    }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(WebPluginClient::PLUGIN_NAME, self)
+    }
+}
+
 /// Struct for implementing the client side of a web plugin
-#[derive(Debug)]
-pub struct _WebPluginClient {
+#[derive(CustomDebug)]
+pub struct WebPluginClient {
     pub url: Url,
     pub timeout: std::time::Duration,
     pub ca_cert_path: Option<PathBuf>,
     pub client_cert_path: Option<PathBuf>,
+
+    #[debug(skip)]
+    client: reqwest::Client,
+}
+
+/// Outcome of a single request to the plugin service.
+enum Attempt {
+    /// The service answered, successfully or with a plugin error.
+    Done(Fallible<ExternalIO>),
+    /// The request failed for a reason which may be transient.
+    Retry(String),
+}
+
+impl WebPluginClient {
+    /// Plugin name, for configuration.
+    pub const PLUGIN_NAME: &'static str = "web-plugin";
+
+    /// Creates a client for the plugin service at the given URL.
+    pub fn try_new(
+        url: Url,
+        timeout: Duration,
+        ca_cert_path: Option<PathBuf>,
+        client_cert_path: Option<PathBuf>,
+    ) -> Fallible<Self> {
+        ensure!(
+            matches!(url.scheme(), "https" | "http"),
+            "unsupported url scheme '{}'",
+            url.scheme()
+        );
+
+        let mut builder = reqwest::ClientBuilder::new().use_rustls_tls();
+        if let Some(path) = &ca_cert_path {
+            let pem = std::fs::read(path).context(format!("reading CA certificate {:?}", path))?;
+            builder = builder.add_root_certificate(
+                reqwest::Certificate::from_pem(&pem)
+                    .context(format!("parsing CA certificate {:?}", path))?,
+            );
+        }
+        if let Some(path) = &client_cert_path {
+            let pem =
+                std::fs::read(path).context(format!("reading client certificate {:?}", path))?;
+            builder = builder.identity(
+                reqwest::Identity::from_pem(&pem)
+                    .context(format!("parsing client certificate {:?}", path))?,
+            );
+        }
+        let client = builder.build().context("Building reqwest client")?;
+
+        Ok(Self {
+            url,
+            timeout,
+            ca_cert_path,
+            client_cert_path,
+            client,
+        })
+    }
+
+    /// Sends the exchange once, waiting at most `timeout` for the response.
+    async fn attempt(&self, body: bytes::Bytes, timeout: Duration) -> Attempt {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(PROTOBUF_CONTENT_TYPE));
+        headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(PROTOBUF_CONTENT_TYPE),
+        );
+        {
+            let span = get_tracer().start("");
+            let _active_span = mark_span_as_active(span);
+            if let Err(e) = set_context(ot_context::current(), &mut headers) {
+                warn!("failed to set the tracing context: {}", e);
+            }
+        }
+
+        let res = match self
+            .client
+            .post(self.url.clone())
+            .headers(headers)
+            .body(body)
+            .timeout(timeout)
+            .send()
+            .await
+        {
+            Ok(res) => res,
+            Err(e) => return Attempt::Retry(e.to_string()),
+        };
+
+        let status = res.status();
+        let is_protobuf = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .map_or(false, |content_type| content_type == PROTOBUF_CONTENT_TYPE);
+        let bytes = match res.bytes().await {
+            Ok(bytes) => bytes,
+            Err(e) => return Attempt::Retry(e.to_string()),
+        };
+
+        if status.is_success() {
+            return Attempt::Done(Ok(ExternalIO {
+                bytes: bytes.to_vec(),
+            }));
+        }
+        if is_protobuf {
+            return Attempt::Done(match interface::PluginError::parse_from_bytes(&bytes) {
+                Ok(error) => Err(plugin_error_to_graph_error(error).into()),
+                Err(e) => Err(GraphError::FailedPluginExecution(format!(
+                    "failed to decode the error of web plugin at {}: {}",
+                    self.url, e
+                ))
+                .into()),
+            });
+        }
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Attempt::Retry(status.to_string());
+        }
+
+        Attempt::Done(Err(GraphError::FailedPluginExecution(format!(
+            "web plugin at {} responded with {}",
+            self.url, status
+        ))
+        .into()))
+    }
+}
+
+#[async_trait]
+impl ExternalPlugin for WebPluginClient {
+    const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+
+    async fn run_external(&self, io: ExternalIO) -> Fallible<ExternalIO> {
+        let body = bytes::Bytes::from(io.bytes);
+        let deadline = Instant::now() + self.timeout;
+        let mut backoff = INITIAL_BACKOFF;
+        let mut attempts: i64 = 0;
+
+        let result = loop {
+            attempts += 1;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let reason = match self.attempt(body.clone(), remaining).await {
+                Attempt::Done(result) => break result,
+                Attempt::Retry(reason) => reason,
+            };
+
+            if Instant::now() + backoff >= deadline {
+                break Err(GraphError::FailedPluginExecution(format!(
+                    "web plugin at {} failed after {} attempts: {}",
+                    self.url, attempts, reason
+                ))
+                .into());
+            }
+            debug!(
+                "retrying web plugin at {} in {:?}: {}",
+                self.url, backoff, reason
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        };
+
+        get_active_span(|span| {
+            span.set_attribute(Key::new("attempts").i64(attempts));
+        });
+
+        result
+    }
 }
 
 #[cfg(test)]
@@ -18,7 +258,9 @@ mod tests {
     use crate as cincinnati;
 
     use async_trait::async_trait;
-    use cincinnati::plugins::{interface, ExternalIO, ExternalPlugin, InternalIO, PluginResult};
+    use cincinnati::plugins::{
+        interface, ExternalIO, ExternalPlugin, InternalIO, Plugin, PluginResult,
+    };
     use cincinnati::testing::generate_graph;
     use commons::prelude_errors::*;
     use commons::testing::init_runtime;
@@ -54,7 +296,10 @@ mod tests {
         let runtime = init_runtime().unwrap();
 
         fn callback(mut input: interface::PluginExchange) -> PluginResult {
-            let graph: cincinnati::Graph = input.take_graph().into();
+            let graph: cincinnati::Graph = input
+                .take_graph()
+                .try_into()
+                .expect("the passed graph to be valid");
 
             trace!(
                 "[external passthrough plugin] got graph with {} nodes",
@@ -73,7 +318,7 @@ mod tests {
         });
 
         let input_internal = InternalIO {
-            graph: generate_graph(false, false),
+            graph: generate_graph(false, false).into(),
             parameters: [("hello".to_string(), "plugin".to_string())]
                 .iter()
                 .cloned()
@@ -107,7 +352,7 @@ mod tests {
         });
 
         let input_internal = InternalIO {
-            graph: generate_graph(false, false),
+            graph: generate_graph(false, false).into(),
             parameters: [("hello".to_string(), "plugin".to_string())]
                 .iter()
                 .cloned()
@@ -123,4 +368,252 @@ mod tests {
 
         assert_eq!(expected_result, output_result);
     }
+
+    fn web_plugin(path: &str, timeout: u64) -> Fallible<cincinnati::plugins::BoxedPlugin> {
+        let cfg = format!(
+            "name = 'web-plugin'\nurl = '{}{}'\ntimeout = {}",
+            mockito::server_url(),
+            path,
+            timeout
+        );
+        cincinnati::plugins::catalog::deserialize_config(toml::from_str(&cfg)?)?.build_plugin(None)
+    }
+
+    fn empty_io() -> InternalIO {
+        InternalIO {
+            graph: Default::default(),
+            parameters: Default::default(),
+        }
+    }
+
+    fn run_web_plugin(
+        plugin: &cincinnati::plugins::BoxedPlugin,
+        input: InternalIO,
+    ) -> Fallible<InternalIO> {
+        let runtime = init_runtime()?;
+        let output =
+            runtime.block_on(plugin.run(cincinnati::plugins::PluginIO::InternalIO(input)))?;
+        output.try_into()
+    }
+
+    #[test]
+    fn web_plugin_exchange() -> Fallible<()> {
+        use protobuf::Message;
+
+        let input = InternalIO {
+            graph: generate_graph(false, false).into(),
+            parameters: [("hello".to_string(), "plugin".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+        };
+        let expected = InternalIO {
+            graph: generate_graph(true, false).into(),
+            parameters: input.parameters.clone(),
+        };
+        let response_body = interface::PluginExchange::from(expected.clone()).write_to_bytes()?;
+
+        let _m = mockito::mock("POST", "/exchange")
+            .match_header("content-type", cincinnati::PROTOBUF_CONTENT_TYPE)
+            .with_status(200)
+            .with_header("content-type", cincinnati::PROTOBUF_CONTENT_TYPE)
+            .with_body(response_body)
+            .create();
+
+        let plugin = web_plugin("/exchange", 5)?;
+        assert_eq!(plugin.get_name(), "web-plugin");
+        assert_eq!(run_web_plugin(&plugin, input)?, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn web_plugin_maps_plugin_errors() -> Fallible<()> {
+        use protobuf::Message;
+
+        let mut error = interface::PluginError::new();
+        error.set_kind(interface::PluginError_Kind::INVALID_PARAM);
+        error.set_value("unknown channel".to_string());
+
+        let m = mockito::mock("POST", "/error")
+            .with_status(400)
+            .with_header("content-type", cincinnati::PROTOBUF_CONTENT_TYPE)
+            .with_body(error.write_to_bytes()?)
+            .expect(1)
+            .create();
+
+        let plugin = web_plugin("/error", 5)?;
+        let err = run_web_plugin(&plugin, empty_io()).unwrap_err();
+        assert_eq!(
+            err.downcast::<commons::GraphError>()?,
+            commons::GraphError::InvalidParams("unknown channel".to_string())
+        );
+        m.assert();
+
+        Ok(())
+    }
+
+    #[test]
+    fn web_plugin_retries_until_timeout() -> Fallible<()> {
+        let m = mockito::mock("POST", "/unavailable")
+            .with_status(503)
+            .expect_at_least(2)
+            .create();
+
+        let plugin = web_plugin("/unavailable", 1)?;
+        let err = run_web_plugin(&plugin, empty_io()).unwrap_err();
+        match err.downcast::<commons::GraphError>()? {
+            commons::GraphError::FailedPluginExecution(msg) => {
+                assert!(msg.contains("503"), "unexpected message: {}", msg)
+            }
+            other => panic!("unexpected error: {}", other),
+        }
+        m.assert();
+
+        Ok(())
+    }
+
+    fn tls_fixture(name: &str) -> std::path::PathBuf {
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/tls")
+            .join(name)
+    }
+
+    /// Starts a server on a local port which requires a client certificate
+    /// signed by the test CA and answers every request with its own body.
+    fn spawn_mtls_echo_server() -> Fallible<u16> {
+        use std::sync::Arc;
+
+        let mut roots = rustls::RootCertStore::empty();
+        let ca_pem = std::fs::read(tls_fixture("ca.pem"))?;
+        for cert in rustls_pemfile::certs(&mut ca_pem.as_slice())? {
+            roots.add(&rustls::Certificate(cert))?;
+        }
+
+        let server_pem = std::fs::read(tls_fixture("server.pem"))?;
+        let certs = rustls_pemfile::certs(&mut server_pem.as_slice())?
+            .into_iter()
+            .map(rustls::Certificate)
+            .collect();
+        let key = rustls_pemfile::pkcs8_private_keys(&mut server_pem.as_slice())?
+            .pop()
+            .ok_or_else(|| format_err!("no private key in server.pem"))?;
+
+        let config = Arc::new(
+            rustls::ServerConfig::builder()
+                .with_safe_defaults()
+                .with_client_cert_verifier(Arc::new(
+                    rustls::server::AllowAnyAuthenticatedClient::new(roots),
+                ))
+                .with_single_cert(certs, rustls::PrivateKey(key))?,
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let port = listener.local_addr()?.port();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if let Err(e) = serve_echo(config.clone(), stream) {
+                    trace!("[mtls echo server] connection failed: {}", e);
+                }
+            }
+        });
+
+        Ok(port)
+    }
+
+    fn serve_echo(
+        config: std::sync::Arc<rustls::ServerConfig>,
+        stream: std::net::TcpStream,
+    ) -> Fallible<()> {
+        use std::io::{Read, Write};
+
+        let mut tls = rustls::StreamOwned::new(rustls::ServerConnection::new(config)?, stream);
+        let mut request = Vec::new();
+        let mut chunk = [0u8; 4096];
+        let mut read_more = |request: &mut Vec<u8>| -> Fallible<()> {
+            let n = tls.read(&mut chunk)?;
+            ensure!(n > 0, "connection closed before the end of the request");
+            request.extend_from_slice(&chunk[..n]);
+            Ok(())
+        };
+
+        let body_start = loop {
+            if let Some(pos) = request.windows(4).position(|w| w == b"\r\n\r\n") {
+                break pos + 4;
+            }
+            read_more(&mut request)?;
+        };
+        let content_length: usize = String::from_utf8_lossy(&request[..body_start])
+            .to_lowercase()
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:").map(str::trim))
+            .unwrap_or("0")
+            .parse()?;
+        while request.len() < body_start + content_length {
+            read_more(&mut request)?;
+        }
+        let body = &request[body_start..body_start + content_length];
+
+        write!(
+            tls,
+            "HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+            cincinnati::PROTOBUF_CONTENT_TYPE,
+            body.len()
+        )?;
+        tls.write_all(body)?;
+        tls.conn.send_close_notify();
+        tls.flush()?;
+
+        Ok(())
+    }
+
+    fn mtls_web_plugin(port: u16, client_cert: bool) -> Fallible<cincinnati::plugins::BoxedPlugin> {
+        let mut cfg = format!(
+            "name = 'web-plugin'\nurl = 'https://localhost:{}/exchange'\ntimeout = 2\nca_cert_path = '{}'",
+            port,
+            tls_fixture("ca.pem").display()
+        );
+        if client_cert {
+            cfg.push_str(&format!(
+                "\nclient_cert_path = '{}'",
+                tls_fixture("client.pem").display()
+            ));
+        }
+        cincinnati::plugins::catalog::deserialize_config(toml::from_str(&cfg)?)?.build_plugin(None)
+    }
+
+    #[test]
+    fn web_plugin_mutual_tls() -> Fallible<()> {
+        let port = spawn_mtls_echo_server()?;
+        let input = InternalIO {
+            graph: generate_graph(false, false).into(),
+            parameters: [("hello".to_string(), "plugin".to_string())]
+                .iter()
+                .cloned()
+                .collect(),
+        };
+
+        let plugin = mtls_web_plugin(port, true)?;
+        assert_eq!(run_web_plugin(&plugin, input.clone())?, input);
+
+        let plugin = mtls_web_plugin(port, false)?;
+        assert!(run_web_plugin(&plugin, input).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn web_plugin_invalid_config() -> Fallible<()> {
+        for cfg in &[
+            "name = 'web-plugin'",
+            "name = 'web-plugin'\nurl = 'ftp://example.com/plugin'",
+            "name = 'web-plugin'\nurl = 'https://example.com/plugin'\nca_cert_path = '/nonexistent'",
+        ] {
+            let result = cincinnati::plugins::catalog::deserialize_config(toml::from_str(cfg)?)
+                .and_then(|settings| settings.build_plugin(None));
+            assert!(result.is_err(), "{}", cfg);
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,584 @@
+//! Failure policy of plugins.
+//!
+//! A plugin entry in the configuration may carry an `on_error` policy, which
+//! decides what happens when the plugin fails:
+//!
+//! ```toml
+//! [[plugin_settings]]
+//! name = "github-secondary-metadata-scrape"
+//! on_error = { policy = "reuse", max_staleness_secs = 3600 }
+//! ```
+//!
+//! * `fail` (the default) fails the whole plugin chain.
+//! * `skip` passes the input of the plugin through unchanged.
+//! * `reuse` returns the graph of the last successful run for the same values of
+//!   the parameters the plugin depends on, along with its parameters, the input
+//!   parameters taking precedence.
+//!
+//! The short form `on_error = "skip"` has no staleness limit. Otherwise, once the
+//! last success (or the start, if there was none) is older than `max_staleness_secs`,
+//! the plugin fails again as with `fail`, which takes no staleness limit.
+//! Invalid or missing request parameters always fail the plugin chain.
+//!
+//! Outputs produced by a failure policy carry the [`DEGRADED_PARAMETER`], so that
+//! they are not cached.
+
+use crate as cincinnati;
+
+use self::cincinnati::plugins::catalog::{self, PluginSettings};
+use self::cincinnati::plugins::{
+    condition, register_shared_collectors, BoxedPlugin, InternalIO, Plugin, PluginIO,
+};
+
+use async_trait::async_trait;
+use commons::prelude_errors::*;
+use commons::GraphError;
+use lazy_static::lazy_static;
+use opentelemetry::{trace::get_active_span, Key};
+use prometheus::{IntCounterVec, IntGaugeVec, Opts};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+lazy_static! {
+    static ref PLUGIN_DEGRADED: IntGaugeVec = IntGaugeVec::new(
+        Opts::new(
+            "plugin_degraded",
+            "Whether the last run of a plugin failed and its failure policy was applied"
+        ),
+        &["plugin"]
+    )
+    .unwrap();
+    static ref PLUGIN_FALLBACKS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "plugin_fallbacks_total",
+            "Total number of failed plugin runs handled by the failure policy"
+        ),
+        &["plugin", "policy"]
+    )
+    .unwrap();
+}
+
+/// Parameter set on outputs produced by a failure policy, listing the failed plugins.
+pub static DEGRADED_PARAMETER: &str = "degraded";
+
+/// Maximum number of outputs kept for the `reuse` policy, one per parameter set.
+const MAX_REUSED_OUTPUTS: usize = 32;
+
+/// Returns whether a failure policy replaced the output of a plugin.
+pub fn is_degraded(parameters: &HashMap<String, String>) -> bool {
+    parameters.contains_key(DEGRADED_PARAMETER)
+}
+
+/// What to do when a plugin fails.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// Fail the plugin chain.
+    Fail,
+    /// Pass the input through unchanged.
+    Skip,
+    /// Return the output of the last successful run.
+    Reuse,
+}
+
+impl fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailurePolicy::Fail => write!(f, "fail"),
+            FailurePolicy::Skip => write!(f, "skip"),
+            FailurePolicy::Reuse => write!(f, "reuse"),
+        }
+    }
+}
+
+/// Raw `on_error` value, as found in the configuration.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum OnErrorConfig {
+    Policy(FailurePolicy),
+    Table(OnErrorTable),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct OnErrorTable {
+    policy: FailurePolicy,
+    max_staleness_secs: Option<u64>,
+}
+
+/// Failure policy of a plugin, with its staleness limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OnError {
+    pub policy: FailurePolicy,
+    pub max_staleness: Option<Duration>,
+}
+
+impl OnError {
+    /// Validate an `on_error` value.
+    pub fn deserialize_config(cfg: toml::Value) -> Fallible<Self> {
+        let config: OnErrorConfig = cfg
+            .try_into()
+            .context("expected a policy ('fail', 'skip' or 'reuse') or a table")?;
+
+        Ok(match config {
+            OnErrorConfig::Policy(policy) => Self {
+                policy,
+                max_staleness: None,
+            },
+            OnErrorConfig::Table(table) => {
+                ensure!(
+                    table.policy != FailurePolicy::Fail || table.max_staleness_secs.is_none(),
+                    "'max_staleness_secs' does not apply to the '{}' policy",
+                    FailurePolicy::Fail
+                );
+                Self {
+                    policy: table.policy,
+                    max_staleness: table.max_staleness_secs.map(Duration::from_secs),
+                }
+            }
+        })
+    }
+
+    /// Serialize the policy as an `on_error` value, in its short form if it has
+    /// no staleness limit.
+    pub fn serialize_config(&self) -> Fallible<toml::Value> {
+        match self.max_staleness {
+            None => Ok(toml::Value::try_from(self.policy)?),
+            Some(max_staleness) => Ok(toml::Value::try_from(OnErrorTable {
+                policy: self.policy,
+                max_staleness_secs: Some(max_staleness.as_secs()),
+            })?),
+        }
+    }
+}
+
+/// Settings of a plugin whose failures are handled by a policy.
+#[derive(Debug)]
+pub struct FailurePolicyPluginSettings {
+    pub on_error: OnError,
+    pub settings: Box<dyn PluginSettings>,
+}
+
+impl PluginSettings for FailurePolicyPluginSettings {
+    fn build_plugin(&self, registry: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
+        if let Some(registry) = registry {
+            register_shared_collectors(
+                registry,
+                vec![
+                    Box::new(PLUGIN_DEGRADED.clone()),
+                    Box::new(PLUGIN_FALLBACKS.clone()),
+                ],
+            )?;
+        }
+
+        Ok(Box::new(FailurePolicyPlugin::new(
+            self.on_error,
+            self.settings.build_plugin(registry)?,
+        )))
+    }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        let mut cfg = self.settings.serialize_config()?;
+        catalog::insert_config_key(
+            &mut cfg,
+            catalog::CONFIG_PLUGIN_ON_ERROR_KEY,
+            self.on_error.serialize_config()?,
+        )?;
+
+        Ok(cfg)
+    }
+}
+
+/// Values of the parameters an output depends on, sorted by name.
+type ParameterValues = Vec<(String, Option<String>)>;
+
+/// Last successful runs of a plugin.
+#[derive(Debug)]
+struct LastSuccess {
+    /// When the plugin last succeeded, or was built if it never did.
+    at: Instant,
+    /// The outputs of the last runs per parameter values, with the time of the run,
+    /// only kept for the `reuse` policy.
+    outputs: HashMap<ParameterValues, (Instant, InternalIO)>,
+}
+
+/// Plugin which applies the failure policy to the failures of the wrapped plugin.
+#[derive(Debug)]
+pub struct FailurePolicyPlugin {
+    on_error: OnError,
+    plugin: BoxedPlugin,
+    last_success: Mutex<LastSuccess>,
+}
+
+impl FailurePolicyPlugin {
+    /// Wraps the plugin with the failure policy.
+    pub fn new(on_error: OnError, plugin: BoxedPlugin) -> Self {
+        Self {
+            on_error,
+            plugin,
+            last_success: Mutex::new(LastSuccess {
+                at: Instant::now(),
+                outputs: HashMap::new(),
+            }),
+        }
+    }
+
+    /// Returns the values of the parameters the output of the plugin depends on,
+    /// all of them if it does not declare them.
+    fn parameter_values(&self, parameters: &HashMap<String, String>) -> ParameterValues {
+        let mut values: ParameterValues = match self.plugin.parameters() {
+            Some(names) => names
+                .into_iter()
+                .map(|name| {
+                    let value = parameters.get(&name).cloned();
+                    (name, value)
+                })
+                .collect(),
+            None => parameters
+                .iter()
+                .filter(|(name, _)| name.as_str() != DEGRADED_PARAMETER)
+                .map(|(name, value)| (name.clone(), Some(value.clone())))
+                .collect(),
+        };
+        values.sort();
+        values.dedup();
+        values
+    }
+
+    /// Records a successful run.
+    fn record_success(&self, input: &InternalIO, output: &InternalIO) {
+        let mut last_success = match self.last_success.lock() {
+            Ok(last_success) => last_success,
+            Err(_) => return,
+        };
+
+        let now = Instant::now();
+        last_success.at = now;
+        if self.on_error.policy != FailurePolicy::Reuse {
+            return;
+        }
+
+        let key = self.parameter_values(&input.parameters);
+        if !last_success.outputs.contains_key(&key)
+            && last_success.outputs.len() >= MAX_REUSED_OUTPUTS
+        {
+            let oldest = last_success
+                .outputs
+                .iter()
+                .min_by_key(|(_, (at, _))| *at)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                last_success.outputs.remove(&oldest);
+            }
+        }
+        last_success.outputs.insert(key, (now, output.clone()));
+    }
+
+    /// Returns the output replacing the one of the failed run, or the error
+    /// if the policy does not apply.
+    fn fallback(&self, input: InternalIO, error: Error) -> Fallible<InternalIO> {
+        // Falling back would hide invalid requests from the client.
+        if let Some(GraphError::InvalidParams(_)) | Some(GraphError::MissingParams(_)) =
+            error.downcast_ref::<GraphError>()
+        {
+            return Err(error);
+        }
+
+        let last_success = self
+            .last_success
+            .lock()
+            .map_err(|_| format_err!("poisoned failure policy state"))?;
+
+        if let Some(max_staleness) = self.on_error.max_staleness {
+            let staleness = last_success.at.elapsed();
+            if staleness > max_staleness {
+                return Err(error.context(format!(
+                    "last success {}s ago exceeds the staleness limit of {}s",
+                    staleness.as_secs(),
+                    max_staleness.as_secs()
+                )));
+            }
+        }
+
+        match self.on_error.policy {
+            FailurePolicy::Fail => Err(error),
+            FailurePolicy::Skip => Ok(input),
+            FailurePolicy::Reuse => match last_success
+                .outputs
+                .get(&self.parameter_values(&input.parameters))
+            {
+                Some((_, output)) => {
+                    let mut parameters = output.parameters.clone();
+                    parameters.extend(input.parameters);
+                    Ok(InternalIO {
+                        graph: output.graph.clone(),
+                        parameters,
+                    })
+                }
+                None => Err(error
+                    .context("no successful run with the same parameters to reuse the output of")),
+            },
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin<PluginIO> for FailurePolicyPlugin {
+    async fn run(&self, plugin_io: PluginIO) -> Fallible<PluginIO> {
+        let name = self.get_name();
+        let input: InternalIO = plugin_io.try_into()?;
+
+        let result: Fallible<InternalIO> = match self.plugin.run(input.clone().into()).await {
+            Ok(output) => output.try_into(),
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(output) => {
+                self.record_success(&input, &output);
+                PLUGIN_DEGRADED.with_label_values(&[name]).set(0);
+                Ok(output.into())
+            }
+            Err(e) => match self.fallback(input, e) {
+                Ok(mut output) => {
+                    let degraded = output
+                        .parameters
+                        .entry(DEGRADED_PARAMETER.to_string())
+                        .or_default();
+                    if !degraded.is_empty() {
+                        degraded.push(',');
+                    }
+                    degraded.push_str(name);
+
+                    log::warn!(
+                        "plugin '{}' failed, applying failure policy '{}'",
+                        name,
+                        self.on_error.policy
+                    );
+                    PLUGIN_DEGRADED.with_label_values(&[name]).set(1);
+                    PLUGIN_FALLBACKS
+                        .with_label_values(&[name, &self.on_error.policy.to_string()])
+                        .inc();
+                    get_active_span(|span| {
+                        span.set_attribute(
+                            Key::new("on_error").string(self.on_error.policy.to_string()),
+                        );
+                    });
+                    Ok(output.into())
+                }
+                Err(e) => {
+                    PLUGIN_DEGRADED.with_label_values(&[name]).set(0);
+                    Err(e)
+                }
+            },
+        }
+    }
+
+    fn get_name(&self) -> &'static str {
+        self.plugin.get_name()
+    }
+
+    fn condition(&self) -> Option<&condition::PluginCondition> {
+        self.plugin.condition()
+    }
+
+    fn parameters(&self) -> Option<Vec<String>> {
+        self.plugin.parameters()
+    }
+
+    fn channels_key(&self) -> Option<String> {
+        self.plugin.channels_key()
+    }
+
+    fn nested_plugins(&self) -> Vec<&BoxedPlugin> {
+        self.plugin.nested_plugins()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugins::catalog::deserialize_config;
+    use crate::plugins::{InternalPlugin, InternalPluginWrapper};
+    use std::sync::Arc;
+
+    /// Fails if the `fail` parameter is set, rejects an `invalid` parameter,
+    /// and records the run and its `variant` parameter otherwise.
+    #[derive(Debug)]
+    struct FlakyPlugin;
+
+    #[async_trait]
+    impl InternalPlugin for FlakyPlugin {
+        const PLUGIN_NAME: &'static str = "flaky";
+        const PARAMETERS: Option<&'static [&'static str]> = Some(&["variant"]);
+
+        async fn run_internal(&self, mut io: InternalIO) -> Fallible<InternalIO> {
+            if io.parameters.contains_key("invalid") {
+                return Err(GraphError::InvalidParams("invalid".to_string()).into());
+            }
+            ensure!(!io.parameters.contains_key("fail"), "flaky failure");
+            let variant = io.parameters.get("variant").cloned().unwrap_or_default();
+            io.parameters.insert("run".to_string(), variant);
+            io.graph = Arc::new(crate::testing::generate_graph(false, false));
+            Ok(io)
+        }
+    }
+
+    fn build_plugin(cfg: &str) -> Fallible<BoxedPlugin> {
+        let on_error =
+            OnError::deserialize_config(toml::from_str::<toml::Table>(cfg)?["on_error"].clone())?;
+        Ok(Box::new(FailurePolicyPlugin::new(
+            on_error,
+            Box::new(InternalPluginWrapper(FlakyPlugin)),
+        )))
+    }
+
+    fn io_with(parameters: &[(&str, &str)]) -> PluginIO {
+        PluginIO::InternalIO(InternalIO {
+            graph: Default::default(),
+            parameters: parameters
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        })
+    }
+
+    fn io(fail: bool) -> PluginIO {
+        if fail {
+            io_with(&[("fail", "true")])
+        } else {
+            io_with(&[])
+        }
+    }
+
+    #[test]
+    fn deserialize_on_error() -> Fallible<()> {
+        for (cfg, expected) in &[
+            ("on_error = 'skip'", Some((FailurePolicy::Skip, None))),
+            (
+                "on_error = { policy = 'reuse', max_staleness_secs = 60 }",
+                Some((FailurePolicy::Reuse, Some(Duration::from_secs(60)))),
+            ),
+            (
+                "on_error = { policy = 'fail' }",
+                Some((FailurePolicy::Fail, None)),
+            ),
+            (
+                "on_error = { policy = 'fail', max_staleness_secs = 60 }",
+                None,
+            ),
+            ("on_error = 'retry'", None),
+            ("on_error = { policy = 'skip', unknown = 1 }", None),
+        ] {
+            let value = toml::from_str::<toml::Table>(cfg)?["on_error"].clone();
+            let result = OnError::deserialize_config(value).ok();
+            let expected = expected.map(|(policy, max_staleness)| OnError {
+                policy,
+                max_staleness,
+            });
+            assert_eq!(result, expected, "{}", cfg);
+        }
+
+        let cfg = r#"
+            name = "node-remove"
+            on_error = "skip"
+            when = { parameter = "remove", present = true }
+        "#;
+        let plugin = deserialize_config(toml::from_str(cfg)?)?.build_plugin(None)?;
+        assert_eq!(plugin.get_name(), "node-remove");
+        assert!(plugin.condition().is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn skip_passes_input_through() -> Fallible<()> {
+        let runtime = commons::testing::init_runtime()?;
+        let plugin = build_plugin("on_error = 'skip'")?;
+
+        let mut input: InternalIO = io(true).try_into()?;
+        let output: InternalIO = runtime.block_on(plugin.run(io(true)))?.try_into()?;
+        assert!(is_degraded(&output.parameters));
+        input
+            .parameters
+            .insert(DEGRADED_PARAMETER.to_string(), "flaky".to_string());
+        assert_eq!(output, input);
+
+        Ok(())
+    }
+
+    #[test]
+    fn never_fall_back_on_invalid_parameters() -> Fallible<()> {
+        let runtime = commons::testing::init_runtime()?;
+
+        for cfg in &["on_error = 'skip'", "on_error = 'reuse'"] {
+            let plugin = build_plugin(cfg)?;
+            runtime.block_on(plugin.run(io(false)))?;
+
+            let err = runtime
+                .block_on(plugin.run(io_with(&[("invalid", "true")])))
+                .unwrap_err();
+            assert!(
+                matches!(
+                    err.downcast_ref::<GraphError>(),
+                    Some(GraphError::InvalidParams(_))
+                ),
+                "{}: {:#}",
+                cfg,
+                err
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn reuse_last_output() -> Fallible<()> {
+        let runtime = commons::testing::init_runtime()?;
+        let plugin = build_plugin("on_error = 'reuse'")?;
+
+        // Nothing to reuse yet.
+        assert!(runtime.block_on(plugin.run(io(true))).is_err());
+
+        let expected: InternalIO = runtime
+            .block_on(plugin.run(io_with(&[("variant", "a")])))?
+            .try_into()?;
+        let output: InternalIO = runtime
+            .block_on(plugin.run(io_with(&[("variant", "a"), ("fail", "true")])))?
+            .try_into()?;
+        assert_eq!(output.graph, expected.graph);
+        assert_eq!(output.parameters.get("run").map(String::as_str), Some("a"));
+        assert_eq!(
+            output.parameters.get("fail").map(String::as_str),
+            Some("true")
+        );
+        assert!(is_degraded(&output.parameters));
+
+        // The output for other parameter values is never reused.
+        assert!(runtime
+            .block_on(plugin.run(io_with(&[("variant", "b"), ("fail", "true")])))
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn escalate_when_stale() -> Fallible<()> {
+        let runtime = commons::testing::init_runtime()?;
+        let plugin = build_plugin("on_error = { policy = 'skip', max_staleness_secs = 0 }")?;
+
+        std::thread::sleep(Duration::from_millis(10));
+        let err = runtime.block_on(plugin.run(io(true))).unwrap_err();
+        assert!(
+            err.to_string().contains("exceeds the staleness limit"),
+            "{:#}",
+            err
+        );
+
+        Ok(())
+    }
+}
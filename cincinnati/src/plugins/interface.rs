@@ -28,6 +28,7 @@ pub struct Graph {
     // message fields
     pub nodes: ::protobuf::RepeatedField<Graph_Node>,
     pub edges: ::protobuf::RepeatedField<Graph_Edge>,
+    pub conditional_edges: ::protobuf::RepeatedField<Graph_ConditionalEdge>,
     // special fields
     pub unknown_fields: ::protobuf::UnknownFields,
     pub cached_size: ::protobuf::CachedSize,
@@ -93,6 +94,31 @@ impl Graph {
     pub fn take_edges(&mut self) -> ::protobuf::RepeatedField<Graph_Edge> {
         ::std::mem::replace(&mut self.edges, ::protobuf::RepeatedField::new())
     }
+
+    // repeated .Graph.ConditionalEdge conditional_edges = 3;
+
+
+    pub fn get_conditional_edges(&self) -> &[Graph_ConditionalEdge] {
+        &self.conditional_edges
+    }
+    pub fn clear_conditional_edges(&mut self) {
+        self.conditional_edges.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_conditional_edges(&mut self, v: ::protobuf::RepeatedField<Graph_ConditionalEdge>) {
+        self.conditional_edges = v;
+    }
+
+    // Mutable pointer to the field.
+    pub fn mut_conditional_edges(&mut self) -> &mut ::protobuf::RepeatedField<Graph_ConditionalEdge> {
+        &mut self.conditional_edges
+    }
+
+    // Take field
+    pub fn take_conditional_edges(&mut self) -> ::protobuf::RepeatedField<Graph_ConditionalEdge> {
+        ::std::mem::replace(&mut self.conditional_edges, ::protobuf::RepeatedField::new())
+    }
 }
 
 impl ::protobuf::Message for Graph {
@@ -107,6 +133,11 @@ impl ::protobuf::Message for Graph {
                 return false;
             }
         };
+        for v in &self.conditional_edges {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
         true
     }
 
@@ -120,6 +151,9 @@ impl ::protobuf::Message for Graph {
                 2 => {
                     ::protobuf::rt::read_repeated_message_into(wire_type, is, &mut self.edges)?;
                 },
+                3 => {
+                    ::protobuf::rt::read_repeated_message_into(wire_type, is, &mut self.conditional_edges)?;
+                },
                 _ => {
                     ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
                 },
@@ -140,6 +174,10 @@ impl ::protobuf::Message for Graph {
             let len = value.compute_size();
             my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
         };
+        for value in &self.conditional_edges {
+            let len = value.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        };
         my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
         self.cached_size.set(my_size);
         my_size
@@ -156,6 +194,11 @@ impl ::protobuf::Message for Graph {
             os.write_raw_varint32(v.get_cached_size())?;
             v.write_to_with_cached_sizes(os)?;
         };
+        for v in &self.conditional_edges {
+            os.write_tag(3, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        };
         os.write_unknown_fields(self.get_unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -204,6 +247,11 @@ impl ::protobuf::Message for Graph {
                 |m: &Graph| { &m.edges },
                 |m: &mut Graph| { &mut m.edges },
             ));
+            fields.push(::protobuf::reflect::accessor::make_repeated_field_accessor::<_, ::protobuf::types::ProtobufTypeMessage<Graph_ConditionalEdge>>(
+                "conditional_edges",
+                |m: &Graph| { &m.conditional_edges },
+                |m: &mut Graph| { &mut m.conditional_edges },
+            ));
             ::protobuf::reflect::MessageDescriptor::new_pb_name::<Graph>(
                 "Graph",
                 fields,
@@ -222,6 +270,7 @@ impl ::protobuf::Clear for Graph {
     fn clear(&mut self) {
         self.nodes.clear();
         self.edges.clear();
+        self.conditional_edges.clear();
         self.unknown_fields.clear();
     }
 }
@@ -663,6 +712,714 @@ impl ::protobuf::reflect::ProtobufValue for Graph_Edge {
     }
 }
 
+#[derive(PartialEq,Clone,Default)]
+pub struct Graph_ClusterCondition {
+    // message fields
+    pub field_type: ::std::string::String,
+    pub promql: ::std::string::String,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a Graph_ClusterCondition {
+    fn default() -> &'a Graph_ClusterCondition {
+        <Graph_ClusterCondition as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Graph_ClusterCondition {
+    pub fn new() -> Graph_ClusterCondition {
+        ::std::default::Default::default()
+    }
+
+    // string type = 1;
+
+
+    pub fn get_field_type(&self) -> &str {
+        &self.field_type
+    }
+    pub fn clear_field_type(&mut self) {
+        self.field_type.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_field_type(&mut self, v: ::std::string::String) {
+        self.field_type = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_field_type(&mut self) -> &mut ::std::string::String {
+        &mut self.field_type
+    }
+
+    // Take field
+    pub fn take_field_type(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.field_type, ::std::string::String::new())
+    }
+
+    // string promql = 2;
+
+
+    pub fn get_promql(&self) -> &str {
+        &self.promql
+    }
+    pub fn clear_promql(&mut self) {
+        self.promql.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_promql(&mut self, v: ::std::string::String) {
+        self.promql = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_promql(&mut self) -> &mut ::std::string::String {
+        &mut self.promql
+    }
+
+    // Take field
+    pub fn take_promql(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.promql, ::std::string::String::new())
+    }
+}
+
+impl ::protobuf::Message for Graph_ClusterCondition {
+    fn is_initialized(&self) -> bool {
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.field_type)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.promql)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.field_type.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.field_type);
+        }
+        if !self.promql.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.promql);
+        }
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.field_type.is_empty() {
+            os.write_string(1, &self.field_type)?;
+        }
+        if !self.promql.is_empty() {
+            os.write_string(2, &self.promql)?;
+        }
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> Graph_ClusterCondition {
+        Graph_ClusterCondition::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "type",
+                |m: &Graph_ClusterCondition| { &m.field_type },
+                |m: &mut Graph_ClusterCondition| { &mut m.field_type },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "promql",
+                |m: &Graph_ClusterCondition| { &m.promql },
+                |m: &mut Graph_ClusterCondition| { &mut m.promql },
+            ));
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<Graph_ClusterCondition>(
+                "Graph.ClusterCondition",
+                fields,
+                file_descriptor_proto()
+            )
+        })
+    }
+
+    fn default_instance() -> &'static Graph_ClusterCondition {
+        static instance: ::protobuf::rt::LazyV2<Graph_ClusterCondition> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Graph_ClusterCondition::new)
+    }
+}
+
+impl ::protobuf::Clear for Graph_ClusterCondition {
+    fn clear(&mut self) {
+        self.field_type.clear();
+        self.promql.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for Graph_ClusterCondition {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Graph_ClusterCondition {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default)]
+pub struct Graph_Risk {
+    // message fields
+    pub url: ::std::string::String,
+    pub name: ::std::string::String,
+    pub message: ::std::string::String,
+    pub matching_rules: ::protobuf::RepeatedField<Graph_ClusterCondition>,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a Graph_Risk {
+    fn default() -> &'a Graph_Risk {
+        <Graph_Risk as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Graph_Risk {
+    pub fn new() -> Graph_Risk {
+        ::std::default::Default::default()
+    }
+
+    // string url = 1;
+
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+    pub fn clear_url(&mut self) {
+        self.url.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_url(&mut self, v: ::std::string::String) {
+        self.url = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_url(&mut self) -> &mut ::std::string::String {
+        &mut self.url
+    }
+
+    // Take field
+    pub fn take_url(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.url, ::std::string::String::new())
+    }
+
+    // string name = 2;
+
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+    pub fn clear_name(&mut self) {
+        self.name.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_name(&mut self, v: ::std::string::String) {
+        self.name = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_name(&mut self) -> &mut ::std::string::String {
+        &mut self.name
+    }
+
+    // Take field
+    pub fn take_name(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.name, ::std::string::String::new())
+    }
+
+    // string message = 3;
+
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+    pub fn clear_message(&mut self) {
+        self.message.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_message(&mut self, v: ::std::string::String) {
+        self.message = v;
+    }
+
+    // Mutable pointer to the field.
+    // If field is not initialized, it is initialized with default value first.
+    pub fn mut_message(&mut self) -> &mut ::std::string::String {
+        &mut self.message
+    }
+
+    // Take field
+    pub fn take_message(&mut self) -> ::std::string::String {
+        ::std::mem::replace(&mut self.message, ::std::string::String::new())
+    }
+
+    // repeated .Graph.ClusterCondition matching_rules = 4;
+
+
+    pub fn get_matching_rules(&self) -> &[Graph_ClusterCondition] {
+        &self.matching_rules
+    }
+    pub fn clear_matching_rules(&mut self) {
+        self.matching_rules.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_matching_rules(&mut self, v: ::protobuf::RepeatedField<Graph_ClusterCondition>) {
+        self.matching_rules = v;
+    }
+
+    // Mutable pointer to the field.
+    pub fn mut_matching_rules(&mut self) -> &mut ::protobuf::RepeatedField<Graph_ClusterCondition> {
+        &mut self.matching_rules
+    }
+
+    // Take field
+    pub fn take_matching_rules(&mut self) -> ::protobuf::RepeatedField<Graph_ClusterCondition> {
+        ::std::mem::replace(&mut self.matching_rules, ::protobuf::RepeatedField::new())
+    }
+}
+
+impl ::protobuf::Message for Graph_Risk {
+    fn is_initialized(&self) -> bool {
+        for v in &self.matching_rules {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.url)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.name)?;
+                },
+                3 => {
+                    ::protobuf::rt::read_singular_proto3_string_into(wire_type, is, &mut self.message)?;
+                },
+                4 => {
+                    ::protobuf::rt::read_repeated_message_into(wire_type, is, &mut self.matching_rules)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        if !self.url.is_empty() {
+            my_size += ::protobuf::rt::string_size(1, &self.url);
+        }
+        if !self.name.is_empty() {
+            my_size += ::protobuf::rt::string_size(2, &self.name);
+        }
+        if !self.message.is_empty() {
+            my_size += ::protobuf::rt::string_size(3, &self.message);
+        }
+        for value in &self.matching_rules {
+            let len = value.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        };
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        if !self.url.is_empty() {
+            os.write_string(1, &self.url)?;
+        }
+        if !self.name.is_empty() {
+            os.write_string(2, &self.name)?;
+        }
+        if !self.message.is_empty() {
+            os.write_string(3, &self.message)?;
+        }
+        for v in &self.matching_rules {
+            os.write_tag(4, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        };
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> Graph_Risk {
+        Graph_Risk::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "url",
+                |m: &Graph_Risk| { &m.url },
+                |m: &mut Graph_Risk| { &mut m.url },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "name",
+                |m: &Graph_Risk| { &m.name },
+                |m: &mut Graph_Risk| { &mut m.name },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_simple_field_accessor::<_, ::protobuf::types::ProtobufTypeString>(
+                "message",
+                |m: &Graph_Risk| { &m.message },
+                |m: &mut Graph_Risk| { &mut m.message },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_repeated_field_accessor::<_, ::protobuf::types::ProtobufTypeMessage<Graph_ClusterCondition>>(
+                "matching_rules",
+                |m: &Graph_Risk| { &m.matching_rules },
+                |m: &mut Graph_Risk| { &mut m.matching_rules },
+            ));
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<Graph_Risk>(
+                "Graph.Risk",
+                fields,
+                file_descriptor_proto()
+            )
+        })
+    }
+
+    fn default_instance() -> &'static Graph_Risk {
+        static instance: ::protobuf::rt::LazyV2<Graph_Risk> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Graph_Risk::new)
+    }
+}
+
+impl ::protobuf::Clear for Graph_Risk {
+    fn clear(&mut self) {
+        self.url.clear();
+        self.name.clear();
+        self.message.clear();
+        self.matching_rules.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for Graph_Risk {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Graph_Risk {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
+#[derive(PartialEq,Clone,Default)]
+pub struct Graph_ConditionalEdge {
+    // message fields
+    pub edges: ::protobuf::RepeatedField<Graph_Edge>,
+    pub risks: ::protobuf::RepeatedField<Graph_Risk>,
+    // special fields
+    pub unknown_fields: ::protobuf::UnknownFields,
+    pub cached_size: ::protobuf::CachedSize,
+}
+
+impl<'a> ::std::default::Default for &'a Graph_ConditionalEdge {
+    fn default() -> &'a Graph_ConditionalEdge {
+        <Graph_ConditionalEdge as ::protobuf::Message>::default_instance()
+    }
+}
+
+impl Graph_ConditionalEdge {
+    pub fn new() -> Graph_ConditionalEdge {
+        ::std::default::Default::default()
+    }
+
+    // repeated .Graph.Edge edges = 1;
+
+
+    pub fn get_edges(&self) -> &[Graph_Edge] {
+        &self.edges
+    }
+    pub fn clear_edges(&mut self) {
+        self.edges.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_edges(&mut self, v: ::protobuf::RepeatedField<Graph_Edge>) {
+        self.edges = v;
+    }
+
+    // Mutable pointer to the field.
+    pub fn mut_edges(&mut self) -> &mut ::protobuf::RepeatedField<Graph_Edge> {
+        &mut self.edges
+    }
+
+    // Take field
+    pub fn take_edges(&mut self) -> ::protobuf::RepeatedField<Graph_Edge> {
+        ::std::mem::replace(&mut self.edges, ::protobuf::RepeatedField::new())
+    }
+
+    // repeated .Graph.Risk risks = 2;
+
+
+    pub fn get_risks(&self) -> &[Graph_Risk] {
+        &self.risks
+    }
+    pub fn clear_risks(&mut self) {
+        self.risks.clear();
+    }
+
+    // Param is passed by value, moved
+    pub fn set_risks(&mut self, v: ::protobuf::RepeatedField<Graph_Risk>) {
+        self.risks = v;
+    }
+
+    // Mutable pointer to the field.
+    pub fn mut_risks(&mut self) -> &mut ::protobuf::RepeatedField<Graph_Risk> {
+        &mut self.risks
+    }
+
+    // Take field
+    pub fn take_risks(&mut self) -> ::protobuf::RepeatedField<Graph_Risk> {
+        ::std::mem::replace(&mut self.risks, ::protobuf::RepeatedField::new())
+    }
+}
+
+impl ::protobuf::Message for Graph_ConditionalEdge {
+    fn is_initialized(&self) -> bool {
+        for v in &self.edges {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
+        for v in &self.risks {
+            if !v.is_initialized() {
+                return false;
+            }
+        };
+        true
+    }
+
+    fn merge_from(&mut self, is: &mut ::protobuf::CodedInputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        while !is.eof()? {
+            let (field_number, wire_type) = is.read_tag_unpack()?;
+            match field_number {
+                1 => {
+                    ::protobuf::rt::read_repeated_message_into(wire_type, is, &mut self.edges)?;
+                },
+                2 => {
+                    ::protobuf::rt::read_repeated_message_into(wire_type, is, &mut self.risks)?;
+                },
+                _ => {
+                    ::protobuf::rt::read_unknown_or_skip_group(field_number, wire_type, is, self.mut_unknown_fields())?;
+                },
+            };
+        }
+        ::std::result::Result::Ok(())
+    }
+
+    // Compute sizes of nested messages
+    #[allow(unused_variables)]
+    fn compute_size(&self) -> u32 {
+        let mut my_size = 0;
+        for value in &self.edges {
+            let len = value.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        };
+        for value in &self.risks {
+            let len = value.compute_size();
+            my_size += 1 + ::protobuf::rt::compute_raw_varint32_size(len) + len;
+        };
+        my_size += ::protobuf::rt::unknown_fields_size(self.get_unknown_fields());
+        self.cached_size.set(my_size);
+        my_size
+    }
+
+    fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::ProtobufResult<()> {
+        for v in &self.edges {
+            os.write_tag(1, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        };
+        for v in &self.risks {
+            os.write_tag(2, ::protobuf::wire_format::WireTypeLengthDelimited)?;
+            os.write_raw_varint32(v.get_cached_size())?;
+            v.write_to_with_cached_sizes(os)?;
+        };
+        os.write_unknown_fields(self.get_unknown_fields())?;
+        ::std::result::Result::Ok(())
+    }
+
+    fn get_cached_size(&self) -> u32 {
+        self.cached_size.get()
+    }
+
+    fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {
+        &self.unknown_fields
+    }
+
+    fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {
+        &mut self.unknown_fields
+    }
+
+    fn as_any(&self) -> &dyn (::std::any::Any) {
+        self as &dyn (::std::any::Any)
+    }
+    fn as_any_mut(&mut self) -> &mut dyn (::std::any::Any) {
+        self as &mut dyn (::std::any::Any)
+    }
+    fn into_any(self: ::std::boxed::Box<Self>) -> ::std::boxed::Box<dyn (::std::any::Any)> {
+        self
+    }
+
+    fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {
+        Self::descriptor_static()
+    }
+
+    fn new() -> Graph_ConditionalEdge {
+        Graph_ConditionalEdge::new()
+    }
+
+    fn descriptor_static() -> &'static ::protobuf::reflect::MessageDescriptor {
+        static descriptor: ::protobuf::rt::LazyV2<::protobuf::reflect::MessageDescriptor> = ::protobuf::rt::LazyV2::INIT;
+        descriptor.get(|| {
+            let mut fields = ::std::vec::Vec::new();
+            fields.push(::protobuf::reflect::accessor::make_repeated_field_accessor::<_, ::protobuf::types::ProtobufTypeMessage<Graph_Edge>>(
+                "edges",
+                |m: &Graph_ConditionalEdge| { &m.edges },
+                |m: &mut Graph_ConditionalEdge| { &mut m.edges },
+            ));
+            fields.push(::protobuf::reflect::accessor::make_repeated_field_accessor::<_, ::protobuf::types::ProtobufTypeMessage<Graph_Risk>>(
+                "risks",
+                |m: &Graph_ConditionalEdge| { &m.risks },
+                |m: &mut Graph_ConditionalEdge| { &mut m.risks },
+            ));
+            ::protobuf::reflect::MessageDescriptor::new_pb_name::<Graph_ConditionalEdge>(
+                "Graph.ConditionalEdge",
+                fields,
+                file_descriptor_proto()
+            )
+        })
+    }
+
+    fn default_instance() -> &'static Graph_ConditionalEdge {
+        static instance: ::protobuf::rt::LazyV2<Graph_ConditionalEdge> = ::protobuf::rt::LazyV2::INIT;
+        instance.get(Graph_ConditionalEdge::new)
+    }
+}
+
+impl ::protobuf::Clear for Graph_ConditionalEdge {
+    fn clear(&mut self) {
+        self.edges.clear();
+        self.risks.clear();
+        self.unknown_fields.clear();
+    }
+}
+
+impl ::std::fmt::Debug for Graph_ConditionalEdge {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+        ::protobuf::text_format::fmt(self, f)
+    }
+}
+
+impl ::protobuf::reflect::ProtobufValue for Graph_ConditionalEdge {
+    fn as_ref(&self) -> ::protobuf::reflect::ReflectValueRef {
+        ::protobuf::reflect::ReflectValueRef::Message(self)
+    }
+}
+
 #[derive(PartialEq,Clone,Default)]
 pub struct PluginExchange {
     // message fields
@@ -1124,24 +1881,33 @@ impl ::protobuf::reflect::ProtobufValue for PluginError_Kind {
 }
 
 static file_descriptor_proto_data: &'static [u8] = b"\
-    \n\x1bsrc/plugins/interface.proto\"\xaa\x02\n\x05Graph\x12!\n\x05nodes\
+    \n\x1bsrc/plugins/interface.proto\"\x91\x05\n\x05Graph\x12!\n\x05nodes\
     \x18\x01\x20\x03(\x0b2\x0b.Graph.NodeR\x05nodes\x12!\n\x05edges\x18\x02\
-    \x20\x03(\x0b2\x0b.Graph.EdgeR\x05edges\x1a\xae\x01\n\x04Node\x12\x18\n\
-    \x07version\x18\x01\x20\x01(\tR\x07version\x12\x18\n\x07payload\x18\x02\
-    \x20\x01(\tR\x07payload\x125\n\x08metadata\x18\x03\x20\x03(\x0b2\x19.Gra\
-    ph.Node.MetadataEntryR\x08metadata\x1a;\n\rMetadataEntry\x12\x10\n\x03ke\
-    y\x18\x01\x20\x01(\tR\x03key\x12\x14\n\x05value\x18\x02\x20\x01(\tR\x05v\
-    alue:\x028\x01\x1a*\n\x04Edge\x12\x12\n\x04from\x18\x01\x20\x01(\x04R\
-    \x04from\x12\x0e\n\x02to\x18\x02\x20\x01(\x04R\x02to\"\xae\x01\n\x0ePlug\
-    inExchange\x12\x1c\n\x05graph\x18\x01\x20\x01(\x0b2\x06.GraphR\x05graph\
-    \x12?\n\nparameters\x18\x02\x20\x03(\x0b2\x1f.PluginExchange.ParametersE\
-    ntryR\nparameters\x1a=\n\x0fParametersEntry\x12\x10\n\x03key\x18\x01\x20\
-    \x01(\tR\x03key\x12\x14\n\x05value\x18\x02\x20\x01(\tR\x05value:\x028\
-    \x01\"\xb2\x01\n\x0bPluginError\x12%\n\x04kind\x18\x01\x20\x01(\x0e2\x11\
-    .PluginError.KindR\x04kind\x12\x14\n\x05value\x18\x02\x20\x01(\tR\x05val\
-    ue\"f\n\x04Kind\x12\x0b\n\x07GENERIC\x10\0\x12\x11\n\rINVALID_GRAPH\x10\
-    \x01\x12\x11\n\rINVALID_PARAM\x10\x02\x12\x15\n\x11FAILED_DEPENDENCY\x10\
-    \x03\x12\x14\n\x10INTERNAL_FAILURE\x10\x04b\x06proto3\
+    \x20\x03(\x0b2\x0b.Graph.EdgeR\x05edges\x12C\n\x11conditional_edges\x18\
+    \x03\x20\x03(\x0b2\x16.Graph.ConditionalEdgeR\x10conditionalEdges\x1a\
+    \xae\x01\n\x04Node\x12\x18\n\x07version\x18\x01\x20\x01(\tR\x07version\
+    \x12\x18\n\x07payload\x18\x02\x20\x01(\tR\x07payload\x125\n\x08metadata\
+    \x18\x03\x20\x03(\x0b2\x19.Graph.Node.MetadataEntryR\x08metadata\x1a;\n\
+    \rMetadataEntry\x12\x10\n\x03key\x18\x01\x20\x01(\tR\x03key\x12\x14\n\
+    \x05value\x18\x02\x20\x01(\tR\x05value:\x028\x01\x1a*\n\x04Edge\x12\x12\
+    \n\x04from\x18\x01\x20\x01(\x04R\x04from\x12\x0e\n\x02to\x18\x02\x20\x01\
+    (\x04R\x02to\x1a>\n\x10ClusterCondition\x12\x12\n\x04type\x18\x01\x20\
+    \x01(\tR\x04type\x12\x16\n\x06promql\x18\x02\x20\x01(\tR\x06promql\x1a\
+    \x86\x01\n\x04Risk\x12\x10\n\x03url\x18\x01\x20\x01(\tR\x03url\x12\x12\n\
+    \x04name\x18\x02\x20\x01(\tR\x04name\x12\x18\n\x07message\x18\x03\x20\
+    \x01(\tR\x07message\x12>\n\x0ematching_rules\x18\x04\x20\x03(\x0b2\x17.G\
+    raph.ClusterConditionR\rmatchingRules\x1aW\n\x0fConditionalEdge\x12!\n\
+    \x05edges\x18\x01\x20\x03(\x0b2\x0b.Graph.EdgeR\x05edges\x12!\n\x05risks\
+    \x18\x02\x20\x03(\x0b2\x0b.Graph.RiskR\x05risks\"\xae\x01\n\x0ePluginExc\
+    hange\x12\x1c\n\x05graph\x18\x01\x20\x01(\x0b2\x06.GraphR\x05graph\x12?\
+    \n\nparameters\x18\x02\x20\x03(\x0b2\x1f.PluginExchange.ParametersEntryR\
+    \nparameters\x1a=\n\x0fParametersEntry\x12\x10\n\x03key\x18\x01\x20\x01(\
+    \tR\x03key\x12\x14\n\x05value\x18\x02\x20\x01(\tR\x05value:\x028\x01\"\
+    \xb2\x01\n\x0bPluginError\x12%\n\x04kind\x18\x01\x20\x01(\x0e2\x11.Plugi\
+    nError.KindR\x04kind\x12\x14\n\x05value\x18\x02\x20\x01(\tR\x05value\"f\
+    \n\x04Kind\x12\x0b\n\x07GENERIC\x10\0\x12\x11\n\rINVALID_GRAPH\x10\x01\
+    \x12\x11\n\rINVALID_PARAM\x10\x02\x12\x15\n\x11FAILED_DEPENDENCY\x10\x03\
+    \x12\x14\n\x10INTERNAL_FAILURE\x10\x04b\x06proto3\
 ";
 
 static file_descriptor_proto_lazy: ::protobuf::rt::LazyV2<::protobuf::descriptor::FileDescriptorProto> = ::protobuf::rt::LazyV2::INIT;
@@ -5,6 +5,14 @@
 //! The filtering also removes any architecture suffixes from the version strings
 //! if they are present. The assumption for this is that the architecture would
 //! be encoded as part of the _build_ information according to the SemVer specification.
+//!
+//! Migrations to another architecture, typically `multi`, can be requested with the
+//! parameter at key "migrate" if that architecture is listed in `migration_arches`.
+//! The releases of the requested architecture are then kept along with their
+//! equivalent release of the migration architecture, which keeps its version suffix,
+//! and an edge is added between the two. The edges of the migration targets
+//! themselves are removed. Only the versions matching `migration_versions`
+//! qualify, if it is set.
 
 use crate as cincinnati;
 
@@ -13,13 +21,15 @@ use self::cincinnati::plugins::prelude_plugin_impl::*;
 
 use commons::GraphError;
 use lazy_static::lazy_static;
+use semver::{Version, VersionReq};
+use std::collections::{HashMap, HashSet};
 
 pub static DEFAULT_KEY_FILTER: &str = "io.openshift.upgrades.graph";
 pub static DEFAULT_ARCH_KEY: &str = "release.arch";
 pub static DEFAULT_DEFAULT_ARCH: &str = "amd64";
 pub static DEFAULT_DEFAULT_ARCH_THRESHOLD_VERSION: &str = "4.2.0-rc.0";
 
-#[derive(Clone, Debug, Deserialize, SmartDefault)]
+#[derive(Clone, Debug, Deserialize, Serialize, SmartDefault)]
 #[serde(default)]
 pub struct ArchFilterPlugin {
     #[default(DEFAULT_KEY_FILTER.to_string())]
@@ -30,12 +40,22 @@ pub struct ArchFilterPlugin {
 
     #[default(DEFAULT_DEFAULT_ARCH.to_string())]
     pub default_arch: String,
+
+    /// Architectures which requests may migrate to, none by default.
+    pub migration_arches: Vec<String>,
+
+    /// Versions which may migrate, all of them if unset.
+    pub migration_versions: Option<VersionReq>,
 }
 
 impl PluginSettings for ArchFilterPlugin {
     fn build_plugin(&self, _: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
         Ok(new_plugin!(InternalPluginWrapper(self.clone())))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(Self::PLUGIN_NAME, self)
+    }
 }
 
 impl ArchFilterPlugin {
@@ -44,62 +64,97 @@ impl ArchFilterPlugin {
 
     /// Validate plugin configuration and fill in defaults.
     pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
-        let plugin: Self = cfg.try_into()?;
+        let plugin: Self = commons::de::from_toml_value(cfg)?;
 
         ensure!(!plugin.key_prefix.is_empty(), "empty arch-key prefix");
         ensure!(!plugin.key_suffix.is_empty(), "empty arch-key suffix");
+        for arch in &plugin.migration_arches {
+            ensure!(
+                ARCH_VALIDATION_REGEX_RE.is_match(arch),
+                "migration arch '{}' does not match regex '{}'",
+                arch,
+                ARCH_VALIDATION_REGEX_STR
+            );
+        }
 
         Ok(Box::new(plugin))
     }
 
-    /// Takes the arch identifier and removes all the conditional edges that does not contain
-    /// the provided arch identifier.
-    pub fn remove_conditional_edges(
+    /// Evaluate the architecture to migrate to from the given "migrate" parameter.
+    fn infer_migration(
         &self,
-        ce: &mut Vec<cincinnati::ConditionalEdge>,
-        arch: String,
-    ) -> Fallible<i32> {
-        let mut edges_removed: usize = 0;
-        ce.iter_mut()
-            .for_each(|ce: &mut cincinnati::ConditionalEdge| {
-                let total_edges = ce.edges.len();
-                ce.edges
-                    .retain(|e| e.from.contains(&arch) && e.to.contains(&arch));
-                edges_removed += total_edges - ce.edges.len();
-            });
-        ce.retain(|ce| !ce.edges.is_empty());
-        Ok(edges_removed as i32)
+        migrate: Option<&String>,
+        arch: &str,
+    ) -> Result<Option<String>, GraphError> {
+        let migrate = match migrate {
+            Some(migrate) => migrate,
+            None => return Ok(None),
+        };
+        if !self.migration_arches.contains(migrate) {
+            return Err(GraphError::InvalidParams(format!(
+                "migration to arch '{}' is not enabled",
+                migrate
+            )));
+        }
+        if migrate == arch {
+            return Err(GraphError::InvalidParams(format!(
+                "cannot migrate from arch '{}' to itself",
+                arch
+            )));
+        }
+
+        Ok(Some(migrate.clone()))
     }
 
-    /// Remove the arch identifier from the conditional edges.
-    pub fn rm_conditional_edges_arch_identifier(
+    /// Returns the migrations from the releases of `arch` to their equivalent
+    /// releases of `target`, as pairs of versions.
+    fn find_migrations(
         &self,
-        vce: &mut Vec<cincinnati::ConditionalEdge>,
-        arch: String,
-    ) -> Fallible<()> {
-        vce.iter_mut()
-            .for_each(|ce: &mut cincinnati::ConditionalEdge| {
-                ce.mut_edges().iter_mut().for_each(|e| {
-                    e.from = self.remove_arch_info(&e.from, arch.clone()).unwrap();
-                    e.to = self.remove_arch_info(&e.to, arch.clone()).unwrap();
-                });
-            });
-
-        Ok(())
-    }
+        graph: &cincinnati::Graph,
+        keys: &MetadataKeys,
+        arch: &str,
+        target: &str,
+    ) -> Vec<(String, String)> {
+        let mut sources: Vec<(Version, String)> = vec![];
+        let mut targets: HashMap<Version, String> = HashMap::new();
+        for release in graph.releases() {
+            let metadata = match release.release_metadata(keys) {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+            let base = match Version::parse(release.version()) {
+                Ok(mut version) => {
+                    version.build.clear();
+                    version
+                }
+                Err(_) => continue,
+            };
+            if metadata.has_arch(arch) {
+                sources.push((base, release.version().to_string()));
+            } else if metadata.has_arch(target) {
+                targets.insert(base, release.version().to_string());
+            }
+        }
 
-    /// gets the string and arch identifier and removes the arch identifier from the string
-    fn remove_arch_info(&self, v: &str, arch: String) -> Result<String, Error> {
-        semver::Version::parse(v)
-            .context(v.to_string())
-            .map(|mut version| {
-                version.build.retain(|elem| elem.to_string() != arch);
-                trace!("CE: rewriting version {} -> {}", v, version);
-                version.to_string()
+        sources
+            .into_iter()
+            .filter(|(base, _)| {
+                self.migration_versions
+                    .as_ref()
+                    .map_or(true, |versions| versions.matches(base))
             })
+            .filter_map(|(base, source)| Some((source, targets.get(&base)?.clone())))
+            .collect()
     }
 }
 
+/// Removes the architecture from the build information of the version.
+fn strip_arch(version: &str, arch: &str) -> Fallible<String> {
+    let mut version = Version::parse(version).context(version.to_string())?;
+    version.build.retain(|elem| elem.to_string() != arch);
+    Ok(version.to_string())
+}
+
 /// Evaluate an architecture from the given "arch" parameters.
 fn infer_arch(arch: Option<String>, default_arch: String) -> Result<String, GraphError> {
     match arch {
@@ -134,31 +189,45 @@ lazy_static! {
 #[async_trait]
 impl InternalPlugin for ArchFilterPlugin {
     const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+    const PARAMETERS: Option<&'static [&'static str]> = Some(&["arch", "migrate"]);
 
-    async fn run_internal(&self, internal_io: InternalIO) -> Fallible<InternalIO> {
+    async fn run_internal(&self, mut internal_io: InternalIO) -> Fallible<InternalIO> {
         let arch = infer_arch(
             internal_io.parameters.get("arch").map(|s| s.to_string()),
             self.default_arch.clone(),
         )?;
+        let migrate = self.infer_migration(internal_io.parameters.get("migrate"), &arch)?;
+
+        let keys = MetadataKeys::with_prefix(&self.key_prefix)
+            .with_arch_key(format!("{}.{}", self.key_prefix, self.key_suffix));
+
+        let migrations = match &migrate {
+            Some(target) => self.find_migrations(&internal_io.graph, &keys, &arch, target),
+            None => vec![],
+        };
+        let migration_targets: HashSet<&str> = migrations
+            .iter()
+            .map(|(_, target)| target.as_str())
+            .collect();
 
-        let mut graph = internal_io.graph;
+        let graph = internal_io.graph_mut();
 
         // iterate over all releases attempt to remove the arch metadata key
-        // 1. if it exists, keep every release which matches the given `arch`
+        // 1. if it exists, keep every release which matches the given `arch`,
+        //    and the releases it migrates to
         // 2. collect all other releases to be removed
         let to_remove = {
             graph
                 .find_by_fn_mut(|release| {
-                    match release {
-                        cincinnati::Release::Concrete(concrete_release) => concrete_release
-                            .metadata
-                            .remove(&format!("{}.{}", self.key_prefix, self.key_suffix))
-                            .map_or(true, |values| {
-                                !values.split(',').any(|value| value.trim() == arch)
-                            }),
+                    let remove = release
+                        .release_metadata(&keys)
                         // remove if it's not a ConcreteRelease
-                        _ => true,
+                        .map_or(true, |metadata| !metadata.has_arch(&arch))
+                        && !migration_targets.contains(release.version());
+                    if let Some(metadata) = release.get_metadata_mut() {
+                        metadata.remove(&keys.arch);
                     }
+                    remove
                 })
                 .into_iter()
                 .map(|(release_id, version)| {
@@ -168,31 +237,16 @@ impl InternalPlugin for ArchFilterPlugin {
                 .collect()
         };
 
-        // remove all matches from the Graph
+        // remove all matches from the Graph, including their conditional edges
         let removed = graph.remove_releases(to_remove);
-        let removed_ce: i32 =
-            self.remove_conditional_edges(graph.conditional_edges.as_mut().unwrap(), arch.clone())?;
 
-        trace!(
-            "removed {} releases and {} conditional edges",
-            removed,
-            removed_ce
-        );
+        trace!("removed {} releases", removed);
 
         // remove the build suffix from the version
         graph
             .iter_releases_mut(|mut release| {
-                let version = {
-                    let release_version = release.version().to_owned();
-
-                    semver::Version::parse(&release_version)
-                        .context(release_version.clone())
-                        .map(|mut version| {
-                            version.build.retain(|elem| elem.to_string() != arch);
-                            trace!("rewriting version {} ->  {}", release_version, version);
-                            version.to_string()
-                        })?
-                };
+                let version = strip_arch(release.version(), &arch)?;
+                trace!("rewriting version {} ->  {}", release.version(), version);
 
                 match &mut release {
                     cincinnati::Release::Abstract(release) => release.version = version,
@@ -202,14 +256,56 @@ impl InternalPlugin for ArchFilterPlugin {
                 Ok(())
             })
             .map_err(|e| GraphError::ArchVersionError(e.to_string()))?;
-        self.rm_conditional_edges_arch_identifier(
-            graph.conditional_edges.as_mut().unwrap(),
-            arch.clone(),
-        )?;
-        Ok(InternalIO {
-            graph,
-            parameters: internal_io.parameters,
-        })
+
+        // the migration targets are only reachable through their migration edges,
+        // their own edges belong to the graph of the target arch
+        let target_edges: Vec<(String, String, bool, bool)> = graph
+            .edges()
+            .filter(|(from, _, _)| migration_targets.contains(from.version()))
+            .map(|(from, to, weight)| {
+                (
+                    from.version().to_string(),
+                    to.version().to_string(),
+                    weight.unconditional,
+                    weight.is_conditional(),
+                )
+            })
+            .collect();
+        for (from, to, unconditional, conditional) in target_edges {
+            let (from, to) = match (graph.find_by_version(&from), graph.find_by_version(&to)) {
+                (Some(from), Some(to)) => (from, to),
+                _ => continue,
+            };
+            if unconditional {
+                graph.remove_edge(&from, &to)?;
+            }
+            if conditional {
+                graph.remove_conditional_edge(&from, &to)?;
+            }
+        }
+
+        // add the migration edges, between the rewritten versions
+        for (source, target) in &migrations {
+            let source = strip_arch(source, &arch)
+                .map_err(|e| GraphError::ArchVersionError(e.to_string()))?;
+            let (from, to) = match (
+                graph.find_by_version(&source),
+                graph.find_by_version(target),
+            ) {
+                (Some(from), Some(to)) => (from, to),
+                _ => continue,
+            };
+            if graph
+                .edge_weight(&from, &to)
+                .map_or(false, |weight| weight.unconditional)
+            {
+                continue;
+            }
+            trace!("adding migration edge {} -> {}", source, target);
+            graph.add_edge(&from, &to)?;
+        }
+
+        Ok(internal_io)
     }
 }
 
@@ -284,9 +380,10 @@ mod tests {
             key_prefix: "release".to_string(),
             key_suffix: "arch".to_string(),
             default_arch: "amd64".to_string(),
+            ..Default::default()
         });
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: [("arch", "arm64")]
                 .iter()
                 .map(|(k, v)| (k.to_string(), v.to_string()))
@@ -295,7 +392,167 @@ mod tests {
 
         let processed_graph = runtime.block_on(future_processed_graph)?.graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
+
+        Ok(())
+    }
+
+    #[test]
+    fn plugin_keeps_conditional_edges_of_arch() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let metadata = |arch: &str| -> cincinnati::MapImpl<String, String> {
+            [
+                (String::from("version_suffix"), format!("+{}", arch)),
+                (String::from("release.arch"), arch.to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        };
+        let mut input_graph = generate_custom_graph(
+            "image",
+            vec![
+                (0, metadata("amd64")),
+                (1, metadata("amd64")),
+                (2, metadata("arm64")),
+                (3, metadata("arm64")),
+            ],
+            Some(vec![]),
+        );
+        let risk = cincinnati::ConditionalUpdateRisk {
+            name: "SomeRisk".to_string(),
+            ..Default::default()
+        };
+        for (from, to) in &[
+            ("0.0.0+amd64", "1.0.0+amd64"),
+            ("2.0.0+arm64", "3.0.0+arm64"),
+        ] {
+            let from = input_graph.find_by_version(from).unwrap();
+            let to = input_graph.find_by_version(to).unwrap();
+            input_graph.add_conditional_edge(&from, &to, &[risk.clone()])?;
+        }
+
+        let plugin = Box::new(ArchFilterPlugin {
+            key_prefix: "release".to_string(),
+            key_suffix: "arch".to_string(),
+            default_arch: "amd64".to_string(),
+            ..Default::default()
+        });
+        let processed_graph = runtime
+            .block_on(
+                plugin.run_internal(InternalIO {
+                    graph: input_graph.into(),
+                    parameters: [("arch", "arm64")]
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                }),
+            )?
+            .graph;
+
+        let conditional_edges = processed_graph.conditional_edges();
+        assert_eq!(conditional_edges.len(), 1);
+        assert_eq!(
+            conditional_edges[0].edges,
+            vec![cincinnati::ConditionalUpdateEdge {
+                from: "2.0.0".to_string(),
+                to: "3.0.0".to_string(),
+            }]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn plugin_adds_migration_edges() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let metadata = |arch: &str| -> cincinnati::MapImpl<String, String> {
+            [
+                (String::from("version_suffix"), format!("+{}", arch)),
+                (String::from("release.arch"), arch.to_string()),
+            ]
+            .iter()
+            .cloned()
+            .collect()
+        };
+        // 4.{1,2,3}.0+amd64, 4.{1,2,3}.0+multi and 4.4.0+arm64
+        let input_graph = cincinnati::testing::TestGraphBuilder::new()
+            .with_version_template("4.{{i}}.0")
+            .with_metadata(vec![
+                (1, metadata("amd64")),
+                (2, metadata("amd64")),
+                (3, metadata("amd64")),
+                (1, metadata("multi")),
+                (2, metadata("multi")),
+                (3, metadata("multi")),
+                (4, metadata("arm64")),
+            ])
+            .with_edges(Some(vec![(0, 1), (1, 2), (3, 4), (4, 5)]))
+            .build();
+
+        let plugin: ArchFilterPlugin = toml::from_str(
+            r#"
+                key_prefix = "release"
+                key_suffix = "arch"
+                migration_arches = ["multi"]
+                migration_versions = ">=4.2.0"
+            "#,
+        )?;
+        let run = |parameters: &[(&str, &str)]| {
+            runtime.block_on(
+                plugin.run_internal(InternalIO {
+                    graph: input_graph.clone().into(),
+                    parameters: parameters
+                        .iter()
+                        .map(|(k, v)| (k.to_string(), v.to_string()))
+                        .collect(),
+                }),
+            )
+        };
+
+        let processed_graph = run(&[("arch", "amd64"), ("migrate", "multi")])?.graph;
+        let mut edges: Vec<(String, String)> = processed_graph
+            .get_edges(false)?
+            .into_iter()
+            .flat_map(|(from, to)| to.into_iter().map(move |to| (from.clone(), to)))
+            .collect();
+        edges.sort();
+        assert_eq!(
+            edges,
+            vec![
+                ("4.1.0".to_string(), "4.2.0".to_string()),
+                ("4.2.0".to_string(), "4.2.0+multi".to_string()),
+                ("4.2.0".to_string(), "4.3.0".to_string()),
+                ("4.3.0".to_string(), "4.3.0+multi".to_string()),
+            ]
+        );
+        assert_eq!(processed_graph.releases_count(), 5);
+        // the update edge between the multi releases is not part of the amd64 graph
+        let multi = |version| {
+            processed_graph
+                .find_by_version(version)
+                .expect("missing migration target")
+        };
+        assert_eq!(
+            processed_graph.edge_weight(&multi("4.2.0+multi"), &multi("4.3.0+multi")),
+            None
+        );
+
+        // without migration, the graph is filtered as usual
+        let processed_graph = run(&[("arch", "amd64")])?.graph;
+        assert_eq!(processed_graph.releases_count(), 3);
+
+        for parameters in &[
+            [("arch", "amd64"), ("migrate", "arm64")],
+            [("arch", "multi"), ("migrate", "multi")],
+        ] {
+            match run(parameters).unwrap_err().downcast::<GraphError>() {
+                Ok(GraphError::InvalidParams(_)) => {}
+                res => panic!("expected InvalidParams error, got: {:?}", res),
+            }
+        }
 
         Ok(())
     }
@@ -3,7 +3,6 @@
 //! and the value must match the regex specified at CHANNEL_VALIDATION_REGEX_STR
 
 use crate as cincinnati;
-use std::collections::HashSet;
 
 use self::cincinnati::plugins::prelude::*;
 use self::cincinnati::plugins::prelude_plugin_impl::*;
@@ -14,7 +13,7 @@ use lazy_static::lazy_static;
 static DEFAULT_KEY_FILTER: &str = "io.openshift.upgrades.graph";
 static DEFAULT_CHANNEL_KEY: &str = "release.channels";
 
-#[derive(Clone, Debug, Deserialize, SmartDefault)]
+#[derive(Clone, Debug, Deserialize, Serialize, SmartDefault)]
 #[serde(default)]
 pub struct ChannelFilterPlugin {
     #[default(DEFAULT_KEY_FILTER.to_string())]
@@ -28,6 +27,10 @@ impl PluginSettings for ChannelFilterPlugin {
     fn build_plugin(&self, _: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
         Ok(new_plugin!(InternalPluginWrapper(self.clone())))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(Self::PLUGIN_NAME, self)
+    }
 }
 
 impl ChannelFilterPlugin {
@@ -35,32 +38,13 @@ impl ChannelFilterPlugin {
 
     /// Validate plugin configuration and fill in defaults.
     pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
-        let plugin: Self = cfg.try_into()?;
+        let plugin: Self = commons::de::from_toml_value(cfg)?;
 
         ensure!(!plugin.key_prefix.is_empty(), "empty channel-key prefix");
         ensure!(!plugin.key_suffix.is_empty(), "empty channel-key suffix");
 
         Ok(Box::new(plugin))
     }
-
-    /// Takes a HashSet of release names and removes the corresponding conditional edges
-    /// containing the releases.  
-    pub fn remove_conditional_edges(
-        &self,
-        ce: &mut Vec<cincinnati::ConditionalEdge>,
-        releases: HashSet<String>,
-    ) -> Fallible<i32> {
-        let mut edges_removed: usize = 0;
-        ce.iter_mut()
-            .for_each(|ce: &mut cincinnati::ConditionalEdge| {
-                let total_edges = ce.edges.len();
-                ce.edges
-                    .retain(|e| !releases.contains(&e.from) && !releases.contains(&e.to));
-                edges_removed += total_edges - ce.edges.len();
-            });
-        ce.retain(|ce| !ce.edges.is_empty());
-        Ok(edges_removed as i32)
-    }
 }
 
 /// Regex for channel label validation.
@@ -74,8 +58,13 @@ lazy_static! {
 #[async_trait]
 impl InternalPlugin for ChannelFilterPlugin {
     const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+    const PARAMETERS: Option<&'static [&'static str]> = Some(&["channel"]);
+
+    fn channels_key(&self) -> Option<String> {
+        Some(format!("{}.{}", self.key_prefix, self.key_suffix))
+    }
 
-    async fn run_internal(&self, internal_io: InternalIO) -> Fallible<InternalIO> {
+    async fn run_internal(&self, mut internal_io: InternalIO) -> Fallible<InternalIO> {
         let channel = get_multiple_values!(internal_io.parameters, "channel")
             .map_err(|e| GraphError::MissingParams(vec![e.to_string()]))?
             .clone();
@@ -88,49 +77,34 @@ impl InternalPlugin for ChannelFilterPlugin {
             .into());
         };
 
-        let mut graph = internal_io.graph;
-        let mut releases_version: HashSet<String> = HashSet::new();
+        let keys = MetadataKeys::with_prefix(&self.key_prefix)
+            .with_channels_key(format!("{}.{}", self.key_prefix, self.key_suffix));
 
         let to_remove: Vec<ReleaseId> = {
-            graph
-                .find_by_fn_mut(|release| {
-                    match release {
-                        cincinnati::Release::Concrete(concrete_release) => concrete_release
-                            .metadata
-                            .get_mut(&format!("{}.{}", self.key_prefix, self.key_suffix))
-                            .map_or(true, |values| {
-                                !values.split(',').any(|value| value.trim() == channel)
-                            }),
+            internal_io
+                .graph
+                .find_by_fn(|release| {
+                    release
+                        .release_metadata(&keys)
                         // remove if it's not a ConcreteRelease
-                        _ => true,
-                    }
+                        .map_or(true, |metadata| !metadata.in_channel(&channel))
                 })
                 .into_iter()
                 .map(|(release_id, version)| {
                     trace!("queuing '{}' for removal", version);
-                    releases_version.insert(version);
                     release_id
                 })
                 .collect()
         };
 
-        // remove all matches from the Graph
-        let removed = graph.remove_releases(to_remove);
-        let removed_ce = self.remove_conditional_edges(
-            graph.conditional_edges.as_mut().unwrap(),
-            releases_version,
-        )?;
-
-        trace!(
-            "removed {} releases and {} conditional edges",
-            removed,
-            removed_ce
-        );
-
-        Ok(InternalIO {
-            graph,
-            parameters: internal_io.parameters,
-        })
+        // remove all matches from the Graph, including their conditional edges;
+        // the graph is only copied if there is anything to remove
+        if !to_remove.is_empty() {
+            let removed = internal_io.graph_mut().remove_releases(to_remove);
+            trace!("removed {} releases", removed);
+        }
+
+        Ok(internal_io)
     }
 }
 
@@ -141,6 +115,7 @@ mod tests {
     use cincinnati::MapImpl;
     use commons::testing::init_runtime;
     use std::collections::HashMap;
+    use std::sync::Arc;
 
     #[test]
     fn ensure_channel_param_validation() {
@@ -398,7 +373,7 @@ mod tests {
             println!("processing data set #{}: '{}'", i, datum.description);
             let plugin = plugin.clone();
             let future_processed_graph = plugin.run_internal(InternalIO {
-                graph: datum.input_graph,
+                graph: datum.input_graph.into(),
                 parameters: datum.parameters,
             });
 
@@ -407,7 +382,49 @@ mod tests {
                 .expect("plugin run failed")
                 .graph;
 
-            assert_eq!(datum.expected_graph, processed_graph);
+            assert_eq!(datum.expected_graph, *processed_graph);
         }
     }
+
+    #[test]
+    fn ensure_unfiltered_graph_is_not_copied() {
+        let runtime = init_runtime().unwrap();
+
+        let plugin = Box::new(ChannelFilterPlugin {
+            key_prefix: "test_prefix".to_string(),
+            key_suffix: "channels".to_string(),
+        });
+
+        let metadata: Vec<(usize, MapImpl<String, String>)> = (0..3)
+            .map(|i| {
+                (
+                    i,
+                    [("test_prefix.channels".to_string(), "a".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                )
+            })
+            .collect();
+        let graph = Arc::new(generate_custom_graph(
+            "image",
+            metadata,
+            Some(vec![(0, 1), (1, 2)]),
+        ));
+
+        let processed_graph = runtime
+            .block_on(
+                plugin.run_internal(InternalIO {
+                    graph: graph.clone(),
+                    parameters: [("channel".to_string(), "a".to_string())]
+                        .iter()
+                        .cloned()
+                        .collect(),
+                }),
+            )
+            .expect("plugin run failed")
+            .graph;
+
+        assert!(Arc::ptr_eq(&graph, &processed_graph));
+    }
 }
@@ -2,12 +2,16 @@
 //!
 //! Instead of processing the input graph, this plugin fetches a graph from a
 //! remote endpoint, which makes it effectively discard any given input graph.
+//!
+//! With `protobuf = true`, the graph is requested as `application/x-protobuf`,
+//! which is much cheaper to decode for large graphs. The upstream must support
+//! it, as graph-builder does.
 
 use crate as cincinnati;
 
 use self::cincinnati::plugins::prelude::*;
 use self::cincinnati::plugins::prelude_plugin_impl::*;
-use self::cincinnati::CONTENT_TYPE;
+use self::cincinnati::{CONTENT_TYPE, PROTOBUF_CONTENT_TYPE};
 
 use commons::prelude_errors::*;
 use commons::tracing::{get_tracer, set_context};
@@ -19,9 +23,15 @@ use opentelemetry::{
 use cached::{proc_macro::cached, Return};
 use commons::prelude_errors::Context;
 use commons::GraphError;
+use lazy_static::lazy_static;
 use prometheus::Counter;
 use reqwest;
-use reqwest::header::{HeaderMap, HeaderValue, ACCEPT};
+use reqwest::header::{self as reqwest_header, HeaderMap, HeaderValue, ACCEPT};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, Weak};
 use std::time::Duration;
 
 /// Default URL to upstream graph provider.
@@ -30,8 +40,27 @@ pub static DEFAULT_UPSTREAM_URL: &str = "http://localhost:8080/graph";
 /// Default graph-builder connection timeout in seconds.
 pub static DEFAULT_TIMEOUT_SECS: u64 = 30;
 
+lazy_static! {
+    /// Content hash of the last graph fetched from each upstream.
+    static ref UPSTREAM_GRAPH_HASHES: Mutex<HashMap<String, (Weak<crate::Graph>, u64)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the content hash of the given graph, if it is the last one fetched from
+/// any upstream.
+///
+/// Graphs served from the cache of this plugin are the same instance, so this allows
+/// telling whether the upstream graph changed without comparing graphs.
+pub fn upstream_graph_hash(graph: &Arc<crate::Graph>) -> Option<u64> {
+    let guard = UPSTREAM_GRAPH_HASHES.lock().ok()?;
+    guard
+        .values()
+        .find(|(last, _)| last.as_ptr() == Arc::as_ptr(graph))
+        .map(|(_, hash)| *hash)
+}
+
 /// Plugin settings.
-#[derive(Clone, CustomDebug, Deserialize, SmartDefault)]
+#[derive(Clone, CustomDebug, Deserialize, Serialize, SmartDefault)]
 #[serde(default)]
 struct CincinnatiGraphFetchSettings {
     #[default(DEFAULT_UPSTREAM_URL.to_string())]
@@ -39,6 +68,9 @@ struct CincinnatiGraphFetchSettings {
 
     #[default(DEFAULT_TIMEOUT_SECS)]
     timeout: u64,
+
+    /// Request the graph as protobuf instead of JSON.
+    protobuf: bool,
 }
 
 /// Graph fetcher for Cincinnati `/graph` endpoints.
@@ -47,6 +79,9 @@ pub struct CincinnatiGraphFetchPlugin {
     /// The upstream from which to fetch the graph
     pub upstream: String,
 
+    /// Whether the graph is requested as protobuf instead of JSON
+    pub protobuf: bool,
+
     /// The optional metric for counting upstream requests
     #[debug(skip)]
     pub http_upstream_reqs: Counter,
@@ -62,9 +97,14 @@ pub struct CincinnatiGraphFetchPlugin {
 impl PluginSettings for CincinnatiGraphFetchSettings {
     fn build_plugin(&self, registry: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
         let cfg = self.clone();
-        let plugin = CincinnatiGraphFetchPlugin::try_new(cfg.upstream, cfg.timeout, registry)?;
+        let mut plugin = CincinnatiGraphFetchPlugin::try_new(cfg.upstream, cfg.timeout, registry)?;
+        plugin.protobuf = cfg.protobuf;
         Ok(new_plugin!(InternalPluginWrapper(plugin)))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(CincinnatiGraphFetchPlugin::PLUGIN_NAME, self)
+    }
 }
 
 impl CincinnatiGraphFetchPlugin {
@@ -73,7 +113,7 @@ impl CincinnatiGraphFetchPlugin {
 
     /// Validate plugin configuration and fill in defaults.
     pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
-        let settings: CincinnatiGraphFetchSettings = cfg.try_into()?;
+        let settings: CincinnatiGraphFetchSettings = commons::de::from_toml_value(cfg)?;
 
         ensure!(!settings.upstream.is_empty(), "empty upstream");
 
@@ -108,6 +148,7 @@ impl CincinnatiGraphFetchPlugin {
 
         Ok(Self {
             upstream,
+            protobuf: false,
             http_upstream_reqs,
             http_upstream_errors_total,
             client,
@@ -115,12 +156,13 @@ impl CincinnatiGraphFetchPlugin {
     }
 }
 
-// Cache successful responses, ignoring input, invalidating after 60 seconds
+// Cache successful responses per upstream, ignoring input, invalidating after 60 seconds.
+// The graph is shared by all requests served from the cache.
 #[cached(
-    size = 1,
+    size = 16,
     time = 60,
-    key = "bool",
-    convert = r#"{ true }"#,
+    key = "String",
+    convert = r#"{ upstream.to_string() }"#,
     sync_writes = true,
     with_cached_flag = true,
     result = true
@@ -129,7 +171,7 @@ async fn cached_graph(
     client: &reqwest::Client,
     upstream: &str,
     headers: HeaderMap,
-) -> Fallible<Return<crate::Graph>, GraphError> {
+) -> Fallible<Return<Arc<crate::Graph>>, GraphError> {
     let res = client
         .get(upstream)
         .headers(headers)
@@ -140,10 +182,39 @@ async fn cached_graph(
     if !res.status().is_success() {
         return Err(GraphError::FailedUpstreamFetch(res.status().to_string()));
     }
-    let graph = res
-        .json()
-        .map_err(|e| GraphError::FailedJsonIn(e.to_string()))
+
+    let is_protobuf = res
+        .headers()
+        .get(reqwest_header::CONTENT_TYPE)
+        .map_or(false, |content_type| content_type == PROTOBUF_CONTENT_TYPE);
+    let bytes = res
+        .bytes()
+        .map_err(|e| GraphError::FailedUpstreamFetch(e.to_string()))
         .await?;
+    let graph: crate::Graph = if is_protobuf {
+        protobuf::Message::parse_from_bytes(&bytes)
+            .map_err(|e| {
+                GraphError::FailedUpstreamFetch(format!("failed to decode protobuf: {}", e))
+            })
+            .and_then(|graph: cincinnati::plugins::interface::Graph| {
+                crate::Graph::try_from(graph).map_err(|e| {
+                    GraphError::FailedUpstreamFetch(format!("invalid protobuf graph: {}", e))
+                })
+            })?
+    } else {
+        serde_json::from_slice(&bytes).map_err(|e| GraphError::FailedJsonIn(e.to_string()))?
+    };
+
+    let graph = Arc::new(graph);
+    let mut hasher = DefaultHasher::new();
+    bytes.hash(&mut hasher);
+    if let Ok(mut hashes) = UPSTREAM_GRAPH_HASHES.lock() {
+        hashes.insert(
+            upstream.to_string(),
+            (Arc::downgrade(&graph), hasher.finish()),
+        );
+    }
+
     Ok(Return::new(graph))
 }
 
@@ -152,7 +223,12 @@ impl CincinnatiGraphFetchPlugin {
         // extract current trace ID from headers
         // this is required to make graph-builder trace a child of police-engine request
         let mut headers = HeaderMap::new();
-        headers.insert(ACCEPT, HeaderValue::from_static(CONTENT_TYPE));
+        let accept = if self.protobuf {
+            PROTOBUF_CONTENT_TYPE
+        } else {
+            CONTENT_TYPE
+        };
+        headers.insert(ACCEPT, HeaderValue::from_static(accept));
         {
             let span = get_tracer().start("");
             let _active_span = mark_span_as_active(span);
@@ -179,6 +255,7 @@ impl CincinnatiGraphFetchPlugin {
 #[async_trait]
 impl InternalPlugin for CincinnatiGraphFetchPlugin {
     const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+    const PARAMETERS: Option<&'static [&'static str]> = Some(&[]);
 
     async fn run_internal(&self, io: InternalIO) -> Fallible<InternalIO> {
         self.do_run_internal(io)
@@ -238,7 +315,7 @@ mod tests {
                     .expect("plugin run failed")
                     .graph;
 
-                assert_eq!($expected_graph, processed_graph);
+                assert_eq!($expected_graph, *processed_graph);
 
                 assert_eq!(1, http_upstream_reqs.get() as u64);
                 assert_eq!(0, http_upstream_errors_total.get() as u64);
@@ -268,6 +345,84 @@ mod tests {
         ),
     );
 
+    #[test]
+    fn fetch_success_protobuf_graph_fetch() -> Fallible<()> {
+        use protobuf::Message;
+
+        let runtime = init_runtime()?;
+
+        let graph = cincinnati::testing::generate_graph(true, false);
+        let body = cincinnati::plugins::interface::Graph::from(graph.clone()).write_to_bytes()?;
+        let _m = mockito::mock("GET", "/protobuf")
+            .match_header("accept", PROTOBUF_CONTENT_TYPE)
+            .with_status(200)
+            .with_header("content-type", PROTOBUF_CONTENT_TYPE)
+            .with_body(body)
+            .create();
+
+        let mut plugin = CincinnatiGraphFetchPlugin::try_new(
+            format!("{}/protobuf", mockito::server_url()),
+            30,
+            None,
+        )?;
+        plugin.protobuf = true;
+
+        let processed_graph = runtime
+            .block_on(plugin.run_internal(InternalIO {
+                graph: Default::default(),
+                parameters: Default::default(),
+            }))
+            .expect("plugin run failed")
+            .graph;
+
+        assert_eq!(graph, *processed_graph);
+        assert_eq!(
+            graph.conditional_edges().len(),
+            processed_graph.conditional_edges().len()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn upstream_graph_hash_per_upstream() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let fetch = |path: &str, graph: &cincinnati::Graph| -> Fallible<Arc<cincinnati::Graph>> {
+            let _m = mockito::mock("GET", path)
+                .with_status(200)
+                .with_header("content-type", "application/json")
+                .with_body(serde_json::to_string(graph)?)
+                .create();
+            let plugin = CincinnatiGraphFetchPlugin::try_new(
+                format!("{}{}", mockito::server_url(), path),
+                30,
+                None,
+            )?;
+            let io = runtime.block_on(plugin.run_internal(InternalIO {
+                graph: Default::default(),
+                parameters: Default::default(),
+            }))?;
+            Ok(io.graph)
+        };
+
+        let first = fetch(
+            "/hash-first",
+            &cincinnati::testing::generate_graph(false, false),
+        )?;
+        let second = fetch(
+            "/hash-second",
+            &cincinnati::testing::generate_graph(true, false),
+        )?;
+
+        let first_hash = upstream_graph_hash(&first).expect("missing hash of the first graph");
+        let second_hash = upstream_graph_hash(&second).expect("missing hash of the second graph");
+        assert_ne!(first_hash, second_hash);
+        assert_eq!(upstream_graph_hash(&Arc::new((*first).clone())), None);
+
+        Ok(())
+    }
+
     macro_rules! fetch_upstream_failure_test {
         (
             name: $name:ident,
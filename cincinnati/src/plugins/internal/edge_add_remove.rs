@@ -1,16 +1,15 @@
 //! This plugin adds and removes Edges from Nodes based on metadata labels.
 
 use crate as cincinnati;
-use crate::conditional_edges::{ConditionalEdge, ConditionalUpdateEdge, ConditionalUpdateRisk};
-use std::collections::HashMap;
 
 use self::cincinnati::plugins::prelude::*;
 use self::cincinnati::plugins::prelude_plugin_impl::*;
+use self::cincinnati::release_metadata;
 
 pub static DEFAULT_KEY_FILTER: &str = "io.openshift.upgrades.graph";
 pub static DEFAULT_REMOVE_ALL_EDGES_VALUE: &str = "*";
 
-#[derive(Clone, Debug, Deserialize, SmartDefault)]
+#[derive(Clone, Debug, Deserialize, Serialize, SmartDefault)]
 #[serde(default)]
 pub struct EdgeAddRemovePlugin {
     #[default(DEFAULT_KEY_FILTER.to_string())]
@@ -31,19 +30,43 @@ pub struct EdgeAddRemovePlugin {
 #[async_trait]
 impl InternalPlugin for EdgeAddRemovePlugin {
     const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+    const PARAMETERS: Option<&'static [&'static str]> = Some(&[]);
 
-    async fn run_internal(&self, io: InternalIO) -> Fallible<InternalIO> {
-        let mut graph = io.graph;
-        self.add_edges(&mut graph)?;
+    async fn run_internal(&self, mut io: InternalIO) -> Fallible<InternalIO> {
+        // the architecture used to annotate versions is always looked up under the default prefix
+        let keys =
+            MetadataKeys::with_prefix(&self.key_prefix).with_arch_key(MetadataKeys::default().arch);
+
+        // fail on all invalid values at once, before modifying the graph
+        let errors: Vec<String> = release_metadata::graph_errors(&io.graph, &keys)
+            .into_iter()
+            .filter(|((_, key), _)| key == &keys.previous_remove_regex)
+            .map(|((version, _), error)| format!("[{}]: {}", version, error))
+            .collect();
+        ensure!(
+            errors.is_empty(),
+            "invalid release metadata: {}",
+            errors.join("; ")
+        );
+
+        let graph = io.graph_mut();
+        for key in &[
+            &keys.previous_add,
+            &keys.next_add,
+            &keys.previous_remove,
+            &keys.previous_remove_regex,
+            &keys.next_remove,
+        ] {
+            graph.index_metadata_key(key);
+        }
+
+        self.add_edges(graph, &keys)?;
         if self.include_conditional_edges {
-            self.add_conditional_edges(&mut graph)?;
+            self.add_conditional_edges(graph)?;
         }
-        self.remove_edges(&mut graph)?;
+        self.remove_edges(graph, &keys)?;
 
-        Ok(InternalIO {
-            graph,
-            parameters: io.parameters,
-        })
+        Ok(io)
     }
 }
 
@@ -51,6 +74,10 @@ impl PluginSettings for EdgeAddRemovePlugin {
     fn build_plugin(&self, _: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
         Ok(new_plugin!(InternalPluginWrapper(self.clone())))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(Self::PLUGIN_NAME, self)
+    }
 }
 
 /// Adds and removes next and previous releases specified by metadata.
@@ -79,7 +106,7 @@ impl EdgeAddRemovePlugin {
 
     /// Validate plugin configuration and fill in defaults.
     pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
-        let plugin: Self = cfg.try_into()?;
+        let plugin: Self = commons::de::from_toml_value(cfg)?;
 
         ensure!(!plugin.key_prefix.is_empty(), "empty prefix");
         ensure!(
@@ -94,7 +121,7 @@ impl EdgeAddRemovePlugin {
     ///
     /// The labels are assumed to have the syntax `<prefix>.(previous|next).remove=(<Version>,)*<Version>`
     /// If the value equals a single `REMOVE_ALL_EDGES_VALUE` all edges at the given direction are removed.
-    fn remove_edges(&self, graph: &mut cincinnati::Graph) -> Fallible<()> {
+    fn remove_edges(&self, graph: &mut cincinnati::Graph, keys: &MetadataKeys) -> Fallible<()> {
         macro_rules! handle_remove_edge {
             ($from:ident, $to:ident) => {
                 if let Err(e) = graph.remove_edge(&$from, &$to) {
@@ -107,127 +134,127 @@ impl EdgeAddRemovePlugin {
             };
         }
 
-        let previous_remove_key = format!("{}.{}", self.key_prefix, "previous.remove");
-        graph
-            .find_by_metadata_key(&previous_remove_key)
-            .into_iter()
-            .try_for_each(
-                |(to, to_version, from_csv): (ReleaseId, String, String)| -> Fallible<()> {
-                    if self.remove_consumed_metadata {
-                        graph
-                            .get_metadata_as_ref_mut(&to)
-                            .map(|metadata| metadata.remove(&previous_remove_key))?;
-                    }
+        find_by_metadata(graph, keys, &keys.previous_remove, |metadata| {
+            owned_versions(metadata.previous_remove())
+        })
+        .into_iter()
+        .try_for_each(
+            |(to, to_version, from_versions): (ReleaseId, String, Vec<String>)| -> Fallible<()> {
+                if self.remove_consumed_metadata {
+                    graph
+                        .get_metadata_as_ref_mut(&to)
+                        .map(|metadata| metadata.remove(&keys.previous_remove))?;
+                }
 
-                    if from_csv.trim() == self.remove_all_edges_value {
-                        let parents: Vec<cincinnati::daggy::EdgeIndex> = graph
-                            .previous_releases(&to)
-                            .map(|(edge_index, _, _)| edge_index)
-                            .collect();
+                if from_versions == [self.remove_all_edges_value.as_str()] {
+                    let parents: Vec<cincinnati::daggy::EdgeIndex> = graph
+                        .previous_releases(&to)
+                        .map(|(edge_index, _, _)| edge_index)
+                        .collect();
 
-                        trace!("removing parents for '{}': {:?}", to_version, parents);
-                        return graph.remove_edges_by_index(&parents);
-                    }
+                    trace!("removing parents for '{}': {:?}", to_version, parents);
+                    return graph.remove_edges_by_index(&parents);
+                }
 
-                    for from_version in from_csv.split(',').map(str::trim) {
-                        let from_version = try_annotate_semver_build(graph, from_version, &to)?;
-
-                        if let Some(from) = graph.find_by_version(&from_version) {
-                            info!("[{}]: removing previous {}", to_version, from_version);
-                            handle_remove_edge!(from, to)
-                        } else {
-                            warn!(
-                                "couldn't find version given by 'previous.remove={}' in graph",
-                                from_version
-                            )
-                        }
+                for from_version in &from_versions {
+                    let from_version = try_annotate_semver_build(graph, keys, from_version, &to)?;
+
+                    if let Some(from) = graph.find_by_version(&from_version) {
+                        info!("[{}]: removing previous {}", to_version, from_version);
+                        handle_remove_edge!(from, to)
+                    } else {
+                        warn!(
+                            "couldn't find version given by 'previous.remove={}' in graph",
+                            from_version
+                        )
                     }
-                    Ok(())
-                },
-            )?;
+                }
+                Ok(())
+            },
+        )?;
 
         // Remove edges instructed by "previous.remove_regex"
-        let previous_remove_regex_key = format!("{}.{}", self.key_prefix, "previous.remove_regex");
-        graph
-            .find_by_metadata_key(&previous_remove_regex_key)
-            .into_iter()
-            .try_for_each(
-                |(to, to_version, from_regex_string): (ReleaseId, String, String)| -> Fallible<()> {
-                    if self.remove_consumed_metadata {
-                        graph
-                            .get_metadata_as_ref_mut(&to)
-                            .map(|metadata| metadata.remove(&previous_remove_regex_key))?;
-                    }
+        find_by_metadata(graph, keys, &keys.previous_remove_regex, |metadata| {
+            metadata.previous_remove_regex()
+        })
+        .into_iter()
+        .try_for_each(|(to, to_version, from_regex)| -> Fallible<()> {
+            if self.remove_consumed_metadata {
+                graph
+                    .get_metadata_as_ref_mut(&to)
+                    .map(|metadata| metadata.remove(&keys.previous_remove_regex))?;
+            }
 
-                    let from_regex = regex::Regex::new(&from_regex_string)
-                        .context(format!("Parsing {} as Regex", &from_regex_string))?;
-
-                    if from_regex_string == ".*" {
-                        let parents: Vec<daggy::EdgeIndex> = graph
-                            .previous_releases(&to)
-                            .map(|(edge_index, _, _)| edge_index)
-                            .collect();
-
-                        trace!(
-                            "removing parents by regex for '{}': {:?}",
-                            to_version,
-                            parents
-                        );
-                        return graph.remove_edges_by_index(&parents);
-                    };
+            let from_regex = match from_regex? {
+                Some(from_regex) => from_regex,
+                None => return Ok(()),
+            };
 
-                    let froms = graph.find_by_fn_mut(|release| {
-                        if from_regex.is_match(release.version()) {
-                            debug!(
-                                "Regex '{}' matches version '{}'",
-                                &from_regex,
-                                release.version(),
-                            );
-                            true
-                        } else {
-                            false
-                        }
-                    });
-
-                    for (from, from_version) in froms {
-                        debug!(
-                            "[{}]: removing previous {} by regex",
-                            to_version, from_version
-                        );
-                        handle_remove_edge!(from, to);
-                    }
+            if from_regex.as_str() == ".*" {
+                let parents: Vec<daggy::EdgeIndex> = graph
+                    .previous_releases(&to)
+                    .map(|(edge_index, _, _)| edge_index)
+                    .collect();
 
-                    Ok(())
-                },
-            )?;
+                trace!(
+                    "removing parents by regex for '{}': {:?}",
+                    to_version,
+                    parents
+                );
+                return graph.remove_edges_by_index(&parents);
+            };
 
-        let next_remove_key = format!("{}.{}", self.key_prefix, "next.remove");
-        graph
-            .find_by_metadata_key(&next_remove_key)
-            .into_iter()
-            .try_for_each(
-                |(from, from_version, to_csv): (ReleaseId, String, String)| -> Fallible<()> {
-                    if self.remove_consumed_metadata {
-                        graph
-                            .get_metadata_as_ref_mut(&from)
-                            .map(|metadata| metadata.remove(&next_remove_key))?;
-                    }
+            let froms = graph.find_by_fn(|release| {
+                if from_regex.is_match(release.version()) {
+                    debug!(
+                        "Regex '{}' matches version '{}'",
+                        &from_regex,
+                        release.version(),
+                    );
+                    true
+                } else {
+                    false
+                }
+            });
+
+            for (from, from_version) in froms {
+                debug!(
+                    "[{}]: removing previous {} by regex",
+                    to_version, from_version
+                );
+                handle_remove_edge!(from, to);
+            }
+
+            Ok(())
+        })?;
 
-                    for to_version in to_csv.split(',').map(str::trim) {
-                        let to_version = try_annotate_semver_build(graph, to_version, &from)?;
-                        if let Some(to) = graph.find_by_version(&to_version) {
-                            debug!("[{}]: removing next {}", from_version, to_version);
-                            handle_remove_edge!(from, to)
-                        } else {
-                            warn!(
-                                "couldn't find version given by 'next.remove={}' in graph",
-                                to_version
-                            )
-                        }
+        find_by_metadata(graph, keys, &keys.next_remove, |metadata| {
+            owned_versions(metadata.next_remove())
+        })
+        .into_iter()
+        .try_for_each(
+            |(from, from_version, to_versions): (ReleaseId, String, Vec<String>)| -> Fallible<()> {
+                if self.remove_consumed_metadata {
+                    graph
+                        .get_metadata_as_ref_mut(&from)
+                        .map(|metadata| metadata.remove(&keys.next_remove))?;
+                }
+
+                for to_version in &to_versions {
+                    let to_version = try_annotate_semver_build(graph, keys, to_version, &from)?;
+                    if let Some(to) = graph.find_by_version(&to_version) {
+                        debug!("[{}]: removing next {}", from_version, to_version);
+                        handle_remove_edge!(from, to)
+                    } else {
+                        warn!(
+                            "couldn't find version given by 'next.remove={}' in graph",
+                            to_version
+                        )
                     }
-                    Ok(())
-                },
-            )?;
+                }
+                Ok(())
+            },
+        )?;
 
         Ok(())
     }
@@ -235,7 +262,7 @@ impl EdgeAddRemovePlugin {
     /// Add next and previous releases specified by metadata.
     ///
     /// The labels are assumed to have the syntax `<prefix>.(previous|next).add=(<Version>,)*<Version>`
-    fn add_edges(&self, graph: &mut cincinnati::Graph) -> Fallible<()> {
+    fn add_edges(&self, graph: &mut cincinnati::Graph, keys: &MetadataKeys) -> Fallible<()> {
         macro_rules! handle_add_edge {
             ($direction:expr, $from:ident, $to:ident, $from_string:ident, $to_string:ident) => {
                 if let Err(e) = graph.add_edge(&$from, &$to) {
@@ -248,80 +275,78 @@ impl EdgeAddRemovePlugin {
             };
         }
 
-        let previous_add_key = format!("{}.{}", self.key_prefix, "previous.add");
-        graph
-            .find_by_metadata_key(&previous_add_key)
-            .into_iter()
-            .try_for_each(|(to, to_version, from_csv)| -> Fallible<()> {
-                if self.remove_consumed_metadata {
-                    graph
-                        .get_metadata_as_ref_mut(&to)
-                        .map(|metadata| metadata.remove(&previous_add_key))?;
-                }
-
-                for from_version in from_csv.split(',').map(str::trim) {
-                    let from_version_annotated =
-                        try_annotate_semver_build(graph, from_version, &to)?;
-
-                    if let Some(from) = graph.find_by_version(&from_version_annotated) {
-                        debug!(
-                            "[{}]: adding {} {}",
-                            &to_version, "previous", &from_version_annotated
-                        );
-                        handle_add_edge!("previous", from, to, from_string, to_string);
-                    } else {
-                        warn!(
-                            "couldn't find version given by 'previous.add={}' in graph",
-                            from_version_annotated
-                        )
-                    }
-                }
-                Ok(())
-            })?;
+        find_by_metadata(graph, keys, &keys.previous_add, |metadata| {
+            owned_versions(metadata.previous_add())
+        })
+        .into_iter()
+        .try_for_each(|(to, to_version, from_versions)| -> Fallible<()> {
+            if self.remove_consumed_metadata {
+                graph
+                    .get_metadata_as_ref_mut(&to)
+                    .map(|metadata| metadata.remove(&keys.previous_add))?;
+            }
 
-        let next_add_key = format!("{}.{}", self.key_prefix, "next.add");
-        graph
-            .find_by_metadata_key(&next_add_key)
-            .into_iter()
-            .try_for_each(|(from, from_version, to_csv)| -> Fallible<()> {
-                if self.remove_consumed_metadata {
-                    graph
-                        .get_metadata_as_ref_mut(&from)
-                        .map(|metadata| metadata.remove(&next_add_key))?;
+            for from_version in &from_versions {
+                let from_version_annotated =
+                    try_annotate_semver_build(graph, keys, from_version, &to)?;
+
+                if let Some(from) = graph.find_by_version(&from_version_annotated) {
+                    debug!(
+                        "[{}]: adding {} {}",
+                        &to_version, "previous", &from_version_annotated
+                    );
+                    handle_add_edge!("previous", from, to, from_string, to_string);
+                } else {
+                    warn!(
+                        "couldn't find version given by 'previous.add={}' in graph",
+                        from_version_annotated
+                    )
                 }
+            }
+            Ok(())
+        })?;
 
-                for to_version in to_csv.split(',').map(str::trim) {
-                    let to_version_annotated = try_annotate_semver_build(graph, to_version, &from)?;
+        find_by_metadata(graph, keys, &keys.next_add, |metadata| {
+            owned_versions(metadata.next_add())
+        })
+        .into_iter()
+        .try_for_each(|(from, from_version, to_versions)| -> Fallible<()> {
+            if self.remove_consumed_metadata {
+                graph
+                    .get_metadata_as_ref_mut(&from)
+                    .map(|metadata| metadata.remove(&keys.next_add))?;
+            }
 
-                    if let Some(to) = graph.find_by_version(&to_version_annotated) {
-                        debug!(
-                            "[{}]: adding {} {}",
-                            &from_version, "next", &to_version_annotated
-                        );
-                        handle_add_edge!("next", from, to, from_version, to_version_annotated);
-                    } else {
-                        warn!(
-                            "couldn't find version given by 'next.add={}' in graph",
-                            to_version_annotated
-                        )
-                    }
+            for to_version in &to_versions {
+                let to_version_annotated =
+                    try_annotate_semver_build(graph, keys, to_version, &from)?;
+
+                if let Some(to) = graph.find_by_version(&to_version_annotated) {
+                    debug!(
+                        "[{}]: adding {} {}",
+                        &from_version, "next", &to_version_annotated
+                    );
+                    handle_add_edge!("next", from, to, from_version, to_version_annotated);
+                } else {
+                    warn!(
+                        "couldn't find version given by 'next.add={}' in graph",
+                        to_version_annotated
+                    )
                 }
-                Ok(())
-            })?;
+            }
+            Ok(())
+        })?;
 
         Ok(())
     }
 
     /// Add conditional edges specified by the metadata.
     ///
-    /// this replaces the regex in edge regex with conditional edges and the risks associated.
+    /// this resolves the regexes of the conditional edge declarations to the matching edges
+    /// and attaches the associated risks to them.
     fn add_conditional_edges(&self, graph: &mut cincinnati::Graph) -> Fallible<()> {
-        let mut edge_risk_map: HashMap<ConditionalUpdateEdge, Vec<ConditionalUpdateRisk>> =
-            HashMap::new();
         graph
-            .conditional_edges
-            .as_ref()
-            .unwrap()
+            .take_conditional_edge_declarations()
             .iter()
             .try_for_each(|ce| -> Fallible<()> {
                 let to_string = &ce.edge_regex.to;
@@ -330,94 +355,64 @@ impl EdgeAddRemovePlugin {
                 let from_regex = regex::Regex::new(from_regex_string)
                     .context(format!("Parsing {} as Regex", from_regex_string))?;
 
-                let risk = ce.risks.clone();
-
                 let to = graph.find_by_version_vec(to_string);
                 to.iter().try_for_each(|(to, to_version)| -> Fallible<()> {
-                    if from_regex_string == ".*" {
-                        let froms: Vec<String> = graph
-                            .previous_releases(to)
-                            .map(|(_, _, release)| release.version().to_string())
-                            .collect();
-                        trace!(
-                            "adding conditional edge by regex for '{}': {:?}",
-                            to_version,
-                            froms
-                        );
-                        froms.iter().for_each(|from| {
-                            let e = ConditionalUpdateEdge {
-                                from: from.to_string(),
-                                to: to_version.clone(),
-                            };
-                            match edge_risk_map.get_mut(&e) {
-                                None => {
-                                    edge_risk_map.insert(e.clone(), risk.clone());
+                    let froms: Vec<(ReleaseId, String)> = graph
+                        .previous_releases(to)
+                        .map(|(_, from, release)| (ReleaseId(from), release.version().to_string()))
+                        .filter(|(_, from)| {
+                            from_regex_string == ".*" || {
+                                let is_match = from_regex.is_match(from);
+                                if is_match {
+                                    debug!("Regex '{}' matches version '{}'", &from_regex, from);
                                 }
-                                Some(risks) => risks.append(&mut risk.clone()),
+                                is_match
                             }
-                        });
-                    } else {
-                        let froms: Vec<String> = graph
-                            .previous_releases(to)
-                            .map(|(_, _, release)| release.version().to_string())
-                            .collect();
-                        debug!(
-                            "adding conditional edge by regex for '{}': {:?}",
-                            to_version, froms
-                        );
-                        froms.iter().for_each(|from| {
-                            if let true = from_regex.is_match(from) {
-                                debug!(
-                                    "Regex '{}' matches version '{}'",
-                                    &from_regex,
-                                    from.to_string(),
-                                );
-                                let e = ConditionalUpdateEdge {
-                                    from: from.to_string(),
-                                    to: to_version.clone(),
-                                };
-                                match edge_risk_map.get_mut(&e) {
-                                    None => {
-                                        edge_risk_map.insert(e.clone(), risk.clone());
-                                    }
-                                    Some(risks) => risks.append(&mut risk.clone()),
-                                }
-                            }
-                        });
-                    }
-                    Ok(())
-                })?;
-                Ok(())
-            })?;
-
-        let mut risk_edge_map: HashMap<Vec<ConditionalUpdateRisk>, Vec<ConditionalUpdateEdge>> =
-            HashMap::new();
+                        })
+                        .collect();
+                    debug!(
+                        "adding conditional edge by regex for '{}': {:?}",
+                        to_version,
+                        froms.iter().map(|(_, from)| from).collect::<Vec<_>>()
+                    );
+
+                    froms.iter().try_for_each(|(from, _)| -> Fallible<()> {
+                        graph.add_conditional_edge(from, to, &ce.risks)?;
+                        Ok(())
+                    })
+                })
+            })
+    }
+}
 
-        edge_risk_map.iter().for_each(
-            |(edge, risks): (&ConditionalUpdateEdge, &Vec<ConditionalUpdateRisk>)| {
-                match risk_edge_map.get_mut(risks) {
-                    None => {
-                        risk_edge_map.insert(risks.clone(), vec![edge.clone()]);
-                    }
-                    Some(edges) => edges.push(edge.clone()),
-                }
-            },
-        );
+/// Returns the releases which have the given metadata key, with their version
+/// and the value parsed from their metadata.
+fn find_by_metadata<T, F>(
+    graph: &cincinnati::Graph,
+    keys: &MetadataKeys,
+    key: &str,
+    parse: F,
+) -> Vec<(ReleaseId, String, T)>
+where
+    F: Fn(&ReleaseMetadata) -> T,
+{
+    graph
+        .find_by_metadata_key(key)
+        .into_iter()
+        .filter_map(|(release_id, version, _)| {
+            let metadata = graph
+                .find_by_releaseid(&release_id)
+                .ok()?
+                .release_metadata(keys)?;
+            let parsed = parse(&metadata);
+            Some((release_id, version, parsed))
+        })
+        .collect()
+}
 
-        let mut conditional_edges: Vec<ConditionalEdge> = vec![];
-        risk_edge_map.iter().for_each(
-            |(risks, edges): (&Vec<ConditionalUpdateRisk>, &Vec<ConditionalUpdateEdge>)| {
-                let conditional_edge = ConditionalEdge {
-                    edge_regex: Default::default(),
-                    edges: edges.clone(),
-                    risks: risks.clone(),
-                };
-                conditional_edges.push(conditional_edge);
-            },
-        );
-        graph.conditional_edges = Some(conditional_edges);
-        Ok(())
-    }
+/// Copies borrowed versions, so that the graph can be modified while iterating over them.
+fn owned_versions(versions: Vec<&str>) -> Vec<String> {
+    versions.into_iter().map(str::to_string).collect()
 }
 
 /// Try to find the architecture metadata and add it to the version String assuming SemVer.
@@ -425,14 +420,20 @@ impl EdgeAddRemovePlugin {
 /// If the referenced ReleaseId doesn't have the arch metadata, the version
 /// string will be passed through unchanged.
 fn try_annotate_semver_build(
-    graph: &mut cincinnati::Graph,
+    graph: &cincinnati::Graph,
+    keys: &MetadataKeys,
     version: &str,
     arch_reference: &ReleaseId,
 ) -> Fallible<String> {
-    let version = if let Some(arch) = graph
-        .get_metadata_as_ref_mut(arch_reference)?
-        .get("io.openshift.upgrades.graph.release.arch")
+    let arch = match graph
+        .find_by_releaseid(arch_reference)?
+        .release_metadata(keys)
     {
+        Some(metadata) => metadata.arch(),
+        None => bail!("could not get metadata reference"),
+    };
+
+    let version = if let Some(arch) = arch {
         let mut version = semver::Version::parse(version)?;
         version.build = vec![semver::Identifier::AlphaNumeric(arch.to_string())];
         version.to_string()
@@ -491,7 +492,7 @@ mod tests {
             ..Default::default()
         });
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         });
 
@@ -500,7 +501,37 @@ mod tests {
             .context("plugin run failed")?
             .graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_previous_remove_regex() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let key = "test_prefix.previous.remove_regex".to_string();
+        let metadata: Vec<(usize, MapImpl<String, String>)> = vec![
+            (
+                0,
+                [(key.clone(), "(".to_string())].iter().cloned().collect(),
+            ),
+            (1, [(key, "1.(".to_string())].iter().cloned().collect()),
+        ];
+
+        let plugin = Box::new(EdgeAddRemovePlugin {
+            key_prefix: "test_prefix".to_string(),
+            ..Default::default()
+        });
+        let err = runtime
+            .block_on(plugin.run_internal(InternalIO {
+                graph: generate_custom_graph("image", metadata, None).into(),
+                parameters: Default::default(),
+            }))
+            .unwrap_err()
+            .to_string();
+
+        assert!(err.contains("[0.0.0]"), "{}", err);
+        assert!(err.contains("[1.0.0]"), "{}", err);
         Ok(())
     }
 
@@ -544,7 +575,7 @@ mod tests {
             ..Default::default()
         });
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         });
 
@@ -553,7 +584,7 @@ mod tests {
             .context("plugin run failed")?
             .graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
 
         Ok(())
     }
@@ -606,7 +637,7 @@ mod tests {
             ..Default::default()
         });
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         });
 
@@ -615,7 +646,7 @@ mod tests {
             .context("plugin run failed")?
             .graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
 
         Ok(())
     }
@@ -656,7 +687,7 @@ mod tests {
             ..Default::default()
         });
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         });
 
@@ -665,7 +696,7 @@ mod tests {
             .context("plugin run failed")?
             .graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
 
         Ok(())
     }
@@ -710,7 +741,7 @@ mod tests {
         });
 
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         });
 
@@ -719,7 +750,7 @@ mod tests {
             .context("plugin run failed")?
             .graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
 
         Ok(())
     }
@@ -761,13 +792,13 @@ mod tests {
                     ..Default::default()
                 });
                 let future_processed_graph = plugin.run_internal(InternalIO {
-                    graph: input_graph.clone(),
+                    graph: input_graph.clone().into(),
                     parameters: Default::default(),
                 });
 
                 let processed_graph = runtime.block_on(future_processed_graph)?.graph;
 
-                assert_eq!(expected_graph, processed_graph);
+                assert_eq!(expected_graph, *processed_graph);
 
                 Ok(())
             }
@@ -933,13 +964,13 @@ mod tests {
         let process_result = cincinnati::plugins::process(
             PLUGINS.iter(),
             cincinnati::plugins::PluginIO::InternalIO(cincinnati::plugins::InternalIO {
-                graph: input_graph.clone(),
+                graph: input_graph.clone().into(),
                 parameters: Default::default(),
             }),
         );
 
         let graph = runtime.block_on(process_result)?.graph;
-        assert_ne!(graph, input_graph);
+        assert_ne!(*graph, input_graph);
 
         Ok(())
     }
@@ -31,7 +31,7 @@ pub static DEFAULT_SIGNATURE_BASEURL: &str =
 pub static DEFAULT_SIGNATURE_FETCH_TIMEOUT_SECS: u64 = 30;
 
 /// Plugin settings.
-#[derive(Debug, SmartDefault, Clone, Deserialize)]
+#[derive(Debug, SmartDefault, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct DkrV2OpenshiftSecondaryMetadataScraperSettings {
     /// Directory where the image will be unpacked. Will be created if it doesn't exist.
@@ -63,8 +63,9 @@ pub struct DkrV2OpenshiftSecondaryMetadataScraperSettings {
     #[default(Option::None)]
     username: Option<String>,
 
-    /// Password for authenticating with the registry
+    /// Password for authenticating with the registry, never serialized
     #[default(Option::None)]
+    #[serde(skip_serializing)]
     password: Option<String>,
 
     /// File containing the credentials for authenticating with the registry.
@@ -243,6 +244,13 @@ impl PluginSettings for DkrV2OpenshiftSecondaryMetadataScraperSettings {
         let plugin = DkrV2OpenshiftSecondaryMetadataScraperPlugin::try_new(self.clone())?;
         Ok(new_plugin!(InternalPluginWrapper(plugin)))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(
+            DkrV2OpenshiftSecondaryMetadataScraperPlugin::PLUGIN_NAME,
+            self,
+        )
+    }
 }
 
 #[async_trait]
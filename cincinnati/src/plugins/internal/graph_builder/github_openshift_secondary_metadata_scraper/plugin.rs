@@ -66,7 +66,7 @@ impl TryFrom<(Option<&String>, Option<&String>)> for Reference {
 }
 
 /// Plugin settings.
-#[derive(Debug, SmartDefault, Clone, Deserialize)]
+#[derive(Debug, SmartDefault, Clone, Deserialize, Serialize)]
 #[serde(default)]
 pub struct GithubOpenshiftSecondaryMetadataScraperSettings {
     github_org: String,
@@ -468,6 +468,13 @@ impl PluginSettings for GithubOpenshiftSecondaryMetadataScraperSettings {
         let plugin = GithubOpenshiftSecondaryMetadataScraperPlugin::try_new(self.clone())?;
         Ok(new_plugin!(InternalPluginWrapper(plugin)))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(
+            GithubOpenshiftSecondaryMetadataScraperPlugin::PLUGIN_NAME,
+            self,
+        )
+    }
 }
 
 #[async_trait]
@@ -119,7 +119,7 @@ mod state {
 pub static DEFAULT_ARCH: &str = "amd64";
 
 /// Plugin settings.
-#[derive(Clone, Debug, Deserialize, SmartDefault)]
+#[derive(Clone, Debug, Deserialize, Serialize, SmartDefault)]
 #[serde(default)]
 pub struct OpenshiftSecondaryMetadataParserSettings {
     data_directory: PathBuf,
@@ -138,7 +138,7 @@ pub struct OpenshiftSecondaryMetadataParserSettings {
 impl OpenshiftSecondaryMetadataParserSettings {
     /// Validate plugin configuration and fill in defaults.
     pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
-        let settings: Self = cfg.try_into()?;
+        let settings: Self = commons::de::from_toml_value(cfg)?;
 
         ensure!(!settings.key_prefix.is_empty(), "empty key_prefix");
         ensure!(!settings.default_arch.is_empty(), "empty default_arch");
@@ -171,6 +171,10 @@ impl PluginSettings for OpenshiftSecondaryMetadataParserSettings {
         let plugin = OpenshiftSecondaryMetadataParserPlugin::new(self.clone());
         Ok(new_plugin!(InternalPluginWrapper(plugin)))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(OpenshiftSecondaryMetadataParserPlugin::PLUGIN_NAME, self)
+    }
 }
 
 #[derive(Debug, Fail, strum_macros::EnumDiscriminants)]
@@ -431,7 +435,7 @@ impl OpenshiftSecondaryMetadataParserPlugin {
         let architectures = {
             let mut collection = std::collections::BTreeSet::<Vec<semver::Identifier>>::new();
 
-            let _ = graph.find_by_fn_mut(|release| {
+            let _ = graph.find_by_fn(|release| {
                 match semver::Version::from_str(release.version()) {
                     Ok(version_semver) => {
                         collection.insert(version_semver.build);
@@ -579,7 +583,7 @@ impl OpenshiftSecondaryMetadataParserPlugin {
                         matching_rules: cey.matching_rules,
                     }],
                 };
-                graph.conditional_edges.as_mut().unwrap().push(ce);
+                graph.add_conditional_edge_declaration(ce);
 
                 Ok(())
             })?;
@@ -610,7 +614,7 @@ impl OpenshiftSecondaryMetadataParserPlugin {
                 .iter()
                 .collect::<Vec<&semver::Version>>();
 
-            let releases_in_channel = graph.find_by_fn_mut(|release| {
+            let releases_in_channel = graph.find_by_fn(|release| {
                 let release_semver = match semver::Version::from_str(release.version())
                     .context(format!("Parsing {} as SemVer", release.version()))
                 {
@@ -694,11 +698,11 @@ impl InternalPlugin for OpenshiftSecondaryMetadataParserPlugin {
         let data_dir = self.get_data_directory(&io);
 
         self.process_version(&data_dir).await?;
-        self.process_raw_metadata(&mut io.graph, &data_dir).await?;
-        self.process_blocked_edges(&mut io.graph, &data_dir).await?;
-        self.process_conditional_edges(&mut io.graph, &data_dir)
-            .await?;
-        self.process_channels(&mut io.graph, &data_dir).await?;
+        let graph = io.graph_mut();
+        self.process_raw_metadata(graph, &data_dir).await?;
+        self.process_blocked_edges(graph, &data_dir).await?;
+        self.process_conditional_edges(graph, &data_dir).await?;
+        self.process_channels(graph, &data_dir).await?;
 
         Ok(io)
     }
@@ -773,7 +777,7 @@ mod tests {
             // Run the plugin
             let io = runtime
                 .block_on(plugin.run_internal(InternalIO {
-                    graph: graph_raw,
+                    graph: graph_raw.into(),
                     parameters: Default::default(),
                 }))
                 .context("Running plugin")
@@ -794,7 +798,7 @@ mod tests {
         let graph_expected = {
             runtime
                 .block_on(edge_add_remove_plugin.run_internal(InternalIO {
-                    graph: graph_with_quay_metadata,
+                    graph: graph_with_quay_metadata.into(),
                     parameters: Default::default(),
                 }))
                 .context(
@@ -805,8 +809,8 @@ mod tests {
         };
 
         if let Err(e) = compare_graphs_verbose(
-            graph_expected,
-            graph_result,
+            (*graph_expected).clone(),
+            (*graph_result).clone(),
             cincinnati::testing::CompareGraphsVerboseSettings {
                 unwanted_metadata_keys: &[
                     "io.openshift.upgrades.graph.previous.remove_regex",
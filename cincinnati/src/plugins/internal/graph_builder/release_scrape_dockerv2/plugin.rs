@@ -23,7 +23,7 @@ pub static DEFAULT_MANIFESTREF_KEY: &str = "io.openshift.upgrades.graph.release.
 pub static DEFAULT_FETCH_CONCURRENCY: usize = 16;
 
 /// Plugin settings.
-#[derive(Clone, Debug, Deserialize, SmartDefault)]
+#[derive(Clone, Debug, Deserialize, Serialize, SmartDefault)]
 #[serde(default)]
 pub struct ReleaseScrapeDockerv2Settings {
     #[default(DEFAULT_SCRAPE_REGISTRY.to_string())]
@@ -43,8 +43,9 @@ pub struct ReleaseScrapeDockerv2Settings {
     #[default(Option::None)]
     pub username: Option<String>,
 
-    /// Password for authenticating with the registry
+    /// Password for authenticating with the registry, never serialized
     #[default(Option::None)]
+    #[serde(skip_serializing)]
     pub password: Option<String>,
 
     /// File containing the credentials for authenticating with the registry.
@@ -63,12 +64,16 @@ impl PluginSettings for ReleaseScrapeDockerv2Settings {
         let plugin = ReleaseScrapeDockerv2Plugin::try_new(self.clone(), None, registry)?;
         Ok(new_plugin!(InternalPluginWrapper(plugin)))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(ReleaseScrapeDockerv2Plugin::PLUGIN_NAME, self)
+    }
 }
 
 impl ReleaseScrapeDockerv2Settings {
     /// Validate plugin configuration and fill in defaults.
     pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
-        let mut settings: Self = cfg.try_into()?;
+        let mut settings: Self = commons::de::from_toml_value(cfg)?;
 
         ensure!(!settings.repository.is_empty(), "empty repository");
         ensure!(!settings.registry.is_empty(), "empty registry");
@@ -200,7 +205,7 @@ impl InternalPlugin for ReleaseScrapeDockerv2Plugin {
         let graph = cincinnati::plugins::internal::graph_builder::release::create_graph(releases)?;
 
         Ok(InternalIO {
-            graph,
+            graph: graph.into(),
             parameters: io.parameters,
         })
     }
@@ -50,7 +50,9 @@ fn scrape_private_with_credentials_must_succeed() -> Fallible<()> {
             graph: Default::default(),
             parameters: Default::default(),
         }))?
-        .graph;
+        .graph
+        .as_ref()
+        .clone();
 
     let expected_graph = {
         let input_edges = Some(vec![(0, 1)]);
@@ -169,7 +171,9 @@ fn scrape_public_with_no_release_metadata_must_not_error() -> Fallible<()> {
             parameters: Default::default(),
         }))
         .context("should not error on emtpy repo")?
-        .graph;
+        .graph
+        .as_ref()
+        .clone();
 
     crate::testing::compare_graphs_verbose(
         Default::default(),
@@ -208,7 +212,9 @@ fn scrape_public_with_first_empty_tag_must_succeed() -> Fallible<()> {
             graph: Default::default(),
             parameters: Default::default(),
         }))?
-        .graph;
+        .graph
+        .as_ref()
+        .clone();
 
     let expected_graph = {
         let input_edges = Some(vec![(0, 1)]);
@@ -298,7 +304,9 @@ fn scrape_public_must_succeed_with_various_registry_urls(registry: &str) {
             parameters: Default::default(),
         }))
         .unwrap()
-        .graph;
+        .graph
+        .as_ref()
+        .clone();
 
     let expected_graph = {
         let input_edges = Some(vec![(0, 1)]);
@@ -384,7 +392,7 @@ fn scrape_public_with_cyclic_metadata_fails() -> Fallible<()> {
         .expect_err("create_graph succeeded despite cyclic metadata");
 
     ensure!(
-        err.downcast_ref::<crate::WouldCycle<crate::Empty>>()
+        err.downcast_ref::<crate::WouldCycle<crate::EdgeWeight>>()
             .is_some(),
         "error {:#?} has wrong type",
         err,
@@ -423,7 +431,9 @@ fn scrape_public_multiarch_manual_succeeds() -> Fallible<()> {
             graph: Default::default(),
             parameters: Default::default(),
         }))?
-        .graph;
+        .graph
+        .as_ref()
+        .clone();
 
     let expected_graph: cincinnati::Graph = {
         let input_edges = Some(vec![(0, 1), (1, 2), (2, 3), (3, 4), (5, 6)]);
@@ -0,0 +1,271 @@
+//! This plugin runs semantic consistency checks against the graph.
+//!
+//! Findings are logged and counted per check. In `fail` mode any finding
+//! aborts the plugin chain; in `warn` mode the graph is passed on unchanged.
+
+use crate as cincinnati;
+
+use self::cincinnati::lint::{self, Linter};
+use self::cincinnati::plugins::prelude::*;
+use self::cincinnati::plugins::prelude_plugin_impl::*;
+use lazy_static::lazy_static;
+use prometheus::{IntCounterVec, Opts};
+
+lazy_static! {
+    static ref GRAPH_LINT_FINDINGS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "graph_lint_findings_total",
+            "Total number of findings reported by graph lint checks"
+        ),
+        &["check"]
+    )
+    .unwrap();
+}
+
+/// Prefix for the metadata key operations.
+pub static DEFAULT_KEY_FILTER: &str = "io.openshift.upgrades.graph";
+
+/// Default suffix for the metadata key holding the channels.
+pub static DEFAULT_CHANNEL_KEY: &str = "release.channels";
+
+/// Default suffix for the metadata key holding the architecture.
+pub static DEFAULT_ARCH_KEY: &str = "release.arch";
+
+/// What to do when checks report findings.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LintMode {
+    /// Log findings and continue.
+    Warn,
+    /// Log findings and fail the plugin chain.
+    Fail,
+}
+
+/// Plugin settings.
+#[derive(Clone, Debug, Deserialize, Serialize, SmartDefault)]
+#[serde(default)]
+struct GraphLintSettings {
+    #[default(LintMode::Warn)]
+    mode: LintMode,
+
+    /// Names of the checks to run; the default checks are run if empty.
+    checks: Vec<String>,
+
+    #[default(DEFAULT_KEY_FILTER.to_string())]
+    key_prefix: String,
+
+    #[default(DEFAULT_CHANNEL_KEY.to_string())]
+    key_suffix: String,
+
+    #[default(DEFAULT_ARCH_KEY.to_string())]
+    arch_key_suffix: String,
+}
+
+/// Graph linter.
+#[derive(Debug)]
+pub struct GraphLintPlugin {
+    /// What to do when checks report findings.
+    pub mode: LintMode,
+
+    linter: Linter,
+}
+
+impl PluginSettings for GraphLintSettings {
+    fn build_plugin(&self, registry: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
+        let plugin = GraphLintPlugin::try_new(self, registry)?;
+        Ok(new_plugin!(InternalPluginWrapper(plugin)))
+    }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(GraphLintPlugin::PLUGIN_NAME, self)
+    }
+}
+
+impl GraphLintPlugin {
+    /// Plugin name, for configuration.
+    pub const PLUGIN_NAME: &'static str = "graph-lint";
+
+    /// Validate plugin configuration and fill in defaults.
+    pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+        let settings: GraphLintSettings = commons::de::from_toml_value(cfg)?;
+
+        ensure!(!settings.key_prefix.is_empty(), "empty prefix");
+        ensure!(!settings.key_suffix.is_empty(), "empty suffix");
+        ensure!(!settings.arch_key_suffix.is_empty(), "empty arch suffix");
+        for check in &settings.checks {
+            ensure!(
+                lint::check_by_name(check, &settings.metadata_keys()).is_some(),
+                "unknown lint check '{}'",
+                check
+            );
+        }
+
+        Ok(Box::new(settings))
+    }
+
+    fn try_new(
+        settings: &GraphLintSettings,
+        prometheus_registry: Option<&prometheus::Registry>,
+    ) -> Fallible<Self> {
+        let keys = settings.metadata_keys();
+        let linter = if settings.checks.is_empty() {
+            Linter::new(lint::default_checks(&keys))
+        } else {
+            Linter::new(
+                settings
+                    .checks
+                    .iter()
+                    .map(|name| {
+                        lint::check_by_name(name, &keys)
+                            .ok_or_else(|| format_err!("unknown lint check '{}'", name))
+                    })
+                    .collect::<Fallible<_>>()?,
+            )
+        };
+
+        // Initialize all series, so that checks without findings are exported too.
+        for check in linter.checks() {
+            GRAPH_LINT_FINDINGS.with_label_values(&[check.name()]);
+        }
+
+        if let Some(registry) = &prometheus_registry {
+            cincinnati::plugins::register_shared_collectors(
+                registry,
+                vec![Box::new(GRAPH_LINT_FINDINGS.clone())],
+            )?;
+        };
+
+        Ok(Self {
+            mode: settings.mode,
+            linter,
+        })
+    }
+}
+
+impl GraphLintSettings {
+    fn metadata_keys(&self) -> MetadataKeys {
+        let key = |suffix: &str| format!("{}.{}", self.key_prefix, suffix);
+
+        MetadataKeys::with_prefix(&self.key_prefix)
+            .with_channels_key(key(&self.key_suffix))
+            .with_arch_key(key(&self.arch_key_suffix))
+    }
+}
+
+#[async_trait]
+impl InternalPlugin for GraphLintPlugin {
+    const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+    const PARAMETERS: Option<&'static [&'static str]> = Some(&[]);
+
+    async fn run_internal(&self, io: InternalIO) -> Fallible<InternalIO> {
+        let findings = self.linter.run(&io.graph);
+
+        for finding in &findings {
+            GRAPH_LINT_FINDINGS
+                .with_label_values(&[finding.check])
+                .inc();
+            warn!("graph lint: {}", finding);
+        }
+
+        if self.mode == LintMode::Fail && !findings.is_empty() {
+            bail!("graph lint reported {} findings", findings.len());
+        }
+
+        Ok(io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as cincinnati;
+    use cincinnati::testing::generate_graph;
+    use commons::testing::init_runtime;
+
+    fn build(cfg: &str) -> Fallible<GraphLintPlugin> {
+        let settings: GraphLintSettings = toml::from_str(cfg)?;
+        GraphLintPlugin::try_new(&settings, None)
+    }
+
+    #[test]
+    fn deserialize_config() {
+        GraphLintPlugin::deserialize_config(toml::from_str("name = 'graph-lint'").unwrap())
+            .unwrap();
+        GraphLintPlugin::deserialize_config(
+            toml::from_str("name = 'graph-lint'\nchecks = ['downgrade-edges']").unwrap(),
+        )
+        .unwrap();
+        GraphLintPlugin::deserialize_config(
+            toml::from_str("name = 'graph-lint'\nchecks = ['unknown']").unwrap(),
+        )
+        .unwrap_err();
+        GraphLintPlugin::deserialize_config(
+            toml::from_str(
+                "name = 'graph-lint'\nchecks = ['duplicate-versions-across-arches']\narch_key_suffix = 'custom.arch'",
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        GraphLintPlugin::deserialize_config(
+            toml::from_str("name = 'graph-lint'\nmode = 'panic'").unwrap(),
+        )
+        .unwrap_err();
+    }
+
+    #[test]
+    fn warn_mode_passes_graph() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let plugin = build("checks = ['shadowed-conditional-edges']")?;
+        let graph = generate_graph(true, false);
+        let findings = || {
+            GRAPH_LINT_FINDINGS
+                .with_label_values(&["shadowed-conditional-edges"])
+                .get()
+        };
+        let findings_before = findings();
+
+        let io = runtime.block_on(plugin.run_internal(InternalIO {
+            graph: graph.clone().into(),
+            parameters: Default::default(),
+        }))?;
+
+        assert_eq!(*io.graph, graph);
+        // Other tests may report findings for the same check concurrently.
+        assert!(findings() > findings_before);
+        Ok(())
+    }
+
+    #[test]
+    fn build_several_instances() -> Fallible<()> {
+        let registry = prometheus::Registry::new();
+        let settings: GraphLintSettings = toml::from_str("mode = 'warn'")?;
+
+        GraphLintPlugin::try_new(&settings, Some(&registry))?;
+        GraphLintPlugin::try_new(&settings, Some(&registry))?;
+
+        assert!(registry
+            .gather()
+            .iter()
+            .any(|family| family.get_name() == "graph_lint_findings_total"));
+        Ok(())
+    }
+
+    #[test]
+    fn fail_mode_fails_on_findings() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let plugin = build("mode = 'fail'\nchecks = ['shadowed-conditional-edges']")?;
+
+        runtime
+            .block_on(plugin.run_internal(InternalIO {
+                graph: generate_graph(true, false).into(),
+                parameters: Default::default(),
+            }))
+            .unwrap_err();
+
+        runtime.block_on(plugin.run_internal(InternalIO {
+            graph: generate_graph(false, false).into(),
+            parameters: Default::default(),
+        }))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,670 @@
+//! This plugin runs several plugin chains and merges the resulting graphs.
+//!
+//! Every chain runs on a copy of the plugin's input, concurrently with the other
+//! chains. The resulting graphs are merged in the order of the chains, resolving
+//! releases which exist in several graphs according to the configured strategy.
+//! All chains run to completion, and the errors of all failed chains are reported.
+//! The plugins of the chains are run like top-level plugins, with their own spans
+//! and run metrics, but they don't export metrics of their own.
+//!
+//! The resulting parameters of the chains are merged in the same order, values
+//! which several chains set differently being resolved by the `parameter_strategy`.
+//! By default the later chain wins, as a later plugin of a sequential chain would;
+//! `ignore` passes on the parameters of the input unchanged instead.
+//!
+//! The plugin can also be configured as `parallel`, with `branches` and
+//! `graph_strategy` as aliases of `chains` and `strategy`:
+//!
+//! ```toml
+//! [[plugin_settings]]
+//! name = "parallel"
+//! graph_strategy = "merge-metadata"
+//!
+//! [[plugin_settings.branches]]
+//! name = "ocp"
+//! plugins = [
+//!   { name = "release-scrape-dockerv2", repository = "openshift-release-dev/ocp-release" },
+//! ]
+//!
+//! [[plugin_settings.branches]]
+//! name = "secondary-metadata"
+//! plugins = [
+//!   { name = "github-secondary-metadata-scrape", output_directory = "/tmp/graph-data" },
+//! ]
+//! ```
+//!
+//! ```toml
+//! [[plugin_settings]]
+//! name = "graph-merge"
+//! strategy = "merge-metadata"
+//! parameter_strategy = "error"
+//!
+//! [[plugin_settings.chains]]
+//! name = "ocp"
+//! plugins = [
+//!   { name = "release-scrape-dockerv2", repository = "openshift-release-dev/ocp-release" },
+//! ]
+//!
+//! [[plugin_settings.chains]]
+//! name = "okd"
+//! plugins = [
+//!   { name = "release-scrape-dockerv2", repository = "openshift-release-dev/okd-release" },
+//! ]
+//! ```
+
+use crate as cincinnati;
+
+use self::cincinnati::plugins::catalog::{self, build_plugins};
+use self::cincinnati::plugins::prelude::*;
+use self::cincinnati::plugins::prelude_plugin_impl::*;
+use self::cincinnati::plugins::{run_plugin, Plugin, PluginIO};
+use self::cincinnati::MergeStrategy;
+use commons::de::in_toml_path;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::sync::Arc;
+
+/// How the resulting parameters of the chains are merged.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParameterStrategy {
+    /// Pass on the parameters of the input, ignoring the ones of the chains.
+    Ignore,
+    /// Keep the value of the earlier chain.
+    PreferLeft,
+    /// Keep the value of the later chain.
+    PreferRight,
+    /// Fail on parameters which several chains set to different values.
+    Error,
+}
+
+impl Default for ParameterStrategy {
+    fn default() -> Self {
+        ParameterStrategy::PreferRight
+    }
+}
+
+/// Configuration of a single plugin chain.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct ChainConfig {
+    name: Option<String>,
+    plugins: Vec<toml::Value>,
+}
+
+/// Plugin configuration, as deserialized.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+struct GraphMergeConfig {
+    #[serde(alias = "graph_strategy")]
+    strategy: MergeStrategy,
+    parameter_strategy: ParameterStrategy,
+    #[serde(alias = "branches")]
+    chains: Vec<ChainConfig>,
+}
+
+/// Plugin settings.
+#[derive(Debug)]
+pub struct GraphMergeSettings {
+    /// Whether the plugin was configured as `parallel`.
+    parallel: bool,
+    strategy: MergeStrategy,
+    parameter_strategy: ParameterStrategy,
+    chains: Vec<(String, Vec<Box<dyn PluginSettings>>)>,
+}
+
+/// Merges the graphs of several plugin chains.
+#[derive(Debug)]
+pub struct GraphMergePlugin {
+    /// How releases which exist in several graphs are resolved.
+    pub strategy: MergeStrategy,
+
+    /// How the resulting parameters of the chains are merged.
+    pub parameter_strategy: ParameterStrategy,
+
+    chains: Vec<(String, Vec<BoxedPlugin>)>,
+}
+
+impl PluginSettings for GraphMergeSettings {
+    fn build_plugin(&self, _: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
+        // The chains may contain the same plugins several times, which would
+        // register the same metrics repeatedly.
+        let chains = self
+            .chains
+            .iter()
+            .map(|(name, chain)| {
+                let plugins =
+                    build_plugins(chain, None).context(format!("building chain '{}'", name))?;
+                Ok((name.clone(), plugins))
+            })
+            .collect::<Fallible<_>>()?;
+
+        let plugin = GraphMergePlugin {
+            strategy: self.strategy,
+            parameter_strategy: self.parameter_strategy,
+            chains,
+        };
+
+        if self.parallel {
+            Ok(new_plugin!(InternalPluginWrapper(ParallelPlugin(plugin))))
+        } else {
+            Ok(new_plugin!(InternalPluginWrapper(plugin)))
+        }
+    }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        let chains = self
+            .chains
+            .iter()
+            .map(|(name, chain)| {
+                let plugins = chain
+                    .iter()
+                    .map(|settings| settings.serialize_config())
+                    .collect::<Fallible<_>>()?;
+                Ok(ChainConfig {
+                    name: Some(name.clone()),
+                    plugins,
+                })
+            })
+            .collect::<Fallible<_>>()?;
+        let config = GraphMergeConfig {
+            strategy: self.strategy,
+            parameter_strategy: self.parameter_strategy,
+            chains,
+        };
+
+        let name = if self.parallel {
+            ParallelPlugin::PLUGIN_NAME
+        } else {
+            GraphMergePlugin::PLUGIN_NAME
+        };
+        serialize_plugin_config(name, &config)
+    }
+}
+
+impl GraphMergeSettings {
+    /// Validate plugin configuration and fill in defaults.
+    pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+        Ok(Box::new(Self::deserialize(cfg, false)?))
+    }
+
+    /// Validate the configuration of a `parallel` plugin and fill in defaults.
+    pub fn deserialize_parallel_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+        Ok(Box::new(Self::deserialize(cfg, true)?))
+    }
+
+    fn deserialize(cfg: toml::Value, parallel: bool) -> Fallible<Self> {
+        let config: GraphMergeConfig = commons::de::from_toml_value(cfg)?;
+
+        ensure!(!config.chains.is_empty(), "no chains configured");
+        let chains = config
+            .chains
+            .into_iter()
+            .enumerate()
+            .map(|(i, chain)| {
+                let name = chain.name.unwrap_or_else(|| i.to_string());
+                ensure!(!chain.plugins.is_empty(), "chain '{}' has no plugins", name);
+                let plugins = chain
+                    .plugins
+                    .into_iter()
+                    .enumerate()
+                    .map(|(j, cfg)| {
+                        catalog::deserialize_config(cfg)
+                            .map_err(|e| in_toml_path(e, &format!("chains[{}].plugins[{}]", i, j)))
+                    })
+                    .collect::<Fallible<Vec<_>>>()?;
+                Ok((name, plugins))
+            })
+            .collect::<Fallible<_>>()?;
+
+        Ok(GraphMergeSettings {
+            parallel,
+            strategy: config.strategy,
+            parameter_strategy: config.parameter_strategy,
+            chains,
+        })
+    }
+}
+
+impl GraphMergePlugin {
+    /// Plugin name, for configuration.
+    pub const PLUGIN_NAME: &'static str = "graph-merge";
+}
+
+/// `graph-merge` plugin configured as `parallel`.
+#[derive(Debug)]
+pub struct ParallelPlugin(pub GraphMergePlugin);
+
+impl ParallelPlugin {
+    /// Plugin name, for configuration.
+    pub const PLUGIN_NAME: &'static str = "parallel";
+}
+
+/// Runs the plugins of a chain sequentially.
+async fn run_chain(chain: &[BoxedPlugin], io: InternalIO) -> Fallible<InternalIO> {
+    let mut io = PluginIO::from(io);
+    for plugin in chain {
+        io = run_plugin(plugin, io).await?;
+    }
+
+    io.try_into()
+}
+
+/// Merges the `other` parameters into `parameters`, resolving conflicting values
+/// according to `strategy`.
+fn merge_parameters(
+    parameters: &mut HashMap<String, String>,
+    other: HashMap<String, String>,
+    strategy: ParameterStrategy,
+) -> Fallible<()> {
+    for (key, value) in other {
+        match parameters.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+            Entry::Occupied(entry) if *entry.get() == value => {}
+            Entry::Occupied(mut entry) => match strategy {
+                ParameterStrategy::Ignore | ParameterStrategy::PreferLeft => {}
+                ParameterStrategy::PreferRight => {
+                    entry.insert(value);
+                }
+                ParameterStrategy::Error => bail!(
+                    "conflicting values '{}' and '{}' for parameter '{}'",
+                    entry.get(),
+                    value,
+                    entry.key()
+                ),
+            },
+        }
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl InternalPlugin for GraphMergePlugin {
+    const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+
+    async fn run_internal(&self, io: InternalIO) -> Fallible<InternalIO> {
+        let chains = self
+            .chains
+            .iter()
+            .map(|(_, chain)| run_chain(chain, io.clone()));
+        let results = futures::future::join_all(chains).await;
+
+        let mut outputs = Vec::with_capacity(results.len());
+        let mut errors = Vec::new();
+        for ((name, _), result) in self.chains.iter().zip(results) {
+            match result {
+                Ok(output) => outputs.push((name, output)),
+                Err(e) => errors.push(e.context(format!("chain '{}' failed", name))),
+            }
+        }
+        match errors.len() {
+            0 => {}
+            // Keep the error itself, so that it can still be downcast.
+            1 => return Err(errors.remove(0)),
+            n => bail!(
+                "{} chains failed: {}",
+                n,
+                errors
+                    .iter()
+                    .map(|e| format!("{:#}", e))
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        }
+
+        let mut outputs = outputs.into_iter();
+        let (_, mut merged) = outputs
+            .next()
+            .ok_or_else(|| format_err!("no chains configured"))?;
+        for (name, output) in outputs {
+            let graph = Arc::try_unwrap(output.graph).unwrap_or_else(|graph| (*graph).clone());
+            merged
+                .graph_mut()
+                .merge(graph, self.strategy)
+                .context(format!("merging the graph of chain '{}'", name))?;
+            merge_parameters(
+                &mut merged.parameters,
+                output.parameters,
+                self.parameter_strategy,
+            )
+            .context(format!("merging the parameters of chain '{}'", name))?;
+        }
+
+        if self.parameter_strategy == ParameterStrategy::Ignore {
+            merged.parameters = io.parameters;
+        }
+
+        Ok(merged)
+    }
+
+    fn nested_plugins(&self) -> Vec<&BoxedPlugin> {
+        self.chains
+            .iter()
+            .flat_map(|(_, chain)| chain.iter())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl InternalPlugin for ParallelPlugin {
+    const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+
+    async fn run_internal(&self, io: InternalIO) -> Fallible<InternalIO> {
+        self.0.run_internal(io).await
+    }
+
+    fn nested_plugins(&self) -> Vec<&BoxedPlugin> {
+        self.0.nested_plugins()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cincinnati::testing::{generate_custom_graph, TestMetadata};
+    use commons::testing::init_runtime;
+    use commons::GraphError;
+
+    /// Sets a parameter.
+    #[derive(Debug)]
+    struct SetParameterPlugin(&'static str, &'static str);
+
+    #[async_trait]
+    impl InternalPlugin for SetParameterPlugin {
+        const PLUGIN_NAME: &'static str = "set-parameter";
+
+        async fn run_internal(&self, mut io: InternalIO) -> Fallible<InternalIO> {
+            io.parameters.insert(self.0.to_string(), self.1.to_string());
+            Ok(io)
+        }
+    }
+
+    fn remove_label(prefix: &str) -> (String, String) {
+        (format!("{}.release.remove", prefix), "true".to_string())
+    }
+
+    fn build_plugin(cfg: &str) -> Fallible<BoxedPlugin> {
+        GraphMergeSettings::deserialize_config(toml::from_str(cfg)?)?.build_plugin(None)
+    }
+
+    #[test]
+    fn merges_outputs_of_chains() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let metadata: TestMetadata = vec![
+            (0, Default::default()),
+            (1, vec![remove_label("a")].into_iter().collect()),
+            (2, vec![remove_label("b")].into_iter().collect()),
+            (3, Default::default()),
+        ];
+        let input_graph = generate_custom_graph("image", metadata.clone(), None);
+        let expected_graph = generate_custom_graph("image", metadata, Some(vec![(0, 1), (2, 3)]));
+
+        let plugin = build_plugin(
+            r#"
+            name = "graph-merge"
+            strategy = "error"
+
+            [[chains]]
+            plugins = [ { name = "node-remove", key_prefix = "a" } ]
+
+            [[chains]]
+            plugins = [ { name = "node-remove", key_prefix = "b" } ]
+            "#,
+        )?;
+
+        let io = runtime.block_on(plugin.run(PluginIO::InternalIO(InternalIO {
+            graph: input_graph.into(),
+            parameters: Default::default(),
+        })))?;
+        let processed_graph = TryInto::<InternalIO>::try_into(io)?.graph;
+
+        assert_eq!(expected_graph, *processed_graph);
+
+        Ok(())
+    }
+
+    #[test]
+    fn chains_are_instrumented_and_expose_conditions() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let plugin = build_plugin(
+            r#"
+            name = "graph-merge"
+
+            [[chains]]
+            plugins = [
+              { name = "node-remove", when = { header = "X-Remove", present = true } },
+            ]
+            "#,
+        )?;
+
+        let conditions = cincinnati::plugins::all_conditions(std::iter::once(&plugin));
+        assert_eq!(
+            conditions
+                .iter()
+                .filter_map(|condition| condition.header())
+                .collect::<Vec<_>>(),
+            vec!["X-Remove"]
+        );
+
+        let runs = || {
+            cincinnati::plugins::PLUGIN_RUN_DURATION
+                .with_label_values(&["node-remove"])
+                .get_sample_count()
+        };
+        let runs_before = runs();
+        runtime.block_on(plugin.run(PluginIO::InternalIO(InternalIO {
+            graph: Default::default(),
+            parameters: Default::default(),
+        })))?;
+        assert!(runs() > runs_before);
+
+        Ok(())
+    }
+
+    fn parameter_plugin(strategy: ParameterStrategy) -> GraphMergePlugin {
+        let set_parameters = |parameters: &[(&'static str, &'static str)]| -> Vec<BoxedPlugin> {
+            parameters
+                .iter()
+                .map(|(key, value)| -> BoxedPlugin {
+                    new_plugin!(InternalPluginWrapper(SetParameterPlugin(key, value)))
+                })
+                .collect()
+        };
+
+        GraphMergePlugin {
+            strategy: MergeStrategy::Error,
+            parameter_strategy: strategy,
+            chains: vec![
+                (
+                    "a".to_string(),
+                    set_parameters(&[("shared", "a"), ("a", "1")]),
+                ),
+                (
+                    "b".to_string(),
+                    set_parameters(&[("shared", "b"), ("b", "2")]),
+                ),
+            ],
+        }
+    }
+
+    #[test]
+    fn merges_parameters_of_chains() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        for (strategy, expected) in &[
+            (ParameterStrategy::PreferLeft, Some("a")),
+            (ParameterStrategy::PreferRight, Some("b")),
+            (ParameterStrategy::Error, None),
+            (ParameterStrategy::default(), Some("b")),
+        ] {
+            let result = runtime.block_on(
+                parameter_plugin(*strategy).run_internal(InternalIO {
+                    graph: Default::default(),
+                    parameters: vec![("input".to_string(), "0".to_string())]
+                        .into_iter()
+                        .collect(),
+                }),
+            );
+
+            match (result, expected) {
+                (Ok(io), Some(expected)) => {
+                    assert_eq!(
+                        io.parameters.get("shared").map(String::as_str),
+                        Some(*expected)
+                    );
+                    for (key, value) in &[("input", "0"), ("a", "1"), ("b", "2")] {
+                        assert_eq!(io.parameters.get(*key).map(String::as_str), Some(*value));
+                    }
+                }
+                (Err(e), None) => {
+                    assert!(format!("{:#}", e).contains("conflicting values"), "{:#}", e)
+                }
+                (result, _) => panic!("unexpected result for {:?}: {:?}", strategy, result),
+            }
+        }
+
+        let io = runtime.block_on(
+            parameter_plugin(ParameterStrategy::Ignore).run_internal(InternalIO {
+                graph: Default::default(),
+                parameters: vec![("input".to_string(), "0".to_string())]
+                    .into_iter()
+                    .collect(),
+            }),
+        )?;
+        assert_eq!(
+            io.parameters,
+            vec![("input".to_string(), "0".to_string())]
+                .into_iter()
+                .collect()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn configure_as_parallel() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let metadata: TestMetadata = vec![
+            (0, vec![remove_label("a")].into_iter().collect()),
+            (1, Default::default()),
+        ];
+        let input_graph = generate_custom_graph("image", metadata.clone(), None);
+
+        let plugin = catalog::deserialize_config(toml::from_str(
+            r#"
+            name = "parallel"
+            graph_strategy = "error"
+
+            [[branches]]
+            plugins = [ { name = "node-remove", key_prefix = "a" } ]
+            "#,
+        )?)?
+        .build_plugin(None)?;
+        assert_eq!(plugin.get_name(), ParallelPlugin::PLUGIN_NAME);
+        assert_eq!(plugin.nested_plugins().len(), 1);
+
+        let io = runtime.block_on(plugin.run(PluginIO::InternalIO(InternalIO {
+            graph: input_graph.into(),
+            parameters: Default::default(),
+        })))?;
+        let processed_graph = TryInto::<InternalIO>::try_into(io)?.graph;
+
+        assert_eq!(
+            generate_custom_graph("image", metadata[1..].to_vec(), None),
+            *processed_graph
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_errors_per_chain() -> Fallible<()> {
+        let runtime = init_runtime()?;
+
+        let run = |cfg: &str| -> Fallible<Fallible<PluginIO>> {
+            let plugin = build_plugin(cfg)?;
+            Ok(
+                runtime.block_on(plugin.run(PluginIO::InternalIO(InternalIO {
+                    graph: Default::default(),
+                    parameters: Default::default(),
+                }))),
+            )
+        };
+
+        // The channel filter fails on the missing channel parameter.
+        let err = run(r#"
+            name = "graph-merge"
+
+            [[chains]]
+            name = "filter"
+            plugins = [ { name = "channel-filter" } ]
+
+            [[chains]]
+            plugins = [ { name = "node-remove" } ]
+            "#)?
+        .unwrap_err();
+        assert!(
+            err.to_string().contains("chain 'filter' failed"),
+            "{:#}",
+            err
+        );
+        assert!(err.downcast_ref::<GraphError>().is_some(), "{:#}", err);
+
+        let err = run(r#"
+            name = "graph-merge"
+
+            [[chains]]
+            name = "first"
+            plugins = [ { name = "channel-filter" } ]
+
+            [[chains]]
+            name = "second"
+            plugins = [ { name = "channel-filter" } ]
+            "#)?
+        .unwrap_err();
+        let message = err.to_string();
+        assert!(message.starts_with("2 chains failed"), "{}", message);
+        assert!(message.contains("chain 'first' failed"), "{}", message);
+        assert!(message.contains("chain 'second' failed"), "{}", message);
+
+        Ok(())
+    }
+
+    #[test]
+    fn deserialize_config_validates_chains() {
+        for cfg in &[
+            r#"name = "graph-merge""#,
+            r#"
+            name = "graph-merge"
+            [[chains]]
+            plugins = []
+            "#,
+            r#"
+            name = "graph-merge"
+            [[chains]]
+            plugins = [ { name = "no-such-plugin" } ]
+            "#,
+            r#"
+            name = "graph-merge"
+            strategy = "no-such-strategy"
+            [[chains]]
+            plugins = [ { name = "node-remove" } ]
+            "#,
+            r#"
+            name = "graph-merge"
+            parameter_strategy = "merge-metadata"
+            [[chains]]
+            plugins = [ { name = "node-remove" } ]
+            "#,
+        ] {
+            assert!(build_plugin(cfg).is_err(), "accepted config: {}", cfg);
+        }
+    }
+}
@@ -14,7 +14,7 @@ pub static DEFAULT_QUAY_MANIFESTREF_KEY: &str = "io.openshift.upgrades.graph.rel
 pub static DEFAULT_QUAY_REPOSITORY: &str = "openshift";
 
 /// Plugin settings.
-#[derive(Clone, Debug, Deserialize, SmartDefault)]
+#[derive(Clone, Debug, Deserialize, Serialize, SmartDefault)]
 #[serde(default)]
 struct QuayMetadataSettings {
     #[default(quay::v1::DEFAULT_API_BASE.to_string())]
@@ -54,6 +54,10 @@ impl PluginSettings for QuayMetadataSettings {
         )?;
         Ok(new_plugin!(InternalPluginWrapper(plugin)))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(QuayMetadataFetchPlugin::PLUGIN_NAME, self)
+    }
 }
 
 impl QuayMetadataFetchPlugin {
@@ -62,7 +66,7 @@ impl QuayMetadataFetchPlugin {
 
     /// Validate plugin configuration and fill in defaults.
     pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
-        let settings: QuayMetadataSettings = cfg.try_into()?;
+        let settings: QuayMetadataSettings = commons::de::from_toml_value(cfg)?;
 
         ensure!(!settings.repository.is_empty(), "empty repository");
         ensure!(!settings.label_filter.is_empty(), "empty label_filter");
@@ -99,14 +103,13 @@ impl QuayMetadataFetchPlugin {
 #[async_trait]
 impl InternalPlugin for QuayMetadataFetchPlugin {
     const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+    const PARAMETERS: Option<&'static [&'static str]> = Some(&[]);
 
-    async fn run_internal(&self, io: InternalIO) -> Fallible<InternalIO> {
-        let (mut graph, parameters) = (io.graph, io.parameters);
-
+    async fn run_internal(&self, mut io: InternalIO) -> Fallible<InternalIO> {
         trace!("fetching metadata from quay labels...");
 
         let release_manifestrefs: Vec<(ReleaseId, String, String)> =
-            graph.find_by_metadata_key(&self.manifestref_key);
+            io.graph.find_by_metadata_key(&self.manifestref_key);
 
         if release_manifestrefs.is_empty() {
             warn!(
@@ -138,7 +141,8 @@ impl InternalPlugin for QuayMetadataFetchPlugin {
         }
 
         for (labels, (release_id, release_version)) in labels_with_releaseinfo {
-            let metadata = graph
+            let metadata = io
+                .graph_mut()
                 .get_metadata_as_ref_mut(&release_id)
                 .context("trying to find metadata for release")?;
             for (key, value) in labels {
@@ -168,7 +172,7 @@ impl InternalPlugin for QuayMetadataFetchPlugin {
             }
         }
 
-        Ok(InternalIO { graph, parameters })
+        Ok(io)
     }
 }
 
@@ -329,7 +333,7 @@ mod tests_net {
             .expect("could not initialize the QuayMetadataPlugin"),
         );
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         });
 
@@ -338,7 +342,7 @@ mod tests_net {
             .expect("plugin run failed")
             .graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
 
         Ok(())
     }
@@ -389,7 +393,7 @@ mod tests_net {
             .context("could not initialize the QuayMetadataPlugin")?,
         );
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         });
 
@@ -398,7 +402,7 @@ mod tests_net {
             .context("plugin run failed")?
             .graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
         Ok(())
     }
 }
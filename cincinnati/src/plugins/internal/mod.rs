@@ -4,8 +4,11 @@ pub mod arch_filter;
 pub mod channel_filter;
 pub mod cincinnati_graph_fetch;
 pub mod edge_add_remove;
+pub mod graph_lint;
+pub mod graph_merge;
 pub mod metadata_fetch_quay;
 pub mod node_remove;
+pub mod version_range_filter;
 pub mod versioned_graph;
 
 mod graph_builder;
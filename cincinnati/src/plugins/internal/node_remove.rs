@@ -4,21 +4,31 @@ use crate as cincinnati;
 
 use self::cincinnati::plugins::prelude::*;
 use self::cincinnati::plugins::prelude_plugin_impl::*;
+use self::cincinnati::release_metadata::MetadataErrorReporter;
+use std::sync::Arc;
 
 /// Prefix for the metadata key operations.
 pub static DEFAULT_KEY_FILTER: &str = "io.openshift.upgrades.graph";
 
-#[derive(Clone, Debug, Deserialize, SmartDefault)]
+#[derive(Clone, Debug, Deserialize, Serialize, SmartDefault)]
 #[serde(default)]
 pub struct NodeRemovePlugin {
     #[default(DEFAULT_KEY_FILTER.to_string())]
     pub key_prefix: String,
+
+    /// Reports invalid removal flags once, instead of on every run.
+    #[serde(skip)]
+    metadata_errors: Arc<MetadataErrorReporter>,
 }
 
 impl PluginSettings for NodeRemovePlugin {
     fn build_plugin(&self, _: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
         Ok(new_plugin!(InternalPluginWrapper(self.clone())))
     }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(Self::PLUGIN_NAME, self)
+    }
 }
 
 impl NodeRemovePlugin {
@@ -27,7 +37,7 @@ impl NodeRemovePlugin {
 
     /// Validate plugin configuration and fill in defaults.
     pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
-        let plugin: Self = cfg.try_into()?;
+        let plugin: Self = commons::de::from_toml_value(cfg)?;
 
         ensure!(!plugin.key_prefix.is_empty(), "empty prefix");
 
@@ -38,31 +48,41 @@ impl NodeRemovePlugin {
 #[async_trait]
 impl InternalPlugin for NodeRemovePlugin {
     const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+    const PARAMETERS: Option<&'static [&'static str]> = Some(&[]);
 
-    async fn run_internal(&self, io: InternalIO) -> Fallible<InternalIO> {
-        let mut graph = io.graph;
-        let key_suffix = "release.remove";
+    async fn run_internal(&self, mut io: InternalIO) -> Fallible<InternalIO> {
+        let keys = MetadataKeys::with_prefix(&self.key_prefix);
+        self.metadata_errors.report(&io.graph, &keys);
 
-        let to_remove = {
+        let to_remove: Vec<ReleaseId> = {
+            let graph = &io.graph;
             graph
-                .find_by_metadata_pair(&format!("{}.{}", self.key_prefix, key_suffix), "true")
+                .find_by_metadata_key(&keys.remove)
                 .into_iter()
-                .map(|(release_id, version)| {
-                    trace!("queuing '{}' for removal", version);
-                    release_id
+                .filter_map(|(release_id, version, _)| {
+                    let metadata = graph
+                        .find_by_releaseid(&release_id)
+                        .ok()?
+                        .release_metadata(&keys)?;
+                    match metadata.is_removed() {
+                        Ok(true) => {
+                            trace!("queuing '{}' for removal", version);
+                            Some(release_id)
+                        }
+                        // invalid values are reported above, and keep the release
+                        Ok(false) | Err(_) => None,
+                    }
                 })
                 .collect()
         };
 
-        // remove all matches from the Graph
-        let removed = graph.remove_releases(to_remove);
+        // remove all matches from the Graph, copying it only if there are any
+        if !to_remove.is_empty() {
+            let removed = io.graph_mut().remove_releases(to_remove);
+            trace!("removed {} releases", removed);
+        }
 
-        trace!("removed {} releases", removed);
-
-        Ok(InternalIO {
-            graph,
-            parameters: io.parameters,
-        })
+        Ok(io)
     }
 }
 
@@ -114,9 +134,12 @@ mod tests {
             generate_custom_graph("image", metadata, None)
         };
 
-        let plugin = Box::new(NodeRemovePlugin { key_prefix });
+        let plugin = Box::new(NodeRemovePlugin {
+            key_prefix,
+            ..Default::default()
+        });
         let future_processed_graph = plugin.run_internal(InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         });
 
@@ -125,7 +148,7 @@ mod tests {
             .context("plugin run failed")?
             .graph;
 
-        assert_eq!(expected_graph, processed_graph);
+        assert_eq!(expected_graph, *processed_graph);
 
         Ok(())
     }
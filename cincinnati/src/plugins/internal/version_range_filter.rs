@@ -0,0 +1,224 @@
+//! This plugin can be used to filter a graph by a semver range, e.g. `>=4.12.0, <4.15.0`.
+//! It reads the requested range from the parameters value at key "version_range",
+//! and drops all releases outside of it as well as the ones with an invalid version.
+//!
+//! A range can also be given in the configuration, in which case releases are kept
+//! only if they match both. Pre-releases only match a range which explicitly names
+//! a pre-release of the same version, as per the semver matching rules.
+
+use crate as cincinnati;
+
+use self::cincinnati::plugins::prelude::*;
+use self::cincinnati::plugins::prelude_plugin_impl::*;
+
+use commons::GraphError;
+use semver::{Version, VersionReq};
+
+/// Request parameter holding the range.
+pub static VERSION_RANGE_PARAMETER: &str = "version_range";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct VersionRangeFilterPlugin {
+    /// Range applying to all requests.
+    pub range: Option<VersionReq>,
+}
+
+impl PluginSettings for VersionRangeFilterPlugin {
+    fn build_plugin(&self, _: Option<&prometheus::Registry>) -> Fallible<BoxedPlugin> {
+        Ok(new_plugin!(InternalPluginWrapper(self.clone())))
+    }
+
+    fn serialize_config(&self) -> Fallible<toml::Value> {
+        serialize_plugin_config(Self::PLUGIN_NAME, self)
+    }
+}
+
+impl VersionRangeFilterPlugin {
+    /// Plugin name, for configuration.
+    pub const PLUGIN_NAME: &'static str = "version-range-filter";
+
+    /// Validate plugin configuration and fill in defaults.
+    pub fn deserialize_config(cfg: toml::Value) -> Fallible<Box<dyn PluginSettings>> {
+        let plugin: Self = commons::de::from_toml_value(cfg)?;
+
+        Ok(Box::new(plugin))
+    }
+}
+
+/// Parse the range from the given "version_range" parameter.
+fn parse_range(range: &str) -> Result<VersionReq, GraphError> {
+    VersionReq::parse(range)
+        .map_err(|e| GraphError::InvalidParams(format!("invalid version range '{}': {}", range, e)))
+}
+
+#[async_trait]
+impl InternalPlugin for VersionRangeFilterPlugin {
+    const PLUGIN_NAME: &'static str = Self::PLUGIN_NAME;
+    const PARAMETERS: Option<&'static [&'static str]> = Some(&["version_range"]);
+
+    async fn run_internal(&self, mut internal_io: InternalIO) -> Fallible<InternalIO> {
+        let requested = internal_io
+            .parameters
+            .get(VERSION_RANGE_PARAMETER)
+            .map(|range| parse_range(range))
+            .transpose()?;
+
+        let ranges: Vec<&VersionReq> = self.range.iter().chain(requested.iter()).collect();
+        if ranges.is_empty() {
+            trace!("no version range given, keeping all releases");
+            return Ok(internal_io);
+        }
+
+        let to_remove: Vec<ReleaseId> = {
+            internal_io
+                .graph
+                .find_by_fn(|release| match Version::parse(release.version()) {
+                    Ok(version) => !ranges.iter().all(|range| range.matches(&version)),
+                    Err(e) => {
+                        warn!("[{}]: removing release: {}", release.version(), e);
+                        true
+                    }
+                })
+                .into_iter()
+                .map(|(release_id, version)| {
+                    trace!("queuing '{}' for removal", version);
+                    release_id
+                })
+                .collect()
+        };
+
+        // remove all matches from the Graph, including their conditional edges;
+        // the graph is only copied if there is anything to remove
+        if !to_remove.is_empty() {
+            let removed = internal_io.graph_mut().remove_releases(to_remove);
+            trace!("removed {} releases", removed);
+        }
+
+        Ok(internal_io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cincinnati::testing::generate_custom_graph;
+    use commons::testing::init_runtime;
+
+    /// Graph with releases 0.0.0 to 4.0.0, each one updating to the next.
+    fn input_graph() -> cincinnati::Graph {
+        generate_custom_graph(
+            "image",
+            (0..5).map(|i| (i, Default::default())).collect(),
+            Some(vec![(0, 1), (1, 2), (2, 3), (3, 4)]),
+        )
+    }
+
+    /// Graph with the given consecutive releases, each one updating to the next.
+    fn expected_graph(releases: std::ops::Range<usize>) -> cincinnati::Graph {
+        let edges = (1..releases.len()).map(|i| (i - 1, i)).collect();
+        generate_custom_graph(
+            "image",
+            releases.map(|i| (i, Default::default())).collect(),
+            Some(edges),
+        )
+    }
+
+    fn run(plugin: VersionRangeFilterPlugin, range: Option<&str>) -> Fallible<cincinnati::Graph> {
+        let runtime = init_runtime()?;
+        let io = runtime.block_on(
+            plugin.run_internal(InternalIO {
+                graph: input_graph().into(),
+                parameters: range
+                    .map(|range| (VERSION_RANGE_PARAMETER.to_string(), range.to_string()))
+                    .into_iter()
+                    .collect(),
+            }),
+        )?;
+
+        Ok((*io.graph).clone())
+    }
+
+    #[test]
+    fn filter_by_requested_range() -> Fallible<()> {
+        assert_eq!(
+            run(Default::default(), Some(">=1.0.0, <3.0.0"))?,
+            expected_graph(1..3)
+        );
+        assert_eq!(run(Default::default(), None)?, input_graph());
+
+        Ok(())
+    }
+
+    #[test]
+    fn filter_by_configured_and_requested_range() -> Fallible<()> {
+        let plugin: VersionRangeFilterPlugin = toml::from_str(r#"range = "<4.0.0""#)?;
+
+        assert_eq!(run(plugin.clone(), None)?, expected_graph(0..4));
+        assert_eq!(run(plugin, Some(">=2.0.0"))?, expected_graph(2..4));
+
+        Ok(())
+    }
+
+    #[test]
+    fn build_from_catalog() -> Fallible<()> {
+        use cincinnati::plugins::{catalog, Plugin, PluginIO};
+        use std::convert::TryInto;
+
+        let runtime = init_runtime()?;
+        let cfg = r#"
+            name = "version-range-filter"
+            range = "<4.0.0"
+        "#;
+        let plugin = catalog::deserialize_config(toml::from_str(cfg)?)?.build_plugin(None)?;
+        assert_eq!(plugin.get_name(), VersionRangeFilterPlugin::PLUGIN_NAME);
+
+        let io: InternalIO = runtime
+            .block_on(plugin.run(PluginIO::InternalIO(InternalIO {
+                graph: input_graph().into(),
+                parameters: Default::default(),
+            })))?
+            .try_into()?;
+        assert_eq!(*io.graph, expected_graph(0..4));
+
+        Ok(())
+    }
+
+    #[test]
+    fn remove_conditional_edges_into_removed_releases() -> Fallible<()> {
+        let runtime = init_runtime()?;
+        let graph: cincinnati::Graph = serde_json::from_str(
+            r#"{"nodes":[{"version":"1.0.0","payload":"image/1.0.0","metadata":{}},{"version":"2.0.0","payload":"image/2.0.0","metadata":{}},{"version":"3.0.0","payload":"image/3.0.0","metadata":{}}],"edges":[[0,1]],"conditionalEdges":[{"edges":[{"from":"1.0.0","to":"3.0.0"},{"from":"2.0.0","to":"3.0.0"}],"risks":[{"url":"https://bug.example.com","name":"SomeRisk","message":"","matchingRules":[{"type":"Always"}]}]}]}"#,
+        )?;
+        assert_eq!(graph.conditional_edges().len(), 1);
+
+        let io = runtime.block_on(
+            VersionRangeFilterPlugin::default().run_internal(InternalIO {
+                graph: graph.into(),
+                parameters: vec![(VERSION_RANGE_PARAMETER.to_string(), "<3.0.0".to_string())]
+                    .into_iter()
+                    .collect(),
+            }),
+        )?;
+
+        assert_eq!(io.graph.releases_count(), 2);
+        assert!(io.graph.conditional_edges().is_empty());
+        assert!(io.graph.dangling_conditional_edges().is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_range() -> Fallible<()> {
+        let err = run(Default::default(), Some("not a range")).unwrap_err();
+        match err.downcast_ref::<GraphError>() {
+            Some(GraphError::InvalidParams(msg)) => assert!(msg.contains("not a range")),
+            _ => panic!("unexpected error: {:?}", err),
+        }
+
+        let cfg = toml::from_str(r#"range = "not a range""#)?;
+        assert!(VersionRangeFilterPlugin::deserialize_config(cfg).is_err());
+
+        Ok(())
+    }
+}
@@ -1,13 +1,85 @@
 use self::cincinnati::plugins::prelude_plugin_impl::*;
 use crate as cincinnati;
 use commons::{CINCINNATI_VERSION, MIN_CINCINNATI_VERSION};
-
-#[derive(Debug, Serialize, Deserialize, SmartDefault)]
-#[serde(default)]
+use serde::de::Deserializer;
+use serde::ser::{Serialize, Serializer};
+use std::sync::Arc;
+
+/// Graph tagged with the version of the Cincinnati media type it is serialized as.
+///
+/// Versions below 2 use the v1 format, all others use `cincinnati::v2`.
+#[derive(Debug, SmartDefault)]
 pub struct VersionedGraph {
     pub version: i32,
+    pub graph: Arc<cincinnati::Graph>,
+}
+
+/// Serializes the wrapped graph in the v2 format.
+struct V2Graph<'a>(&'a cincinnati::Graph);
+
+impl<'a> Serialize for V2Graph<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        cincinnati::v2::serialize(self.0, serializer)
+    }
+}
+
+#[derive(Serialize)]
+struct Versioned<G> {
+    version: i32,
     #[serde(flatten)]
-    pub graph: cincinnati::Graph,
+    graph: G,
+}
+
+impl Serialize for VersionedGraph {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        if self.version >= 2 {
+            Versioned {
+                version: self.version,
+                graph: V2Graph(&self.graph),
+            }
+            .serialize(serializer)
+        } else {
+            Versioned {
+                version: self.version,
+                graph: self.graph.as_ref(),
+            }
+            .serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for VersionedGraph {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+
+        // The graph format depends on the version, so the input is buffered first.
+        let mut value = serde_json::Value::deserialize(deserializer)?;
+        let version = match value.as_object_mut().and_then(|map| map.remove("version")) {
+            Some(version) => i32::deserialize(version).map_err(D::Error::custom)?,
+            None => Default::default(),
+        };
+
+        let graph = if version >= 2 {
+            cincinnati::v2::deserialize(value)
+        } else {
+            cincinnati::Graph::deserialize(value)
+        }
+        .map_err(D::Error::custom)?;
+
+        Ok(VersionedGraph {
+            version,
+            graph: Arc::new(graph),
+        })
+    }
 }
 
 impl VersionedGraph {
@@ -58,7 +130,7 @@ mod tests {
         };
 
         let versioned_graph = VersionedGraph::new(&InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: Default::default(),
         })
         .unwrap();
@@ -80,7 +152,7 @@ mod tests {
         plugin_params.insert(String::from("version"), "application/json".to_string());
 
         let versioned_graph = VersionedGraph::new(&InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: plugin_params,
         })
         .unwrap();
@@ -102,7 +174,7 @@ mod tests {
         plugin_params.insert(String::from("version"), MIN_CINCINNATI_VERSION.to_string());
 
         let versioned_graph = VersionedGraph::new(&InternalIO {
-            graph: input_graph,
+            graph: input_graph.into(),
             parameters: plugin_params,
         })
         .unwrap();
@@ -110,4 +182,52 @@ mod tests {
         assert_eq!(versioned_graph.version, 1);
         Ok(())
     }
+
+    #[test]
+    fn ensure_version_2() -> Fallible<()> {
+        let _ = init_runtime()?;
+
+        let input_graph = cincinnati::testing::generate_graph(true, false);
+
+        let mut plugin_params: HashMap<String, String> = HashMap::new();
+        plugin_params.insert(
+            String::from("content_type"),
+            "application/vnd.redhat.cincinnati.v2+json".to_string(),
+        );
+
+        let versioned_graph = VersionedGraph::new(&InternalIO {
+            graph: input_graph.clone().into(),
+            parameters: plugin_params,
+        })?;
+        assert_eq!(versioned_graph.version, 2);
+
+        let json = serde_json::to_value(&versioned_graph)?;
+        assert_eq!(json["version"], 2);
+        assert_eq!(json["edges"][0]["from"], "1.0.0");
+        assert_eq!(json["risks"][0]["name"], "BrokenUpdates");
+
+        let roundtrip: VersionedGraph = serde_json::from_value(json)?;
+        assert_eq!(roundtrip.version, 2);
+        assert_eq!(*roundtrip.graph, input_graph);
+        Ok(())
+    }
+
+    #[test]
+    fn version_1_roundtrip() -> Fallible<()> {
+        let input_graph = cincinnati::testing::generate_graph(true, false);
+
+        let versioned_graph = VersionedGraph {
+            version: 1,
+            graph: input_graph.clone().into(),
+        };
+
+        let json = serde_json::to_value(&versioned_graph)?;
+        assert_eq!(json["version"], 1);
+        assert_eq!(json["edges"][0], serde_json::json!([0, 1]));
+
+        let roundtrip: VersionedGraph = serde_json::from_value(json)?;
+        assert_eq!(roundtrip.version, 1);
+        assert_eq!(*roundtrip.graph, input_graph);
+        Ok(())
+    }
 }
@@ -5,7 +5,9 @@
 pub mod macros;
 
 pub mod catalog;
+pub mod condition;
 pub mod external;
+pub mod failure_policy;
 pub mod interface;
 pub mod internal;
 
@@ -16,15 +18,68 @@ use self::cincinnati::plugins::interface::{PluginError, PluginExchange};
 use async_trait::async_trait;
 pub use commons::prelude_errors::*;
 use commons::tracing::get_tracer;
+use lazy_static::lazy_static;
+use prometheus::{HistogramOpts, HistogramVec, IntCounterVec, Opts};
 use std::collections::HashMap;
 use std::convert::{TryFrom, TryInto};
 use std::fmt::Debug;
+use std::sync::Arc;
 
 use opentelemetry::{
-    trace::{mark_span_as_active, FutureExt, Tracer},
-    Context as ot_context,
+    trace::{mark_span_as_active, FutureExt, TraceContextExt, Tracer},
+    Context as ot_context, Key,
 };
 
+lazy_static! {
+    static ref PLUGIN_RUN_DURATION: HistogramVec = HistogramVec::new(
+        HistogramOpts::new(
+            "plugin_run_duration_seconds",
+            "Duration of plugin runs, in seconds"
+        )
+        .buckets(vec![
+            0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0, 10.0, 30.0, 60.0, 300.0
+        ]),
+        &["plugin"]
+    )
+    .unwrap();
+    static ref PLUGIN_RUN_ERRORS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "plugin_run_errors_total",
+            "Total number of failed plugin runs"
+        ),
+        &["plugin"]
+    )
+    .unwrap();
+}
+
+/// Registers the metrics of plugin runs, unless they are registered already.
+///
+/// The metrics are shared by all plugin chains using the same registry.
+pub fn register_metrics(registry: &prometheus::Registry) -> Fallible<()> {
+    register_shared_collectors(
+        registry,
+        vec![
+            Box::new(PLUGIN_RUN_DURATION.clone()),
+            Box::new(PLUGIN_RUN_ERRORS.clone()),
+        ],
+    )
+}
+
+/// Registers collectors which may be shared by several plugins, skipping those registered already.
+pub(crate) fn register_shared_collectors(
+    registry: &prometheus::Registry,
+    collectors: Vec<Box<dyn prometheus::core::Collector>>,
+) -> Fallible<()> {
+    for collector in collectors {
+        match registry.register(collector) {
+            Ok(()) | Err(prometheus::Error::AlreadyReg) => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(())
+}
+
 pub mod prelude {
     use crate as cincinnati;
 
@@ -33,6 +88,9 @@ pub mod prelude {
     pub use plugins::{BoxedPlugin, InternalPluginWrapper};
 
     pub use plugins::catalog::PluginSettings;
+    pub use plugins::external::subprocess::{SubprocessPlugin, SubprocessSettings};
+    pub use plugins::external::wasm::{WasmPlugin, WasmPluginSettings};
+    pub use plugins::external::web::{WebPluginClient, WebPluginSettings};
     pub use plugins::internal::arch_filter::ArchFilterPlugin;
     pub use plugins::internal::channel_filter::ChannelFilterPlugin;
     pub use plugins::internal::cincinnati_graph_fetch::CincinnatiGraphFetchPlugin;
@@ -41,6 +99,10 @@ pub mod prelude {
         GithubOpenshiftSecondaryMetadataScraperPlugin,
         GithubOpenshiftSecondaryMetadataScraperSettings,
     };
+    pub use plugins::internal::graph_lint::GraphLintPlugin;
+    pub use plugins::internal::graph_merge::{
+        GraphMergePlugin, GraphMergeSettings, ParallelPlugin, ParameterStrategy,
+    };
     pub use plugins::internal::metadata_fetch_quay::QuayMetadataFetchPlugin;
     pub use plugins::internal::node_remove::NodeRemovePlugin;
     pub use plugins::internal::openshift_secondary_metadata_parser::{
@@ -49,6 +111,7 @@ pub mod prelude {
     pub use plugins::internal::release_scrape_dockerv2::{
         ReleaseScrapeDockerv2Plugin, ReleaseScrapeDockerv2Settings,
     };
+    pub use plugins::internal::version_range_filter::VersionRangeFilterPlugin;
 
     pub use std::iter::FromIterator;
 
@@ -59,8 +122,8 @@ pub mod prelude_plugin_impl {
     use self::cincinnati::plugins;
     use crate as cincinnati;
 
-    pub use self::cincinnati::{daggy, ReleaseId};
-    pub use plugins::catalog::PluginSettings;
+    pub use self::cincinnati::{daggy, MetadataKeys, ReleaseId, ReleaseMetadata};
+    pub use plugins::catalog::{serialize_plugin_config, PluginSettings};
     pub use plugins::{BoxedPlugin, InternalIO, InternalPlugin, InternalPluginWrapper};
 
     pub use async_trait::async_trait;
@@ -112,10 +175,20 @@ pub enum PluginResult {
 #[derive(Debug, Clone)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct InternalIO {
-    pub graph: cincinnati::Graph,
+    /// The graph, shared copy-on-write.
+    ///
+    /// Plugins which modify it go through `graph_mut`, which copies it only if it's shared.
+    pub graph: Arc<cincinnati::Graph>,
     pub parameters: HashMap<String, String>,
 }
 
+impl InternalIO {
+    /// Returns the graph for modification, copying it first if it is shared.
+    pub fn graph_mut(&mut self) -> &mut cincinnati::Graph {
+        Arc::make_mut(&mut self.graph)
+    }
+}
+
 /// Struct used by the InternalPlugin trait impl's
 #[derive(Debug, PartialEq)]
 #[cfg_attr(test, derive(Clone))]
@@ -134,6 +207,27 @@ where
     async fn run(&self, t: T) -> Fallible<T>;
 
     fn get_name(&self) -> &'static str;
+
+    /// Returns the condition on the parameters for running this plugin, if any.
+    fn condition(&self) -> Option<&condition::PluginCondition> {
+        None
+    }
+
+    /// Returns the request parameters which the output of this plugin depends on,
+    /// or `None` if it may depend on any of them.
+    fn parameters(&self) -> Option<Vec<String>> {
+        None
+    }
+
+    /// Returns the metadata key holding the release channels, if this plugin filters on it.
+    fn channels_key(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the plugins which this plugin runs itself, e.g. as part of sub-chains.
+    fn nested_plugins(&self) -> Vec<&BoxedPlugin> {
+        vec![]
+    }
 }
 
 /// Trait to be implemented by internal plugins with their native IO type
@@ -141,11 +235,24 @@ where
 pub trait InternalPlugin {
     const PLUGIN_NAME: &'static str;
 
+    /// Request parameters which the output depends on, `None` meaning any of them.
+    const PARAMETERS: Option<&'static [&'static str]> = None;
+
     async fn run_internal(&self, input: InternalIO) -> Fallible<InternalIO>;
 
     fn get_name(&self) -> &'static str {
         Self::PLUGIN_NAME
     }
+
+    /// Metadata key holding the release channels, if this plugin filters on it.
+    fn channels_key(&self) -> Option<String> {
+        None
+    }
+
+    /// Plugins which this plugin runs itself, e.g. as part of sub-chains.
+    fn nested_plugins(&self) -> Vec<&BoxedPlugin> {
+        vec![]
+    }
 }
 
 /// Trait to be implemented by external plugins with its native IO type
@@ -264,9 +371,11 @@ impl TryFrom<ExternalIO> for InternalIO {
 
     fn try_from(external_io: ExternalIO) -> Fallible<Self> {
         let mut plugin_exchange: PluginExchange = external_io.try_into()?;
+        let graph = crate::Graph::try_from(plugin_exchange.take_graph())
+            .context("could not convert the graph of the PluginExchange")?;
 
         Ok(Self {
-            graph: plugin_exchange.take_graph().into(),
+            graph: Arc::new(graph),
             parameters: plugin_exchange.take_parameters(),
         })
     }
@@ -279,7 +388,7 @@ impl From<InternalIO> for interface::PluginExchange {
     fn from(internal_io: InternalIO) -> Self {
         let mut plugin_exchange = Self::new();
 
-        plugin_exchange.set_graph(internal_io.graph.into());
+        plugin_exchange.set_graph(internal_io.graph.as_ref().into());
         plugin_exchange.set_parameters(internal_io.parameters);
 
         plugin_exchange
@@ -354,6 +463,18 @@ where
     fn get_name(&self) -> &'static str {
         <T as InternalPlugin>::PLUGIN_NAME
     }
+
+    fn parameters(&self) -> Option<Vec<String>> {
+        T::PARAMETERS.map(|parameters| parameters.iter().map(ToString::to_string).collect())
+    }
+
+    fn channels_key(&self) -> Option<String> {
+        self.0.channels_key()
+    }
+
+    fn nested_plugins(&self) -> Vec<&BoxedPlugin> {
+        self.0.nested_plugins()
+    }
 }
 
 /// This implementation allows the process function to run ipmlementors of
@@ -391,18 +512,70 @@ where
     let _active_span = mark_span_as_active(span);
 
     for next_plugin in plugins {
-        let plugin_name = next_plugin.get_name();
-        log::trace!("Running next plugin '{}'", plugin_name);
-
-        let plugin_span = get_tracer().start(plugin_name);
-        let _active_plugin_span = mark_span_as_active(plugin_span);
-        let cx = ot_context::current();
-        io = next_plugin.run(io).with_context(cx).await?;
+        io = run_plugin(next_plugin, io).await?;
     }
 
     io.try_into()
 }
 
+/// Runs a single plugin within its own span, recording its duration, errors and graph sizes.
+///
+/// This is used by `process` and by plugins which run plugin chains themselves.
+pub(crate) async fn run_plugin(plugin: &BoxedPlugin, io: PluginIO) -> Fallible<PluginIO> {
+    let plugin_name = plugin.get_name();
+    log::trace!("Running next plugin '{}'", plugin_name);
+
+    // The span is not marked as active, so that the returned future stays `Send`.
+    let cx = ot_context::current_with_span(get_tracer().start(plugin_name));
+    set_graph_size_attributes(&cx, "before", &io);
+
+    let timer = PLUGIN_RUN_DURATION
+        .with_label_values(&[plugin_name])
+        .start_timer();
+    let result = plugin.run(io).with_context(cx.clone()).await;
+    timer.observe_duration();
+
+    let io = result.map_err(|e| {
+        PLUGIN_RUN_ERRORS.with_label_values(&[plugin_name]).inc();
+        cx.span()
+            .set_attribute(Key::new("error").string(e.to_string()));
+        e
+    })?;
+    set_graph_size_attributes(&cx, "after", &io);
+
+    Ok(io)
+}
+
+/// Returns the conditions of the given plugins, including the ones of the plugins nested in them.
+pub fn all_conditions<'a, I>(plugins: I) -> Vec<&'a condition::PluginCondition>
+where
+    I: IntoIterator<Item = &'a BoxedPlugin>,
+{
+    let mut conditions = vec![];
+    for plugin in plugins {
+        conditions.extend(plugin.condition());
+        conditions.extend(all_conditions(plugin.nested_plugins()));
+    }
+
+    conditions
+}
+
+/// Records the number of releases and edges of the graph on the span of the context.
+///
+/// Nothing is recorded for serialized graphs, to avoid deserializing them only for this.
+fn set_graph_size_attributes(cx: &ot_context, stage: &str, io: &PluginIO) {
+    if let PluginIO::InternalIO(internal_io) = io {
+        let span = cx.span();
+        span.set_attribute(
+            Key::new(format!("graph.releases.{}", stage))
+                .i64(internal_io.graph.releases_count() as i64),
+        );
+        span.set_attribute(
+            Key::new(format!("graph.edges.{}", stage)).i64(internal_io.graph.edges_count() as i64),
+        );
+    }
+}
+
 /// Wrapper around `process` with an optional timeout.
 ///
 /// It creates a new runtime per call which is moved to a new thread.
@@ -495,7 +668,7 @@ mod tests {
     fn convert_roundtrip_internalio_externalio() {
         let graph = generate_graph(false, false);
         let input_internal = InternalIO {
-            graph,
+            graph: graph.into(),
             parameters: [("hello".to_string(), "plugin".to_string())]
                 .iter()
                 .cloned()
@@ -568,7 +741,7 @@ mod tests {
         }
 
         let initial_internalio = InternalIO {
-            graph: generate_graph(false, false),
+            graph: generate_graph(false, false).into(),
             parameters: [("hello".to_string(), "plugin".to_string())]
                 .iter()
                 .cloned()
@@ -576,7 +749,7 @@ mod tests {
         };
 
         let expected_internalio = InternalIO {
-            graph: generate_graph(false, false),
+            graph: generate_graph(false, false).into(),
             parameters: [
                 ("hello".to_string(), "plugin".to_string()),
                 ("COUNTER".to_string(), "1".to_string()),
@@ -613,7 +786,7 @@ mod tests {
         }
 
         let initial_internalio = InternalIO {
-            graph: generate_graph(false, false),
+            graph: generate_graph(false, false).into(),
             parameters: [("hello".to_string(), "plugin".to_string())]
                 .iter()
                 .cloned()
@@ -624,7 +797,7 @@ mod tests {
 
         for i in 0..runs {
             let expected_internalio = InternalIO {
-                graph: generate_graph(false, false),
+                graph: generate_graph(false, false).into(),
                 parameters: [
                     ("hello".to_string(), "plugin".to_string()),
                     ("COUNTER".to_string(), format!("{}", i + 1)),
@@ -647,6 +820,53 @@ mod tests {
         Ok(())
     }
 
+    #[derive(Debug)]
+    struct TestFailingPlugin {}
+    #[async_trait]
+    impl InternalPlugin for TestFailingPlugin {
+        const PLUGIN_NAME: &'static str = "test_failing_plugin";
+
+        async fn run_internal(&self, _: InternalIO) -> Fallible<InternalIO> {
+            bail!("test plugin failure")
+        }
+    }
+
+    #[test]
+    fn process_plugins_records_metrics() -> Fallible<()> {
+        let runtime = commons::testing::init_runtime()?;
+
+        lazy_static! {
+            static ref PLUGINS: Vec<BoxedPlugin> =
+                new_plugins!(InternalPluginWrapper(TestFailingPlugin {}));
+        }
+
+        let registry = prometheus::Registry::new();
+        register_metrics(&registry)?;
+        // registering again, e.g. for another plugin chain, is fine
+        register_metrics(&registry)?;
+
+        let initial_internalio = InternalIO {
+            graph: generate_graph(false, false).into(),
+            parameters: Default::default(),
+        };
+        let result = runtime.block_on(process(
+            PLUGINS.iter(),
+            PluginIO::InternalIO(initial_internalio),
+        ));
+        assert!(result.is_err());
+
+        let name = TestFailingPlugin::PLUGIN_NAME;
+        assert_eq!(PLUGIN_RUN_ERRORS.with_label_values(&[name]).get(), 1);
+        assert_eq!(
+            PLUGIN_RUN_DURATION
+                .with_label_values(&[name])
+                .get_sample_count(),
+            1
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn process_blocking_succeeds() -> Fallible<()> {
         lazy_static! {
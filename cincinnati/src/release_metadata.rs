@@ -0,0 +1,376 @@
+//! Typed view over the metadata of a release.
+//!
+//! Release metadata is a flat map of strings. `MetadataKeys` holds the keys of
+//! the well-known entries for a given key prefix, and `ReleaseMetadata` parses
+//! their values, so that plugins don't need to know how each one is encoded.
+
+use crate::{Graph, MapImpl};
+use commons::prelude_errors::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+
+/// Default prefix of the metadata keys.
+pub static DEFAULT_KEY_PREFIX: &str = "io.openshift.upgrades.graph";
+
+/// Metadata key of the release URL, which isn't prefixed.
+pub static URL_KEY: &str = "url";
+
+/// Invalid value for a metadata key.
+#[derive(Debug, Fail, Eq, PartialEq, Ord, PartialOrd, Clone)]
+#[error("invalid value {value:?} for metadata key {key:?}: {reason}")]
+pub struct MetadataError {
+    pub key: String,
+    pub value: String,
+    pub reason: String,
+}
+
+/// Full metadata keys of the well-known metadata entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataKeys {
+    /// Comma-separated list of channels the release is part of.
+    pub channels: String,
+    /// Comma-separated list of architectures of the release.
+    pub arch: String,
+    /// Digest of the release image manifest.
+    pub manifestref: String,
+    /// URL with information about the release.
+    pub url: String,
+    /// Whether the release is to be removed from the graph.
+    pub remove: String,
+    /// Comma-separated list of versions to add edges from.
+    pub previous_add: String,
+    /// Comma-separated list of versions to remove edges from.
+    pub previous_remove: String,
+    /// Regex of the versions to remove edges from.
+    pub previous_remove_regex: String,
+    /// Comma-separated list of versions to add edges to.
+    pub next_add: String,
+    /// Comma-separated list of versions to remove edges to.
+    pub next_remove: String,
+}
+
+impl Default for MetadataKeys {
+    fn default() -> Self {
+        Self::with_prefix(DEFAULT_KEY_PREFIX)
+    }
+}
+
+impl MetadataKeys {
+    /// Returns the keys under the given prefix.
+    pub fn with_prefix(prefix: &str) -> Self {
+        let key = |suffix: &str| format!("{}.{}", prefix, suffix);
+
+        Self {
+            channels: key("release.channels"),
+            arch: key("release.arch"),
+            manifestref: key("release.manifestref"),
+            url: URL_KEY.to_string(),
+            remove: key("release.remove"),
+            previous_add: key("previous.add"),
+            previous_remove: key("previous.remove"),
+            previous_remove_regex: key("previous.remove_regex"),
+            next_add: key("next.add"),
+            next_remove: key("next.remove"),
+        }
+    }
+
+    /// Overrides the key of the channels.
+    pub fn with_channels_key(mut self, key: String) -> Self {
+        self.channels = key;
+        self
+    }
+
+    /// Overrides the key of the architectures.
+    pub fn with_arch_key(mut self, key: String) -> Self {
+        self.arch = key;
+        self
+    }
+}
+
+/// Typed, read-only view over the metadata of a release.
+///
+/// Values are parsed on access. Lists are comma-separated, with surrounding
+/// whitespace and empty items ignored.
+#[derive(Debug, Clone, Copy)]
+pub struct ReleaseMetadata<'a> {
+    metadata: &'a MapImpl<String, String>,
+    keys: &'a MetadataKeys,
+}
+
+impl<'a> ReleaseMetadata<'a> {
+    /// Creates a view over the given metadata.
+    pub fn new(metadata: &'a MapImpl<String, String>, keys: &'a MetadataKeys) -> Self {
+        Self { metadata, keys }
+    }
+
+    /// Returns the channels of the release.
+    pub fn channels(&self) -> impl Iterator<Item = &'a str> {
+        split_list(self.get(&self.keys.channels))
+    }
+
+    /// Returns whether the release is part of the given channel.
+    pub fn in_channel(&self, channel: &str) -> bool {
+        self.channels().any(|candidate| candidate == channel)
+    }
+
+    /// Returns the raw architecture value of the release.
+    pub fn arch(&self) -> Option<&'a str> {
+        self.get(&self.keys.arch)
+    }
+
+    /// Returns the architectures of the release.
+    pub fn arches(&self) -> impl Iterator<Item = &'a str> {
+        split_list(self.arch())
+    }
+
+    /// Returns whether the release is available for the given architecture.
+    pub fn has_arch(&self, arch: &str) -> bool {
+        self.arches().any(|candidate| candidate == arch)
+    }
+
+    /// Returns the manifest reference of the release.
+    pub fn manifestref(&self) -> Option<&'a str> {
+        self.get(&self.keys.manifestref)
+    }
+
+    /// Returns the URL of the release.
+    pub fn url(&self) -> Option<&'a str> {
+        self.get(&self.keys.url)
+    }
+
+    /// Returns whether the release is flagged for removal.
+    pub fn is_removed(&self) -> Result<bool, MetadataError> {
+        match self.get(&self.keys.remove) {
+            None => Ok(false),
+            Some(value) => value.parse().map_err(|_| MetadataError {
+                key: self.keys.remove.clone(),
+                value: value.to_string(),
+                reason: "expected \"true\" or \"false\"".to_string(),
+            }),
+        }
+    }
+
+    /// Returns the versions to add edges from.
+    pub fn previous_add(&self) -> Vec<&'a str> {
+        split_list(self.get(&self.keys.previous_add)).collect()
+    }
+
+    /// Returns the versions to remove edges from.
+    pub fn previous_remove(&self) -> Vec<&'a str> {
+        split_list(self.get(&self.keys.previous_remove)).collect()
+    }
+
+    /// Returns the regex of the versions to remove edges from.
+    pub fn previous_remove_regex(&self) -> Result<Option<regex::Regex>, MetadataError> {
+        self.get(&self.keys.previous_remove_regex)
+            .map(|value| {
+                regex::Regex::new(value).map_err(|e| MetadataError {
+                    key: self.keys.previous_remove_regex.clone(),
+                    value: value.to_string(),
+                    reason: e.to_string(),
+                })
+            })
+            .transpose()
+    }
+
+    /// Returns the versions to add edges to.
+    pub fn next_add(&self) -> Vec<&'a str> {
+        split_list(self.get(&self.keys.next_add)).collect()
+    }
+
+    /// Returns the versions to remove edges to.
+    pub fn next_remove(&self) -> Vec<&'a str> {
+        split_list(self.get(&self.keys.next_remove)).collect()
+    }
+
+    /// Returns the errors of all values which can't be parsed.
+    pub fn errors(&self) -> Vec<MetadataError> {
+        let mut errors = vec![];
+        if let Err(e) = self.is_removed() {
+            errors.push(e);
+        }
+        if let Err(e) = self.previous_remove_regex() {
+            errors.push(e);
+        }
+        errors
+    }
+
+    fn get(&self, key: &str) -> Option<&'a str> {
+        self.metadata.get(key).map(String::as_str)
+    }
+}
+
+/// Returns the invalid metadata values of the releases in the graph, by version and key.
+pub fn graph_errors(
+    graph: &Graph,
+    keys: &MetadataKeys,
+) -> BTreeMap<(String, String), MetadataError> {
+    let mut errors = BTreeMap::new();
+    for key in &[&keys.remove, &keys.previous_remove_regex] {
+        for (release_id, version, _) in graph.find_by_metadata_key(key) {
+            let metadata = match graph
+                .find_by_releaseid(&release_id)
+                .ok()
+                .and_then(|release| release.release_metadata(keys))
+            {
+                Some(metadata) => metadata,
+                None => continue,
+            };
+            for error in metadata.errors() {
+                errors.insert((version.clone(), error.key.clone()), error);
+            }
+        }
+    }
+    errors
+}
+
+/// Logs the invalid metadata values of graphs, each of them only once
+/// for as long as it is found in the graphs.
+#[derive(Debug, Default)]
+pub struct MetadataErrorReporter {
+    reported: Mutex<BTreeSet<(String, MetadataError)>>,
+}
+
+impl MetadataErrorReporter {
+    /// Logs the invalid metadata values of the graph which were not found in
+    /// the previous one.
+    pub fn report(&self, graph: &Graph, keys: &MetadataKeys) {
+        let current: BTreeSet<(String, MetadataError)> = graph_errors(graph, keys)
+            .into_iter()
+            .map(|((version, _), error)| (version, error))
+            .collect();
+
+        let mut reported = match self.reported.lock() {
+            Ok(reported) => reported,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        for (version, error) in current.difference(&reported) {
+            log::warn!("[{}]: {}", version, error);
+        }
+        *reported = current;
+    }
+}
+
+/// Splits a comma-separated list.
+fn split_list(value: Option<&str>) -> impl Iterator<Item = &str> {
+    value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(entries: &[(&str, &str)]) -> MapImpl<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parse_lists() {
+        let keys = MetadataKeys::with_prefix("test");
+        let metadata = metadata(&[
+            ("test.release.channels", "stable-4.1, fast-4.1,,"),
+            ("test.release.arch", "amd64,arm64"),
+            ("test.previous.add", "1.0.0, 1.1.0"),
+            ("url", "https://example.com/1.2.0"),
+        ]);
+        let release_metadata = ReleaseMetadata::new(&metadata, &keys);
+
+        assert_eq!(
+            release_metadata.channels().collect::<Vec<_>>(),
+            vec!["stable-4.1", "fast-4.1"]
+        );
+        assert!(release_metadata.in_channel("fast-4.1"));
+        assert!(!release_metadata.in_channel("candidate-4.1"));
+        assert!(release_metadata.has_arch("arm64"));
+        assert!(!release_metadata.has_arch("s390x"));
+        assert_eq!(release_metadata.previous_add(), vec!["1.0.0", "1.1.0"]);
+        assert!(release_metadata.next_add().is_empty());
+        assert_eq!(release_metadata.url(), Some("https://example.com/1.2.0"));
+        assert_eq!(release_metadata.manifestref(), None);
+        assert!(release_metadata.errors().is_empty());
+    }
+
+    #[test]
+    fn report_invalid_values() {
+        let keys = MetadataKeys::default();
+        let metadata = metadata(&[
+            ("io.openshift.upgrades.graph.release.remove", "yes"),
+            ("io.openshift.upgrades.graph.previous.remove_regex", "4.1.("),
+        ]);
+        let release_metadata = ReleaseMetadata::new(&metadata, &keys);
+
+        assert!(release_metadata.is_removed().is_err());
+        assert!(release_metadata.previous_remove_regex().is_err());
+        assert_eq!(
+            release_metadata
+                .errors()
+                .into_iter()
+                .map(|e| e.key)
+                .collect::<Vec<_>>(),
+            vec![keys.remove.clone(), keys.previous_remove_regex.clone()]
+        );
+    }
+
+    #[test]
+    fn values_are_parsed_exactly() {
+        let keys = MetadataKeys::default();
+        let metadata = metadata(&[("io.openshift.upgrades.graph.release.remove", " true")]);
+        let release_metadata = ReleaseMetadata::new(&metadata, &keys);
+
+        assert!(release_metadata.is_removed().is_err());
+    }
+
+    #[test]
+    fn collect_graph_errors() {
+        let keys = MetadataKeys::default();
+        let graph = crate::testing::generate_custom_graph(
+            "image",
+            vec![
+                (
+                    0,
+                    metadata(&[
+                        (keys.remove.as_str(), "yes"),
+                        (keys.previous_remove_regex.as_str(), "("),
+                    ]),
+                ),
+                (1, metadata(&[(keys.remove.as_str(), "true")])),
+                (2, metadata(&[(keys.remove.as_str(), "no")])),
+            ],
+            None,
+        );
+
+        let errors = graph_errors(&graph, &keys);
+        assert_eq!(
+            errors.keys().cloned().collect::<Vec<_>>(),
+            vec![
+                ("0.0.0".to_string(), keys.previous_remove_regex.clone()),
+                ("0.0.0".to_string(), keys.remove.clone()),
+                ("2.0.0".to_string(), keys.remove.clone()),
+            ]
+        );
+    }
+
+    #[test]
+    fn custom_keys() {
+        let keys = MetadataKeys::with_prefix("test")
+            .with_channels_key("custom.channels".to_string())
+            .with_arch_key("custom.arch".to_string());
+        let metadata = metadata(&[
+            ("custom.channels", "a"),
+            ("custom.arch", "amd64"),
+            ("test.release.remove", "true"),
+        ]);
+        let release_metadata = ReleaseMetadata::new(&metadata, &keys);
+
+        assert!(release_metadata.in_channel("a"));
+        assert_eq!(release_metadata.arch(), Some("amd64"));
+        assert_eq!(release_metadata.is_removed(), Ok(true));
+    }
+}
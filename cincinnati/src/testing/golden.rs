@@ -0,0 +1,333 @@
+//! Golden-file tests for plugin chains.
+//!
+//! A case is a directory holding:
+//!  * `plugins.toml`: the plugin chain, as `[[plugins]]` entries of the same
+//!    form as in the graph-builder and policy-engine configuration.
+//!  * `input.json` (optional): the input graph, empty if missing.
+//!  * `parameters.toml` (optional): the request parameters, as string values.
+//!  * `expected.json`: the expected output graph.
+//!
+//! Running a case processes the input with the plugin chain, and compares the
+//! output with the expected graph, including its conditional edges and their
+//! risks. In bless mode, the expected graph is written
+//! from the output instead, so that fixtures can be regenerated after intended
+//! changes and reviewed as part of the diff.
+
+use super::{compare_graphs_verbose, CompareGraphsVerboseSettings};
+use crate::plugins::{self, catalog, InternalIO, PluginIO};
+use crate::Graph;
+use commons::prelude_errors::*;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::convert::TryInto;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// File holding the plugin chain of a case.
+pub static PLUGINS_FILE: &str = "plugins.toml";
+
+/// File holding the input graph of a case.
+pub static INPUT_FILE: &str = "input.json";
+
+/// File holding the parameters of a case.
+pub static PARAMETERS_FILE: &str = "parameters.toml";
+
+/// File holding the expected output graph of a case.
+pub static EXPECTED_FILE: &str = "expected.json";
+
+/// Command-line flag requesting bless mode.
+pub static BLESS_FLAG: &str = "--bless";
+
+/// Environment variable requesting bless mode, when set to a non-empty value.
+pub static BLESS_ENV: &str = "CINCINNATI_BLESS";
+
+/// Plugin chain of a case.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PluginsFile {
+    plugins: Vec<toml::Value>,
+}
+
+/// Returns whether bless mode was requested on the command line or in the environment.
+pub fn bless_requested() -> bool {
+    std::env::args().any(|arg| arg == BLESS_FLAG)
+        || std::env::var(BLESS_ENV).map_or(false, |value| !value.is_empty())
+}
+
+/// Returns the directories of the cases below `dir`, sorted by name.
+pub fn find_cases<P: AsRef<Path>>(dir: P) -> Fallible<Vec<PathBuf>> {
+    let dir = dir.as_ref();
+    let mut cases = vec![];
+    for entry in fs::read_dir(dir).context(format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.join(PLUGINS_FILE).is_file() {
+            cases.push(path);
+        }
+    }
+    cases.sort();
+
+    Ok(cases)
+}
+
+/// Runs a case, or regenerates its expected graph in bless mode.
+pub async fn run_case<P: AsRef<Path>>(dir: P, bless: bool) -> Fallible<()> {
+    let dir = dir.as_ref();
+
+    let plugins: PluginsFile = commons::de::from_toml_str(&read(&dir.join(PLUGINS_FILE))?)
+        .context(format!("parsing {}", PLUGINS_FILE))?;
+    let settings = plugins
+        .plugins
+        .into_iter()
+        .enumerate()
+        .map(|(i, cfg)| {
+            catalog::deserialize_config(cfg).context(format!("invalid plugin at 'plugins[{}]'", i))
+        })
+        .collect::<Fallible<Vec<_>>>()?;
+    let plugins = catalog::build_plugins(&settings, None)?;
+
+    let input_path = dir.join(INPUT_FILE);
+    let graph: Graph = if input_path.exists() {
+        serde_json::from_str(&read(&input_path)?).context(format!("parsing {}", INPUT_FILE))?
+    } else {
+        Graph::default()
+    };
+
+    let parameters_path = dir.join(PARAMETERS_FILE);
+    let parameters: HashMap<String, String> = if parameters_path.exists() {
+        commons::de::from_toml_str(&read(&parameters_path)?)
+            .context(format!("parsing {}", PARAMETERS_FILE))?
+    } else {
+        HashMap::new()
+    };
+
+    let mut io = PluginIO::InternalIO(InternalIO {
+        graph: graph.into(),
+        parameters,
+    });
+    for plugin in &plugins {
+        io = plugins::run_plugin(plugin, io)
+            .await
+            .context("running the plugin chain")?;
+    }
+    let output: InternalIO = io.try_into()?;
+
+    let expected_path = dir.join(EXPECTED_FILE);
+    if bless {
+        let mut json = serde_json::to_string_pretty(&*output.graph)?;
+        json.push('\n');
+        return fs::write(&expected_path, json)
+            .context(format!("writing {}", expected_path.display()));
+    }
+
+    ensure!(
+        expected_path.exists(),
+        "missing {}, run with {} to create it",
+        EXPECTED_FILE,
+        BLESS_FLAG
+    );
+    let expected: Graph = serde_json::from_str(&read(&expected_path)?)
+        .context(format!("parsing {}", EXPECTED_FILE))?;
+
+    let conditional_edges = compare_conditional_edges(&expected, &output.graph);
+    compare_graphs_verbose(
+        expected,
+        (*output.graph).clone(),
+        CompareGraphsVerboseSettings::default(),
+    )?;
+    conditional_edges
+}
+
+/// Conditional edges of a graph by their versions, with whether the edge is also
+/// unconditional and its serialized risks, sorted.
+fn conditional_edges(graph: &Graph) -> Fallible<BTreeMap<(String, String), (bool, Vec<String>)>> {
+    graph
+        .edges()
+        .filter(|(_, _, weight)| weight.is_conditional())
+        .map(|(from, to, weight)| {
+            let mut risks = weight
+                .risks
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<_>, _>>()?;
+            risks.sort();
+            Ok((
+                (from.version().to_string(), to.version().to_string()),
+                (weight.unconditional, risks),
+            ))
+        })
+        .collect()
+}
+
+/// Compares the conditional edges and their risks, which the graph comparison ignores.
+fn compare_conditional_edges(expected: &Graph, output: &Graph) -> Fallible<()> {
+    let expected = conditional_edges(expected)?;
+    let output = conditional_edges(output)?;
+
+    let mut differences = vec![];
+    for (edge, weight) in &expected {
+        match output.get(edge) {
+            None => differences.push(format!("- {} -> {}: {:?}", edge.0, edge.1, weight)),
+            Some(other) if other != weight => differences.push(format!(
+                "~ {} -> {}: expected {:?}, got {:?}",
+                edge.0, edge.1, weight, other
+            )),
+            Some(_) => {}
+        }
+    }
+    for (edge, weight) in &output {
+        if !expected.contains_key(edge) {
+            differences.push(format!("+ {} -> {}: {:?}", edge.0, edge.1, weight));
+        }
+    }
+    ensure!(
+        differences.is_empty(),
+        "Conditional edges differ!\n{}",
+        differences.join("\n")
+    );
+
+    Ok(())
+}
+
+/// Runs all cases below `dir`, reporting the failures of all of them.
+pub fn run_cases<P: AsRef<Path>>(dir: P, bless: bool) -> Fallible<()> {
+    let runtime = commons::testing::init_runtime()?;
+
+    let cases = find_cases(&dir)?;
+    ensure!(!cases.is_empty(), "no cases in {}", dir.as_ref().display());
+
+    let mut failures = vec![];
+    for case in &cases {
+        if let Err(e) = runtime.block_on(run_case(case, bless)) {
+            failures.push(format!("case {} failed: {:#}", case.display(), e));
+        }
+    }
+    ensure!(
+        failures.is_empty(),
+        "{} of {} cases failed\n\n{}",
+        failures.len(),
+        cases.len(),
+        failures.join("\n\n")
+    );
+
+    Ok(())
+}
+
+fn read(path: &Path) -> Fallible<String> {
+    fs::read_to_string(path).context(format!("reading {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_case(dir: &Path, expected: Option<&str>) -> Fallible<()> {
+        fs::write(
+            dir.join(PLUGINS_FILE),
+            r#"
+                [[plugins]]
+                name = "channel-filter"
+                key_prefix = "io.openshift.upgrades.graph"
+                key_suffix = "release.channels"
+            "#,
+        )?;
+        fs::write(
+            dir.join(INPUT_FILE),
+            r#"{
+                "nodes": [
+                    {"version": "4.1.0", "payload": "image/4.1.0", "metadata": {"io.openshift.upgrades.graph.release.channels": "stable-4.1"}},
+                    {"version": "4.1.1", "payload": "image/4.1.1", "metadata": {"io.openshift.upgrades.graph.release.channels": "fast-4.1"}}
+                ],
+                "edges": [[0, 1]],
+                "conditionalEdges": []
+            }"#,
+        )?;
+        fs::write(dir.join(PARAMETERS_FILE), r#"channel = "stable-4.1""#)?;
+        if let Some(expected) = expected {
+            fs::write(dir.join(EXPECTED_FILE), expected)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn bless_then_check() -> Fallible<()> {
+        let runtime = commons::testing::init_runtime()?;
+        let dir = tempfile::tempdir()?;
+        write_case(dir.path(), None)?;
+
+        let err = runtime.block_on(run_case(dir.path(), false)).unwrap_err();
+        assert!(err.to_string().contains("--bless"), "{:#}", err);
+
+        runtime.block_on(run_case(dir.path(), true))?;
+        let expected: Graph = serde_json::from_str(&read(&dir.path().join(EXPECTED_FILE))?)?;
+        assert_eq!(expected.releases_count(), 1);
+
+        runtime.block_on(run_case(dir.path(), false))?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_differences() -> Fallible<()> {
+        let root = tempfile::tempdir()?;
+        let case = root.path().join("stable");
+        fs::create_dir(&case)?;
+        write_case(
+            &case,
+            Some(r#"{"nodes": [], "edges": [], "conditionalEdges": []}"#),
+        )?;
+
+        let err = run_cases(root.path(), false).unwrap_err().to_string();
+        assert!(err.starts_with("1 of 1 cases failed"), "{}", err);
+        assert!(err.contains("Graphs differ!"), "{}", err);
+        assert!(err.contains("4.1.0"), "{}", err);
+
+        Ok(())
+    }
+
+    #[test]
+    fn report_conditional_edge_differences() -> Fallible<()> {
+        let runtime = commons::testing::init_runtime()?;
+        let dir = tempfile::tempdir()?;
+        fs::write(
+            dir.path().join(PLUGINS_FILE),
+            r#"
+                [[plugins]]
+                name = "node-remove"
+            "#,
+        )?;
+        let graph = |risk: &str| {
+            format!(
+                r#"{{
+                    "nodes": [
+                        {{"version": "4.1.0", "payload": "image/4.1.0", "metadata": {{}}}},
+                        {{"version": "4.1.1", "payload": "image/4.1.1", "metadata": {{}}}}
+                    ],
+                    "edges": [],
+                    "conditionalEdges": [{{
+                        "edges": [{{"from": "4.1.0", "to": "4.1.1"}}],
+                        "risks": [{{
+                            "url": "https://example.com/{0}",
+                            "name": "{0}",
+                            "message": "{0}",
+                            "matchingRules": [{{"type": "Always"}}]
+                        }}]
+                    }}]
+                }}"#,
+                risk
+            )
+        };
+        fs::write(dir.path().join(INPUT_FILE), graph("Actual"))?;
+        fs::write(dir.path().join(EXPECTED_FILE), graph("Expected"))?;
+
+        let err = runtime.block_on(run_case(dir.path(), false)).unwrap_err();
+        let err = err.to_string();
+        assert!(err.contains("Conditional edges differ!"), "{}", err);
+        assert!(err.contains("~ 4.1.0 -> 4.1.1"), "{}", err);
+        assert!(err.contains("Actual"), "{}", err);
+
+        fs::write(dir.path().join(EXPECTED_FILE), graph("Actual"))?;
+        runtime.block_on(run_case(dir.path(), false))?;
+
+        Ok(())
+    }
+}
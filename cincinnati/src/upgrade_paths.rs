@@ -0,0 +1,500 @@
+//! Upgrade path computation on top of `Graph`.
+//!
+//! Paths are computed in terms of hops, so a shortest path is the one with
+//! the fewest intermediate releases. Conditional edges are only traversed if
+//! the given `ConditionalEdgesFilter` allows it.
+
+use crate::{errors, Graph};
+use commons::prelude_errors::*;
+use daggy::NodeIndex;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+/// Selects which conditional edges may be traversed when computing upgrade paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionalEdgesFilter {
+    /// Only traverse unconditional edges.
+    Exclude,
+    /// Traverse all conditional edges regardless of their risks.
+    IncludeAll,
+    /// Traverse conditional edges whose risks are all contained in the given set of risk names.
+    IncludeRisks(HashSet<String>),
+}
+
+impl Default for ConditionalEdgesFilter {
+    fn default() -> Self {
+        ConditionalEdgesFilter::Exclude
+    }
+}
+
+impl ConditionalEdgesFilter {
+    /// Returns true if an edge exposed to the given risks may be traversed.
+    fn accepts(&self, risks: &[String]) -> bool {
+        match self {
+            ConditionalEdgesFilter::Exclude => risks.is_empty(),
+            ConditionalEdgesFilter::IncludeAll => true,
+            ConditionalEdgesFilter::IncludeRisks(accepted) => {
+                risks.iter().all(|risk| accepted.contains(risk))
+            }
+        }
+    }
+}
+
+/// A single path through the graph.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct UpgradePath {
+    /// Versions along the path, starting with the source and ending with the target.
+    pub versions: Vec<String>,
+    /// Names of the risks which need to be accepted to follow this path.
+    pub risks: Vec<String>,
+}
+
+/// Number of partial paths per requested path after which `collect_upgrade_paths`
+/// stops branching out.
+const PARTIAL_PATHS_PER_PATH: usize = 64;
+
+/// Outgoing edges per release, with the risks attached to each edge.
+///
+/// Unconditional edges have no risks attached.
+type Adjacency = HashMap<NodeIndex, Vec<(NodeIndex, Vec<String>)>>;
+
+impl Graph {
+    /// Returns one of the shortest upgrade paths from `from` to `to`.
+    ///
+    /// Returns `None` if the target can't be reached using the edges selected by `filter`.
+    /// Fails if either version doesn't exist in the graph.
+    pub fn shortest_upgrade_path(
+        &self,
+        from: &str,
+        to: &str,
+        filter: &ConditionalEdgesFilter,
+    ) -> Fallible<Option<UpgradePath>> {
+        Ok(self
+            .all_shortest_upgrade_paths(from, to, filter, 1)?
+            .into_iter()
+            .next())
+    }
+
+    /// Returns up to `limit` upgrade paths from `from` to `to` which all have the minimal number of hops.
+    ///
+    /// Paths which don't require accepting any risks are returned first.
+    /// Fails if either version doesn't exist in the graph.
+    pub fn all_shortest_upgrade_paths(
+        &self,
+        from: &str,
+        to: &str,
+        filter: &ConditionalEdgesFilter,
+        limit: usize,
+    ) -> Fallible<Vec<UpgradePath>> {
+        let source = self
+            .find_by_version(from)
+            .ok_or_else(|| errors::ReleaseDoesntExist(from.to_string()))?
+            .0;
+        let target = self
+            .find_by_version(to)
+            .ok_or_else(|| errors::ReleaseDoesntExist(to.to_string()))?
+            .0;
+
+        if limit == 0 {
+            return Ok(vec![]);
+        }
+
+        let adjacency = self.upgrade_adjacency(filter);
+
+        // Breadth-first search which records all predecessors on a shortest path.
+        let mut distances: HashMap<NodeIndex, usize> = HashMap::new();
+        let mut predecessors: Adjacency = HashMap::new();
+        let mut queue: VecDeque<NodeIndex> = VecDeque::new();
+
+        distances.insert(source, 0);
+        queue.push_back(source);
+
+        while let Some(current) = queue.pop_front() {
+            let distance = distances[&current];
+            if let Some(target_distance) = distances.get(&target) {
+                if distance >= *target_distance {
+                    break;
+                }
+            }
+
+            for (next, risks) in adjacency.get(&current).into_iter().flatten() {
+                match distances.get(next) {
+                    Some(next_distance) if *next_distance != distance + 1 => continue,
+                    Some(_) => {}
+                    None => {
+                        distances.insert(*next, distance + 1);
+                        queue.push_back(*next);
+                    }
+                };
+                predecessors
+                    .entry(*next)
+                    .or_default()
+                    .push((current, risks.clone()));
+            }
+        }
+
+        if !distances.contains_key(&target) {
+            return Ok(vec![]);
+        }
+
+        Ok(self.collect_upgrade_paths(&predecessors, source, target, limit))
+    }
+
+    /// Walks the predecessors backwards from `target` to `source` and collects up to `limit` paths.
+    ///
+    /// Partial paths are extended best-first by the number of risks they require,
+    /// so the paths are collected in ascending order of risks and the ones which
+    /// are cut off by the limit never need fewer risks than the ones returned.
+    /// Among partial paths with as many risks, the latest one is extended first,
+    /// so that paths are completed depth-first instead of materializing every
+    /// prefix of every shortest path.
+    ///
+    /// Every partial path can be completed, as all predecessors lie on a shortest path.
+    /// Once `PARTIAL_PATHS_PER_PATH` partial paths per requested path were created,
+    /// partial paths are only extended by the predecessor adding the fewest risks,
+    /// which bounds the work on graphs where the risks grow with every hop.
+    fn collect_upgrade_paths(
+        &self,
+        predecessors: &Adjacency,
+        source: NodeIndex,
+        target: NodeIndex,
+        limit: usize,
+    ) -> Vec<UpgradePath> {
+        // Partial paths, from their first release up to the target.
+        let mut partial_paths: Vec<(Vec<NodeIndex>, Vec<String>)> = vec![(vec![target], vec![])];
+        // Partial paths to extend, by number of risks and then latest first.
+        let mut queue: BinaryHeap<Reverse<(usize, Reverse<usize>)>> = BinaryHeap::new();
+        queue.push(Reverse((0, Reverse(0))));
+        let max_partial_paths = limit.saturating_mul(PARTIAL_PATHS_PER_PATH);
+
+        let mut paths = Vec::new();
+        while let Some(Reverse((_, Reverse(index)))) = queue.pop() {
+            let (nodes, risks) = std::mem::take(&mut partial_paths[index]);
+            let current = *nodes.last().expect("partial paths are never empty");
+
+            if current == source {
+                let versions = nodes
+                    .iter()
+                    .rev()
+                    .map(|node| self.version_of(*node))
+                    .collect();
+                paths.push(UpgradePath { versions, risks });
+                if paths.len() >= limit {
+                    break;
+                }
+                continue;
+            }
+
+            let mut extended: Vec<(Vec<NodeIndex>, Vec<String>)> = predecessors
+                .get(&current)
+                .into_iter()
+                .flatten()
+                .map(|(previous, edge_risks)| {
+                    let mut nodes = nodes.clone();
+                    nodes.push(*previous);
+                    let mut risks = risks.clone();
+                    for risk in edge_risks {
+                        if !risks.contains(risk) {
+                            risks.push(risk.clone());
+                        }
+                    }
+                    (nodes, risks)
+                })
+                .collect();
+            if partial_paths.len() >= max_partial_paths {
+                extended.sort_by_key(|(_, risks)| risks.len());
+                extended.truncate(1);
+            }
+
+            for (nodes, risks) in extended {
+                queue.push(Reverse((risks.len(), Reverse(partial_paths.len()))));
+                partial_paths.push((nodes, risks));
+            }
+        }
+
+        paths
+    }
+
+    /// Builds the outgoing edges for every release, including the conditional
+    /// edges selected by `filter`.
+    ///
+    /// If an edge is both unconditional and conditional, the unconditional one wins.
+    fn upgrade_adjacency(&self, filter: &ConditionalEdgesFilter) -> Adjacency {
+        let mut adjacency: Adjacency = HashMap::with_capacity(self.dag.node_count());
+        for edge in self.dag.raw_edges() {
+            let risks = if edge.weight.unconditional {
+                vec![]
+            } else {
+                let mut risks: Vec<String> = vec![];
+                for risk in &edge.weight.risks {
+                    if !risks.contains(&risk.name) {
+                        risks.push(risk.name.clone());
+                    }
+                }
+                if risks.is_empty() || !filter.accepts(&risks) {
+                    continue;
+                }
+                risks
+            };
+
+            adjacency
+                .entry(edge.source())
+                .or_default()
+                .push((edge.target(), risks));
+        }
+
+        adjacency
+    }
+
+    fn version_of(&self, index: NodeIndex) -> String {
+        self.dag
+            .node_weight(index)
+            .map(|release| release.version().to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{generate_custom_graph, generate_graph};
+    use crate::ConditionalUpdateRisk;
+
+    fn versions(path: &UpgradePath) -> Vec<&str> {
+        path.versions.iter().map(String::as_str).collect()
+    }
+
+    fn add_conditional_edge(graph: &mut Graph, from: &str, to: &str, risk: &str) {
+        let from = graph.find_by_version(from).unwrap();
+        let to = graph.find_by_version(to).unwrap();
+        let risk = ConditionalUpdateRisk {
+            name: risk.to_string(),
+            ..Default::default()
+        };
+        graph.add_conditional_edge(&from, &to, &[risk]).unwrap();
+    }
+
+    #[test]
+    fn shortest_path_uses_direct_edge() -> Fallible<()> {
+        let graph = generate_graph(false, false);
+
+        let path = graph
+            .shortest_upgrade_path("1.0.0", "3.0.0", &ConditionalEdgesFilter::Exclude)?
+            .expect("expected a path");
+
+        assert_eq!(versions(&path), vec!["1.0.0", "3.0.0"]);
+        assert!(path.risks.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn shortest_path_unreachable() -> Fallible<()> {
+        let graph = generate_graph(false, false);
+
+        let path =
+            graph.shortest_upgrade_path("3.0.0", "1.0.0", &ConditionalEdgesFilter::Exclude)?;
+        assert_eq!(path, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn shortest_path_missing_release() {
+        let graph = generate_graph(false, false);
+
+        let err = graph
+            .shortest_upgrade_path("1.0.0", "9.0.0", &ConditionalEdgesFilter::Exclude)
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<errors::ReleaseDoesntExist>(),
+            Some(&errors::ReleaseDoesntExist("9.0.0".to_string()))
+        );
+    }
+
+    #[test]
+    fn all_shortest_paths() -> Fallible<()> {
+        let graph = generate_custom_graph(
+            "image",
+            (0..5).map(|i| (i, Default::default())).collect(),
+            Some(vec![(0, 1), (0, 2), (1, 3), (2, 3), (3, 4), (0, 4)]),
+        );
+
+        let paths = graph.all_shortest_upgrade_paths(
+            "0.0.0",
+            "3.0.0",
+            &ConditionalEdgesFilter::Exclude,
+            10,
+        )?;
+        let mut paths: Vec<Vec<&str>> = paths.iter().map(versions).collect();
+        paths.sort();
+
+        assert_eq!(
+            paths,
+            vec![
+                vec!["0.0.0", "1.0.0", "3.0.0"],
+                vec!["0.0.0", "2.0.0", "3.0.0"],
+            ]
+        );
+
+        let limited = graph.all_shortest_upgrade_paths(
+            "0.0.0",
+            "3.0.0",
+            &ConditionalEdgesFilter::Exclude,
+            1,
+        )?;
+        assert_eq!(limited.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn limited_paths_prefer_fewer_risks() -> Fallible<()> {
+        let mut graph = generate_custom_graph(
+            "image",
+            (0..4).map(|i| (i, Default::default())).collect(),
+            Some(vec![(0, 2), (1, 3), (2, 3)]),
+        );
+        add_conditional_edge(&mut graph, "0.0.0", "1.0.0", "SomeRisk");
+
+        let limited = graph.all_shortest_upgrade_paths(
+            "0.0.0",
+            "3.0.0",
+            &ConditionalEdgesFilter::IncludeAll,
+            1,
+        )?;
+        assert_eq!(limited.len(), 1);
+        assert_eq!(versions(&limited[0]), vec!["0.0.0", "2.0.0", "3.0.0"]);
+        assert!(limited[0].risks.is_empty());
+
+        let all = graph.all_shortest_upgrade_paths(
+            "0.0.0",
+            "3.0.0",
+            &ConditionalEdgesFilter::IncludeAll,
+            10,
+        )?;
+        assert_eq!(
+            all.iter().map(|path| path.risks.len()).collect::<Vec<_>>(),
+            vec![0, 1]
+        );
+
+        Ok(())
+    }
+
+    /// Edges of `layers` layers of `width` releases after a single first release,
+    /// each release updating to all releases of the next layer.
+    fn dense_edges(width: usize, layers: usize) -> Vec<(usize, usize)> {
+        let layer = |i: usize| (1 + i * width..1 + (i + 1) * width);
+        let mut edges: Vec<(usize, usize)> = layer(0).map(|to| (0, to)).collect();
+        for i in 1..layers {
+            for from in layer(i - 1) {
+                edges.extend(layer(i).map(|to| (from, to)));
+            }
+        }
+        edges
+    }
+
+    #[test]
+    fn limited_paths_in_dense_graph() -> Fallible<()> {
+        // 4^12 shortest paths, which must not all be materialized.
+        let (width, layers) = (4, 12);
+        let graph = generate_custom_graph(
+            "image",
+            (0..=width * layers)
+                .map(|i| (i, Default::default()))
+                .collect(),
+            Some(dense_edges(width, layers)),
+        );
+
+        let paths = graph.all_shortest_upgrade_paths(
+            "0.0.0",
+            &format!("{}.0.0", width * layers),
+            &ConditionalEdgesFilter::Exclude,
+            100,
+        )?;
+        assert_eq!(paths.len(), 100);
+        assert!(paths.iter().all(|path| path.versions.len() == layers + 1));
+        let distinct: HashSet<&Vec<String>> = paths.iter().map(|path| &path.versions).collect();
+        assert_eq!(distinct.len(), 100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn limited_paths_with_risk_on_every_hop() -> Fallible<()> {
+        // Every hop adds a risk, so extending best-first by risks alone would
+        // materialize every prefix of every path.
+        let (width, layers) = (4, 10);
+        let mut graph = generate_custom_graph(
+            "image",
+            (0..=width * layers)
+                .map(|i| (i, Default::default()))
+                .collect(),
+            Some(vec![]),
+        );
+        for (from, to) in dense_edges(width, layers) {
+            add_conditional_edge(
+                &mut graph,
+                &format!("{}.0.0", from),
+                &format!("{}.0.0", to),
+                &format!("Risk{}-{}", from, to),
+            );
+        }
+
+        let paths = graph.all_shortest_upgrade_paths(
+            "0.0.0",
+            &format!("{}.0.0", width * layers),
+            &ConditionalEdgesFilter::IncludeAll,
+            10,
+        )?;
+        assert_eq!(paths.len(), 10);
+        assert!(paths.iter().all(|path| path.risks.len() == layers));
+
+        Ok(())
+    }
+
+    #[test]
+    fn conditional_edges_filtered_by_risk() -> Fallible<()> {
+        let mut graph = generate_custom_graph(
+            "image",
+            (0..3).map(|i| (i, Default::default())).collect(),
+            Some(vec![(0, 1), (1, 2)]),
+        );
+        add_conditional_edge(&mut graph, "0.0.0", "2.0.0", "SomeRisk");
+
+        let unconditional = graph
+            .shortest_upgrade_path("0.0.0", "2.0.0", &ConditionalEdgesFilter::Exclude)?
+            .expect("expected a path");
+        assert_eq!(versions(&unconditional), vec!["0.0.0", "1.0.0", "2.0.0"]);
+
+        let conditional = graph
+            .shortest_upgrade_path("0.0.0", "2.0.0", &ConditionalEdgesFilter::IncludeAll)?
+            .expect("expected a path");
+        assert_eq!(versions(&conditional), vec!["0.0.0", "2.0.0"]);
+        assert_eq!(conditional.risks, vec!["SomeRisk".to_string()]);
+
+        let accepted = graph
+            .shortest_upgrade_path(
+                "0.0.0",
+                "2.0.0",
+                &ConditionalEdgesFilter::IncludeRisks(
+                    vec!["SomeRisk".to_string()].into_iter().collect(),
+                ),
+            )?
+            .expect("expected a path");
+        assert_eq!(versions(&accepted), vec!["0.0.0", "2.0.0"]);
+
+        let not_accepted = graph
+            .shortest_upgrade_path(
+                "0.0.0",
+                "2.0.0",
+                &ConditionalEdgesFilter::IncludeRisks(
+                    vec!["OtherRisk".to_string()].into_iter().collect(),
+                ),
+            )?
+            .expect("expected a path");
+        assert_eq!(versions(&not_accepted), vec!["0.0.0", "1.0.0", "2.0.0"]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,299 @@
+//! Serialization of `Graph` for the `application/vnd.redhat.cincinnati.v2+json` media type.
+//!
+//! Unlike v1, edges reference releases by version instead of by their position
+//! in the node list. Conditional edges reference their risks by name and each
+//! risk is emitted once in the top-level `risks` list.
+//!
+//! This module is meant to be used with `#[serde(with = "cincinnati::v2")]`.
+
+use crate::{ConditionalUpdateEdge, ConditionalUpdateRisk, EdgeWeight, Graph, Release};
+use daggy::Dag;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{self, Serialize, Serializer};
+use std::collections::HashMap;
+
+/// Edge between two releases, referenced by version.
+#[derive(Debug, Serialize, Deserialize)]
+struct Edge<V> {
+    from: V,
+    to: V,
+}
+
+/// Conditional edges referencing their risks by name.
+#[derive(Debug, Serialize, Deserialize)]
+struct ConditionalEdges<E, R> {
+    edges: E,
+    risks: Vec<R>,
+}
+
+#[derive(Serialize)]
+struct SerializeGraph<'a> {
+    nodes: Vec<&'a Release>,
+    edges: Vec<Edge<&'a str>>,
+    #[serde(rename = "conditionalEdges")]
+    conditional_edges: Vec<ConditionalEdges<Vec<ConditionalUpdateEdge>, String>>,
+    risks: Vec<ConditionalUpdateRisk>,
+}
+
+#[derive(Deserialize)]
+struct DeserializeGraph {
+    nodes: Vec<Release>,
+    edges: Vec<Edge<String>>,
+    #[serde(rename = "conditionalEdges", default)]
+    conditional_edges: Vec<ConditionalEdges<Vec<ConditionalUpdateEdge>, String>>,
+    #[serde(default)]
+    risks: Vec<ConditionalUpdateRisk>,
+}
+
+/// Serialize a graph in the v2 format.
+///
+/// Risks are identified by name, so this fails if different risks share a name.
+pub fn serialize<S>(graph: &Graph, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let edges = graph
+        .edges()
+        .filter(|(_, _, weight)| weight.unconditional)
+        .map(|(from, to, _)| Edge {
+            from: from.version(),
+            to: to.version(),
+        })
+        .collect();
+
+    let mut risks_by_name: HashMap<String, usize> = HashMap::new();
+    let mut risks: Vec<ConditionalUpdateRisk> = vec![];
+    let mut conditional_edges = vec![];
+    for ce in graph.conditional_edges() {
+        let names = ce.risks.iter().map(|risk| risk.name.clone()).collect();
+        for risk in ce.risks {
+            match risks_by_name.get(&risk.name) {
+                Some(index) if risks[*index] == risk => {}
+                Some(_) => {
+                    return Err(ser::Error::custom(format!(
+                        "conflicting risks named {}",
+                        risk.name
+                    )))
+                }
+                None => {
+                    risks_by_name.insert(risk.name.clone(), risks.len());
+                    risks.push(risk);
+                }
+            }
+        }
+        conditional_edges.push(ConditionalEdges {
+            edges: ce.edges,
+            risks: names,
+        });
+    }
+
+    SerializeGraph {
+        nodes: graph
+            .dag
+            .raw_nodes()
+            .iter()
+            .map(|node| &node.weight)
+            .collect(),
+        edges,
+        conditional_edges,
+        risks,
+    }
+    .serialize(serializer)
+}
+
+/// Deserialize a graph from the v2 format.
+pub fn deserialize<'de, D>(deserializer: D) -> Result<Graph, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let input = DeserializeGraph::deserialize(deserializer)?;
+
+    let mut graph = Graph {
+        dag: Dag::with_capacity(input.nodes.len(), input.edges.len()),
+        ..Default::default()
+    };
+
+    let mut indices = HashMap::with_capacity(input.nodes.len());
+    for node in input.nodes {
+        // Validate version string is non-empty.
+        if node.version().is_empty() {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Str(node.version()),
+                &"a non-empty string version",
+            ));
+        }
+        let version = node.version().to_string();
+        // Validate version string is unique in "nodes" set.
+        if indices.contains_key(&version) {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Str(&version),
+                &"a unique string version",
+            ));
+        }
+        indices.insert(version, graph.add_node(node));
+    }
+
+    let index = |version: &str| -> Result<daggy::NodeIndex, D::Error> {
+        indices.get(version).copied().ok_or_else(|| {
+            de::Error::invalid_value(de::Unexpected::Str(version), &"a version in \"nodes\"")
+        })
+    };
+    for edge in input.edges {
+        let (from, to) = (index(&edge.from)?, index(&edge.to)?);
+        graph
+            .dag
+            .add_edge(from, to, EdgeWeight::unconditional())
+            .map_err(|_| {
+                de::Error::custom(format!(
+                    "edge from {} to {} would cause a cycle",
+                    edge.from, edge.to
+                ))
+            })?;
+    }
+
+    let mut risks: HashMap<&str, &ConditionalUpdateRisk> =
+        HashMap::with_capacity(input.risks.len());
+    for risk in &input.risks {
+        match risks.insert(risk.name.as_str(), risk) {
+            Some(previous) if previous != risk => {
+                return Err(de::Error::invalid_value(
+                    de::Unexpected::Str(&risk.name),
+                    &"a unique risk name",
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    for ce in input.conditional_edges {
+        let ce_risks: Vec<ConditionalUpdateRisk> = ce
+            .risks
+            .iter()
+            .map(|name| {
+                risks
+                    .get(name.as_str())
+                    .map(|risk| (*risk).clone())
+                    .ok_or_else(|| {
+                        de::Error::invalid_value(de::Unexpected::Str(name), &"a risk in \"risks\"")
+                    })
+            })
+            .collect::<Result<_, D::Error>>()?;
+
+        for edge in ce.edges {
+            let (from, to) = (index(&edge.from)?, index(&edge.to)?);
+            graph.attach_risks(from, to, &ce_risks).map_err(|_| {
+                de::Error::custom(format!(
+                    "conditional edge from {} to {} would cause a cycle",
+                    edge.from, edge.to
+                ))
+            })?;
+        }
+    }
+
+    Ok(graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::generate_graph;
+    use commons::prelude_errors::*;
+
+    #[derive(Serialize, Deserialize)]
+    struct V2(#[serde(with = "crate::v2")] Graph);
+
+    #[test]
+    fn roundtrip() -> Fallible<()> {
+        let graph = generate_graph(true, false);
+
+        let json = serde_json::to_string(&V2(graph.clone()))?;
+        let V2(result) = serde_json::from_str(&json)?;
+
+        assert_eq!(graph, result);
+        assert_eq!(
+            graph.conditional_edges()[0].risks,
+            result.conditional_edges()[0].risks
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn edges_by_version_and_risks_once() -> Fallible<()> {
+        let mut graph = generate_graph(true, false);
+        let risks = graph.conditional_edges()[0].risks.clone();
+        let v2 = graph.find_by_version("2.0.0").unwrap();
+        let v3 = graph.find_by_version("3.0.0").unwrap();
+        graph.remove_edge(&v2, &v3)?;
+        graph.add_conditional_edge(&v2, &v3, &risks)?;
+
+        let json = serde_json::to_value(&V2(graph))?;
+
+        let mut edges = json["edges"].as_array().unwrap().clone();
+        edges.sort_by_key(|edge| edge.to_string());
+        assert_eq!(
+            edges,
+            vec![
+                serde_json::json!({"from": "1.0.0", "to": "2.0.0"}),
+                serde_json::json!({"from": "1.0.0", "to": "3.0.0"}),
+            ]
+        );
+        assert_eq!(
+            json["conditionalEdges"],
+            serde_json::json!([{
+                "edges": [
+                    {"from": "1.0.0", "to": "2.0.0"},
+                    {"from": "2.0.0", "to": "3.0.0"},
+                ],
+                "risks": ["BrokenUpdates"],
+            }])
+        );
+        assert_eq!(json["risks"].as_array().unwrap().len(), 1);
+        assert_eq!(json["risks"][0]["name"], "BrokenUpdates");
+        Ok(())
+    }
+
+    #[test]
+    fn conflicting_risk_names() -> Fallible<()> {
+        let mut graph = generate_graph(true, false);
+        let mut risks = graph.conditional_edges()[0].risks.clone();
+        risks[0].message = "Some other message".to_string();
+        let v2 = graph.find_by_version("2.0.0").unwrap();
+        let v3 = graph.find_by_version("3.0.0").unwrap();
+        graph.add_conditional_edge(&v2, &v3, &risks)?;
+
+        let err = serde_json::to_string(&V2(graph)).unwrap_err();
+        assert!(err.to_string().contains("BrokenUpdates"), "{}", err);
+
+        let conflicting = r#"{
+            "nodes": [{"version": "1.0.0", "payload": "image/1.0.0", "metadata": {}}],
+            "edges": [],
+            "conditionalEdges": [],
+            "risks": [
+                {"name": "Risk", "url": "https://example.com/a", "message": "a", "matchingRules": []},
+                {"name": "Risk", "url": "https://example.com/b", "message": "b", "matchingRules": []}
+            ]
+        }"#;
+        assert!(serde_json::from_str::<V2>(conflicting).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_references() {
+        let unknown_version = r#"{
+            "nodes": [{"version": "1.0.0", "payload": "image/1.0.0", "metadata": {}}],
+            "edges": [{"from": "1.0.0", "to": "2.0.0"}]
+        }"#;
+        assert!(serde_json::from_str::<V2>(unknown_version).is_err());
+
+        let unknown_risk = r#"{
+            "nodes": [{"version": "1.0.0", "payload": "image/1.0.0", "metadata": {}}],
+            "edges": [],
+            "conditionalEdges": [
+                {"edges": [{"from": "1.0.0", "to": "2.0.0"}], "risks": ["Missing"]}
+            ],
+            "risks": []
+        }"#;
+        assert!(serde_json::from_str::<V2>(unknown_risk).is_err());
+    }
+}
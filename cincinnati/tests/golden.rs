@@ -0,0 +1,20 @@
+//! Golden-file tests for plugin chains.
+//!
+//! Each directory below `tests/golden` is a case, as described in
+//! `cincinnati::testing::golden`. Run with `--bless` to regenerate the
+//! expected graphs:
+//!
+//! ```sh
+//! cargo test -p cincinnati --features test --test golden -- --bless
+//! ```
+
+use cincinnati::testing::golden;
+use std::path::Path;
+
+fn main() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    if let Err(e) = golden::run_cases(dir, golden::bless_requested()) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
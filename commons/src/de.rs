@@ -1,4 +1,6 @@
-//! Deserializers.
+//! Deserializers, and the matching serializers.
+
+use crate::errors::prelude::*;
 
 /// Deserialize a log-level from a numerical value.
 pub fn de_loglevel<'de, D>(deserializer: D) -> Result<Option<log::LevelFilter>, D::Error>
@@ -16,3 +18,119 @@ where
     };
     Ok(Some(verbosity))
 }
+
+/// Serialize a log-level as the value read by `de_loglevel`.
+pub fn ser_loglevel<S>(
+    verbosity: &Option<log::LevelFilter>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    let occurrences = match verbosity {
+        None => return serializer.serialize_none(),
+        Some(log::LevelFilter::Info) => "v",
+        Some(log::LevelFilter::Debug) => "vv",
+        Some(log::LevelFilter::Trace) => "vvv",
+        Some(_) => "",
+    };
+    serializer.serialize_str(occurrences)
+}
+
+/// Invalid TOML value, with the path of the offending key.
+#[derive(Debug, Fail)]
+#[error("invalid value for key '{path}': {reason}")]
+pub struct TomlPathError {
+    pub path: String,
+    pub reason: String,
+}
+
+impl TomlPathError {
+    fn new<E: std::fmt::Display>(error: serde_path_to_error::Error<E>) -> Self {
+        Self {
+            path: error.path().to_string(),
+            reason: error.into_inner().to_string(),
+        }
+    }
+}
+
+/// Deserialize a TOML document, naming the path of the offending key on errors.
+pub fn from_toml_str<T>(input: &str) -> Fallible<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    serde_path_to_error::deserialize(toml::Deserializer::new(input))
+        .map_err(|e| TomlPathError::new(e).into())
+}
+
+/// Deserialize a TOML value, naming the path of the offending key on errors.
+pub fn from_toml_value<T>(value: toml::Value) -> Fallible<T>
+where
+    T: serde::de::DeserializeOwned,
+{
+    serde_path_to_error::deserialize(value).map_err(|e| TomlPathError::new(e).into())
+}
+
+/// Locate an error of deserializing the TOML value found at `path`.
+///
+/// The path of a `TomlPathError` is prefixed with it, other errors get it as context.
+pub fn in_toml_path(error: Error, path: &str) -> Error {
+    if error.chain().count() == 1 {
+        if let Some(e) = error.downcast_ref::<TomlPathError>() {
+            let path = match e.path.as_str() {
+                "." => path.to_string(),
+                key => format!("{}.{}", path, key),
+            };
+            return TomlPathError {
+                path,
+                reason: e.reason.clone(),
+            }
+            .into();
+        }
+    }
+    error.context(format!("invalid value for key '{}'", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Config {
+        #[allow(dead_code)]
+        service: Service,
+    }
+
+    #[derive(Debug, Deserialize)]
+    struct Service {
+        #[allow(dead_code)]
+        port: u16,
+    }
+
+    #[test]
+    fn toml_error_path() {
+        let err = from_toml_str::<Config>("[service]\nport = 'http'").unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("invalid value for key 'service.port'"),
+            "{}",
+            err
+        );
+
+        let value: toml::Value = toml::from_str("port = 'http'").unwrap();
+        let err = from_toml_value::<Service>(value).unwrap_err();
+        let err = in_toml_path(err, "plugin_settings[1]");
+        assert!(
+            err.to_string()
+                .starts_with("invalid value for key 'plugin_settings[1].port'"),
+            "{}",
+            err
+        );
+
+        let err = in_toml_path(format_err!("no such plugin"), "plugin_settings[1]");
+        assert_eq!(
+            err.to_string(),
+            "invalid value for key 'plugin_settings[1]'"
+        );
+    }
+}
@@ -44,7 +44,10 @@ pub static DEFAULT_ROOT_CERT_DIR: &str = "/etc/pki/ca-trust/extracted/";
 lazy_static! {
     /// list of cincinnati versions
     pub static ref CINCINNATI_VERSION: HashMap<&'static str, i32> =
-        [("application/vnd.redhat.cincinnati.v1+json", 1)]
+        [
+            ("application/vnd.redhat.cincinnati.v1+json", 1),
+            ("application/vnd.redhat.cincinnati.v2+json", 2),
+        ]
             .iter()
             .cloned()
             .collect();
@@ -269,7 +272,7 @@ mod tests {
 
     #[test]
     fn test_validate_content_type() {
-        let most_recent_version = "application/vnd.redhat.cincinnati.v1+json";
+        let most_recent_version = "application/vnd.redhat.cincinnati.v2+json";
         let all_supported_versions: Vec<HeaderValue> = CINCINNATI_VERSION
             .keys()
             .map(|val| HeaderValue::from_static(val))
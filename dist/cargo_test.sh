@@ -3,7 +3,7 @@
 set -e
 
 declare -A cargo_test_flags
-cargo_test_flags["cincinnati"]="--features test-net"
+cargo_test_flags["cincinnati"]="--features test,test-net"
 cargo_test_flags["commons"]=""
 cargo_test_flags["graph-builder"]="--features test-net"
 cargo_test_flags["policy-engine"]=""
@@ -2,17 +2,21 @@
 
 use super::options;
 use super::AppSettings;
-use commons::de::de_loglevel;
+use commons::de::{de_loglevel, ser_loglevel};
 use commons::prelude_errors::*;
 use commons::MergeOptions;
 use std::io::Read;
 use std::{fs, io, path};
 
 /// TOML configuration, top-level.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct FileOptions {
     /// Verbosity level.
-    #[serde(default = "Option::default", deserialize_with = "de_loglevel")]
+    #[serde(
+        default = "Option::default",
+        deserialize_with = "de_loglevel",
+        serialize_with = "ser_loglevel"
+    )]
     pub verbosity: Option<log::LevelFilter>,
 
     /// Upstream options.
@@ -41,7 +45,7 @@ impl FileOptions {
 
         let mut content = vec![];
         bufrd.read_to_end(&mut content)?;
-        let cfg = toml::from_str(std::str::from_utf8(&content)?).context(format!(
+        let cfg = commons::de::from_toml_str(std::str::from_utf8(&content)?).context(format!(
             "failed to parse config file {}:\n{}",
             cfg_path.as_ref().display(),
             std::str::from_utf8(&content).unwrap_or("file not decodable")
@@ -67,8 +71,11 @@ impl MergeOptions<Option<FileOptions>> for AppSettings {
 impl MergeOptions<Option<Vec<toml::Value>>> for AppSettings {
     fn try_merge(&mut self, opts: Option<Vec<toml::Value>>) -> Fallible<()> {
         if let Some(policies) = opts {
-            for conf in policies {
-                let plugin = cincinnati::plugins::catalog::deserialize_config(conf)?;
+            for (i, conf) in policies.into_iter().enumerate() {
+                let plugin =
+                    cincinnati::plugins::catalog::deserialize_config(conf).map_err(|e| {
+                        commons::de::in_toml_path(e, &format!("plugin_settings[{}]", i))
+                    })?;
                 self.plugin_settings.push(plugin);
             }
         }
@@ -77,7 +84,7 @@ impl MergeOptions<Option<Vec<toml::Value>>> for AppSettings {
 }
 
 /// Options for upstream fetcher.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 pub struct UpstreamOptions {
     /// Fetcher method.
     pub method: Option<String>,
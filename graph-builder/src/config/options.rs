@@ -21,6 +21,10 @@ pub struct StatusOptions {
     /// Port to which the status service will bind
     #[structopt(name = "status_port", long = "status.port")]
     pub port: Option<u16>,
+
+    /// Number of graph deltas served by the status service
+    #[structopt(name = "status_delta_history_size", long = "status.delta_history_size")]
+    pub delta_history_size: Option<usize>,
 }
 
 /// Options for the main Cincinnati service.
@@ -31,7 +35,11 @@ pub struct ServiceOptions {
         long = "service.pause_secs",
         parse(try_from_str = duration_from_secs)
     )]
-    #[serde(default = "Option::default", deserialize_with = "de_duration_secs")]
+    #[serde(
+        default = "Option::default",
+        deserialize_with = "de_duration_secs",
+        serialize_with = "ser_duration_secs"
+    )]
     pub pause_secs: Option<Duration>,
 
     /// Timeout for a single scrape in seconds
@@ -39,7 +47,11 @@ pub struct ServiceOptions {
         long = "service.scrape_timeout",
         parse(try_from_str = duration_from_secs)
     )]
-    #[serde(default = "Option::default", deserialize_with = "de_duration_secs")]
+    #[serde(
+        default = "Option::default",
+        deserialize_with = "de_duration_secs",
+        serialize_with = "ser_duration_secs"
+    )]
     pub scrape_timeout_secs: Option<Duration>,
 
     /// Address on which the server will listen
@@ -73,6 +85,14 @@ pub struct ServiceOptions {
     /// Optional tracing endpoint
     #[structopt(name = "tracing_endpoint", long = "service.tracing_endpoint")]
     pub tracing_endpoint: Option<String>,
+
+    /// Directory where graph snapshots are persisted, to serve the last graph right after a restart
+    #[structopt(long = "service.snapshot_dir")]
+    pub snapshot_dir: Option<PathBuf>,
+
+    /// Number of graph snapshots kept in the snapshot directory
+    #[structopt(long = "service.snapshots_kept")]
+    pub snapshots_kept: Option<usize>,
 }
 
 /// Options for the Docker-registry-v2 fetcher.
@@ -112,6 +132,8 @@ impl MergeOptions<Option<ServiceOptions>> for AppSettings {
             assign_if_some!(self.public_port, service.public_port);
             assign_if_some!(self.path_prefix, service.path_prefix);
             assign_if_some!(self.tracing_endpoint, service.tracing_endpoint);
+            assign_if_some!(self.snapshot_dir, service.snapshot_dir);
+            assign_if_some!(self.snapshots_kept, service.snapshots_kept);
             if let Some(params) = service.mandatory_client_parameters {
                 self.mandatory_client_parameters.extend(params);
             }
@@ -125,6 +147,7 @@ impl MergeOptions<Option<StatusOptions>> for AppSettings {
         if let Some(status) = opts {
             assign_if_some!(self.status_address, status.address);
             assign_if_some!(self.status_port, status.port);
+            assign_if_some!(self.delta_history_size, status.delta_history_size);
         }
         Ok(())
     }
@@ -152,6 +175,16 @@ where
     Ok(Some(Duration::from_secs(secs)))
 }
 
+pub fn ser_duration_secs<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match duration {
+        Some(duration) => serializer.serialize_u64(duration.as_secs()),
+        None => serializer.serialize_none(),
+    }
+}
+
 pub fn duration_from_secs<S>(num: S) -> Fallible<Duration>
 where
     S: AsRef<str>,
@@ -1,6 +1,6 @@
 //! Application settings for graph-builder.
 
-use super::{cli, file};
+use super::{cli, file, options};
 use cincinnati::plugins::catalog::{build_plugins, PluginSettings};
 use cincinnati::plugins::BoxedPlugin;
 use commons::prelude_errors::*;
@@ -63,6 +63,10 @@ pub struct AppSettings {
     #[default(9080)]
     pub status_port: u16,
 
+    /// Number of graph deltas kept for the status service.
+    #[default(10)]
+    pub delta_history_size: usize,
+
     /// Global log level.
     #[default(log::LevelFilter::Warn)]
     pub verbosity: log::LevelFilter,
@@ -83,6 +87,16 @@ pub struct AppSettings {
 
     /// Jaeger host and port for tracing support
     pub tracing_endpoint: Option<String>,
+
+    /// Optional directory where graph snapshots are persisted, for a warm start.
+    pub snapshot_dir: Option<PathBuf>,
+
+    /// Number of graph snapshots kept in the snapshot directory.
+    #[default(crate::snapshot_dir::DEFAULT_SNAPSHOTS_KEPT)]
+    pub snapshots_kept: usize,
+
+    /// Only validate the configuration, without starting the services.
+    pub check_config: bool,
 }
 
 impl AppSettings {
@@ -122,11 +136,80 @@ impl AppSettings {
         build_plugins(plugin_settings, registry)
     }
 
+    /// Validate the plugins and render the effective configuration,
+    /// including the settings of each plugin with their defaults.
+    pub fn check_config_report(&self) -> Fallible<String> {
+        let default_plugin_settings = self.default_openshift_plugin_settings()?;
+        let plugin_settings: &Vec<Box<dyn PluginSettings>> = if self.plugin_settings.is_empty() {
+            &default_plugin_settings
+        } else {
+            &self.plugin_settings
+        };
+
+        let registry = prometheus::Registry::new();
+        build_plugins(plugin_settings, Some(&registry))?;
+
+        let mut file_options = self.file_options();
+        file_options.plugin_settings = Some(
+            plugin_settings
+                .iter()
+                .enumerate()
+                .map(|(i, settings)| {
+                    settings.serialize_config().context(format!(
+                        "failed to serialize the plugin settings at 'plugin_settings[{}]'",
+                        i
+                    ))
+                })
+                .collect::<Fallible<_>>()?,
+        );
+
+        toml::to_string(&file_options).context("failed to serialize the effective configuration")
+    }
+
+    /// Return the configuration file equivalent to these settings, plugins excluded.
+    fn file_options(&self) -> file::FileOptions {
+        file::FileOptions {
+            verbosity: Some(self.verbosity),
+            upstream: Some(file::UpstreamOptions {
+                method: None,
+                pause_secs: None,
+                registry: Some(options::DockerRegistryOptions {
+                    url: Some(self.registry.clone()),
+                    repository: Some(self.repository.clone()),
+                    credentials_path: self.credentials_path.clone(),
+                    manifestref_key: Some(self.manifestref_key.clone()),
+                    fetch_concurrency: Some(self.fetch_concurrency),
+                }),
+            }),
+            service: Some(options::ServiceOptions {
+                pause_secs: Some(self.pause_secs),
+                scrape_timeout_secs: self.scrape_timeout_secs,
+                address: Some(self.address),
+                port: Some(self.port),
+                public_port: Some(self.public_port),
+                path_prefix: Some(self.path_prefix.clone()),
+                mandatory_client_parameters: Some(self.mandatory_client_parameters.clone()),
+                tracing_endpoint: self.tracing_endpoint.clone(),
+                snapshot_dir: self.snapshot_dir.clone(),
+                snapshots_kept: Some(self.snapshots_kept),
+            }),
+            status: Some(options::StatusOptions {
+                address: Some(self.status_address),
+                port: Some(self.status_port),
+                delta_history_size: Some(self.delta_history_size),
+            }),
+            plugin_settings: None,
+        }
+    }
+
     /// Validate and build runtime settings.
     fn try_validate(self) -> Fallible<Self> {
         if self.pause_secs.as_secs() == 0 {
             bail!("unexpected 0s pause");
         }
+        if self.snapshots_kept == 0 {
+            bail!("at least one graph snapshot must be kept");
+        }
 
         Ok(self)
     }
@@ -192,3 +275,83 @@ impl AppSettings {
         Ok(plugins)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commons::MergeOptions;
+
+    #[test]
+    fn check_config_report_roundtrip() -> Fallible<()> {
+        let mut settings = AppSettings::default();
+        settings.try_merge(Some(vec![
+            toml::from_str::<toml::Value>(r#"name = "node-remove""#)?,
+            toml::from_str(
+                r#"
+                    name = "edge-add-remove"
+                    key_prefix = "test"
+                "#,
+            )?,
+        ]))?;
+        settings.port = 8888;
+
+        let report = settings.check_config_report()?;
+        let file_opts: file::FileOptions = toml::from_str(&report)?;
+        let plugin_settings = file_opts
+            .plugin_settings
+            .clone()
+            .expect("missing plugin settings");
+        assert_eq!(
+            plugin_settings,
+            vec![
+                toml::from_str::<toml::Value>(
+                    r#"
+                        name = "node-remove"
+                        key_prefix = "io.openshift.upgrades.graph"
+                    "#
+                )?,
+                toml::from_str(
+                    r#"
+                        name = "edge-add-remove"
+                        key_prefix = "test"
+                        remove_all_edges_value = "*"
+                        remove_consumed_metadata = false
+                        include_conditional_edges = true
+                    "#
+                )?,
+            ]
+        );
+
+        let mut merged = AppSettings::default();
+        merged.try_merge(Some(file_opts))?;
+        assert_eq!(merged.port, 8888);
+        assert_eq!(merged.plugin_settings.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn plugin_errors_name_the_key() {
+        let mut settings = AppSettings::default();
+        let err = settings
+            .try_merge(Some(vec![
+                toml::from_str::<toml::Value>(r#"name = "node-remove""#).unwrap(),
+                toml::from_str(
+                    r#"
+                        name = "edge-add-remove"
+                        include_conditional_edges = "yes"
+                    "#,
+                )
+                .unwrap(),
+            ]))
+            .unwrap_err();
+
+        assert!(
+            err.to_string().starts_with(
+                "invalid value for key 'plugin_settings[1].include_conditional_edges'"
+            ),
+            "{}",
+            err
+        );
+    }
+}
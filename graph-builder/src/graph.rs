@@ -14,11 +14,13 @@
 
 use crate::built_info;
 use crate::config;
+use crate::snapshot::{Encoding, GraphSnapshot};
+use crate::snapshot_dir::SnapshotDir;
 use actix_files::NamedFile;
 use actix_web::http::header;
 use actix_web::{HttpRequest, HttpResponse};
 use cincinnati::plugins::prelude::*;
-use cincinnati::CONTENT_TYPE;
+use cincinnati::{GraphDelta, CONTENT_TYPE, PROTOBUF_CONTENT_TYPE};
 use commons::metrics::HasRegistry;
 use commons::tracing::get_tracer;
 use commons::{Fallible, GraphError, SECONDARY_METADATA_PARAM_KEY};
@@ -28,8 +30,7 @@ pub use parking_lot::RwLock;
 use prometheus::{
     self, histogram_opts, labels, opts, Counter, Gauge, Histogram, IntCounterVec, IntGauge, Opts,
 };
-use serde_json;
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::sync::Arc;
 use std::thread;
 
@@ -44,6 +45,11 @@ lazy_static! {
         "UTC timestamp of last successful graph refresh"
     )
     .unwrap();
+    static ref GRAPH_STALE: IntGauge = IntGauge::new(
+        "graph_stale",
+        "Whether the served graph was restored from a snapshot and not refreshed yet"
+    )
+    .unwrap();
     static ref UPSTREAM_ERRORS: Counter = Counter::new(
         "graph_upstream_errors_total",
         "Total number of upstream scraping errors"
@@ -72,6 +78,12 @@ lazy_static! {
         &["uri_path"]
     )
     .unwrap();
+    static ref GRAPH_DELTA_CHANGES: IntCounterVec = IntCounterVec::new(
+        Opts::new("graph_delta_changes_total",
+        "Total number of changes between consecutive graph refreshes"),
+        &["change"]
+    )
+    .unwrap();
     static ref BUILD_INFO: Counter = Counter::with_opts(opts!(
         "build_info",
         "Build information",
@@ -87,15 +99,20 @@ pub fn register_metrics(registry: &prometheus::Registry) -> Fallible<()> {
     commons::register_metrics(registry)?;
     registry.register(Box::new(GRAPH_FINAL_RELEASES.clone()))?;
     registry.register(Box::new(GRAPH_LAST_SUCCESSFUL_REFRESH.clone()))?;
+    registry.register(Box::new(GRAPH_STALE.clone()))?;
     registry.register(Box::new(UPSTREAM_ERRORS.clone()))?;
     registry.register(Box::new(UPSTREAM_SCRAPES.clone()))?;
     registry.register(Box::new(GRAPH_UPSTREAM_INITIAL_SCRAPE.clone()))?;
     registry.register(Box::new(UPSTREAM_SCRAPES_DURATION.clone()))?;
     registry.register(Box::new(GRAPH_INCOMING_REQS.clone()))?;
+    registry.register(Box::new(GRAPH_DELTA_CHANGES.clone()))?;
     registry.register(Box::new(BUILD_INFO.clone()))?;
     Ok(())
 }
 
+/// `Warning` header value for graphs restored from a snapshot, until the first live scrape.
+const STALE_WARNING: &str = "110 - \"Response is Stale\"";
+
 /// Serve Cincinnati graph requests.
 pub async fn index(
     req: HttpRequest,
@@ -110,9 +127,12 @@ pub async fn index(
     let accept_default = header::HeaderValue::from_static(CONTENT_TYPE);
 
     // Check that the client can accept media type.
-    let _content_type: String = commons::validate_content_type(
+    let content_type: String = commons::validate_content_type(
         req.headers(),
-        vec![accept_default.clone()],
+        vec![
+            accept_default.clone(),
+            header::HeaderValue::from_static(PROTOBUF_CONTENT_TYPE),
+        ],
         accept_default,
     )?;
 
@@ -120,9 +140,28 @@ pub async fn index(
     let mandatory_params = &app_data.mandatory_params;
     commons::ensure_query_params(mandatory_params, req.query_string())?;
 
-    let resp = HttpResponse::Ok()
-        .content_type(CONTENT_TYPE)
-        .body(app_data.json.read().clone());
+    let snapshot = app_data.snapshot();
+    let mut resp = HttpResponse::Ok();
+    if snapshot.is_stale() {
+        resp.insert_header((header::WARNING, STALE_WARNING));
+    }
+    let resp = if content_type == PROTOBUF_CONTENT_TYPE {
+        resp.content_type(PROTOBUF_CONTENT_TYPE)
+            .body(snapshot.protobuf())
+    } else {
+        let encoding = req
+            .headers()
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .map_or(Encoding::Identity, Encoding::negotiate);
+
+        resp.content_type(CONTENT_TYPE)
+            .insert_header((header::VARY, header::ACCEPT_ENCODING.as_str()));
+        if let Some(content_encoding) = encoding.content_encoding() {
+            resp.insert_header((header::CONTENT_ENCODING, content_encoding));
+        }
+        resp.body(snapshot.json(encoding))
+    };
     Ok(resp)
 }
 
@@ -142,9 +181,20 @@ pub async fn graph_data(
     Ok(f.unwrap())
 }
 
+/// A graph delta, recorded at the end of a graph refresh.
+#[derive(Clone, Debug, Serialize)]
+pub struct GraphDeltaRecord {
+    /// UTC timestamp of the graph refresh.
+    pub timestamp: i64,
+    /// Changes compared to the previous graph.
+    #[serde(flatten)]
+    pub delta: GraphDelta,
+}
+
 #[derive(Clone)]
 pub struct State {
-    json: Arc<RwLock<String>>,
+    /// The graph served on `/graph`, replaced as a whole on every refresh.
+    snapshot: Arc<RwLock<Arc<GraphSnapshot>>>,
     /// Query parameters that must be present in all client requests.
     mandatory_params: HashSet<String>,
     live: Arc<RwLock<bool>>,
@@ -152,12 +202,13 @@ pub struct State {
     plugins: &'static [BoxedPlugin],
     registry: &'static prometheus::Registry,
     secondary_metadata: Arc<RwLock<String>>,
+    /// Most recent graph deltas, newest first.
+    deltas: Arc<RwLock<VecDeque<GraphDeltaRecord>>>,
 }
 
 impl State {
     /// Creates a new State with the given arguments
     pub fn new(
-        json: Arc<RwLock<String>>,
         mandatory_params: HashSet<String>,
         live: Arc<RwLock<bool>>,
         ready: Arc<RwLock<bool>>,
@@ -166,13 +217,14 @@ impl State {
         secondary_metadata: Arc<RwLock<String>>,
     ) -> State {
         State {
-            json,
+            snapshot: Default::default(),
             mandatory_params,
             live,
             ready,
             plugins,
             registry,
             secondary_metadata,
+            deltas: Default::default(),
         }
     }
 
@@ -185,6 +237,28 @@ impl State {
     pub fn is_ready(&self) -> bool {
         *self.ready.read()
     }
+
+    /// Returns the current graph snapshot.
+    pub fn snapshot(&self) -> Arc<GraphSnapshot> {
+        self.snapshot.read().clone()
+    }
+
+    /// Replaces the graph snapshot served to clients.
+    pub fn publish_snapshot(&self, snapshot: GraphSnapshot) {
+        *self.snapshot.write() = Arc::new(snapshot);
+    }
+
+    /// Returns a copy of the most recent graph deltas, newest first.
+    pub fn deltas(&self) -> Vec<GraphDeltaRecord> {
+        self.deltas.read().iter().cloned().collect()
+    }
+
+    /// Records a graph delta, keeping at most `history_size` of them.
+    fn record_delta(&self, delta: GraphDeltaRecord, history_size: usize) {
+        let mut deltas = self.deltas.write();
+        deltas.push_front(delta);
+        deltas.truncate(history_size);
+    }
 }
 
 impl HasRegistry for State {
@@ -212,6 +286,27 @@ pub fn run(settings: &config::AppSettings, state: &State) -> ! {
     // Store amount of nodes in the graph for metrics
     let mut nodes_count: i64;
 
+    // Graph of the previous successful refresh, to compute deltas against
+    let mut previous_graph: Option<Arc<cincinnati::Graph>> = None;
+
+    let snapshot_dir = match settings
+        .snapshot_dir
+        .as_ref()
+        .map(|path| SnapshotDir::open(path, settings.snapshots_kept))
+    {
+        Some(Ok(snapshot_dir)) => Some(snapshot_dir),
+        Some(Err(err)) => {
+            err.chain().for_each(|cause| error!("{}", cause));
+            None
+        }
+        None => None,
+    };
+    if let Some(snapshot_dir) = &snapshot_dir {
+        if let Some(graph) = restore_snapshot(snapshot_dir, state) {
+            previous_graph = Some(Arc::new(graph));
+        }
+    }
+
     loop {
         // Store scrape duration value. It would be used for initial scrape gauge or scrape histogram
         let scrape_value: f64;
@@ -248,29 +343,48 @@ pub fn run(settings: &config::AppSettings, state: &State) -> ! {
                 }
             };
 
-            let json_graph = match serde_json::to_string(&internal_io.graph) {
-                Ok(json) => json,
+            let snapshot = match GraphSnapshot::new(&internal_io.graph) {
+                Ok(snapshot) => snapshot,
                 Err(err) => {
                     UPSTREAM_ERRORS.inc();
-                    error!("Failed to serialize graph: {}", err);
+                    err.chain().for_each(|cause| error!("{}", cause));
                     continue;
                 }
             };
 
-            if internal_io
-                .parameters
-                .contains_key(SECONDARY_METADATA_PARAM_KEY)
-            {
-                let secondary_metadata = internal_io
-                    .parameters
-                    .get(SECONDARY_METADATA_PARAM_KEY)
-                    .unwrap();
+            let secondary_metadata = internal_io.parameters.get(SECONDARY_METADATA_PARAM_KEY);
+
+            if let Some(snapshot_dir) = &snapshot_dir {
+                if let Err(err) = snapshot_dir.write(
+                    chrono::Utc::now().timestamp_millis(),
+                    &snapshot.json(Encoding::Identity),
+                    secondary_metadata.map(std::path::Path::new),
+                ) {
+                    error!("Failed to persist graph snapshot");
+                    err.chain().for_each(|cause| error!("{}", cause));
+                }
+            }
 
+            if let Some(secondary_metadata) = secondary_metadata {
                 *state.secondary_metadata.write() = secondary_metadata.to_string();
             }
 
-            *state.json.write() = json_graph;
+            state.publish_snapshot(snapshot);
+            GRAPH_STALE.set(0);
             nodes_count = internal_io.graph.releases_count() as i64;
+
+            if let Some(previous_graph) = &previous_graph {
+                let delta = previous_graph.diff(&internal_io.graph);
+                observe_delta(&delta);
+                state.record_delta(
+                    GraphDeltaRecord {
+                        timestamp: chrono::Utc::now().timestamp(),
+                        delta,
+                    },
+                    settings.delta_history_size,
+                );
+            }
+            previous_graph = Some(internal_io.graph);
         }
 
         // Record scrape duration
@@ -290,3 +404,82 @@ pub fn run(settings: &config::AppSettings, state: &State) -> ! {
         info!("graph update completed, {} valid releases", nodes_count);
     }
 }
+
+/// Serve the newest valid snapshot on disk, until the first live scrape.
+///
+/// Returns the restored graph.
+fn restore_snapshot(snapshot_dir: &SnapshotDir, state: &State) -> Option<cincinnati::Graph> {
+    let loaded = match snapshot_dir.load_latest() {
+        Ok(Some(loaded)) => loaded,
+        Ok(None) => {
+            info!("no graph snapshot to restore");
+            return None;
+        }
+        Err(err) => {
+            err.chain().for_each(|cause| error!("{}", cause));
+            return None;
+        }
+    };
+
+    let snapshot = match GraphSnapshot::new(&loaded.graph) {
+        Ok(snapshot) => snapshot.into_stale(),
+        Err(err) => {
+            err.chain().for_each(|cause| error!("{}", cause));
+            return None;
+        }
+    };
+
+    if let Some(secondary_metadata) = &loaded.secondary_metadata {
+        *state.secondary_metadata.write() = secondary_metadata.to_string_lossy().into_owned();
+    }
+    state.publish_snapshot(snapshot);
+    *state.ready.write() = true;
+    GRAPH_STALE.set(1);
+    GRAPH_FINAL_RELEASES.set(loaded.graph.releases_count() as i64);
+    info!(
+        "restored graph snapshot {:?}, {} releases, serving it as stale until the first scrape",
+        loaded.path,
+        loaded.graph.releases_count()
+    );
+
+    Some(loaded.graph)
+}
+
+/// Log a graph delta and account its changes in metrics.
+fn observe_delta(delta: &GraphDelta) {
+    if delta.is_empty() {
+        debug!("graph unchanged since last refresh");
+        return;
+    }
+
+    info!("graph changed since last refresh: {}", delta);
+    debug!("graph delta: {:?}", delta);
+
+    [
+        ("releases_added", delta.added_releases.len()),
+        ("releases_removed", delta.removed_releases.len()),
+        ("edges_added", delta.added_edges.len()),
+        ("edges_removed", delta.removed_edges.len()),
+        ("metadata_changed", delta.changed_metadata.len()),
+        (
+            "conditional_edges_added",
+            delta.added_conditional_edges.len(),
+        ),
+        (
+            "conditional_edges_removed",
+            delta.removed_conditional_edges.len(),
+        ),
+        (
+            "conditional_edges_changed",
+            delta.changed_conditional_edges.len(),
+        ),
+        ("risks_added", delta.added_risks.len()),
+        ("risks_removed", delta.removed_risks.len()),
+    ]
+    .iter()
+    .for_each(|(change, count)| {
+        GRAPH_DELTA_CHANGES
+            .with_label_values(&[change])
+            .inc_by(*count as u64)
+    });
+}
@@ -15,6 +15,8 @@ extern crate cincinnati;
 
 pub mod config;
 pub mod graph;
+pub mod snapshot;
+pub mod snapshot_dir;
 pub mod status;
 
 #[allow(dead_code)]
@@ -39,6 +39,11 @@ async fn main() -> Result<(), Error> {
         .init();
     info!("application settings:\n{:#?}", settings);
 
+    if settings.check_config {
+        print!("{}", settings.check_config_report()?);
+        return Ok(());
+    }
+
     let registry: prometheus::Registry =
         metrics::new_registry(Some(config::METRICS_PREFIX.to_string()))?;
 
@@ -61,12 +66,10 @@ async fn main() -> Result<(), Error> {
 
     // Shared state.
     let state = {
-        let json_graph = Arc::new(RwLock::new(String::new()));
         let live = Arc::new(RwLock::new(false));
         let ready = Arc::new(RwLock::new(false));
         let secondary_metadata = Arc::new(RwLock::new(String::new()));
         graph::State::new(
-            json_graph,
             settings.mandatory_client_parameters.clone(),
             live,
             ready,
@@ -103,6 +106,10 @@ async fn main() -> Result<(), Error> {
                 actix_web::web::resource("/readiness")
                     .route(actix_web::web::get().to(status::serve_readiness)),
             )
+            .service(
+                actix_web::web::resource("/graph-deltas")
+                    .route(actix_web::web::get().to(status::serve_graph_deltas)),
+            )
     })
     .bind(status_addr)?
     .run();
@@ -203,7 +210,6 @@ mod tests {
     use std::sync::Arc;
 
     fn mock_state(is_live: bool, is_ready: bool) -> State {
-        let json_graph = Arc::new(RwLock::new(String::new()));
         let live = Arc::new(RwLock::new(is_live));
         let ready = Arc::new(RwLock::new(is_ready));
 
@@ -214,7 +220,6 @@ mod tests {
         let secondary_metadata = Arc::new(RwLock::new(String::new()));
 
         State::new(
-            json_graph,
             HashSet::new(),
             live,
             ready,
@@ -253,6 +258,89 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn serve_graph_deltas_empty() -> Fallible<()> {
+        let rt = testing::init_runtime()?;
+
+        let deltas_call =
+            status::serve_graph_deltas(actix_web::web::Data::new(mock_state(true, true)));
+        let resp = rt.block_on(deltas_call);
+
+        assert_eq!(resp.status(), 200);
+        if let Ok(bytes) = resp.into_body().try_into_bytes() {
+            assert_eq!(bytes.as_ref(), b"[]");
+        } else {
+            bail!("expected bytes in body")
+        };
+
+        Ok(())
+    }
+
+    #[test]
+    fn serve_graph_compressed() -> Fallible<()> {
+        use actix_web::http::header;
+        use std::io::Read;
+
+        let rt = testing::init_runtime()?;
+        let state = mock_state(true, true);
+        let mut graph = cincinnati::Graph::default();
+        graph.add_release(cincinnati::Release::Concrete(cincinnati::ConcreteRelease {
+            version: "1.0.0".to_string(),
+            payload: "image/1.0.0".to_string(),
+            metadata: Default::default(),
+        }))?;
+        state.publish_snapshot(graph_builder::snapshot::GraphSnapshot::new(&graph)?);
+
+        let http_req = actix_web::test::TestRequest::get()
+            .insert_header((header::ACCEPT, cincinnati::CONTENT_TYPE))
+            .insert_header((header::ACCEPT_ENCODING, "gzip, deflate"))
+            .to_http_request();
+        let resp = rt.block_on(graph::index(http_req, actix_web::web::Data::new(state)))?;
+
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get(header::CONTENT_ENCODING).unwrap(),
+            "gzip"
+        );
+        assert_eq!(resp.headers().get(header::VARY).unwrap(), "accept-encoding");
+        if let Ok(bytes) = resp.into_body().try_into_bytes() {
+            let mut body = Vec::new();
+            flate2::read::GzDecoder::new(bytes.as_ref()).read_to_end(&mut body)?;
+            assert_eq!(body, serde_json::to_vec(&graph)?);
+        } else {
+            bail!("expected bytes in body")
+        };
+
+        Ok(())
+    }
+
+    #[test]
+    fn serve_graph_stale() -> Fallible<()> {
+        use actix_web::http::header;
+
+        let rt = testing::init_runtime()?;
+        let state = mock_state(true, true);
+        let graph = cincinnati::Graph::default();
+
+        state.publish_snapshot(graph_builder::snapshot::GraphSnapshot::new(&graph)?.into_stale());
+        let http_req = actix_web::test::TestRequest::get().to_http_request();
+        let resp = rt.block_on(graph::index(
+            http_req,
+            actix_web::web::Data::new(state.clone()),
+        ))?;
+        assert_eq!(
+            resp.headers().get(header::WARNING).unwrap(),
+            "110 - \"Response is Stale\""
+        );
+
+        state.publish_snapshot(graph_builder::snapshot::GraphSnapshot::new(&graph)?);
+        let http_req = actix_web::test::TestRequest::get().to_http_request();
+        let resp = rt.block_on(graph::index(http_req, actix_web::web::Data::new(state)))?;
+        assert!(resp.headers().get(header::WARNING).is_none());
+
+        Ok(())
+    }
+
     #[test]
     fn check_liveness_readiness() -> Fallible<()> {
         let rt = testing::init_runtime()?;
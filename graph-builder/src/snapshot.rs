@@ -0,0 +1,224 @@
+//! Serialized graph snapshots.
+//!
+//! A snapshot holds every representation of the graph served on `/graph`. It is
+//! built once per refresh and shared by all requests until the next one, so
+//! serving a request neither serializes, compresses nor copies the graph.
+
+use bytes::Bytes;
+use commons::prelude_errors::*;
+use flate2::write::GzEncoder;
+use protobuf::Message;
+use std::io::Write;
+
+/// Brotli quality of the precompressed JSON, trading compression time against size.
+const BROTLI_QUALITY: u32 = 9;
+/// Brotli window size, as base-2 logarithm.
+const BROTLI_WINDOW_SIZE: u32 = 22;
+/// Buffer size of the brotli compressor.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+/// Content encoding of a served graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    /// Not compressed.
+    Identity,
+    /// Compressed with gzip.
+    Gzip,
+    /// Compressed with brotli.
+    Brotli,
+}
+
+impl Encoding {
+    /// Picks the preferred encoding allowed by an `Accept-Encoding` header value.
+    ///
+    /// Brotli is preferred over gzip; the quality values only decide whether an
+    /// encoding is acceptable at all.
+    pub fn negotiate(accept_encoding: &str) -> Self {
+        let accepts = |coding: &str| match quality(accept_encoding, coding)
+            .or_else(|| quality(accept_encoding, "*"))
+        {
+            Some(quality) => quality > 0.0,
+            None => false,
+        };
+
+        if accepts("br") {
+            Encoding::Brotli
+        } else if accepts("gzip") {
+            Encoding::Gzip
+        } else {
+            Encoding::Identity
+        }
+    }
+
+    /// Value of the `Content-Encoding` header, unless the content isn't compressed.
+    pub fn content_encoding(self) -> Option<&'static str> {
+        match self {
+            Encoding::Identity => None,
+            Encoding::Gzip => Some("gzip"),
+            Encoding::Brotli => Some("br"),
+        }
+    }
+}
+
+/// Returns the quality value given to `coding` in an `Accept-Encoding` header value, if listed.
+fn quality(accept_encoding: &str, coding: &str) -> Option<f32> {
+    accept_encoding.split(',').find_map(|item| {
+        let mut parts = item.split(';').map(str::trim);
+        if !parts.next()?.eq_ignore_ascii_case(coding) {
+            return None;
+        }
+        let quality = parts
+            .find_map(|param| param.strip_prefix("q="))
+            .map_or(Some(1.0), |q| q.trim().parse().ok())
+            .unwrap_or(0.0);
+        Some(quality)
+    })
+}
+
+/// A graph, serialized in every representation served on `/graph`.
+#[derive(Debug, Default)]
+pub struct GraphSnapshot {
+    json: Bytes,
+    json_gzip: Bytes,
+    json_brotli: Bytes,
+    protobuf: Bytes,
+    /// Whether the graph was restored from disk rather than freshly scraped.
+    stale: bool,
+}
+
+impl GraphSnapshot {
+    /// Serializes the graph and precompresses its JSON representation.
+    pub fn new(graph: &cincinnati::Graph) -> Fallible<Self> {
+        let json = serde_json::to_vec(graph).context("serializing the graph as JSON")?;
+        let protobuf = cincinnati::plugins::interface::Graph::from(graph)
+            .write_to_bytes()
+            .context("encoding the graph as protobuf")?;
+
+        let json_gzip = {
+            let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::best());
+            encoder.write_all(&json)?;
+            encoder
+                .finish()
+                .context("compressing the graph with gzip")?
+        };
+        let json_brotli = {
+            let mut encoder = brotli::CompressorWriter::new(
+                Vec::new(),
+                BROTLI_BUFFER_SIZE,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW_SIZE,
+            );
+            encoder
+                .write_all(&json)
+                .context("compressing the graph with brotli")?;
+            encoder.into_inner()
+        };
+
+        Ok(Self {
+            json: json.into(),
+            json_gzip: json_gzip.into(),
+            json_brotli: json_brotli.into(),
+            protobuf: protobuf.into(),
+            stale: false,
+        })
+    }
+
+    /// Marks the snapshot as stale, i.e. not coming from a live scrape.
+    pub fn into_stale(self) -> Self {
+        Self {
+            stale: true,
+            ..self
+        }
+    }
+
+    /// Returns whether the snapshot is stale.
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    /// Returns the JSON representation in the given encoding.
+    pub fn json(&self, encoding: Encoding) -> Bytes {
+        match encoding {
+            Encoding::Identity => self.json.clone(),
+            Encoding::Gzip => self.json_gzip.clone(),
+            Encoding::Brotli => self.json_brotli.clone(),
+        }
+    }
+
+    /// Returns the protobuf representation.
+    pub fn protobuf(&self) -> Bytes {
+        self.protobuf.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::io::Read;
+
+    static GRAPH_BODY: &str = r#"{
+        "nodes": [
+            { "version": "1.0.0", "payload": "image/1.0.0", "metadata": {} },
+            { "version": "2.0.0", "payload": "image/2.0.0", "metadata": {} },
+            { "version": "3.0.0", "payload": "image/3.0.0", "metadata": {} }
+        ],
+        "edges": [ [0, 1] ],
+        "conditionalEdges": [
+            {
+                "edges": [ { "from": "1.0.0", "to": "3.0.0" } ],
+                "risks": [ { "url": "https://bug.example.com", "name": "SomeRisk", "message": "", "matchingRules": [ { "type": "Always" } ] } ]
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn negotiate_encoding() {
+        for (accept_encoding, expected) in &[
+            ("", Encoding::Identity),
+            ("identity", Encoding::Identity),
+            ("gzip", Encoding::Gzip),
+            ("gzip, deflate, br", Encoding::Brotli),
+            ("br;q=0.1, gzip;q=0.9", Encoding::Brotli),
+            ("br;q=0, gzip", Encoding::Gzip),
+            ("BR", Encoding::Brotli),
+            ("*", Encoding::Brotli),
+            ("br;q=0, *", Encoding::Gzip),
+            ("*;q=0", Encoding::Identity),
+            ("deflate", Encoding::Identity),
+        ] {
+            assert_eq!(
+                Encoding::negotiate(accept_encoding),
+                *expected,
+                "Accept-Encoding: {}",
+                accept_encoding
+            );
+        }
+    }
+
+    #[test]
+    fn snapshot_encodings_decode_to_json() -> Fallible<()> {
+        let graph: cincinnati::Graph = serde_json::from_str(GRAPH_BODY)?;
+        let snapshot = GraphSnapshot::new(&graph)?;
+        let json = serde_json::to_vec(&graph)?;
+
+        assert_eq!(snapshot.json(Encoding::Identity), json);
+
+        let mut gunzipped = Vec::new();
+        flate2::read::GzDecoder::new(snapshot.json(Encoding::Gzip).as_ref())
+            .read_to_end(&mut gunzipped)?;
+        assert_eq!(gunzipped, json);
+
+        let mut unbrotlied = Vec::new();
+        brotli::Decompressor::new(snapshot.json(Encoding::Brotli).as_ref(), BROTLI_BUFFER_SIZE)
+            .read_to_end(&mut unbrotlied)?;
+        assert_eq!(unbrotlied, json);
+
+        let decoded: cincinnati::Graph =
+            cincinnati::plugins::interface::Graph::parse_from_bytes(&snapshot.protobuf())?
+                .try_into()?;
+        assert_eq!(serde_json::to_vec(&decoded)?, json);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+//! On-disk graph snapshots, for a warm start.
+//!
+//! Every successful refresh is persisted as a `snapshot-<timestamp>` directory
+//! holding the graph as JSON and, if any, the secondary-metadata tarball.
+//! Snapshots are written to a temporary directory first, which is then renamed
+//! into place, so a snapshot directory is always complete.
+
+use commons::prelude_errors::*;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Prefix of the names of snapshot directories.
+const SNAPSHOT_PREFIX: &str = "snapshot-";
+/// Name of the graph file within a snapshot directory.
+const GRAPH_FILE: &str = "graph.json";
+/// Name of the secondary-metadata tarball within a snapshot directory.
+const SECONDARY_METADATA_FILE: &str = "graph-data.tar.gz";
+/// Default number of snapshots kept on disk.
+pub const DEFAULT_SNAPSHOTS_KEPT: usize = 2;
+
+/// A snapshot loaded from disk.
+#[derive(Debug)]
+pub struct LoadedSnapshot {
+    /// Path of the snapshot directory.
+    pub path: PathBuf,
+    /// UTC timestamp (in milliseconds) of the refresh which produced the snapshot.
+    pub timestamp: i64,
+    /// The graph.
+    pub graph: cincinnati::Graph,
+    /// Path to the secondary-metadata tarball, if the snapshot has one.
+    pub secondary_metadata: Option<PathBuf>,
+}
+
+/// Directory holding graph snapshots.
+#[derive(Debug, Clone)]
+pub struct SnapshotDir {
+    path: PathBuf,
+    /// Number of snapshots kept on disk.
+    kept: usize,
+}
+
+impl SnapshotDir {
+    /// Opens the snapshot directory, creating it if needed, keeping the newest
+    /// `kept` snapshots.
+    pub fn open<P: AsRef<Path>>(path: P, kept: usize) -> Fallible<Self> {
+        ensure!(kept > 0, "at least one snapshot must be kept");
+        let path = path.as_ref().to_path_buf();
+        fs::create_dir_all(&path).context(format!("creating snapshot directory {:?}", path))?;
+        Ok(Self { path, kept })
+    }
+
+    /// Atomically writes a snapshot and prunes the older ones.
+    ///
+    /// `json` is the serialized graph, `secondary_metadata` the path to the
+    /// secondary-metadata tarball served alongside it.
+    pub fn write(
+        &self,
+        timestamp: i64,
+        json: &[u8],
+        secondary_metadata: Option<&Path>,
+    ) -> Fallible<PathBuf> {
+        let tmp_dir = tempfile::Builder::new()
+            .prefix(".tmp-snapshot-")
+            .tempdir_in(&self.path)
+            .context(format!("creating temporary directory in {:?}", self.path))?;
+
+        {
+            let mut graph_file = fs::File::create(tmp_dir.path().join(GRAPH_FILE))?;
+            graph_file.write_all(json)?;
+            graph_file.sync_all()?;
+        }
+        if let Some(secondary_metadata) = secondary_metadata {
+            let target = tmp_dir.path().join(SECONDARY_METADATA_FILE);
+            fs::copy(secondary_metadata, &target)
+                .context(format!("copying {:?}", secondary_metadata))?;
+            fs::File::open(&target)?.sync_all()?;
+        }
+
+        let path = self.path.join(format!("{}{}", SNAPSHOT_PREFIX, timestamp));
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::rename(tmp_dir.path(), &path)
+            .context(format!("moving snapshot into place at {:?}", path))?;
+        // The directory was moved, there is nothing left to clean up.
+        let _ = tmp_dir.into_path();
+
+        self.prune()?;
+
+        Ok(path)
+    }
+
+    /// Loads the newest valid snapshot, if any.
+    ///
+    /// Snapshots which can't be read are skipped in favor of older ones.
+    pub fn load_latest(&self) -> Fallible<Option<LoadedSnapshot>> {
+        for (timestamp, path) in self.snapshots()?.into_iter().rev() {
+            match Self::load(timestamp, &path) {
+                Ok(snapshot) => return Ok(Some(snapshot)),
+                Err(err) => {
+                    warn!("skipping invalid snapshot {:?}: {}", path, err);
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn load(timestamp: i64, path: &Path) -> Fallible<LoadedSnapshot> {
+        let json = fs::read(path.join(GRAPH_FILE))?;
+        let graph = serde_json::from_slice(&json).context("parsing the graph")?;
+        let secondary_metadata = Some(path.join(SECONDARY_METADATA_FILE)).filter(|p| p.is_file());
+
+        Ok(LoadedSnapshot {
+            path: path.to_path_buf(),
+            timestamp,
+            graph,
+            secondary_metadata,
+        })
+    }
+
+    /// Removes all but the newest `kept` snapshots.
+    fn prune(&self) -> Fallible<()> {
+        let snapshots = self.snapshots()?;
+        let outdated = snapshots.len().saturating_sub(self.kept);
+        for (_, path) in snapshots.into_iter().take(outdated) {
+            debug!("removing outdated snapshot {:?}", path);
+            fs::remove_dir_all(&path).context(format!("removing snapshot {:?}", path))?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the snapshot directories with their timestamps, oldest first.
+    fn snapshots(&self) -> Fallible<Vec<(i64, PathBuf)>> {
+        let mut snapshots: Vec<(i64, PathBuf)> = fs::read_dir(&self.path)
+            .context(format!("reading snapshot directory {:?}", self.path))?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                let timestamp = entry
+                    .file_name()
+                    .to_str()?
+                    .strip_prefix(SNAPSHOT_PREFIX)?
+                    .parse()
+                    .ok()?;
+                Some((timestamp, entry.path()))
+            })
+            .filter(|(_, path)| path.is_dir())
+            .collect();
+        snapshots.sort();
+
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static GRAPH_BODY: &str = r#"{
+        "nodes": [
+            { "version": "1.0.0", "payload": "image/1.0.0", "metadata": {} },
+            { "version": "2.0.0", "payload": "image/2.0.0", "metadata": {} }
+        ],
+        "edges": [ [0, 1] ],
+        "conditionalEdges": []
+    }"#;
+
+    #[test]
+    fn write_and_load_latest() -> Fallible<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = SnapshotDir::open(tmp.path().join("snapshots"), DEFAULT_SNAPSHOTS_KEPT)?;
+        assert!(dir.load_latest()?.is_none());
+
+        let tarball = tmp.path().join("graph-data.tar.gz");
+        fs::write(&tarball, b"tarball")?;
+
+        dir.write(1, b"{}", None)?;
+        dir.write(2, GRAPH_BODY.as_bytes(), Some(&tarball))?;
+
+        let snapshot = dir.load_latest()?.expect("no snapshot loaded");
+        assert_eq!(snapshot.timestamp, 2);
+        assert_eq!(snapshot.graph.releases_count(), 2);
+        assert_eq!(fs::read(snapshot.secondary_metadata.unwrap())?, b"tarball");
+
+        Ok(())
+    }
+
+    #[test]
+    fn load_latest_skips_invalid_snapshots() -> Fallible<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = SnapshotDir::open(tmp.path(), DEFAULT_SNAPSHOTS_KEPT)?;
+
+        dir.write(1, GRAPH_BODY.as_bytes(), None)?;
+        fs::create_dir(tmp.path().join("snapshot-3"))?;
+        fs::create_dir(tmp.path().join(".tmp-snapshot-abc"))?;
+        fs::write(tmp.path().join("snapshot-2"), b"not a directory")?;
+
+        let snapshot = dir.load_latest()?.expect("no snapshot loaded");
+        assert_eq!(snapshot.timestamp, 1);
+        assert!(snapshot.secondary_metadata.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn write_prunes_old_snapshots() -> Fallible<()> {
+        for (kept, expected) in &[(DEFAULT_SNAPSHOTS_KEPT, vec![3, 4]), (3, vec![2, 3, 4])] {
+            let tmp = tempfile::tempdir()?;
+            let dir = SnapshotDir::open(tmp.path(), *kept)?;
+
+            for timestamp in 1..=4 {
+                dir.write(timestamp, GRAPH_BODY.as_bytes(), None)?;
+            }
+
+            let timestamps: Vec<i64> = dir.snapshots()?.into_iter().map(|(ts, _)| ts).collect();
+            assert_eq!(&timestamps, expected);
+        }
+
+        let tmp = tempfile::tempdir()?;
+        assert!(SnapshotDir::open(tmp.path(), 0).is_err());
+
+        Ok(())
+    }
+}
@@ -28,3 +28,13 @@ pub async fn serve_readiness(app_data: actix_web::web::Data<State>) -> HttpRespo
         HttpResponse::ServiceUnavailable().finish()
     }
 }
+
+/// Expose the most recent graph deltas, newest first.
+pub async fn serve_graph_deltas(app_data: actix_web::web::Data<State>) -> HttpResponse {
+    match serde_json::to_string(&app_data.deltas()) {
+        Ok(json) => HttpResponse::Ok()
+            .content_type(cincinnati::CONTENT_TYPE)
+            .body(json),
+        Err(e) => HttpResponse::InternalServerError().body(e.to_string()),
+    }
+}
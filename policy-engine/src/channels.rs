@@ -0,0 +1,325 @@
+//! Channel discovery service.
+//!
+//! Runs the configured plugin chain without its channel filters, like the graph
+//! service does otherwise, and lists the channels found in the release metadata
+//! along with their releases.
+
+use crate::graph::{insert_condition_headers, process_plugins_cached, run_plugins};
+use crate::AppState;
+use actix_web::http::header;
+use actix_web::web::Query;
+use actix_web::{HttpRequest, HttpResponse};
+use cincinnati::plugins::{BoxedPlugin, InternalIO};
+use cincinnati::{MetadataKeys, CONTENT_TYPE};
+use commons::tracing::get_tracer;
+use commons::{self, api_response_error, Fallible, GraphError};
+use opentelemetry::{
+    trace::{mark_span_as_active, FutureExt, Tracer},
+    Context as ot_context,
+};
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// Query parameter holding the channel, which is not required by this service.
+static CHANNEL_PARAM: &str = "channel";
+
+lazy_static! {
+    static ref CHANNELS_INCOMING_REQS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "channels_incoming_requests_total",
+            "Total number of incoming HTTP client request for channels"
+        ),
+        &["uri_path"]
+    )
+    .unwrap();
+}
+
+/// Register relevant metrics to a prometheus registry.
+pub(crate) fn register_metrics(registry: &Registry) -> Fallible<()> {
+    registry.register(Box::new(CHANNELS_INCOMING_REQS.clone()))?;
+    Ok(())
+}
+
+/// Response body of the channel service.
+#[derive(Debug, Serialize)]
+struct Channels {
+    channels: Vec<Channel>,
+}
+
+/// Channel and its releases.
+#[derive(Debug, Serialize)]
+struct Channel {
+    /// Name of the channel.
+    name: String,
+    /// Minor stream of the channel, from the `<major>.<minor>` suffix of its name.
+    minor: Option<String>,
+    /// Latest release in the channel.
+    head: Option<String>,
+    /// Releases in the channel, in ascending order.
+    versions: Vec<String>,
+}
+
+/// Returns the plugins run by the channel service, i.e. all but the channel filters.
+pub(crate) fn channel_plugins(
+    plugins: &'static [BoxedPlugin],
+) -> impl Iterator<Item = &'static BoxedPlugin> + Clone + Sync + Send {
+    plugins
+        .iter()
+        .filter(|plugin| plugin.channels_key().is_none())
+}
+
+/// Serve channel requests.
+pub(crate) async fn index(
+    req: HttpRequest,
+    app_data: actix_web::web::Data<AppState>,
+) -> Result<HttpResponse, GraphError> {
+    _index(&req, app_data)
+        .await
+        .map_err(|e| api_response_error(&req, e))
+}
+
+async fn _index(
+    req: &HttpRequest,
+    app_data: actix_web::web::Data<AppState>,
+) -> Result<HttpResponse, GraphError> {
+    let span = get_tracer().start("channels");
+    let _active_span = mark_span_as_active(span);
+
+    let path = req.uri().path();
+    CHANNELS_INCOMING_REQS.with_label_values(&[path]).inc();
+
+    // Check that the client can accept media type.
+    let accept_default = header::HeaderValue::from_static(CONTENT_TYPE);
+    commons::validate_content_type(req.headers(), vec![accept_default.clone()], accept_default)?;
+
+    // Check for required client parameters, except for the channel.
+    let mandatory_params: HashSet<String> = app_data
+        .mandatory_params
+        .iter()
+        .filter(|param| param.as_str() != CHANNEL_PARAM)
+        .cloned()
+        .collect();
+    commons::ensure_query_params(&mandatory_params, req.query_string())?;
+
+    let mut plugin_params = Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .map_err(|e| GraphError::InvalidParams(e.to_string()))?;
+    plugin_params.remove(CHANNEL_PARAM);
+    insert_condition_headers(req, app_data.plugins, &mut plugin_params);
+
+    // Use the key of the configured channel filter, if any.
+    let keys = {
+        let keys = MetadataKeys::default();
+        match app_data
+            .plugins
+            .iter()
+            .find_map(|plugin| plugin.channels_key())
+        {
+            Some(key) => keys.with_channels_key(key),
+            None => keys,
+        }
+    };
+    let render = move |internal_io: &InternalIO| render_response(internal_io, &keys);
+
+    let cx = ot_context::current();
+    match &app_data.channels_cache {
+        Some(cache) => {
+            process_plugins_cached(
+                cache.clone(),
+                channel_plugins(app_data.plugins),
+                plugin_params,
+                render,
+            )
+            .with_context(cx)
+            .await
+        }
+        None => {
+            let internal_io = run_plugins(channel_plugins(app_data.plugins), plugin_params)
+                .with_context(cx)
+                .await?;
+            let (content_type, body) = render(&internal_io)?;
+            Ok(HttpResponse::Ok().content_type(content_type).body(body))
+        }
+    }
+}
+
+/// Render the channels of the processed graph.
+fn render_response(
+    internal_io: &InternalIO,
+    keys: &MetadataKeys,
+) -> Result<(String, String), GraphError> {
+    let channels = Channels {
+        channels: list_channels(internal_io, keys),
+    };
+    let body =
+        serde_json::to_string(&channels).map_err(|e| GraphError::FailedJsonOut(e.to_string()))?;
+
+    Ok((CONTENT_TYPE.to_string(), body))
+}
+
+/// List the channels of the releases in the graph, sorted by name.
+fn list_channels(internal_io: &InternalIO, keys: &MetadataKeys) -> Vec<Channel> {
+    let graph = &internal_io.graph;
+
+    let mut channels: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for (release_id, version, _) in graph.find_by_metadata_key(&keys.channels) {
+        let metadata = match graph
+            .find_by_releaseid(&release_id)
+            .ok()
+            .and_then(|release| release.release_metadata(keys))
+        {
+            Some(metadata) => metadata,
+            None => continue,
+        };
+        for channel in metadata.channels() {
+            channels
+                .entry(channel.to_string())
+                .or_default()
+                .push(version.clone());
+        }
+    }
+
+    channels
+        .into_iter()
+        .map(|(name, mut versions)| {
+            versions.sort_by_cached_key(|version| {
+                (semver::Version::parse(version).ok(), version.clone())
+            });
+            versions.dedup();
+            let head = versions
+                .iter()
+                .filter_map(|version| semver::Version::parse(version).ok())
+                .max()
+                .map(|version| version.to_string());
+
+            Channel {
+                minor: minor_stream(&name),
+                name,
+                head,
+                versions,
+            }
+        })
+        .collect()
+}
+
+/// Returns the `<major>.<minor>` suffix of a channel name like `stable-4.12`.
+fn minor_stream(channel: &str) -> Option<String> {
+    let (_, suffix) = channel.rsplit_once('-')?;
+    let (major, minor) = suffix.split_once('.')?;
+    major.parse::<u64>().ok()?;
+    minor.parse::<u64>().ok()?;
+
+    Some(suffix.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::common_init;
+    use actix_web::body::MessageBody;
+    use actix_web::http;
+    use cincinnati::plugins::prelude::*;
+
+    static GRAPH_BODY: &str = r#"{
+        "nodes": [
+            {"version": "4.11.1", "payload": "image/4.11.1", "metadata": {
+                "io.openshift.upgrades.graph.release.channels": "stable-4.11,stable-4.12",
+                "io.openshift.upgrades.graph.release.arch": "amd64"
+            }},
+            {"version": "4.12.0", "payload": "image/4.12.0", "metadata": {
+                "io.openshift.upgrades.graph.release.channels": "stable-4.12,fast-4.12",
+                "io.openshift.upgrades.graph.release.arch": "amd64"
+            }},
+            {"version": "4.12.2", "payload": "image/4.12.2", "metadata": {
+                "io.openshift.upgrades.graph.release.channels": "fast-4.12",
+                "io.openshift.upgrades.graph.release.arch": "arm64"
+            }},
+            {"version": "4.12.1", "payload": "image/4.12.1", "metadata": {
+                "io.openshift.upgrades.graph.release.channels": "fast-4.12, candidate",
+                "io.openshift.upgrades.graph.release.arch": "amd64"
+            }}
+        ],
+        "edges": [[0, 1], [1, 3], [3, 2]],
+        "conditionalEdges": []
+    }"#;
+
+    fn run_request(query: &str) -> Fallible<serde_json::Value> {
+        let rt = common_init();
+
+        let _m = mockito::mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(GRAPH_BODY)
+            .create();
+
+        let plugins = cincinnati::plugins::catalog::build_plugins(
+            &[
+                plugin_config!(
+                    ("name", CincinnatiGraphFetchPlugin::PLUGIN_NAME),
+                    ("upstream", &mockito::server_url())
+                )?,
+                plugin_config!(
+                    ("name", ChannelFilterPlugin::PLUGIN_NAME),
+                    ("key_prefix", "io.openshift.upgrades.graph"),
+                    ("key_suffix", "release.channels")
+                )?,
+                plugin_config!(
+                    ("name", ArchFilterPlugin::PLUGIN_NAME),
+                    ("default_arch", "amd64")
+                )?,
+            ],
+            None,
+        )?;
+        let state = AppState {
+            mandatory_params: vec![CHANNEL_PARAM.to_string()].into_iter().collect(),
+            plugins: Box::leak(Box::new(plugins)),
+            ..Default::default()
+        };
+
+        let http_req = actix_web::test::TestRequest::get()
+            .uri(&format!("http://unused.test/channels?{}", query))
+            .insert_header((
+                http::header::ACCEPT,
+                http::header::HeaderValue::from_static(CONTENT_TYPE),
+            ))
+            .to_http_request();
+
+        let resp = rt.block_on(index(http_req, actix_web::web::Data::new(state)))?;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+        let bytes = match resp.into_body().try_into_bytes() {
+            Ok(bytes) => bytes,
+            Err(_) => bail!("expected bytes in body"),
+        };
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    #[test]
+    fn list_channels_by_arch() -> Fallible<()> {
+        assert_eq!(
+            run_request("")?,
+            serde_json::json!({"channels": [
+                {"name": "candidate", "minor": null, "head": "4.12.1", "versions": ["4.12.1"]},
+                {"name": "fast-4.12", "minor": "4.12", "head": "4.12.1", "versions": ["4.12.0", "4.12.1"]},
+                {"name": "stable-4.11", "minor": "4.11", "head": "4.11.1", "versions": ["4.11.1"]},
+                {"name": "stable-4.12", "minor": "4.12", "head": "4.12.0", "versions": ["4.11.1", "4.12.0"]},
+            ]})
+        );
+
+        assert_eq!(
+            run_request("arch=arm64&channel=ignored")?,
+            serde_json::json!({"channels": [
+                {"name": "fast-4.12", "minor": "4.12", "head": "4.12.2", "versions": ["4.12.2"]},
+            ]})
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn channel_minor_stream() {
+        assert_eq!(minor_stream("stable-4.12"), Some("4.12".to_string()));
+        assert_eq!(minor_stream("eus-4.12-dev"), None);
+        assert_eq!(minor_stream("candidate"), None);
+        assert_eq!(minor_stream("stable-4.x"), None);
+    }
+}
@@ -16,6 +16,10 @@ pub struct CliOptions {
     #[structopt(short = "c")]
     pub config_path: Option<String>,
 
+    /// Validate the configuration and print it, without starting the services
+    #[structopt(long = "check-config")]
+    pub check_config: bool,
+
     // Status service options
     #[structopt(flatten)]
     pub service: options::ServiceOptions,
@@ -41,6 +45,7 @@ impl MergeOptions<CliOptions> for AppSettings {
             2 => log::LevelFilter::Debug,
             _ => log::LevelFilter::Trace,
         };
+        self.check_config |= opts.check_config;
 
         self.try_merge(Some(opts.service))?;
         self.try_merge(Some(opts.status))?;
@@ -66,6 +66,14 @@ pub struct ServiceOptions {
     pub keep_alive: Option<u64>,
     #[structopt(name = "client_timeout", long = "service.client_timeout")]
     pub client_timeout: Option<u64>,
+
+    /// Maximum number of graph responses to cache, 0 disabling the cache
+    #[structopt(long = "service.response_cache_size")]
+    pub response_cache_size: Option<usize>,
+
+    /// Maximum total size in bytes of the cached graph responses
+    #[structopt(long = "service.response_cache_max_bytes")]
+    pub response_cache_max_bytes: Option<usize>,
 }
 
 impl MergeOptions<Option<ServiceOptions>> for AppSettings {
@@ -78,6 +86,11 @@ impl MergeOptions<Option<ServiceOptions>> for AppSettings {
             assign_if_some!(self.backlog, service.backlog);
             assign_if_some!(self.max_connections, service.max_connections);
             assign_if_some!(self.max_connection_rate, service.max_connection_rate);
+            assign_if_some!(self.response_cache_size, service.response_cache_size);
+            assign_if_some!(
+                self.response_cache_max_bytes,
+                service.response_cache_max_bytes
+            );
             self.keep_alive = match service.keep_alive {
                 Some(x) => Some(Duration::new(x, 0)),
                 None => None,
@@ -94,11 +107,15 @@ impl MergeOptions<Option<ServiceOptions>> for AppSettings {
 }
 
 /// Options for a Cincinnati upstream.
-#[derive(Debug, Deserialize, StructOpt)]
+#[derive(Debug, Deserialize, Serialize, StructOpt)]
 pub struct UpCincinnatiOptions {
     /// Base URL for the upstream Cincinnati
     #[structopt(long = "upstream.cincinnati.url", parse(try_from_str = uri_from_str))]
-    #[serde(default = "Option::default", deserialize_with = "de_uri")]
+    #[serde(
+        default = "Option::default",
+        deserialize_with = "de_uri",
+        serialize_with = "ser_uri"
+    )]
     pub url: Option<hyper::Uri>,
 }
 
@@ -132,3 +149,14 @@ where
     let uri: hyper::Uri = input.parse().map_err(D::Error::custom)?;
     Ok(Some(uri))
 }
+
+/// Serialize a URI as a string value.
+pub fn ser_uri<S>(uri: &Option<hyper::Uri>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match uri {
+        Some(uri) => serializer.serialize_str(&uri.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
@@ -1,6 +1,6 @@
 //! Application settings for policy-engine.
 
-use super::{cli, file};
+use super::{cli, file, options};
 use cincinnati::plugins::catalog::{self, PluginSettings};
 use cincinnati::plugins::BoxedPlugin;
 use commons::prelude_errors::*;
@@ -68,6 +68,16 @@ pub struct AppSettings {
     /// Actix-web server client timeout for first request, defaults to 5s: https://docs.rs/actix-web/latest/actix_web/struct.HttpServer.html#method.client_timeout
     #[default(Duration::new(5, 0))]
     pub client_timeout: Duration,
+
+    /// Maximum number of graph responses in the response cache, 0 disabling it.
+    #[default(64)]
+    pub response_cache_size: usize,
+    /// Maximum total size in bytes of the graph responses in the response cache.
+    #[default(256 * 1024 * 1024)]
+    pub response_cache_max_bytes: usize,
+
+    /// Only validate the configuration, without starting the services.
+    pub check_config: bool,
 }
 
 impl AppSettings {
@@ -110,6 +120,68 @@ impl AppSettings {
         catalog::build_plugins(plugin_settings, registry)
     }
 
+    /// Validate the plugins and render the effective configuration,
+    /// including the settings of each plugin with their defaults.
+    pub fn check_config_report(&self) -> Fallible<String> {
+        let default_plugin_settings = self.default_openshift_plugin_settings()?;
+        let plugin_settings: &Vec<Box<dyn PluginSettings>> = if self.plugin_settings.is_empty() {
+            &default_plugin_settings
+        } else {
+            &self.plugin_settings
+        };
+
+        let registry = prometheus::Registry::new();
+        catalog::build_plugins(plugin_settings, Some(&registry))?;
+
+        let mut file_options = self.file_options();
+        file_options.policy = Some(
+            plugin_settings
+                .iter()
+                .enumerate()
+                .map(|(i, settings)| {
+                    settings.serialize_config().context(format!(
+                        "failed to serialize the plugin settings at 'policy[{}]'",
+                        i
+                    ))
+                })
+                .collect::<Fallible<_>>()?,
+        );
+
+        toml::to_string(&file_options).context("failed to serialize the effective configuration")
+    }
+
+    /// Return the configuration file equivalent to these settings, plugins excluded.
+    fn file_options(&self) -> file::FileOptions {
+        file::FileOptions {
+            verbosity: Some(self.verbosity),
+            upstream: Some(file::UpstreamOptions {
+                method: None,
+                cincinnati: Some(options::UpCincinnatiOptions {
+                    url: Some(self.upstream.clone()),
+                }),
+            }),
+            policy: None,
+            service: Some(options::ServiceOptions {
+                address: Some(self.address),
+                port: Some(self.port),
+                path_prefix: Some(self.path_prefix.clone()),
+                mandatory_client_parameters: Some(self.mandatory_client_parameters.clone()),
+                tracing_endpoint: self.tracing_endpoint.clone(),
+                backlog: Some(self.backlog),
+                max_connections: Some(self.max_connections),
+                max_connection_rate: Some(self.max_connection_rate),
+                keep_alive: self.keep_alive.map(|duration| duration.as_secs()),
+                client_timeout: Some(self.client_timeout.as_secs()),
+                response_cache_size: Some(self.response_cache_size),
+                response_cache_max_bytes: Some(self.response_cache_max_bytes),
+            }),
+            status: Some(options::StatusOptions {
+                address: Some(self.status_address),
+                port: Some(self.status_port),
+            }),
+        }
+    }
+
     /// Validate and build runtime settings.
     fn try_validate(self) -> Fallible<Self> {
         if self.address == self.status_address && self.port == self.status_port {
@@ -163,3 +235,49 @@ impl AppSettings {
         ])
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use commons::MergeOptions;
+
+    #[test]
+    fn check_config_report_roundtrip() -> Fallible<()> {
+        let mut settings = AppSettings::default();
+        settings.port = 8888;
+        settings.keep_alive = Some(Duration::from_secs(30));
+
+        let report = settings.check_config_report()?;
+        let file_opts: file::FileOptions = toml::from_str(&report)?;
+        assert_eq!(
+            file_opts.policy.as_ref().map(|policy| policy
+                .iter()
+                .filter_map(|config| config.get("name")?.as_str())
+                .collect::<Vec<_>>()),
+            Some(vec![
+                "cincinnati-graph-fetch",
+                "channel-filter",
+                "arch-filter"
+            ])
+        );
+        // Defaults which the default configuration leaves out are reported.
+        let fetch = &file_opts.policy.as_ref().expect("missing policy")[0];
+        assert_eq!(
+            fetch.get("timeout").and_then(toml::Value::as_integer),
+            Some(30)
+        );
+        assert_eq!(
+            fetch.get("protobuf").and_then(toml::Value::as_bool),
+            Some(false)
+        );
+
+        let mut merged = AppSettings::default();
+        merged.try_merge(Some(file_opts))?;
+        assert_eq!(merged.port, 8888);
+        assert_eq!(merged.keep_alive, Some(Duration::from_secs(30)));
+        assert_eq!(merged.upstream, settings.upstream);
+        assert_eq!(merged.plugin_settings.len(), 3);
+
+        Ok(())
+    }
+}
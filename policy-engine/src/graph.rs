@@ -1,9 +1,14 @@
 //! Cincinnati graph service.
 
+use crate::response_cache::{CachedResponse, ResponseCache};
 use crate::AppState;
 use actix_web::http::header;
 use actix_web::web::Query;
 use actix_web::{HttpRequest, HttpResponse};
+use cincinnati::export::ExportFormat;
+use cincinnati::plugins::condition::{header_parameter, HEADER_PARAMETER_PREFIX};
+use cincinnati::plugins::failure_policy::{is_degraded, DEGRADED_PARAMETER};
+use cincinnati::plugins::internal::cincinnati_graph_fetch::upstream_graph_hash;
 use cincinnati::plugins::internal::versioned_graph::VersionedGraph;
 use cincinnati::plugins::{BoxedPlugin, InternalIO};
 use cincinnati::CONTENT_TYPE;
@@ -15,6 +20,7 @@ use opentelemetry::{
 };
 use prometheus::{histogram_opts, Histogram, IntCounterVec, Opts, Registry};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 lazy_static! {
     static ref GRAPH_INCOMING_REQS: IntCounterVec = IntCounterVec::new(
@@ -64,7 +70,9 @@ async fn _index(
 
     let accept_versions: Vec<header::HeaderValue> = commons::CINCINNATI_VERSION
         .keys()
-        .map(|val| header::HeaderValue::from_static(val))
+        .copied()
+        .chain(ExportFormat::ALL.iter().map(|format| format.content_type()))
+        .map(header::HeaderValue::from_static)
         .collect();
 
     // Check that the client can accept media type.
@@ -80,13 +88,28 @@ async fn _index(
         .map_err(|e| commons::GraphError::InvalidParams(e.to_string()))?;
 
     plugin_params.insert(String::from("content_type"), content_type);
+    insert_condition_headers(req, app_data.plugins, &mut plugin_params);
 
     let timer = GRAPH_SERVE_HIST.start_timer();
 
     let cx = ot_context::current();
-    let response = process_plugins(app_data.plugins.iter(), plugin_params)
-        .with_context(cx)
-        .await;
+    let response = match &app_data.response_cache {
+        Some(cache) => {
+            process_plugins_cached(
+                cache.clone(),
+                app_data.plugins.iter(),
+                plugin_params,
+                render_response,
+            )
+            .with_context(cx)
+            .await
+        }
+        None => {
+            process_plugins(app_data.plugins.iter(), plugin_params)
+                .with_context(cx)
+                .await
+        }
+    };
 
     timer.observe_duration();
     response
@@ -100,31 +123,136 @@ where
     P: std::iter::Iterator<Item = &'static BoxedPlugin>,
     P: 'static + Sync + Send,
 {
-    let internal_io = cincinnati::plugins::process(
+    let internal_io = run_plugins(plugins, plugin_params).await?;
+    let (content_type, body) = render_response(&internal_io)?;
+
+    Ok(HttpResponse::Ok().content_type(content_type).body(body))
+}
+
+/// Run the plugins and render their output, serving the response from the cache
+/// when the upstream graph and the relevant parameters are unchanged.
+pub(crate) async fn process_plugins_cached<P, R>(
+    cache: Arc<ResponseCache>,
+    plugins: P,
+    mut plugin_params: HashMap<String, String>,
+    render: R,
+) -> Result<HttpResponse, GraphError>
+where
+    P: std::iter::Iterator<Item = &'static BoxedPlugin> + Clone,
+    P: 'static + Sync + Send,
+    R: FnOnce(&InternalIO) -> Result<(String, String), GraphError>,
+{
+    // Only plugins may mark their outputs as degraded.
+    plugin_params.remove(DEGRADED_PARAMETER);
+
+    let sources = cache.sources();
+    let internal_io = run_plugins(plugins.clone().take(sources), plugin_params).await?;
+
+    let key = upstream_graph_hash(&internal_io.graph)
+        .map(|hash| cache.key(hash, &internal_io.parameters));
+    if let Some(cached) = key.as_ref().and_then(|key| cache.get(key)) {
+        return Ok(cached.to_response());
+    }
+
+    let internal_io = run_plugins_on(plugins.skip(sources), internal_io).await?;
+    let (content_type, body) = render(&internal_io)?;
+    let response = CachedResponse {
+        content_type,
+        body: body.into(),
+    };
+    // Responses built with the fallback output of a failed plugin are not cached,
+    // so that they are not served once the plugin recovers.
+    match key {
+        Some(key) if !is_degraded(&internal_io.parameters) => cache.insert(key, response.clone()),
+        _ => {}
+    }
+
+    Ok(response.to_response())
+}
+
+/// Render the processed graph in the requested content type.
+fn render_response(internal_io: &InternalIO) -> Result<(String, String), GraphError> {
+    let content_type = match &internal_io.parameters.get("content_type") {
+        Some(version) => *version,
+        None => *commons::MIN_CINCINNATI_VERSION,
+    };
+
+    let body = match ExportFormat::from_content_type(content_type) {
+        Some(format) => export_graph(internal_io, format),
+        None => {
+            let versioned_graph = add_version_information(internal_io);
+            serde_json::to_string(&versioned_graph)
+                .map_err(|e| GraphError::FailedJsonOut(e.to_string()))?
+        }
+    };
+
+    Ok((content_type.to_string(), body))
+}
+
+/// render the graph for visualization
+fn export_graph(io: &InternalIO, format: ExportFormat) -> String {
+    let span = get_tracer().start("export");
+    let _active_span = mark_span_as_active(span);
+    log::trace!("exporting the graph as {}", format.content_type());
+    format.render(&io.graph)
+}
+
+/// Add the request headers tested by plugin conditions, including the ones of
+/// nested plugins, to the plugin parameters.
+///
+/// Only those headers are passed on, as parameters are forwarded to external plugins.
+/// Query parameters posing as headers are dropped.
+pub(crate) fn insert_condition_headers(
+    req: &HttpRequest,
+    plugins: &[BoxedPlugin],
+    plugin_params: &mut HashMap<String, String>,
+) {
+    plugin_params.retain(|key, _| !key.starts_with(HEADER_PARAMETER_PREFIX));
+
+    let condition_headers = cincinnati::plugins::all_conditions(plugins)
+        .into_iter()
+        .filter_map(|condition| condition.header());
+    for name in condition_headers {
+        if let Some(value) = req.headers().get(name).and_then(|v| v.to_str().ok()) {
+            plugin_params.insert(header_parameter(name), value.to_string());
+        }
+    }
+}
+
+/// Run the plugin chain on an empty graph and the given parameters.
+pub(crate) async fn run_plugins<P>(
+    plugins: P,
+    plugin_params: HashMap<String, String>,
+) -> Result<InternalIO, GraphError>
+where
+    P: std::iter::Iterator<Item = &'static BoxedPlugin>,
+    P: 'static + Sync + Send,
+{
+    run_plugins_on(
         plugins,
-        cincinnati::plugins::PluginIO::InternalIO(cincinnati::plugins::InternalIO {
+        InternalIO {
             graph: Default::default(),
             parameters: plugin_params,
-        }),
+        },
+    )
+    .await
+}
+
+/// Run the plugin chain on the given graph and parameters.
+async fn run_plugins_on<P>(plugins: P, internal_io: InternalIO) -> Result<InternalIO, GraphError>
+where
+    P: std::iter::Iterator<Item = &'static BoxedPlugin>,
+    P: 'static + Sync + Send,
+{
+    cincinnati::plugins::process(
+        plugins,
+        cincinnati::plugins::PluginIO::InternalIO(internal_io),
     )
     .await
     .map_err(|e| match e.downcast::<GraphError>() {
         Ok(graph_error) => graph_error,
         Err(other_error) => GraphError::FailedPluginExecution(other_error.to_string()),
-    })?;
-
-    let versioned_graph = add_version_information(&internal_io);
-
-    let graph_json = serde_json::to_string(&versioned_graph)
-        .map_err(|e| GraphError::FailedJsonOut(e.to_string()))?;
-
-    let content_type = match &internal_io.parameters.get("content_type") {
-        Some(version) => *version,
-        None => *commons::MIN_CINCINNATI_VERSION,
-    };
-    Ok(HttpResponse::Ok()
-        .content_type(content_type)
-        .body(graph_json))
+    })
 }
 
 /// add version information to the graph json
@@ -139,10 +267,12 @@ fn add_version_information(io: &InternalIO) -> VersionedGraph {
 pub(crate) mod tests {
 
     use crate::graph;
+    use crate::response_cache::ResponseCache;
     use crate::AppState;
     use actix_web::body::MessageBody;
     use actix_web::http;
     use cincinnati::plugins::prelude::*;
+    use std::collections::HashMap;
     use tokio::runtime::Runtime;
 
     pub(crate) fn common_init() -> Runtime {
@@ -175,6 +305,42 @@ pub(crate) mod tests {
         );
     }
 
+    #[test]
+    fn condition_headers_as_params() -> Result<(), Error> {
+        let cfg = r#"
+            name = "node-remove"
+            when = { header = "X-Hotfix", present = true }
+        "#;
+        let plugins = cincinnati::plugins::catalog::build_plugins(
+            &[cincinnati::plugins::catalog::deserialize_config(
+                toml::from_str(cfg)?,
+            )?],
+            None,
+        )?;
+
+        let http_req = actix_web::test::TestRequest::get()
+            .insert_header(("X-Hotfix", "true"))
+            .insert_header(("X-Other", "true"))
+            .to_http_request();
+        let mut params: HashMap<String, String> =
+            vec![("channel", "a"), ("header.x-other", "spoofed")]
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect();
+        graph::insert_condition_headers(&http_req, &plugins, &mut params);
+
+        let mut params: Vec<_> = params.into_iter().collect();
+        params.sort();
+        assert_eq!(
+            params,
+            vec![
+                ("channel".to_string(), "a".to_string()),
+                ("header.x-hotfix".to_string(), "true".to_string()),
+            ]
+        );
+        Ok(())
+    }
+
     #[test]
     fn offline_upstream() -> Result<(), Error> {
         let rt = common_init();
@@ -257,6 +423,182 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn webservice_graph_export_response() -> Result<(), Error> {
+        let runtime = common_init();
+
+        let _m = mockito::mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(
+                r#"{
+                    "nodes": [
+                        {"version": "4.5.1", "payload": "image/4.5.1", "metadata": {}},
+                        {"version": "4.6.0", "payload": "image/4.6.0", "metadata": {}}
+                    ],
+                    "edges": [[0, 1]],
+                    "conditionalEdges": []
+                }"#,
+            )
+            .create();
+
+        let plugins = cincinnati::plugins::catalog::build_plugins(
+            &[plugin_config!(
+                ("name", CincinnatiGraphFetchPlugin::PLUGIN_NAME),
+                ("upstream", &mockito::server_url())
+            )?],
+            None,
+        )?;
+        let app = actix_web::App::new()
+            .app_data(actix_web::web::Data::new(AppState {
+                plugins: Box::leak(Box::new(plugins)),
+                ..Default::default()
+            }))
+            .service(
+                actix_web::web::resource("/graph").route(actix_web::web::get().to(graph::index)),
+            );
+
+        let (content_type, body) = runtime.block_on(async {
+            let pe_svc = actix_web::test::init_service(app).await;
+            let response = actix_web::test::call_service(
+                &pe_svc,
+                actix_web::test::TestRequest::with_uri("/graph")
+                    .insert_header(("Accept", cincinnati::export::DOT_CONTENT_TYPE))
+                    .to_request(),
+            )
+            .await;
+            assert_eq!(response.status(), http::StatusCode::OK);
+
+            let content_type = response.headers().get(http::header::CONTENT_TYPE).cloned();
+            let body = actix_web::test::read_body(response).await;
+            (content_type, body)
+        });
+
+        assert_eq!(
+            content_type,
+            Some(http::header::HeaderValue::from_static(
+                cincinnati::export::DOT_CONTENT_TYPE
+            ))
+        );
+        let body = std::str::from_utf8(&body)?;
+        assert!(body.starts_with("digraph Upgrades {"), "{}", body);
+        assert!(body.contains("subgraph \"cluster_4.5\""), "{}", body);
+        assert!(body.contains("n0->n1;"), "{}", body);
+
+        Ok(())
+    }
+
+    #[test]
+    fn webservice_cached_graph_response() -> Result<(), Error> {
+        let runtime = common_init();
+
+        let _m = mockito::mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"nodes":[],"edges":[],"conditionalEdges":[]}"#)
+            .create();
+
+        let plugins: &'static [BoxedPlugin] =
+            Box::leak(Box::new(cincinnati::plugins::catalog::build_plugins(
+                &[plugin_config!(
+                    ("name", CincinnatiGraphFetchPlugin::PLUGIN_NAME),
+                    ("upstream", &mockito::server_url())
+                )?],
+                None,
+            )?));
+        let cache = std::sync::Arc::new(
+            ResponseCache::new(plugins, 8, 1024 * 1024).expect("cache disabled"),
+        );
+        let app = actix_web::App::new()
+            .app_data(actix_web::web::Data::new(AppState {
+                plugins,
+                response_cache: Some(cache.clone()),
+                ..Default::default()
+            }))
+            .service(
+                actix_web::web::resource("/graph").route(actix_web::web::get().to(graph::index)),
+            );
+
+        let bodies = runtime.block_on(async {
+            let pe_svc = actix_web::test::init_service(app).await;
+            let mut bodies = vec![];
+            for id in &["a", "b"] {
+                let response = actix_web::test::call_service(
+                    &pe_svc,
+                    actix_web::test::TestRequest::with_uri(&format!("/graph?id={}", id))
+                        .insert_header(("Accept", cincinnati::CONTENT_TYPE))
+                        .to_request(),
+                )
+                .await;
+                assert_eq!(response.status(), http::StatusCode::OK);
+                bodies.push(actix_web::test::read_body(response).await);
+            }
+            bodies
+        });
+
+        assert_eq!(bodies[0], bodies[1]);
+        // The fetch plugin does not depend on any parameter, so both share an entry.
+        assert_eq!(cache.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn webservice_skip_caching_degraded_response() -> Result<(), Error> {
+        let runtime = common_init();
+
+        let _m = mockito::mock("GET", "/")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(r#"{"nodes":[],"edges":[],"conditionalEdges":[]}"#)
+            .create();
+
+        // The second upstream is unreachable, and its failure is skipped.
+        let plugins: &'static [BoxedPlugin] =
+            Box::leak(Box::new(cincinnati::plugins::catalog::build_plugins(
+                &[
+                    plugin_config!(
+                        ("name", CincinnatiGraphFetchPlugin::PLUGIN_NAME),
+                        ("upstream", &mockito::server_url())
+                    )?,
+                    plugin_config!(
+                        ("name", CincinnatiGraphFetchPlugin::PLUGIN_NAME),
+                        ("upstream", "http://127.0.0.1:1"),
+                        ("on_error", "skip")
+                    )?,
+                ],
+                None,
+            )?));
+        let cache = std::sync::Arc::new(
+            ResponseCache::new(plugins, 8, 1024 * 1024).expect("cache disabled"),
+        );
+        let app = actix_web::App::new()
+            .app_data(actix_web::web::Data::new(AppState {
+                plugins,
+                response_cache: Some(cache.clone()),
+                ..Default::default()
+            }))
+            .service(
+                actix_web::web::resource("/graph").route(actix_web::web::get().to(graph::index)),
+            );
+
+        runtime.block_on(async {
+            let pe_svc = actix_web::test::init_service(app).await;
+            let response = actix_web::test::call_service(
+                &pe_svc,
+                actix_web::test::TestRequest::with_uri("/graph")
+                    .insert_header(("Accept", cincinnati::CONTENT_TYPE))
+                    .to_request(),
+            )
+            .await;
+            assert_eq!(response.status(), http::StatusCode::OK);
+        });
+
+        assert_eq!(cache.len(), 0);
+
+        Ok(())
+    }
+
     #[test]
     fn webservice_graph_json_response() -> Result<(), Error> {
         let _ = common_init();
@@ -19,10 +19,13 @@ extern crate structopt;
 #[macro_use]
 extern crate custom_debug_derive;
 
+mod channels;
 mod config;
 mod graph;
 mod openapi;
+mod response_cache;
 mod status;
+mod upgrade_paths;
 
 use actix_cors::Cors;
 use actix_service::Service;
@@ -76,6 +79,11 @@ async fn main() -> Result<(), Error> {
         .init();
     info!("application settings:\n{:#?}", &settings);
 
+    if settings.check_config {
+        print!("{}", settings.check_config_report()?);
+        return Ok(());
+    }
+
     // Metrics service.
     let registry: &'static Registry = Box::leak(Box::new(metrics::new_registry(Some(
         METRICS_PREFIX.to_string(),
@@ -90,6 +98,18 @@ async fn main() -> Result<(), Error> {
         let mandatory_params = settings.mandatory_client_parameters.clone();
         let path_prefix = settings.path_prefix.clone();
         let plugins = Box::leak(Box::new(plugins));
+        let response_cache = response_cache::ResponseCache::new(
+            plugins.iter(),
+            settings.response_cache_size,
+            settings.response_cache_max_bytes,
+        )
+        .map(Arc::new);
+        let channels_cache = response_cache::ResponseCache::new(
+            channels::channel_plugins(plugins),
+            settings.response_cache_size,
+            settings.response_cache_max_bytes,
+        )
+        .map(Arc::new);
         let live = Arc::new(RwLock::new(false));
         let ready = Arc::new(RwLock::new(false));
 
@@ -97,13 +117,18 @@ async fn main() -> Result<(), Error> {
             mandatory_params,
             path_prefix,
             plugins,
+            response_cache,
+            channels_cache,
             live,
             ready,
             registry,
         )
     };
 
+    channels::register_metrics(state.registry())?;
     graph::register_metrics(state.registry())?;
+    response_cache::register_metrics(state.registry())?;
+    upgrade_paths::register_metrics(state.registry())?;
     let metric_state = state.clone();
     let metrics_server = HttpServer::new(move || {
         App::new()
@@ -153,6 +178,14 @@ async fn main() -> Result<(), Error> {
                 actix_web::web::resource(&format!("{}/graph", app_prefix))
                     .route(actix_web::web::get().to(graph::index)),
             )
+            .service(
+                actix_web::web::resource(&format!("{}/upgrade-paths", app_prefix))
+                    .route(actix_web::web::get().to(upgrade_paths::index)),
+            )
+            .service(
+                actix_web::web::resource(&format!("{}/channels", app_prefix))
+                    .route(actix_web::web::get().to(channels::index)),
+            )
             .service(
                 actix_web::web::resource(&format!("{}/openapi", app_prefix))
                     .route(actix_web::web::get().to(openapi::index)),
@@ -229,6 +262,10 @@ pub struct AppState {
     path_prefix: String,
     /// Policy plugins.
     plugins: &'static [BoxedPlugin],
+    /// Cache of graph responses, if enabled.
+    response_cache: Option<Arc<response_cache::ResponseCache>>,
+    /// Cache of channel responses, if enabled.
+    channels_cache: Option<Arc<response_cache::ResponseCache>>,
     live: Arc<RwLock<bool>>,
     ready: Arc<RwLock<bool>>,
     registry: &'static Registry,
@@ -236,10 +273,13 @@ pub struct AppState {
 
 impl AppState {
     /// Creates a new State with the given arguments
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         mandatory_params: HashSet<String>,
         path_prefix: String,
         plugins: &'static [BoxedPlugin],
+        response_cache: Option<Arc<response_cache::ResponseCache>>,
+        channels_cache: Option<Arc<response_cache::ResponseCache>>,
         live: Arc<RwLock<bool>>,
         ready: Arc<RwLock<bool>>,
         registry: &'static Registry,
@@ -248,6 +288,8 @@ impl AppState {
             mandatory_params,
             path_prefix,
             plugins,
+            response_cache,
+            channels_cache,
             live,
             ready,
             registry,
@@ -274,6 +316,8 @@ impl Default for AppState {
             mandatory_params: Default::default(),
             path_prefix: Default::default(),
             plugins: Default::default(),
+            response_cache: None,
+            channels_cache: None,
             live: Default::default(),
             ready: Default::default(),
             registry,
@@ -19,9 +19,16 @@ pub(crate) async fn index(app_data: actix_web::web::Data<AppState>) -> HttpRespo
             }
         };
 
-    // Add mandatory parameters to the `graph` endpoint.
-    if let Some(path) = spec_object.paths.paths.get_mut("/graph") {
-        add_mandatory_params(path, &app_data.mandatory_params);
+    // Add mandatory parameters to the endpoints which run the plugin chain.
+    for endpoint in &["/graph", "/upgrade-paths"] {
+        if let Some(path) = spec_object.paths.paths.get_mut(*endpoint) {
+            add_mandatory_params(path, &app_data.mandatory_params);
+        }
+    }
+    if let Some(path) = spec_object.paths.paths.get_mut("/channels") {
+        let mut mandatory_params = app_data.mandatory_params.clone();
+        mandatory_params.remove("channel");
+        add_mandatory_params(path, &mandatory_params);
     }
 
     // Prefix all paths with `path_prefix`
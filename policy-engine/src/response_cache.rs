@@ -0,0 +1,322 @@
+//! In-memory cache of graph responses.
+//!
+//! Responses are keyed by the content hash of the upstream graph, the content type,
+//! and the request parameters which the plugins declare to depend on.
+//! All entries are dropped as soon as the upstream graph changes.
+
+use actix_web::web::Bytes;
+use actix_web::HttpResponse;
+use cincinnati::plugins::BoxedPlugin;
+use commons::Fallible;
+use lru::LruCache;
+use parking_lot::Mutex;
+use prometheus::{IntCounter, IntGauge, Registry};
+use std::collections::{BTreeSet, HashMap};
+use std::num::NonZeroUsize;
+
+lazy_static! {
+    static ref RESPONSE_CACHE_HITS: IntCounter = IntCounter::new(
+        "response_cache_hits_total",
+        "Total number of graph requests served from the response cache"
+    )
+    .unwrap();
+    static ref RESPONSE_CACHE_MISSES: IntCounter = IntCounter::new(
+        "response_cache_misses_total",
+        "Total number of graph requests not found in the response cache"
+    )
+    .unwrap();
+    static ref RESPONSE_CACHE_ENTRIES: IntGauge = IntGauge::new(
+        "response_cache_entries",
+        "Number of responses in the response cache"
+    )
+    .unwrap();
+    static ref RESPONSE_CACHE_BYTES: IntGauge = IntGauge::new(
+        "response_cache_bytes",
+        "Size in bytes of the responses in the response cache"
+    )
+    .unwrap();
+}
+
+/// Register relevant metrics to a prometheus registry.
+pub(crate) fn register_metrics(registry: &Registry) -> Fallible<()> {
+    registry.register(Box::new(RESPONSE_CACHE_HITS.clone()))?;
+    registry.register(Box::new(RESPONSE_CACHE_MISSES.clone()))?;
+    registry.register(Box::new(RESPONSE_CACHE_ENTRIES.clone()))?;
+    registry.register(Box::new(RESPONSE_CACHE_BYTES.clone()))?;
+    Ok(())
+}
+
+/// Key of a cached response.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    upstream_hash: u64,
+    parameters: Vec<(String, String)>,
+}
+
+/// Cached graph response.
+#[derive(Clone, Debug)]
+pub(crate) struct CachedResponse {
+    pub(crate) content_type: String,
+    pub(crate) body: Bytes,
+}
+
+impl CachedResponse {
+    /// Build the HTTP response for this entry.
+    pub(crate) fn to_response(&self) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type(self.content_type.as_str())
+            .body(self.body.clone())
+    }
+}
+
+/// LRU cache of graph responses, bounded in number of entries and total size.
+#[derive(Debug)]
+pub(crate) struct ResponseCache {
+    /// Number of leading plugins which fetch the upstream graph.
+    sources: usize,
+    /// Parameters the responses depend on, `None` meaning all of them.
+    parameters: Option<BTreeSet<String>>,
+    /// Maximum total size in bytes of the cached responses.
+    max_bytes: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug)]
+struct CacheState {
+    upstream_hash: Option<u64>,
+    entries: LruCache<CacheKey, CachedResponse>,
+    bytes: usize,
+}
+
+impl ResponseCache {
+    /// Create a cache for the responses of the given plugins.
+    ///
+    /// Returns `None` if the size is zero, or if the plugins do not start by
+    /// fetching the upstream graph, as there is no way to invalidate entries then.
+    pub(crate) fn new<'a, I>(plugins: I, max_entries: usize, max_bytes: usize) -> Option<Self>
+    where
+        I: IntoIterator<Item = &'a BoxedPlugin>,
+        I::IntoIter: Clone,
+    {
+        use cincinnati::plugins::prelude::CincinnatiGraphFetchPlugin;
+
+        let max_entries = NonZeroUsize::new(max_entries)?;
+        let mut plugins = plugins.into_iter();
+        let sources = plugins
+            .clone()
+            .take_while(|plugin| {
+                plugin.get_name() == CincinnatiGraphFetchPlugin::PLUGIN_NAME
+                    && plugin.condition().is_none()
+            })
+            .count();
+        if sources == 0 {
+            warn!("response cache disabled, the plugins do not start by fetching the graph");
+            return None;
+        }
+
+        let parameters = plugins.try_fold(BTreeSet::new(), |mut acc, plugin| {
+            acc.extend(plugin.parameters()?);
+            Some(acc)
+        });
+        if parameters.is_none() {
+            warn!("some plugins do not declare their parameters, caching per full query");
+        }
+
+        Some(Self {
+            sources,
+            parameters,
+            max_bytes,
+            state: Mutex::new(CacheState {
+                upstream_hash: None,
+                entries: LruCache::new(max_entries),
+                bytes: 0,
+            }),
+        })
+    }
+
+    /// Returns the number of leading plugins which fetch the upstream graph.
+    pub(crate) fn sources(&self) -> usize {
+        self.sources
+    }
+
+    /// Build the key for the given upstream graph hash and request parameters.
+    ///
+    /// The content type is always part of the key.
+    pub(crate) fn key(&self, upstream_hash: u64, parameters: &HashMap<String, String>) -> CacheKey {
+        let mut parameters: Vec<(String, String)> = parameters
+            .iter()
+            .filter(|(key, _)| {
+                key.as_str() == "content_type"
+                    || self
+                        .parameters
+                        .as_ref()
+                        .map_or(true, |dependencies| dependencies.contains(key.as_str()))
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        parameters.sort();
+
+        CacheKey {
+            upstream_hash,
+            parameters,
+        }
+    }
+
+    /// Returns the number of cached responses.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.state.lock().entries.len()
+    }
+
+    /// Look up a response, dropping all entries if the upstream graph changed.
+    pub(crate) fn get(&self, key: &CacheKey) -> Option<CachedResponse> {
+        let mut guard = self.state.lock();
+        let state = &mut *guard;
+        if state.upstream_hash != Some(key.upstream_hash) {
+            state.entries.clear();
+            state.bytes = 0;
+            state.upstream_hash = Some(key.upstream_hash);
+            update_gauges(state);
+        }
+
+        let response = state.entries.get(key).cloned();
+        match response {
+            Some(_) => RESPONSE_CACHE_HITS.inc(),
+            None => RESPONSE_CACHE_MISSES.inc(),
+        };
+        response
+    }
+
+    /// Store a response, evicting the least recently used ones to stay within limits.
+    ///
+    /// Responses larger than the whole cache are not stored.
+    pub(crate) fn insert(&self, key: CacheKey, response: CachedResponse) {
+        let size = response.body.len();
+        if size > self.max_bytes {
+            return;
+        }
+
+        let mut guard = self.state.lock();
+        let state = &mut *guard;
+        if state.upstream_hash != Some(key.upstream_hash) {
+            // The upstream graph changed while this response was computed.
+            return;
+        }
+
+        if let Some((_, evicted)) = state.entries.push(key, response) {
+            state.bytes -= evicted.body.len();
+        }
+        state.bytes += size;
+        while state.bytes > self.max_bytes {
+            match state.entries.pop_lru() {
+                Some((_, evicted)) => state.bytes -= evicted.body.len(),
+                None => break,
+            }
+        }
+        update_gauges(state);
+    }
+}
+
+fn update_gauges(state: &CacheState) {
+    RESPONSE_CACHE_ENTRIES.set(state.entries.len() as i64);
+    RESPONSE_CACHE_BYTES.set(state.bytes as i64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_plugins(cfgs: &[&str]) -> Fallible<Vec<BoxedPlugin>> {
+        cfgs.iter()
+            .map(|cfg| {
+                cincinnati::plugins::catalog::deserialize_config(toml::from_str(cfg)?)?
+                    .build_plugin(None)
+            })
+            .collect()
+    }
+
+    fn response(body: &'static str) -> CachedResponse {
+        CachedResponse {
+            content_type: "application/json".to_string(),
+            body: Bytes::from_static(body.as_bytes()),
+        }
+    }
+
+    fn parameters(entries: &[(&str, &str)]) -> HashMap<String, String> {
+        entries
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn key_on_declared_parameters() -> Fallible<()> {
+        let plugins = build_plugins(&[
+            "name = 'cincinnati-graph-fetch'",
+            "name = 'channel-filter'\nkey_prefix = 'io.openshift.upgrades.graph'\nkey_suffix = 'release.channels'",
+        ])?;
+        let cache = ResponseCache::new(&plugins, 2, 1024).expect("cache disabled");
+        assert_eq!(cache.sources(), 1);
+
+        let key = cache.key(
+            1,
+            &parameters(&[("channel", "a"), ("id", "x"), ("content_type", "json")]),
+        );
+        let other_id = cache.key(
+            1,
+            &parameters(&[("channel", "a"), ("id", "y"), ("content_type", "json")]),
+        );
+        let other_channel = cache.key(
+            1,
+            &parameters(&[("channel", "b"), ("id", "x"), ("content_type", "json")]),
+        );
+        assert_eq!(key, other_id);
+        assert_ne!(key, other_channel);
+
+        Ok(())
+    }
+
+    #[test]
+    fn disabled_without_upstream_fetch() -> Fallible<()> {
+        let plugins = build_plugins(&["name = 'edge-add-remove'"])?;
+        assert!(ResponseCache::new(&plugins, 2, 1024).is_none());
+
+        let plugins = build_plugins(&["name = 'cincinnati-graph-fetch'"])?;
+        assert!(ResponseCache::new(&plugins, 0, 1024).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn evict_and_invalidate() -> Fallible<()> {
+        let plugins = build_plugins(&["name = 'cincinnati-graph-fetch'"])?;
+        let cache = ResponseCache::new(&plugins, 2, 8).expect("cache disabled");
+
+        let keys: Vec<CacheKey> = ["a", "b", "c"]
+            .iter()
+            .map(|content_type| cache.key(1, &parameters(&[("content_type", content_type)])))
+            .collect();
+
+        assert!(cache.get(&keys[0]).is_none());
+        cache.insert(keys[0].clone(), response("1234"));
+        cache.insert(keys[1].clone(), response("1234"));
+        assert!(cache.get(&keys[0]).is_some());
+
+        // Over the byte limit, the least recently used entry is evicted.
+        cache.insert(keys[2].clone(), response("1234"));
+        assert!(cache.get(&keys[1]).is_none());
+        assert!(cache.get(&keys[0]).is_some());
+        assert!(cache.get(&keys[2]).is_some());
+
+        // Too large to be cached at all.
+        cache.insert(keys[1].clone(), response("123456789"));
+        assert!(cache.get(&keys[1]).is_none());
+
+        // A new upstream graph drops all entries.
+        let changed = cache.key(2, &parameters(&[("content_type", "a")]));
+        assert!(cache.get(&changed).is_none());
+        assert!(cache.get(&keys[0]).is_none());
+
+        Ok(())
+    }
+}
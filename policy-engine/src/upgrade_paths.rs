@@ -0,0 +1,323 @@
+//! Upgrade path service.
+//!
+//! Runs the configured plugin chain, like the graph service does, and computes
+//! the shortest upgrade paths between two releases on the resulting graph.
+
+use crate::graph::{insert_condition_headers, run_plugins};
+use crate::AppState;
+use actix_web::http::header;
+use actix_web::web::Query;
+use actix_web::{HttpRequest, HttpResponse};
+use cincinnati::{ConditionalEdgesFilter, UpgradePath, CONTENT_TYPE};
+use commons::tracing::get_tracer;
+use commons::{self, api_response_error, Fallible, GraphError};
+use opentelemetry::{
+    trace::{mark_span_as_active, FutureExt, Tracer},
+    Context as ot_context,
+};
+use prometheus::{IntCounterVec, Opts, Registry};
+use std::collections::HashMap;
+
+/// Query parameter holding the version to upgrade from.
+static FROM_PARAM: &str = "from";
+/// Query parameter holding the version to upgrade to.
+static TO_PARAM: &str = "to";
+/// Query parameter selecting whether conditional edges may be traversed.
+static CONDITIONAL_PARAM: &str = "conditional";
+/// Query parameter holding a comma-separated list of accepted risk names.
+static ACCEPTED_RISKS_PARAM: &str = "accepted_risks";
+/// Query parameter requesting all minimal paths instead of a single one.
+static ALL_PARAM: &str = "all";
+
+/// Upper bound for the number of paths returned for a single request.
+static MAX_UPGRADE_PATHS: usize = 100;
+
+lazy_static! {
+    static ref UPGRADE_PATHS_INCOMING_REQS: IntCounterVec = IntCounterVec::new(
+        Opts::new(
+            "upgrade_paths_incoming_requests_total",
+            "Total number of incoming HTTP client request for upgrade paths"
+        ),
+        &["uri_path"]
+    )
+    .unwrap();
+}
+
+/// Register relevant metrics to a prometheus registry.
+pub(crate) fn register_metrics(registry: &Registry) -> Fallible<()> {
+    registry.register(Box::new(UPGRADE_PATHS_INCOMING_REQS.clone()))?;
+    Ok(())
+}
+
+/// Response body of the upgrade path service.
+#[derive(Debug, Serialize)]
+struct UpgradePaths {
+    from: String,
+    to: String,
+    paths: Vec<UpgradePath>,
+}
+
+/// Serve upgrade path requests.
+pub(crate) async fn index(
+    req: HttpRequest,
+    app_data: actix_web::web::Data<AppState>,
+) -> Result<HttpResponse, GraphError> {
+    _index(&req, app_data)
+        .await
+        .map_err(|e| api_response_error(&req, e))
+}
+
+async fn _index(
+    req: &HttpRequest,
+    app_data: actix_web::web::Data<AppState>,
+) -> Result<HttpResponse, GraphError> {
+    let span = get_tracer().start("upgrade_paths");
+    let _active_span = mark_span_as_active(span);
+
+    let path = req.uri().path();
+    UPGRADE_PATHS_INCOMING_REQS.with_label_values(&[path]).inc();
+
+    // Check that the client can accept media type.
+    let accept_default = header::HeaderValue::from_static(CONTENT_TYPE);
+    commons::validate_content_type(req.headers(), vec![accept_default.clone()], accept_default)?;
+
+    // Check for required client parameters.
+    commons::ensure_query_params(&app_data.mandatory_params, req.query_string())?;
+
+    let mut plugin_params = Query::<HashMap<String, String>>::from_query(req.query_string())
+        .map(|query| query.into_inner())
+        .map_err(|e| GraphError::InvalidParams(e.to_string()))?;
+    insert_condition_headers(req, app_data.plugins, &mut plugin_params);
+
+    let (from, to) = match (plugin_params.get(FROM_PARAM), plugin_params.get(TO_PARAM)) {
+        (Some(from), Some(to)) => (from.clone(), to.clone()),
+        (from, to) => {
+            let missing = vec![(FROM_PARAM, from), (TO_PARAM, to)]
+                .into_iter()
+                .filter(|(_, value)| value.is_none())
+                .map(|(key, _)| key.to_string())
+                .collect();
+            return Err(GraphError::MissingParams(missing));
+        }
+    };
+    let filter = parse_conditional_edges_filter(&plugin_params)?;
+    let all = match plugin_params.get(ALL_PARAM) {
+        Some(all) => all.parse::<bool>().map_err(|_| {
+            GraphError::InvalidParams(format!("{} '{}' is not a boolean", ALL_PARAM, all))
+        })?,
+        None => false,
+    };
+
+    let cx = ot_context::current();
+    let internal_io = run_plugins(app_data.plugins.iter(), plugin_params)
+        .with_context(cx)
+        .await?;
+
+    let paths = if all {
+        internal_io
+            .graph
+            .all_shortest_upgrade_paths(&from, &to, &filter, MAX_UPGRADE_PATHS)
+    } else {
+        internal_io
+            .graph
+            .shortest_upgrade_path(&from, &to, &filter)
+            .map(|path| path.into_iter().collect())
+    }
+    .map_err(
+        |e| match e.downcast_ref::<cincinnati::errors::ReleaseDoesntExist>() {
+            Some(missing) => GraphError::DoesNotExist(missing.to_string()),
+            None => GraphError::FailedPluginExecution(e.to_string()),
+        },
+    )?;
+
+    let body = serde_json::to_string(&UpgradePaths { from, to, paths })
+        .map_err(|e| GraphError::FailedJsonOut(e.to_string()))?;
+
+    Ok(HttpResponse::Ok().content_type(CONTENT_TYPE).body(body))
+}
+
+/// Build the conditional edges filter from the `conditional` and `accepted_risks` parameters.
+fn parse_conditional_edges_filter(
+    params: &HashMap<String, String>,
+) -> Result<ConditionalEdgesFilter, GraphError> {
+    let include = match params.get(CONDITIONAL_PARAM).map(String::as_str) {
+        None | Some("exclude") => false,
+        Some("include") => true,
+        Some(other) => {
+            return Err(GraphError::InvalidParams(format!(
+                "{} '{}' must be one of 'include', 'exclude'",
+                CONDITIONAL_PARAM, other
+            )))
+        }
+    };
+
+    match params.get(ACCEPTED_RISKS_PARAM) {
+        Some(risks) if params.get(CONDITIONAL_PARAM).is_some() && !include => {
+            Err(GraphError::InvalidParams(format!(
+                "{} '{}' conflicts with {} 'exclude'",
+                ACCEPTED_RISKS_PARAM, risks, CONDITIONAL_PARAM
+            )))
+        }
+        Some(risks) => Ok(ConditionalEdgesFilter::IncludeRisks(
+            risks
+                .split(',')
+                .map(str::trim)
+                .filter(|risk| !risk.is_empty())
+                .map(String::from)
+                .collect(),
+        )),
+        None if include => Ok(ConditionalEdgesFilter::IncludeAll),
+        None => Ok(ConditionalEdgesFilter::Exclude),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::graph::tests::common_init;
+    use actix_web::body::MessageBody;
+    use actix_web::http;
+    use cincinnati::plugins::prelude::*;
+    use cincinnati::plugins::prelude_plugin_impl::*;
+    use cincinnati::plugins::InternalIO;
+
+    static GRAPH_BODY: &str = r#"{
+        "nodes": [
+            {"version": "1.0.0", "payload": "image/1.0.0", "metadata": {}},
+            {"version": "2.0.0", "payload": "image/2.0.0", "metadata": {}},
+            {"version": "3.0.0", "payload": "image/3.0.0", "metadata": {}}
+        ],
+        "edges": [[0, 1], [1, 2]],
+        "conditionalEdges": [
+            {
+                "edges": [{"from": "1.0.0", "to": "3.0.0"}],
+                "risks": [
+                    {
+                        "url": "https://bug.example.com/show_bug.cgi?id=example",
+                        "name": "SomeRisk",
+                        "message": "Updates are broken",
+                        "matchingRules": [{"type": "Always"}]
+                    }
+                ]
+            }
+        ]
+    }"#;
+
+    /// Plugin which replaces the graph with a static one.
+    #[derive(Debug)]
+    struct StaticGraphPlugin(cincinnati::Graph);
+
+    #[async_trait]
+    impl InternalPlugin for StaticGraphPlugin {
+        const PLUGIN_NAME: &'static str = "static-graph";
+
+        async fn run_internal(&self, io: InternalIO) -> Fallible<InternalIO> {
+            Ok(InternalIO {
+                graph: self.0.clone().into(),
+                parameters: io.parameters,
+            })
+        }
+    }
+
+    fn run_request(query: &str) -> Result<HttpResponse, GraphError> {
+        let rt = common_init();
+        let graph = serde_json::from_str(GRAPH_BODY).expect("invalid test graph");
+        let plugins: Vec<BoxedPlugin> =
+            new_plugins!(InternalPluginWrapper(StaticGraphPlugin(graph)));
+        let state = AppState {
+            plugins: Box::leak(Box::new(plugins)),
+            ..Default::default()
+        };
+
+        let http_req = actix_web::test::TestRequest::get()
+            .uri(&format!("http://unused.test/upgrade-paths?{}", query))
+            .insert_header((
+                http::header::ACCEPT,
+                http::header::HeaderValue::from_static(CONTENT_TYPE),
+            ))
+            .to_http_request();
+
+        rt.block_on(index(http_req, actix_web::web::Data::new(state)))
+    }
+
+    fn response_json(resp: HttpResponse) -> Fallible<serde_json::Value> {
+        let bytes = match resp.into_body().try_into_bytes() {
+            Ok(bytes) => bytes,
+            Err(_) => bail!("expected bytes in body"),
+        };
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    #[test]
+    fn unconditional_path() -> Fallible<()> {
+        let resp = run_request("from=1.0.0&to=3.0.0")?;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        assert_eq!(
+            response_json(resp)?,
+            serde_json::json!({
+                "from": "1.0.0",
+                "to": "3.0.0",
+                "paths": [{"versions": ["1.0.0", "2.0.0", "3.0.0"], "risks": []}],
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn conditional_path_with_accepted_risk() -> Fallible<()> {
+        let resp = run_request("from=1.0.0&to=3.0.0&accepted_risks=SomeRisk")?;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        assert_eq!(
+            response_json(resp)?,
+            serde_json::json!({
+                "from": "1.0.0",
+                "to": "3.0.0",
+                "paths": [{"versions": ["1.0.0", "3.0.0"], "risks": ["SomeRisk"]}],
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn unreachable_target() -> Fallible<()> {
+        let resp = run_request("from=3.0.0&to=1.0.0&conditional=include&all=true")?;
+        assert_eq!(resp.status(), http::StatusCode::OK);
+
+        assert_eq!(
+            response_json(resp)?,
+            serde_json::json!({"from": "3.0.0", "to": "1.0.0", "paths": []})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_params() {
+        assert_eq!(
+            run_request("from=1.0.0").unwrap_err(),
+            GraphError::MissingParams(vec!["to".to_string()])
+        );
+    }
+
+    #[test]
+    fn invalid_params() {
+        match run_request("from=1.0.0&to=3.0.0&conditional=maybe") {
+            Err(GraphError::InvalidParams(_)) => {}
+            res => panic!("expected InvalidParams error, got: {:?}", res),
+        }
+
+        match run_request("from=1.0.0&to=3.0.0&all=yes") {
+            Err(GraphError::InvalidParams(_)) => {}
+            res => panic!("expected InvalidParams error, got: {:?}", res),
+        }
+    }
+
+    #[test]
+    fn unknown_release() {
+        match run_request("from=1.0.0&to=9.0.0") {
+            Err(GraphError::DoesNotExist(ref msg)) if msg.contains("9.0.0") => {}
+            res => panic!("expected DoesNotExist error, got: {:?}", res),
+        }
+    }
+}